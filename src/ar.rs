@@ -0,0 +1,99 @@
+//! Adjustments to `llvm-ar` command lines for the AR_* settings.
+
+/// An `llvm-ar` command line, with the parts the settings care about.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ArCommand {
+    pub args: Vec<String>,
+    /// The archive, if the operation modifies or creates one.
+    pub modified_archive: Option<String>,
+}
+
+/// Rewrites the arguments to always use deterministic mode and/or create
+/// thin archives.
+pub(crate) fn adjust_args(args: Vec<String>, deterministic: bool, thin: bool) -> ArCommand {
+    let mut adjusted = Vec::with_capacity(args.len() + 1);
+    let mut operation = None;
+    let mut positional = Vec::new();
+
+    for arg in args {
+        if arg.starts_with("--") || arg.starts_with('@') || operation.is_some() {
+            if operation.is_some() && !arg.starts_with('-') {
+                positional.push(arg.clone());
+            }
+            adjusted.push(arg);
+            continue;
+        }
+
+        // The first other argument is the operation and its modifiers, e.g.
+        // `rcs` or `-rcs`.
+        let mut op = arg;
+        if deterministic {
+            op.retain(|c| c != 'U');
+            if !op.contains('D') {
+                op.push('D');
+            }
+        }
+        operation = Some(op.trim_start_matches('-').to_owned());
+        adjusted.push(op);
+    }
+
+    if thin && !adjusted.iter().any(|arg| arg == "--thin") {
+        adjusted.insert(0, "--thin".to_owned());
+    }
+
+    let modified_archive = operation.and_then(|op| {
+        // The operation letter can be anywhere among the modifiers
+        if !op.contains(['r', 'q', 'd', 'm']) {
+            return None;
+        }
+        // relpos and count come before the archive
+        let skip = usize::from(op.contains(['a', 'b', 'i'])) + usize::from(op.contains('N'));
+        positional.into_iter().nth(skip)
+    });
+
+    ArCommand {
+        args: adjusted,
+        modified_archive,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_adjust_args() {
+        assert_eq!(
+            adjust_args(args(&["cruU", "libfoo.a", "a.o"]), true, false),
+            ArCommand {
+                args: args(&["cruD", "libfoo.a", "a.o"]),
+                modified_archive: Some("libfoo.a".to_owned()),
+            }
+        );
+
+        assert_eq!(
+            adjust_args(
+                args(&["--format=gnu", "-rcs", "libfoo.a", "a.o"]),
+                false,
+                true
+            ),
+            ArCommand {
+                args: args(&["--thin", "--format=gnu", "-rcs", "libfoo.a", "a.o"]),
+                modified_archive: Some("libfoo.a".to_owned()),
+            }
+        );
+
+        assert_eq!(
+            adjust_args(args(&["rb", "b.o", "libfoo.a", "a.o"]), false, false).modified_archive,
+            Some("libfoo.a".to_owned())
+        );
+        assert_eq!(
+            adjust_args(args(&["t", "libfoo.a"]), false, false).modified_archive,
+            None
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! `config.site` for configuring Autoconf projects with wasixcc, generated by
+//! `wasixccenv gen-autotools-site`.
+
+use std::{collections::BTreeMap, path::Path};
+
+const HOST_TRIPLE: &str = "wasm32-wasi";
+
+// Answers for checks that need to run test programs, which configure can't
+// do when cross-compiling, or that get the wrong answer for WASIX.
+const CACHE_VARIABLES: &[(&str, &str)] = &[
+    ("ac_cv_c_bigendian", "no"),
+    ("ac_cv_func_fork_works", "no"),
+    ("ac_cv_func_vfork_works", "no"),
+    ("ac_cv_func_getpgrp_void", "yes"),
+    ("ac_cv_func_setpgrp_void", "yes"),
+    ("ac_cv_func_malloc_0_nonnull", "yes"),
+    ("ac_cv_func_realloc_0_nonnull", "yes"),
+    ("ac_cv_func_memcmp_working", "yes"),
+    ("ac_cv_func_mmap_fixed_mapped", "no"),
+    ("ac_cv_func_strerror_r_char_p", "no"),
+    ("ac_cv_func_stat_empty_string_bug", "no"),
+    ("ac_cv_func_lstat_dereferences_slashed_symlink", "yes"),
+    ("ac_cv_func_chown_works", "no"),
+    ("ac_cv_func_getgroups_works", "no"),
+    ("ac_cv_file__dev_zero", "no"),
+];
+
+/// Generates a `config.site` selecting the wasixcc tools. The given settings
+/// are passed to the compilers as `-s` arguments, so they end up in the
+/// generated makefiles too.
+pub(crate) fn config_site(settings: &BTreeMap<String, String>) -> String {
+    let settings_args = settings
+        .iter()
+        .map(|(name, value)| format!(" {}", shell_word(&format!("-s{name}={value}"))))
+        .collect::<String>();
+
+    let mut contents = String::from("# config.site for building with wasixcc\n\n");
+    for (name, tool) in [
+        ("CC", format!("wasixcc{settings_args}")),
+        ("CXX", format!("wasix++{settings_args}")),
+        ("AR", "wasixar".to_owned()),
+        ("RANLIB", "wasixranlib".to_owned()),
+        ("NM", "wasixnm".to_owned()),
+        ("LD", "wasixld".to_owned()),
+    ] {
+        contents.push_str(&format!("{name}={}\n", quote(&tool)));
+    }
+
+    // Only configure sees this; the build runs wasm-opt as usual.
+    contents.push_str(
+        "\n# Skip wasm-opt for configure's test programs\n\
+        export WASIXCC_RUN_WASM_OPT=no\n\n",
+    );
+
+    for (name, value) in CACHE_VARIABLES {
+        contents.push_str(&format!("{name}={value}\n"));
+    }
+
+    contents
+}
+
+/// The configure invocation using the given `config.site`.
+pub(crate) fn configure_command(config_site: &Path) -> String {
+    format!(
+        "CONFIG_SITE={} ./configure --host={HOST_TRIPLE}",
+        quote(&config_site.display().to_string())
+    )
+}
+
+// Arguments end up in makefile commands, so those with spaces or special
+// characters need quoting of their own.
+fn shell_word(arg: &str) -> String {
+    if arg
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_=.,/:+@%".contains(c))
+    {
+        arg.to_owned()
+    } else {
+        quote(arg)
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_site() {
+        let settings = BTreeMap::from([
+            ("COMPILER_FLAGS".to_owned(), "-O2 -g".to_owned()),
+            ("WASM_EXCEPTIONS".to_owned(), "yes".to_owned()),
+        ]);
+        let contents = config_site(&settings);
+
+        assert!(contents
+            .contains(r"CC='wasixcc '\''-sCOMPILER_FLAGS=-O2 -g'\'' -sWASM_EXCEPTIONS=yes'"));
+        assert!(contents.contains("CXX='wasix++ "));
+        assert!(contents.contains("AR='wasixar'\n"));
+        assert!(contents.contains("ac_cv_func_fork_works=no\n"));
+
+        assert_eq!(
+            configure_command(Path::new("/src/config.site")),
+            "CONFIG_SITE='/src/config.site' ./configure --host=wasm32-wasi"
+        );
+    }
+}
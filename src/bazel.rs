@@ -0,0 +1,288 @@
+//! Bazel C++ toolchain using wasixcc, generated by
+//! `wasixccenv gen-bazel-toolchain`.
+
+use std::collections::BTreeMap;
+
+use super::*;
+
+pub(crate) const BUILD_FILE_NAME: &str = "BUILD.bazel";
+pub(crate) const CONFIG_FILE_NAME: &str = "cc_toolchain_config.bzl";
+
+const BUILD_FILE: &str = r#"# Generated by `wasixccenv gen-bazel-toolchain`.
+load(":cc_toolchain_config.bzl", "cc_toolchain_config")
+
+package(default_visibility = ["//visibility:public"])
+
+filegroup(name = "empty")
+
+cc_toolchain_config(name = "wasix_toolchain_config")
+
+cc_toolchain(
+    name = "wasix_cc_toolchain",
+    all_files = ":empty",
+    ar_files = ":empty",
+    as_files = ":empty",
+    compiler_files = ":empty",
+    dwp_files = ":empty",
+    linker_files = ":empty",
+    objcopy_files = ":empty",
+    strip_files = ":empty",
+    supports_param_files = 0,
+    toolchain_config = ":wasix_toolchain_config",
+    toolchain_identifier = "wasix",
+)
+
+toolchain(
+    name = "wasix_toolchain",
+    target_compatible_with = [
+        "@platforms//cpu:wasm32",
+        "@platforms//os:wasi",
+    ],
+    toolchain = ":wasix_cc_toolchain",
+    toolchain_type = "@bazel_tools//tools/cpp:toolchain_type",
+)
+
+platform(
+    name = "wasix",
+    constraint_values = [
+        "@platforms//cpu:wasm32",
+        "@platforms//os:wasi",
+    ],
+)
+"#;
+
+const CONFIG_RULE: &str = r#"
+C_COMPILE_ACTIONS = [
+    ACTION_NAMES.c_compile,
+    ACTION_NAMES.assemble,
+    ACTION_NAMES.preprocess_assemble,
+]
+
+CXX_COMPILE_ACTIONS = [
+    ACTION_NAMES.cpp_compile,
+    ACTION_NAMES.cpp_header_parsing,
+    ACTION_NAMES.cpp_module_compile,
+]
+
+LINK_ACTIONS = [
+    ACTION_NAMES.cpp_link_executable,
+    ACTION_NAMES.cpp_link_dynamic_library,
+    ACTION_NAMES.cpp_link_nodeps_dynamic_library,
+]
+
+def _flag_sets(actions, flags):
+    if not flags:
+        return []
+    return [flag_set(actions = actions, flag_groups = [flag_group(flags = flags)])]
+
+def _impl(ctx):
+    action_configs = [
+        action_config(action_name = name, tools = [tool(path = WASIXCC)])
+        for name in C_COMPILE_ACTIONS
+    ] + [
+        action_config(action_name = name, tools = [tool(path = WASIXXX)])
+        for name in CXX_COMPILE_ACTIONS + LINK_ACTIONS
+    ] + [
+        action_config(
+            action_name = ACTION_NAMES.cpp_link_static_library,
+            tools = [tool(path = WASIXAR)],
+        ),
+    ]
+
+    tool_paths = [
+        tool_path(name = "gcc", path = WASIXCC),
+        tool_path(name = "ar", path = WASIXAR),
+        tool_path(name = "ld", path = WASIXLD),
+        tool_path(name = "nm", path = WASIXNM),
+        tool_path(name = "cpp", path = "/bin/false"),
+        tool_path(name = "gcov", path = "/bin/false"),
+        tool_path(name = "objdump", path = "/bin/false"),
+        tool_path(name = "strip", path = "/bin/false"),
+    ]
+
+    features = [
+        feature(
+            name = "wasixcc_settings",
+            enabled = True,
+            flag_sets = _flag_sets(C_COMPILE_ACTIONS + CXX_COMPILE_ACTIONS + LINK_ACTIONS, SETTINGS_FLAGS),
+        ),
+        feature(
+            name = "wasixcc_default_flags",
+            enabled = True,
+            flag_sets = _flag_sets(C_COMPILE_ACTIONS + CXX_COMPILE_ACTIONS, DEFAULT_COMPILE_FLAGS),
+        ),
+        feature(name = "supports_pic", enabled = SUPPORTS_PIC),
+    ]
+
+    return cc_common.create_cc_toolchain_config_info(
+        ctx = ctx,
+        toolchain_identifier = "wasix",
+        host_system_name = "local",
+        target_system_name = "wasm32-wasi",
+        target_cpu = "wasm32",
+        target_libc = "wasix-libc",
+        compiler = "clang",
+        abi_version = "unknown",
+        abi_libc_version = "unknown",
+        action_configs = action_configs,
+        features = features,
+        tool_paths = tool_paths,
+        cxx_builtin_include_directories = BUILTIN_INCLUDE_DIRECTORIES,
+    )
+
+cc_toolchain_config = rule(
+    implementation = _impl,
+    attrs = {},
+    provides = [CcToolchainConfigInfo],
+)
+"#;
+
+/// Paths to the wasixcc tools the toolchain runs.
+pub(crate) struct Tools {
+    pub cc: PathBuf,
+    pub cxx: PathBuf,
+    pub ar: PathBuf,
+    pub ld: PathBuf,
+    pub nm: PathBuf,
+}
+
+impl Tools {
+    /// Finds the tools in PATH, where `wasixcc --install-executables` puts
+    /// them. Bazel needs absolute paths to run them from its sandbox.
+    pub(crate) fn find() -> Result<Self> {
+        let find = |name: &str| {
+            std::env::var_os("PATH")
+                .into_iter()
+                .flat_map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+                .map(|dir| dir.join(name))
+                .find(|path| path.is_file())
+                .with_context(|| {
+                    format!(
+                        "Could not find {name} in PATH; install the wasixcc executables with \
+                        `wasixcc --install-executables <DIR>` first"
+                    )
+                })
+        };
+
+        Ok(Self {
+            cc: find("wasixcc")?,
+            cxx: find("wasix++")?,
+            ar: find("wasixar")?,
+            ld: find("wasixld")?,
+            nm: find("wasixnm")?,
+        })
+    }
+}
+
+pub(crate) fn build_file() -> &'static str {
+    BUILD_FILE
+}
+
+/// Generates the `cc_toolchain_config` rule. The given settings are passed
+/// to every compile and link as `-s` arguments; wasixcc's own default flags
+/// are listed too, so tools reading the toolchain's flags see them.
+pub(crate) fn toolchain_config(
+    tools: &Tools,
+    settings: &BTreeMap<String, String>,
+    user_settings: &UserSettings,
+) -> Result<String> {
+    let settings_flags = settings
+        .iter()
+        .map(|(name, value)| format!("-s{name}={value}"))
+        .collect::<Vec<_>>();
+    let default_flags = compiler::injected_compiler_flags(user_settings);
+
+    let llvm_path = match &user_settings.llvm_location {
+        LlvmLocation::UserProvided(path) | LlvmLocation::DefaultPath(path) => path.clone(),
+        // Where distributions such as Debian install versioned LLVM
+        LlvmLocation::FromSystem(version) => PathBuf::from(format!("/usr/lib/llvm-{version}")),
+    };
+    let include_dirs = [
+        user_settings.sysroot_location()?.join("include"),
+        llvm_path.join("lib/clang"),
+    ];
+
+    let mut contents = String::from(
+        "# Generated by `wasixccenv gen-bazel-toolchain`.\n\
+        load(\"@bazel_tools//tools/build_defs/cc:action_names.bzl\", \"ACTION_NAMES\")\n\
+        load(\n    \"@bazel_tools//tools/cpp:cc_toolchain_config_lib.bzl\",\n    \
+        \"action_config\",\n    \"feature\",\n    \"flag_group\",\n    \"flag_set\",\n    \
+        \"tool\",\n    \"tool_path\",\n)\n\n",
+    );
+    for (name, path) in [
+        ("WASIXCC", &tools.cc),
+        ("WASIXXX", &tools.cxx),
+        ("WASIXAR", &tools.ar),
+        ("WASIXLD", &tools.ld),
+        ("WASIXNM", &tools.nm),
+    ] {
+        contents.push_str(&format!(
+            "{name} = {}\n",
+            quote(&path.display().to_string())
+        ));
+    }
+
+    contents.push_str(&format!(
+        "\n# Settings passed to every compile and link.\nSETTINGS_FLAGS = {}\n\
+        \n# Flags wasixcc adds to every compilation itself.\nDEFAULT_COMPILE_FLAGS = {}\n\
+        \nBUILTIN_INCLUDE_DIRECTORIES = {}\n\
+        \nSUPPORTS_PIC = {}\n",
+        list(settings_flags.iter().map(String::as_str)),
+        list(default_flags.iter().copied()),
+        list(
+            include_dirs
+                .iter()
+                .map(|dir| dir.to_str().unwrap_or_default())
+        ),
+        if user_settings.module_kind().requires_pic() || user_settings.pic {
+            "True"
+        } else {
+            "False"
+        },
+    ));
+    contents.push_str(CONFIG_RULE);
+
+    Ok(contents)
+}
+
+fn list<'a>(items: impl Iterator<Item = &'a str>) -> String {
+    let items = items.map(quote).collect::<Vec<_>>();
+    if items.is_empty() {
+        return "[]".to_owned();
+    }
+    format!("[\n    {},\n]", items.join(",\n    "))
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toolchain_config() {
+        let tools = Tools {
+            cc: PathBuf::from("/bin/wasixcc"),
+            cxx: PathBuf::from("/bin/wasix++"),
+            ar: PathBuf::from("/bin/wasixar"),
+            ld: PathBuf::from("/bin/wasixld"),
+            nm: PathBuf::from("/bin/wasixnm"),
+        };
+        let settings = BTreeMap::from([("WASM_EXCEPTIONS".to_owned(), "yes".to_owned())]);
+        let user_settings = UserSettings {
+            sysroot_location: Some(PathBuf::from("/sysroot")),
+            wasm_exceptions: true,
+            ..Default::default()
+        };
+
+        let contents = toolchain_config(&tools, &settings, &user_settings).unwrap();
+        assert!(contents.contains("WASIXXX = \"/bin/wasix++\"\n"));
+        assert!(contents.contains("SETTINGS_FLAGS = [\n    \"-sWASM_EXCEPTIONS=yes\",\n]"));
+        assert!(contents.contains("    \"-fwasm-exceptions\",\n"));
+        assert!(contents.contains("    \"/sysroot/include\",\n"));
+        assert!(contents.contains("SUPPORTS_PIC = False\n"));
+        assert!(build_file().contains("name = \"wasix_toolchain\""));
+    }
+}
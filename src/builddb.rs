@@ -0,0 +1,230 @@
+//! `wasixcc build-db`: compiles every entry of an existing
+//! `compile_commands.json` with wasixcc, to see how much of a codebase builds
+//! for WASIX before porting its build system.
+
+use std::time::Instant;
+
+use super::*;
+
+/// An entry of `compile_commands.json`, which has either `arguments` or a
+/// shell-quoted `command`.
+#[derive(Debug, serde::Deserialize)]
+struct Entry {
+    directory: PathBuf,
+    file: PathBuf,
+    arguments: Option<Vec<String>>,
+    command: Option<String>,
+    output: Option<PathBuf>,
+}
+
+// Launchers that may precede the compiler in recorded commands.
+const LAUNCHERS: &[&str] = &["ccache", "sccache", "distcc"];
+
+pub(crate) fn run(database: &Path, out_dir: &Path, settings_args: &[String]) -> Result<()> {
+    let contents = std::fs::read_to_string(database)
+        .with_context(|| format!("Failed to read {}", database.display()))?;
+    let entries: Vec<Entry> = serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse {}", database.display()))?;
+
+    let original_dir = std::env::current_dir().context("Failed to get current directory")?;
+    let started = Instant::now();
+    let mut failed = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        eprintln!("[{}/{}] {}", index + 1, entries.len(), entry.file.display());
+        if let Err(e) = compile_entry(entry, out_dir, settings_args) {
+            print_error(&e);
+            failed.push(&entry.file);
+        }
+    }
+    std::env::set_current_dir(&original_dir)
+        .with_context(|| format!("Failed to change directory to {}", original_dir.display()))?;
+
+    eprintln!(
+        "\nCompiled {} of {} entries in {:.2}s",
+        entries.len() - failed.len(),
+        entries.len(),
+        started.elapsed().as_secs_f64()
+    );
+    if !failed.is_empty() {
+        eprintln!("Failed:");
+        for file in &failed {
+            eprintln!("  {}", file.display());
+        }
+        bail!("{} entries failed to compile", failed.len());
+    }
+    Ok(())
+}
+
+fn compile_entry(entry: &Entry, out_dir: &Path, settings_args: &[String]) -> Result<()> {
+    let arguments = match (&entry.arguments, &entry.command) {
+        (Some(arguments), _) => arguments.clone(),
+        (None, Some(command)) => split_command(command)?,
+        (None, None) => bail!("Entry has neither arguments nor command"),
+    };
+    let (compiler, args) = strip_compiler(&arguments)?;
+    let run_cxx = Path::new(compiler)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().contains("++"))
+        || is_cxx_source(&entry.file);
+
+    let (mut args, output) = take_output(args);
+    let output = output.or_else(|| entry.output.clone()).unwrap_or_else(|| {
+        let mut output = entry.file.clone().into_os_string();
+        output.push(".o");
+        PathBuf::from(output)
+    });
+    let object = out_dir.join(relative_object_path(&entry.directory.join(output)));
+    if let Some(parent) = object.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+
+    args.extend(["-o".to_owned(), object.to_string_lossy().into_owned()]);
+
+    // Arguments are relative to the entry's directory
+    std::env::set_current_dir(&entry.directory).with_context(|| {
+        format!(
+            "Failed to change directory to {}",
+            entry.directory.display()
+        )
+    })?;
+    let mut user_settings = gather_user_settings(settings_args)?;
+    user_settings.out_dir = None;
+    compiler::run(args, user_settings, run_cxx)
+}
+
+// Splits off the recorded compiler, along with any launcher in front of it.
+fn strip_compiler(arguments: &[String]) -> Result<(&str, Vec<String>)> {
+    let mut arguments = arguments.iter();
+    let mut compiler = arguments.next().context("Entry has an empty command")?;
+    let is_launcher = |arg: &str| {
+        Path::new(arg)
+            .file_stem()
+            .is_some_and(|stem| LAUNCHERS.contains(&&*stem.to_string_lossy()))
+    };
+    if is_launcher(compiler) {
+        compiler = arguments
+            .next()
+            .context("Entry has no compiler after the launcher")?;
+    }
+    Ok((compiler, arguments.cloned().collect()))
+}
+
+// Removes `-o <file>` or `-o<file>` from the arguments, returning the file.
+fn take_output(args: Vec<String>) -> (Vec<String>, Option<PathBuf>) {
+    let mut result = Vec::new();
+    let mut output = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "-o" {
+            output = args.next().map(PathBuf::from);
+        } else if let Some(file) = arg.strip_prefix("-o") {
+            output = Some(PathBuf::from(file));
+        } else {
+            result.push(arg);
+        }
+    }
+    (result, output)
+}
+
+// Maps an absolute output path to a unique path inside the output directory,
+// so objects never overwrite the ones of the original build.
+fn relative_object_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter_map(|component| match component {
+            std::path::Component::Normal(name) => Some(name.to_owned()),
+            std::path::Component::ParentDir => Some("__".into()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Splits a command line the way a POSIX shell would, for entries with a
+// `command` string.
+fn split_command(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut current = None::<String>;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => bail!("Unterminated quote in command: {command}"),
+                    }
+                }
+            }
+            '"' => {
+                let word = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => bail!("Unterminated quote in command: {command}"),
+                        },
+                        Some(c) => word.push(c),
+                        None => bail!("Unterminated quote in command: {command}"),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    current.get_or_insert_with(String::new).push(c);
+                }
+            }
+            c if c.is_whitespace() => words.extend(current.take()),
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(current);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(
+            split_command(r#"gcc -DNAME="\"a b\"" -I'my dir' -c a\ b.c  -o x.o"#).unwrap(),
+            [
+                "gcc",
+                r#"-DNAME="a b""#,
+                "-Imy dir",
+                "-c",
+                "a b.c",
+                "-o",
+                "x.o"
+            ]
+        );
+        assert!(split_command("gcc 'a").is_err());
+    }
+
+    #[test]
+    fn test_strip_compiler_and_output() {
+        let arguments = ["ccache", "/usr/bin/g++", "-c", "a.cc", "-oa.o", "-O2"].map(String::from);
+        let (compiler, args) = strip_compiler(&arguments).unwrap();
+        assert_eq!(compiler, "/usr/bin/g++");
+
+        let (args, output) = take_output(args);
+        assert_eq!(args, ["-c", "a.cc", "-O2"]);
+        assert_eq!(output, Some(PathBuf::from("a.o")));
+    }
+
+    #[test]
+    fn test_relative_object_path() {
+        assert_eq!(
+            relative_object_path(Path::new("/src/build/../obj/a.o")),
+            PathBuf::from("src/build/__/obj/a.o")
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//! `wasixccenv`: management of the wasixcc environment, such as downloading
+//! toolchain components.
+
+use std::str::FromStr;
+
+use super::*;
+
+pub(crate) fn run(args: Vec<String>, user_settings: UserSettings) -> Result<()> {
+    let mut args = args.into_iter();
+
+    let Some(subcommand) = args.next() else {
+        print_help();
+        bail!("No subcommand specified");
+    };
+
+    match subcommand.as_str() {
+        "help" | "--help" | "-h" => {
+            print_help();
+            Ok(())
+        }
+
+        "download-sysroot" => {
+            let tag_spec = parse_tag_spec(args.next())?;
+            download::download_sysroot(tag_spec, &user_settings)
+        }
+
+        "download-llvm" => {
+            let tag_spec = parse_tag_spec(args.next())?;
+            download::download_llvm(tag_spec, &user_settings)
+        }
+
+        "download-binaryen" => {
+            let tag_spec = parse_tag_spec(args.next())?;
+            download::download_binaryen(tag_spec, &user_settings)
+        }
+
+        "download-all" | "install-all" => {
+            download::download_llvm(TagSpec::Latest, &user_settings)?;
+            download::download_sysroot(TagSpec::Latest, &user_settings)?;
+            download::download_binaryen(TagSpec::Latest, &user_settings)
+        }
+
+        "status" => status::print_status(&user_settings),
+
+        "clean" => {
+            let targets = args
+                .map(|arg| clean::Target::from_arg(&arg))
+                .collect::<Result<Vec<_>>>()?;
+            if targets.is_empty() {
+                clean::clean(&clean::Target::ALL, &user_settings)
+            } else {
+                clean::clean(&targets, &user_settings)
+            }
+        }
+
+        "uninstall-executables" => {
+            let dir = args.next().context(
+                "uninstall-executables requires the directory passed to --install-executables",
+            )?;
+            clean::uninstall_executables(Path::new(&dir))
+        }
+
+        "use" => toolchains::use_toolchain(args.next().as_deref(), &user_settings),
+
+        "list-versions" => {
+            let components = match args.next().as_deref() {
+                None => download::Component::ALL.to_vec(),
+                Some("--sysroot") => vec![download::Component::Sysroot],
+                Some("--llvm") => vec![download::Component::Llvm],
+                Some("--binaryen") => vec![download::Component::Binaryen],
+                Some(arg) => bail!("Unknown argument to list-versions: {arg}"),
+            };
+            download::list_versions(&components, &user_settings)
+        }
+
+        "env" => {
+            let shell = match (args.next().as_deref(), args.next()) {
+                (None, _) => shellenv::Shell::Bash,
+                (Some("--shell"), Some(shell)) => shell.parse()?,
+                (Some("--shell"), None) => bail!("--shell requires a value"),
+                (Some(arg), _) => bail!("Unknown argument to env: {arg}"),
+            };
+            shellenv::print_env(shell)
+        }
+
+        "cargo-env" => {
+            let write = match args.next().as_deref() {
+                None => false,
+                Some("--write") => true,
+                Some(arg) => bail!("Unknown argument to cargo-env: {arg}"),
+            };
+            let vars = shellenv::cargo_env_vars(&shellenv::current_settings());
+            if !write {
+                print!("{}", shellenv::cargo_env_exports(&vars));
+                return Ok(());
+            }
+
+            let path = Path::new(shellenv::CARGO_CONFIG_PATH);
+            let existing = match std::fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to read {}", path.display()))
+                }
+            };
+            let contents = shellenv::update_cargo_config(&existing, &vars)?;
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            std::fs::write(path, contents)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            eprintln!("Updated {}", path.display());
+            Ok(())
+        }
+
+        "gen-bazel-toolchain" => {
+            let dir = PathBuf::from(
+                args.next()
+                    .context("gen-bazel-toolchain requires a directory")?,
+            );
+            let config = bazel::toolchain_config(
+                &bazel::Tools::find()?,
+                &shellenv::current_settings(),
+                &user_settings,
+            )?;
+
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            for (name, contents) in [
+                (bazel::BUILD_FILE_NAME, bazel::build_file()),
+                (bazel::CONFIG_FILE_NAME, config.as_str()),
+            ] {
+                let path = dir.join(name);
+                std::fs::write(&path, contents)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+
+            eprintln!(
+                "Wrote the toolchain to {}; build with \
+                --extra_toolchains=//{0}:wasix_toolchain --platforms=//{0}:wasix \
+                from the workspace root",
+                dir.display()
+            );
+            Ok(())
+        }
+
+        "gen-conan-profile" => {
+            write_generated_file(args.next(), &conan::profile(&shellenv::current_settings()))
+        }
+
+        "gen-meson-cross" => write_generated_file(
+            args.next(),
+            &meson::cross_file(&shellenv::current_settings()),
+        ),
+
+        "gen-autotools-site" => {
+            let path = PathBuf::from(args.next().as_deref().unwrap_or("config.site"));
+            let contents = autotools::config_site(&shellenv::current_settings());
+            std::fs::write(&path, contents)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            let path = std::path::absolute(&path)
+                .with_context(|| format!("Failed to resolve {}", path.display()))?;
+
+            eprintln!("Wrote {}; configure the project with:", path.display());
+            println!("{}", autotools::configure_command(&path));
+            Ok(())
+        }
+
+        "lock-sysroot" => {
+            let tag_spec = parse_tag_spec(args.next())?;
+            store::lock_sysroot(tag_spec, &user_settings)
+        }
+
+        "sync-sysroot" => store::sync_sysroot(&user_settings),
+
+        "link-sysroot" => {
+            let (copy, path) = match (args.next(), args.next()) {
+                (Some(flag), Some(path)) if flag == "--copy" => (true, path),
+                (Some(path), None) if path != "--copy" => (false, path),
+                _ => bail!("Usage: wasixccenv link-sysroot [--copy] <path-to-wasix-libc>"),
+            };
+            store::link_local_sysroot(Path::new(&path), copy, &user_settings)
+        }
+
+        "build-matrix" => {
+            let config_path = match args.next() {
+                Some(path) => PathBuf::from(path),
+                None => config::find_config_file()
+                    .with_context(|| format!("Could not find {}", config::CONFIG_FILE_NAME))?,
+            };
+            let config = config::load_config(&config_path)?;
+            let matrix = config
+                .matrix
+                .with_context(|| format!("No [matrix] section in {}", config_path.display()))?;
+            let base_dir = config_path.parent().unwrap_or(Path::new("."));
+            matrix::run_matrix(&matrix, base_dir)
+        }
+
+        "tool" => {
+            let tool = args
+                .next()
+                .context("tool requires the name of an LLVM tool to run")?;
+            run_tool_with_passthrough_args(&tool, args.collect(), user_settings)
+        }
+
+        "cache" => match args.next().as_deref() {
+            None | Some("stats") => objcache::print_stats(),
+            Some("clear") => objcache::clear(),
+            Some(arg) => bail!("Unknown cache command: {arg}; expected stats or clear"),
+        },
+
+        "selftest" => {
+            let full = match args.next().as_deref() {
+                None => false,
+                Some("--full") => true,
+                Some(arg) => bail!("Unknown argument to selftest: {arg}"),
+            };
+            selftest::run(full, &user_settings)
+        }
+
+        cmd => bail!("Unknown subcommand: {cmd}; run `wasixccenv help` for usage"),
+    }
+}
+
+fn parse_tag_spec(arg: Option<String>) -> Result<TagSpec> {
+    match arg {
+        Some(spec) => TagSpec::from_str(&spec),
+        None => Ok(TagSpec::Latest),
+    }
+}
+
+// Writes a generated file to the given path, or to stdout if there's none.
+fn write_generated_file(path: Option<String>, contents: &str) -> Result<()> {
+    match path {
+        Some(path) => {
+            std::fs::write(&path, contents).with_context(|| format!("Failed to write {path}"))?;
+            eprintln!("Wrote {path}");
+            Ok(())
+        }
+        None => {
+            print!("{contents}");
+            Ok(())
+        }
+    }
+}
+
+fn print_help() {
+    println!(
+        r#"Usage: wasixccenv [-s[CONFIG]=[VALUE]...] <SUBCOMMAND> [ARGS]
+
+Manages the environment used by wasixcc. Configuration options are the same
+as for wasixcc; run `wasixcc --help` for the full list.
+
+Subcommands:
+  help                       Print this help message
+  download-sysroot [TAG]     Download and install the wasix-libc sysroot
+                             into SYSROOT_PREFIX. The tag can be 'latest'
+                             (the default) or a specific tag such as
+                             'v2025-01-01.1'.
+  download-llvm [TAG]        Download and install the LLVM toolchain into
+                             LLVM_LOCATION.
+  download-binaryen [TAG]    Download and install Binaryen into
+                             BINARYEN_LOCATION. The tag can be 'latest' or
+                             a specific tag such as 'version_124'.
+  download-all               Download the latest version of the sysroot,
+                             the LLVM toolchain and Binaryen.
+  install-all                Same as download-all.
+  list-versions [--sysroot|--llvm|--binaryen]
+                             List the most recent releases of the toolchain
+                             components (all of them by default) with their
+                             dates, to pick a TAG for the download
+                             subcommands.
+  status                     Print the installed LLVM, sysroot variants and
+                             Binaryen with their versions, the wasix*
+                             executables in PATH, and where each configured
+                             setting comes from.
+  clean [--llvm|--sysroot|--binaryen|--cache]
+                             Remove the downloaded LLVM toolchain, sysroot
+                             variants or Binaryen, or the object and download
+                             caches; everything by default. Locations set
+                             through settings are only removed if wasixccenv
+                             downloaded them.
+  uninstall-executables PATH Remove the symlinks created by `wasixcc
+                             --install-executables PATH`, including broken
+                             ones.
+  use [NAME]                 Select the toolchain in ~/.wasixcc/toolchains/NAME
+                             for builds that don't set TOOLCHAIN, or list the
+                             installed toolchains. `default` goes back to the
+                             components directly in ~/.wasixcc.
+  env [--shell SHELL]        Print variables pointing CC, CXX, AR, RANLIB,
+                             NM and LD at the wasixcc tools, along with the
+                             WASIXCC_* settings currently configured, for
+                             use as in `eval "$(wasixccenv env)"`. SHELL is
+                             bash (the default), zsh, fish or cmake; the
+                             latter prints set() commands for a toolchain
+                             file.
+  cargo-env [--write]        Print the CC_wasm32_wasmer_wasi, CXX_..., AR_...
+                             and CFLAGS_... variables making Rust crates that
+                             use the cc crate build their C code with wasixcc.
+                             With --write, add them to the [env] table of
+                             .cargo/config.toml instead.
+  gen-bazel-toolchain DIR    Write a Bazel C++ toolchain running the wasixcc
+                             tools, with a wasm32/wasi platform to select it,
+                             into DIR. The WASIXCC_* settings currently
+                             configured are passed to every compile and link.
+  gen-conan-profile [PATH]   Write a Conan 2 host profile building with the
+                             wasixcc tools to PATH, or print it. The WASIXCC_*
+                             settings currently configured are passed to
+                             every compile and link.
+  gen-meson-cross [PATH]     Write a Meson cross file using the wasixcc
+                             tools to PATH, or print it. The WASIXCC_*
+                             settings currently configured are passed to
+                             every compile and link as -s arguments.
+  gen-autotools-site [PATH]  Write a config.site for Autoconf projects to
+                             PATH (./config.site by default), selecting the
+                             wasixcc tools and answering the configure checks
+                             that can't run when cross-compiling, and print
+                             the configure command using it.
+  lock-sysroot [TAG]         Download a sysroot release into SYSROOT_STORE
+                             and pin it in the project's wasixcc.lock. Builds
+                             under the project then use the pinned sysroots
+                             through links in .wasixcc/sysroot.
+  sync-sysroot               Download the sysroots pinned in wasixcc.lock
+                             into SYSROOT_STORE if they're missing, and
+                             update the project's .wasixcc/sysroot links.
+  link-sysroot [--copy] PATH Install the sysroot variants built in the
+                             wasix-libc tree at PATH into SYSROOT_PREFIX, as
+                             links to the build tree (or copies, with
+                             --copy), to test local changes to wasix-libc.
+  build-matrix [CONFIG]      Build every variant described by the [matrix]
+                             section of wasixcc.toml (found in the current
+                             directory or its ancestors, unless a path is
+                             given) and print a summary table. Each variant
+                             runs the configured command with the variant's
+                             settings exported as WASIXCC_* variables and
+                             {{out_dir}} replaced by its own build directory;
+                             the command's output is saved to build.log in
+                             that directory.
+  tool NAME [--] [ARGS]      Run any tool from LLVM_LOCATION/bin, such as
+                             llvm-readobj or clang-format, with the given
+                             arguments.
+  cache [stats|clear]        Print the size and hit rate of the object cache
+                             used with OBJECT_CACHE (the default), or remove
+                             all cached objects.
+  selftest [--full]          Build a set of example programs in the static,
+                             static-eh and dynamic-eh configurations, run
+                             them under wasmer (or RUNNER, in the directory
+                             of the outputs) and print which ones work.
+                             --full first downloads any missing toolchain
+                             components and adds the dlopen and sockets
+                             examples.
+"#
+    );
+}
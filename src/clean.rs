@@ -0,0 +1,143 @@
+//! `wasixccenv clean` and `uninstall-executables`: removal of what
+//! `wasixccenv` and `wasixcc --install-executables` installed.
+
+use std::fs;
+
+use super::*;
+
+/// What `wasixccenv clean` removes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Target {
+    Llvm,
+    Sysroot,
+    Binaryen,
+    Cache,
+}
+
+impl Target {
+    pub(crate) const ALL: [Target; 4] = [Self::Llvm, Self::Sysroot, Self::Binaryen, Self::Cache];
+
+    pub(crate) fn from_arg(arg: &str) -> Result<Self> {
+        match arg {
+            "--llvm" => Ok(Self::Llvm),
+            "--sysroot" => Ok(Self::Sysroot),
+            "--binaryen" => Ok(Self::Binaryen),
+            "--cache" => Ok(Self::Cache),
+            _ => bail!("Unknown argument to clean: {arg}"),
+        }
+    }
+}
+
+/// Removes downloaded components. Locations given explicitly through
+/// settings are only removed if a download recorded its release there, so
+/// that pointing LLVM_LOCATION at a system installation never deletes it.
+pub(crate) fn clean(targets: &[Target], user_settings: &UserSettings) -> Result<()> {
+    for target in targets {
+        match target {
+            Target::Llvm => match &user_settings.llvm_location {
+                LlvmLocation::DefaultPath(path) => remove(path)?,
+                LlvmLocation::UserProvided(path) => remove_downloaded(path, "LLVM_LOCATION")?,
+                LlvmLocation::FromSystem(_) => {
+                    eprintln!("Not removing the system's LLVM used through LLVM_SYSTEM_VERSION")
+                }
+            },
+
+            Target::Sysroot => {
+                if let Some(path) = &user_settings.sysroot_location {
+                    remove_downloaded(path, "SYSROOT")?;
+                    continue;
+                }
+                for variant in store::SYSROOT_VARIANTS {
+                    remove(&user_settings.sysroot_prefix.join(variant))?;
+                }
+            }
+
+            Target::Binaryen => match &user_settings.binaryen_location {
+                BinaryenLocation::DefaultPath(path) => remove(path)?,
+                BinaryenLocation::UserProvided(path) => {
+                    remove_downloaded(path, "BINARYEN_LOCATION")?
+                }
+            },
+
+            Target::Cache => {
+                objcache::clear()?;
+                remove(&download::download_cache_dir())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn remove_downloaded(path: &Path, setting: &str) -> Result<()> {
+    if compat::read_release_tag(path).is_none() {
+        eprintln!(
+            "Not removing {}, which {setting} points at but wasn't downloaded by wasixccenv",
+            path.display()
+        );
+        return Ok(());
+    }
+    remove(path)
+}
+
+// Removes a directory, or the link to one such as the sysroot links of a
+// project with a wasixcc.lock.
+fn remove(path: &Path) -> Result<()> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if metadata.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .with_context(|| format!("Failed to remove {}", path.display()))?;
+    eprintln!("Removed {}", path.display());
+    Ok(())
+}
+
+/// Removes the `wasix*` links created by `--install-executables` from
+/// `dir`, including broken ones left behind when the wasixcc executable
+/// moved. Regular files with the same names are left alone, except on
+/// Windows, where the executables may have been installed as copies.
+pub(crate) fn uninstall_executables(dir: &Path) -> Result<()> {
+    let mut removed = 0;
+    for command in COMMANDS {
+        let path = dir.join(format!("wasix{command}{}", std::env::consts::EXE_SUFFIX));
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if !metadata.file_type().is_symlink() && !cfg!(windows) {
+            eprintln!("Not removing {}, which isn't a symlink", path.display());
+            continue;
+        }
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        removed += 1;
+    }
+
+    if removed == 0 {
+        bail!("No wasixcc executables found in {}", dir.display());
+    }
+    eprintln!("Removed {removed} executables from {}", dir.display());
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uninstall_executables() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let dir = tmp.path();
+        std::os::unix::fs::symlink("/nonexistent/wasixcc", dir.join("wasixcc")).unwrap();
+        std::os::unix::fs::symlink("/nonexistent/wasixcc", dir.join("wasixar")).unwrap();
+        fs::write(dir.join("wasixld"), "").unwrap();
+
+        uninstall_executables(dir).unwrap();
+        assert!(fs::symlink_metadata(dir.join("wasixcc")).is_err());
+        assert!(fs::symlink_metadata(dir.join("wasixar")).is_err());
+        assert!(dir.join("wasixld").exists());
+
+        assert!(uninstall_executables(dir).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Compatibility checks between the installed sysroot and LLVM, configured
+//! through the COMPAT_CHECK setting.
+//!
+//! Downloads record the release tag they came from in each installation.
+//! Before compiling, the recorded tags are checked against COMPATIBILITY,
+//! since a sysroot built for a different LLVM release tends to fail only at
+//! link time, with inscrutable relocation errors.
+
+use std::{fs, path::Path, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{download::TagSpec, vfs, UserSettings};
+
+/// Name of the file recording the release tag of a downloaded sysroot
+/// variant or LLVM installation.
+pub(crate) const RELEASE_FILE_NAME: &str = ".wasixcc-release";
+
+/// Releases that are compatible with each other, as the first LLVM and
+/// sysroot tags of each group; each group lasts until the next one starts.
+/// Tags are dated (e.g. `v2025-01-01.1`), so they sort chronologically. Add
+/// a group whenever a release of either breaks compatibility with earlier
+/// releases of the other.
+const COMPATIBILITY: &[(&str, &str)] = &[("v2025-01-01", "v2025-01-01")];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CompatCheck {
+    Off,
+    #[default]
+    Warn,
+    Error,
+}
+
+impl FromStr for CompatCheck {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" | "no" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "error" => Ok(Self::Error),
+            _ => bail!("Unknown compatibility check mode: {s}; expected off, warn or error"),
+        }
+    }
+}
+
+pub(crate) fn write_release_tag(dir: &Path, tag: &str) -> Result<()> {
+    let path = dir.join(RELEASE_FILE_NAME);
+    fs::write(&path, format!("{tag}\n"))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub(crate) fn read_release_tag(dir: &Path) -> Option<String> {
+    let contents = vfs::get()
+        .read_to_string(&dir.join(RELEASE_FILE_NAME))
+        .ok()?;
+    Some(contents.trim().to_owned())
+}
+
+/// Checks that the sysroot and LLVM come from compatible releases. Only
+/// installations downloaded by wasixcc record their release, so others are
+/// never reported.
+pub(crate) fn check(user_settings: &UserSettings, sysroot: &Path) -> Result<()> {
+    if user_settings.compat_check == CompatCheck::Off {
+        return Ok(());
+    }
+    let Some(llvm_dir) = user_settings.llvm_location.install_path() else {
+        return Ok(());
+    };
+    let (Some(llvm_tag), Some(sysroot_tag)) =
+        (read_release_tag(llvm_dir), read_release_tag(sysroot))
+    else {
+        tracing::debug!("Not checking compatibility of toolchain without recorded releases");
+        return Ok(());
+    };
+
+    if is_compatible(&llvm_tag, &sysroot_tag) {
+        return Ok(());
+    }
+
+    let message = format!(
+        "The sysroot at {} (release {sysroot_tag}) is not compatible with LLVM at {} \
+        (release {llvm_tag}); run `wasixccenv download-all` to install matching versions",
+        sysroot.display(),
+        llvm_dir.display()
+    );
+    match user_settings.compat_check {
+        CompatCheck::Error => bail!("{message}"),
+        _ => {
+            eprintln!("Warning: {message}");
+            Ok(())
+        }
+    }
+}
+
+/// The sysroot release to download for the installed LLVM: the latest one,
+/// unless LLVM records a release from an earlier compatibility group, in
+/// which case it's the first sysroot release of that group.
+pub(crate) fn matching_sysroot_tag(user_settings: &UserSettings) -> TagSpec {
+    let llvm_tag = user_settings
+        .llvm_location
+        .install_path()
+        .and_then(read_release_tag);
+    match llvm_tag.and_then(|tag| sysroot_tag_for_llvm(&tag)) {
+        Some(tag) => TagSpec::Tag(tag.to_owned()),
+        None => TagSpec::Latest,
+    }
+}
+
+fn sysroot_tag_for_llvm(llvm_tag: &str) -> Option<&'static str> {
+    let group = COMPATIBILITY
+        .iter()
+        .rposition(|(first_llvm, _)| *first_llvm <= llvm_tag)?;
+    (group + 1 < COMPATIBILITY.len()).then_some(COMPATIBILITY[group].1)
+}
+
+fn is_compatible(llvm_tag: &str, sysroot_tag: &str) -> bool {
+    let llvm_group = COMPATIBILITY
+        .iter()
+        .rposition(|(first_llvm, _)| *first_llvm <= llvm_tag);
+    let sysroot_group = COMPATIBILITY
+        .iter()
+        .rposition(|(_, first_sysroot)| *first_sysroot <= sysroot_tag);
+    llvm_group == sysroot_group
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_compatible() {
+        assert!(is_compatible("v2025-03-01.1", "v2025-06-10.2"));
+        assert!(!is_compatible("v2025-03-01.1", "v2024-12-01.1"));
+    }
+
+    #[test]
+    fn test_sysroot_tag_for_llvm() {
+        // The current group's LLVM gets the latest sysroot
+        assert_eq!(sysroot_tag_for_llvm("v2025-03-01.1"), None);
+        // Releases from before the first group aren't known to match any
+        assert_eq!(sysroot_tag_for_llvm("v2024-12-01.1"), None);
+    }
+}
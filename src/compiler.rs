@@ -13,6 +13,8 @@ static CLANG_FLAGS_WITH_ARGS: LazyLock<HashSet<&str>> = LazyLock::new(|| {
         "-o",
         "-x",
         "-Xpreprocessor",
+        "-Xanalyzer",
+        "--analyzer-output",
         "-include",
         "-imacros",
         "-idirafter",
@@ -40,6 +42,7 @@ static CLANG_FLAGS_WITH_ARGS: LazyLock<HashSet<&str>> = LazyLock::new(|| {
         "-Xlinker",
         "-Xclang",
         "-z",
+        "-x",
     ]
     .into()
 });
@@ -47,13 +50,49 @@ static CLANG_FLAGS_WITH_ARGS: LazyLock<HashSet<&str>> = LazyLock::new(|| {
 static CLANG_FLAGS_TO_FORWARD_TO_WASM_LD: LazyLock<HashSet<&str>> =
     LazyLock::new(|| ["-L", "-l"].into());
 
+// Linker flags that apply to the inputs after them, so they have to keep their
+// position among the inputs. -l is also one, as libraries are inputs too.
+static POSITIONAL_LINKER_FLAGS: LazyLock<HashSet<&str>> = LazyLock::new(|| {
+    [
+        "--whole-archive",
+        "--no-whole-archive",
+        "-Bstatic",
+        "-Bdynamic",
+        "--start-group",
+        "--end-group",
+    ]
+    .into()
+});
+
 // We always specify values for these flags according to the build configuration, so
 // they must be discarded even if they're provided externally
 static CLANG_FLAGS_TO_DISCARD: LazyLock<HashSet<&str>> =
     LazyLock::new(|| ["-ftls-model", "--sysroot", "--target", "-mthread-model"].into());
 
-static WASM_LD_FLAGS_WITH_ARGS: LazyLock<HashSet<&str>> =
-    LazyLock::new(|| ["-o", "-mllvm", "-L", "-l", "-m", "-O", "-y", "-z"].into());
+static WASM_LD_FLAGS_WITH_ARGS: LazyLock<HashSet<&str>> = LazyLock::new(|| {
+    [
+        "-o",
+        "-mllvm",
+        "-L",
+        "-l",
+        "-m",
+        "-O",
+        "-y",
+        "-z",
+        "--version-script",
+    ]
+    .into()
+});
+
+static EXTERNAL_DEBUG_INFO_SECTION: &str = "external_debug_info";
+
+static PROFILING_SECTION: &str = "wasmer.profiling";
+
+pub(crate) static BUILD_INFO_SECTION: &str = "wasixcc.meta";
+
+static INSTRUMENT_FUNCTIONS_RUNTIME: &str = include_str!("instrument_functions.c");
+
+static DIRECTORY_FLAGS_FILE_NAME: &str = ".wasixcc-flags";
 
 static WASM_OPT_ENABLED_FEATURES: &[&str] = &[
     "--enable-threads",
@@ -63,7 +102,8 @@ static WASM_OPT_ENABLED_FEATURES: &[&str] = &[
     "--enable-exception-handling",
 ];
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub(crate) enum ModuleKind {
     StaticMain,
     DynamicMain,
@@ -88,6 +128,44 @@ impl ModuleKind {
     }
 }
 
+impl std::str::FromStr for ModuleKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "static-main" => Ok(ModuleKind::StaticMain),
+            "dynamic-main" => Ok(ModuleKind::DynamicMain),
+            "shared-library" => Ok(ModuleKind::SharedLibrary),
+            "object-file" => Ok(ModuleKind::ObjectFile),
+            _ => bail!("Unknown module kind: {}", s),
+        }
+    }
+}
+
+/// What to strip from the output, for STRIP.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Strip {
+    #[default]
+    None,
+    /// The DWARF sections.
+    Debug,
+    /// The DWARF sections, the name section and the producers section.
+    All,
+}
+
+impl std::str::FromStr for Strip {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Strip::None),
+            "debug" => Ok(Strip::Debug),
+            "all" => Ok(Strip::All),
+            _ => bail!("Unknown strip mode: {s}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum OptLevel {
     O0,
@@ -113,6 +191,19 @@ pub(crate) struct BuildSettings {
     opt_level: OptLevel,
     debug_level: DebugLevel,
     use_wasm_opt: bool,
+    source_map: bool,
+    /// Run the output after building it, for `--run`.
+    run: bool,
+    /// Print the largest parts of the output, for `--size-report`.
+    size_report: bool,
+    /// Only check the inputs for errors, for `-fsyntax-only`.
+    syntax_only: bool,
+    /// Only preprocess the inputs, for `-E`.
+    preprocess_only: bool,
+    /// Run the static analyzer on the inputs, for `--analyze`.
+    analyze: bool,
+    /// Write the text format of each output next to it, for `--emit-wat`.
+    emit_wat: bool,
 }
 
 #[derive(Debug)]
@@ -121,7 +212,26 @@ pub(crate) struct PreparedArgs {
     linker_args: Vec<String>,
     compiler_inputs: Vec<PathBuf>,
     linker_inputs: Vec<PathBuf>,
+    /// Linker flags that apply to the inputs after them, such as `-lfoo` and
+    /// `--whole-archive`, each with the number of linker inputs before it.
+    positional_linker_args: Vec<(usize, String)>,
+    /// Where each compiler input was given, as the number of linker inputs
+    /// and of positional linker flags before it, so its object is linked in
+    /// the same place.
+    compiler_input_positions: Vec<(usize, usize)>,
+    /// The flags from the `.wasixcc-flags` file for each compiler input,
+    /// without the ones that only configure wasixcc.
+    directory_flags: Vec<Vec<String>>,
     output: Option<PathBuf>,
+    /// The shared library's soname from `-soname`, which wasm-ld doesn't take.
+    soname: Option<String>,
+    /// The version script from `--version-script`, which wasm-ld doesn't take.
+    version_script: Option<PathBuf>,
+    /// Whether to link the inputs into a single object file, for `-r`.
+    relocatable: bool,
+    /// Directories from `-B`, searched for tools, startup objects and
+    /// libraries before the defaults.
+    prefix_dirs: Vec<PathBuf>,
 }
 
 #[derive(Debug)]
@@ -131,27 +241,46 @@ pub(crate) struct State {
     args: PreparedArgs,
     cxx: bool,
     temp_dir: PathBuf,
+    /// Source file and remarks record of each input compiled for OPT_REPORT.
+    opt_records: Vec<(PathBuf, PathBuf)>,
 }
 
 pub(crate) fn run(args: Vec<String>, mut user_settings: UserSettings, run_cxx: bool) -> Result<()> {
     let original_args = args.clone();
 
-    let (args, build_settings) = prepare_compiler_args(args, &mut user_settings, run_cxx)?;
+    let (mut args, mut build_settings) = prepare_compiler_args(args, &mut user_settings, run_cxx)?;
+    apply_implied_settings(&mut user_settings, &mut build_settings);
+
+    let default_flags = default_compiler_flags(&user_settings);
+    for flag in &user_settings.suppress_default_cflags {
+        if !default_flags
+            .iter()
+            .any(|default| is_suppressed(default, std::slice::from_ref(flag)))
+        {
+            eprintln!(
+                "Warning: SUPPRESS_DEFAULT_CFLAGS: {flag} is not a default flag for this \
+                 configuration"
+            );
+        }
+    }
 
     tracing::debug!("User settings: {user_settings:?}");
     tracing::debug!("Build settings: {build_settings:?}");
     tracing::debug!("Compiler/linker args: {args:?}");
 
+    if args.compiler_inputs.is_empty() && original_args.iter().any(|arg| arg == "-dumpmachine") {
+        // Clang would print its normalized form of the triple instead
+        println!("{}", user_settings.target_triple());
+        return Ok(());
+    }
+
     if args.compiler_inputs.is_empty() && args.linker_inputs.is_empty() {
         // If there are no inputs, just pass everything through to clang.
         // This lets us support invocations such as `wasixcc -dumpmachine`.
-        let mut command = Command::new(user_settings.llvm_location.get_tool_path(if run_cxx {
-            "clang++"
-        } else {
-            "clang"
-        }));
+        let mut command =
+            Command::new(user_settings.tool_path(if run_cxx { "clang++" } else { "clang" }));
         command.args(original_args);
-        command.args([OsStr::new("--target=wasm32-wasi")]);
+        command.arg(target_flag(&user_settings));
 
         let binaryen_bin_path = user_settings.binaryen_location.get_bin_path();
         if let Some(binaryen_bin_path) = binaryen_bin_path {
@@ -167,7 +296,23 @@ pub(crate) fn run(args: Vec<String>, mut user_settings: UserSettings, run_cxx: b
         return run_command(command);
     }
 
-    let temp_dir = tempfile::TempDir::new().context("Failed to create temporary directory")?;
+    if build_settings.syntax_only {
+        // Nothing is written, so the inputs are checked in a single clang run
+        // where possible, and there's nothing to link or optimize
+        user_settings.module_kind = Some(ModuleKind::ObjectFile);
+        args.output = None;
+    } else if build_settings.preprocess_only {
+        // The output goes to stdout, or wherever -o says, as with clang
+        user_settings.module_kind = Some(ModuleKind::ObjectFile);
+    } else if build_settings.analyze {
+        // Clang writes the results to -o or next to where it's run, named
+        // after the input, and there's nothing to link or optimize
+        user_settings.module_kind = Some(ModuleKind::ObjectFile);
+    } else {
+        apply_out_dir(&mut args, &user_settings)?;
+    }
+
+    let temp_dir = user_settings.create_temp_dir()?;
 
     let mut state = State {
         user_settings,
@@ -175,39 +320,227 @@ pub(crate) fn run(args: Vec<String>, mut user_settings: UserSettings, run_cxx: b
         args,
         cxx: run_cxx,
         temp_dir: temp_dir.path().to_owned(),
+        opt_records: Vec::new(),
     };
 
     if !state.args.compiler_inputs.is_empty() {
         compile_inputs(&mut state)?;
     }
 
-    if state.user_settings.module_kind().is_binary() {
+    if state.args.relocatable {
+        link_relocatable(&state)?;
+    } else if state.user_settings.module_kind().is_binary() {
         link_inputs(&state)?;
+        record_dylink_metadata(&state)?;
+        prune_threads_if_enabled(&state)?;
+
+        if state.user_settings.wizer {
+            run_wizer(&state)?;
+        }
+
+        if state.build_settings.source_map {
+            sourcemap::generate(
+                output_path(&state),
+                &sidecar_path(&state, ".map"),
+                &state.user_settings,
+            )?;
+        }
 
         // Run wasm-opt if:
         //  * Explicitly enabled in the user settings, or
         //  * It wasn't disabled in the compiler flags AND it wasn't explicitly disabled in the user settings
-        if matches!(
+        let ran_wasm_opt = matches!(
             (
                 state.build_settings.use_wasm_opt,
                 state.user_settings.run_wasm_opt,
             ),
             (_, Some(true)) | (true, None)
-        ) {
-            run_wasm_opt(&state)?;
+        ) && run_wasm_opt(&state)?;
+
+        // wasm-opt emits the sourceMappingURL section itself, and strips the
+        // DWARF the map was generated from unless it was asked for
+        if state.build_settings.source_map && !ran_wasm_opt {
+            if state.build_settings.debug_level == DebugLevel::G0 {
+                remove_output_sections(&state, |name| {
+                    is_stripped_section(name, Strip::Debug, true)
+                })?;
+            }
+            let mut payload = Vec::new();
+            wasm::write_name(&mut payload, &source_map_url(&state));
+            wasm::append_custom_section(
+                output_path(&state),
+                sourcemap::SOURCE_MAPPING_URL_SECTION,
+                &payload,
+            )?;
+        }
+
+        if state.user_settings.profiling {
+            add_profiling_section(&state)?;
+        }
+
+        if state.user_settings.separate_dwarf {
+            separate_dwarf(&state)?;
+        }
+
+        if state.user_settings.strip != Strip::None {
+            strip_output(&state)?;
+        }
+
+        if state.user_settings.embed_build_info {
+            add_build_info_section(&state)?;
+        }
+
+        if state.user_settings.split_module {
+            run_wasm_split(&state)?;
+        }
+
+        if state.user_settings.link_summary {
+            print_link_summary(&state)?;
+        }
+
+        if state.build_settings.size_report {
+            print_size_report(&state)?;
+        }
+
+        if state.user_settings.component {
+            make_component(&state)?;
+        }
+    }
+
+    if state.build_settings.emit_wat {
+        for module in written_modules(&state) {
+            emit_wat(&module)?;
         }
     }
 
+    write_opt_report(&state)?;
+
     tracing::info!("Done");
+
+    if state.build_settings.run {
+        run_output(&state)?;
+    }
     Ok(())
 }
 
+// Runs the output with the RUNNER command, for `--run`.
+fn run_output(state: &State) -> Result<()> {
+    let module_kind = state.user_settings.module_kind();
+    if !module_kind.is_executable() {
+        bail!("--run requires an executable output, but the module kind is {module_kind:?}");
+    }
+
+    let mut command = runner_command(&state.user_settings);
+    command.arg(output_path(state));
+    tracing::info!("Running: {command:?}");
+
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to run {:?}", command.get_program()))?;
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(code) => Err(crate::ProgramExit(code).into()),
+        // Killed by a signal
+        None => Err(crate::ProgramExit(1).into()),
+    }
+}
+
+pub(crate) fn runner_command(user_settings: &UserSettings) -> Command {
+    let runner = user_settings.runner.as_deref().unwrap_or("wasmer run");
+    let mut parts = runner.split_whitespace();
+    let mut command = Command::new(parts.next().unwrap_or("wasmer"));
+    command.args(parts).args(&user_settings.runner_args);
+    command
+}
+
+// Settings that imply others, applied before compiling.
+fn apply_implied_settings(user_settings: &mut UserSettings, build_settings: &mut BuildSettings) {
+    // SEPARATE_DWARF needs DWARF to move into the companion file
+    if user_settings.separate_dwarf && build_settings.debug_level == DebugLevel::G0 {
+        build_settings.debug_level = DebugLevel::G2;
+    }
+
+    // Profiles are attributed to functions by name
+    if user_settings.profiling {
+        user_settings.keep_names = true;
+    }
+}
+
+/// A compile command, in the format of `compile_commands.json` entries.
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct CompileCommand {
+    pub directory: PathBuf,
+    pub file: PathBuf,
+    pub arguments: Vec<String>,
+}
+
+/// The command `input` would be compiled with, given the other arguments on
+/// the command line. The output file is left out, since it depends on how the
+/// input is built.
+pub(crate) fn query_compile_command(
+    args: Vec<String>,
+    mut user_settings: UserSettings,
+    run_cxx: bool,
+    input: &Path,
+) -> Result<CompileCommand> {
+    let (args, mut build_settings) = prepare_compiler_args(args, &mut user_settings, run_cxx)?;
+    let directory_flags = classify_directory_flags(
+        find_directory_flags(input)?,
+        &mut build_settings,
+        &mut user_settings,
+    )?;
+    apply_implied_settings(&mut user_settings, &mut build_settings);
+
+    // The sysroot doesn't need to exist to know where it's expected
+    let sysroot_path = user_settings.sysroot_location()?;
+
+    let state = State {
+        user_settings,
+        build_settings,
+        args,
+        cxx: run_cxx,
+        temp_dir: PathBuf::new(),
+        opt_records: Vec::new(),
+    };
+
+    let compiler_path = tool_path(&state, if run_cxx { "clang++" } else { "clang" });
+    let mut arguments = vec![compiler_path.to_string_lossy().into_owned()];
+    arguments.extend(
+        compiler_args(&state, &sysroot_path)
+            .iter()
+            .map(|arg| arg.to_string_lossy().into_owned()),
+    );
+    arguments.extend(directory_flags);
+    arguments.extend(
+        env_include_args(&state.user_settings, run_cxx)
+            .iter()
+            .map(|arg| arg.to_string_lossy().into_owned()),
+    );
+    arguments.push(input.display().to_string());
+
+    Ok(CompileCommand {
+        directory: env::current_dir().context("Failed to get current directory")?,
+        file: input.to_owned(),
+        arguments,
+    })
+}
+
+/// The source files and the linker inputs given on the command line.
+pub(crate) fn build_inputs(
+    args: Vec<String>,
+    mut user_settings: UserSettings,
+    run_cxx: bool,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let (args, _) = prepare_compiler_args(args, &mut user_settings, run_cxx)?;
+    Ok((args.compiler_inputs, args.linker_inputs))
+}
+
 pub(crate) fn link_only(args: Vec<String>, mut user_settings: UserSettings) -> Result<()> {
     let original_args = args.clone();
 
-    let args = prepare_linker_args(args, &mut user_settings)?;
+    let mut args = prepare_linker_args(args, &mut user_settings)?;
 
-    if !user_settings.module_kind().is_binary() {
+    if !user_settings.module_kind().is_binary() && !args.relocatable {
         bail!(
             "Only binaries can be linked, current module kind is: {:?}",
             user_settings.module_kind()
@@ -219,212 +552,961 @@ pub(crate) fn link_only(args: Vec<String>, mut user_settings: UserSettings) -> R
 
     if args.linker_inputs.is_empty() {
         // If there are no inputs, just pass everything through to wasm-ld.
-        let mut command = Command::new(user_settings.llvm_location.get_tool_path("wasm-ld"));
+        let mut command = Command::new(user_settings.tool_path("wasm-ld"));
         command.args(original_args);
         return run_command(command);
     }
 
+    apply_out_dir(&mut args, &user_settings)?;
+
+    if user_settings.profiling {
+        user_settings.keep_names = true;
+    }
+
     let build_settings = BuildSettings {
         opt_level: OptLevel::O0,
-        debug_level: DebugLevel::G0,
+        // Keep the DWARF from the inputs through wasm-opt so it can be moved
+        debug_level: if user_settings.separate_dwarf {
+            DebugLevel::G2
+        } else {
+            DebugLevel::G0
+        },
         use_wasm_opt: user_settings.run_wasm_opt.unwrap_or(true),
+        source_map: false,
+        run: false,
+        size_report: false,
+        syntax_only: false,
+        preprocess_only: false,
+        analyze: false,
+        emit_wat: false,
     };
 
+    let temp_dir = user_settings.create_temp_dir()?;
+
     let state = State {
         user_settings,
         build_settings,
         args,
         // TODO: is there a way to figure this out automatically?
         cxx: false,
-        // Not used for linking
-        temp_dir: PathBuf::from("."),
+        temp_dir: temp_dir.path().to_owned(),
+        opt_records: Vec::new(),
     };
 
+    if state.args.relocatable {
+        return link_relocatable(&state);
+    }
+
     link_inputs(&state)?;
+    record_dylink_metadata(&state)?;
+    prune_threads_if_enabled(&state)?;
+
+    if state.user_settings.wizer {
+        run_wizer(&state)?;
+    }
 
     if state.build_settings.use_wasm_opt {
         run_wasm_opt(&state)?;
     }
 
+    if state.user_settings.profiling {
+        add_profiling_section(&state)?;
+    }
+
+    if state.user_settings.separate_dwarf {
+        separate_dwarf(&state)?;
+    }
+
+    if state.user_settings.strip != Strip::None {
+        strip_output(&state)?;
+    }
+
+    if state.user_settings.embed_build_info {
+        add_build_info_section(&state)?;
+    }
+
+    if state.user_settings.split_module {
+        run_wasm_split(&state)?;
+    }
+
+    if state.user_settings.link_summary {
+        print_link_summary(&state)?;
+    }
+
+    if state.user_settings.component {
+        make_component(&state)?;
+    }
+
+    write_opt_report(&state)?;
+
     tracing::info!("Done");
     Ok(())
 }
 
+fn default_output_path(module_kind: ModuleKind) -> &'static Path {
+    match module_kind {
+        ModuleKind::StaticMain | ModuleKind::DynamicMain | ModuleKind::SharedLibrary => {
+            Path::new("a.out")
+        }
+        ModuleKind::ObjectFile => Path::new("a.o"),
+    }
+}
+
 fn output_path(state: &State) -> &Path {
     if let Some(output) = &state.args.output {
         output.as_path()
     } else {
-        match state.user_settings.module_kind() {
-            ModuleKind::StaticMain | ModuleKind::DynamicMain | ModuleKind::SharedLibrary => {
-                Path::new("a.out")
-            }
-            ModuleKind::ObjectFile => Path::new("a.o"),
-        }
+        default_output_path(state.user_settings.module_kind())
     }
 }
 
-fn compile_inputs(state: &mut State) -> Result<()> {
-    let compiler_path = state
-        .user_settings
-        .llvm_location
-        .get_tool_path(if state.cxx { "clang++" } else { "clang" });
-    let binaryen_bin_path = state.user_settings.binaryen_location.get_bin_path();
-    let path_env = if let Some(binaryen_bin_path) = &binaryen_bin_path {
-        format!(
-            "{}:{}",
-            absolute(binaryen_bin_path).unwrap().display(),
-            env::var("PATH").unwrap_or_default()
-        )
-    } else {
-        env::var("PATH").unwrap_or_default()
+// Sidecar files are named after the output, e.g. `app.wasm.map`.
+fn sidecar_path(state: &State, suffix: &str) -> PathBuf {
+    let mut path = output_path(state).as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+// The map is expected to be served next to the module, so a relative URL is
+// all that's needed.
+fn source_map_url(state: &State) -> String {
+    sidecar_path(state, ".map")
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// When OUT_DIR is set, the linked artifact is placed directly inside it,
+// keeping only the file name of the requested output. Sidecar files are
+// placed next to it with predictable names. Outputs of -c, -S and -E stay
+// where they were asked for, since later build steps refer to them there.
+fn apply_out_dir(args: &mut PreparedArgs, user_settings: &UserSettings) -> Result<()> {
+    let Some(out_dir) = &user_settings.out_dir else {
+        return Ok(());
+    };
+    if !user_settings.module_kind().is_binary() && !args.relocatable {
+        return Ok(());
+    }
+
+    let file_name = match &args.output {
+        Some(output) => output
+            .file_name()
+            .with_context(|| format!("Invalid output path: {}", output.display()))?
+            .to_owned(),
+        None => default_output_path(user_settings.module_kind())
+            .as_os_str()
+            .to_owned(),
     };
 
-    let sysroot_path = state.user_settings.ensure_sysroot_location()?;
+    std::fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create output directory {}", out_dir.display()))?;
+
+    args.output = Some(out_dir.join(file_name));
+    Ok(())
+}
+
+// Objects compiled for linking are named after their input and flags rather
+// than a counter, so compiler launchers and remote execution see the same
+// command line for the same input. With OUT_DIR they're kept in OUT_DIR/obj;
+// otherwise they go to the temporary directory, which is removed once the
+// build is done.
+fn object_dir(state: &State) -> Result<PathBuf> {
+    let Some(out_dir) = &state.user_settings.out_dir else {
+        return Ok(state.temp_dir.clone());
+    };
+    let object_dir = out_dir.join("obj");
+    std::fs::create_dir_all(&object_dir)
+        .with_context(|| format!("Failed to create object directory {}", object_dir.display()))?;
+    Ok(object_dir)
+}
+
+// Names the object after the input, with a hash of the input's path and the
+// compiler arguments so different inputs or configurations never collide.
+fn object_name<'a>(input: &Path, args: impl IntoIterator<Item = &'a OsStr>) -> Result<OsString> {
+    use sha2::{Digest, Sha256};
+
+    let input_path = absolute(input)
+        .with_context(|| format!("Failed to get absolute path of {}", input.display()))?;
+    let mut hasher = Sha256::new();
+    hasher.update(input_path.as_os_str().as_encoded_bytes());
+    for arg in args {
+        hasher.update([0]);
+        hasher.update(arg.as_encoded_bytes());
+    }
+    let hash = format!("{:x}", hasher.finalize());
+
+    let mut name = input
+        .file_name()
+        .unwrap_or_else(|| OsStr::new("output"))
+        .to_owned();
+    name.push(format!(".{}.o", &hash[..16]));
+    Ok(name)
+}
+
+// Clang writes depfiles next to the object file by default, which is in the
+// object directory when we're linking. With OUT_DIR, we collect them under
+// OUT_DIR/deps instead, named after the object so inputs with the same file
+// name don't collide.
+fn depfile_path(state: &State, object: &Path) -> Option<PathBuf> {
+    let out_dir = state.user_settings.out_dir.as_ref()?;
+
+    let compiler_args = &state.args.compiler_args;
+    let wants_depfile = compiler_args.iter().any(|a| a == "-MD" || a == "-MMD");
+    let has_depfile_path = compiler_args.iter().any(|a| a.starts_with("-MF"));
+    if !wants_depfile || has_depfile_path {
+        return None;
+    }
+
+    Some(
+        out_dir
+            .join("deps")
+            .join(object.with_extension("d").file_name()?),
+    )
+}
+
+// Whether clang writes a depfile whose target is the output path, which
+// needs naming explicitly when the object is compiled under another name.
+fn wants_depfile_target(compiler_args: &[String]) -> bool {
+    let has_arg = |flags: &[&str]| compiler_args.iter().any(|a| flags.contains(&a.as_str()));
+    has_arg(&["-MD", "-MMD"]) && !has_arg(&["-MT", "-MQ"])
+}
+
+fn add_depfile_arg(command: &mut Command, depfile: Option<PathBuf>) -> Result<()> {
+    if let Some(depfile) = depfile {
+        let deps_dir = depfile.parent().unwrap();
+        std::fs::create_dir_all(deps_dir).with_context(|| {
+            format!("Failed to create depfile directory {}", deps_dir.display())
+        })?;
+        command.arg("-MF").arg(depfile);
+    }
+    Ok(())
+}
+
+fn target_flag(user_settings: &UserSettings) -> String {
+    format!("--target={}", user_settings.target_triple())
+}
 
-    let mut command_args: Vec<&OsStr> = vec![
-        OsStr::new("--sysroot"),
-        sysroot_path.as_os_str(),
-        OsStr::new("--target=wasm32-wasi"),
-        OsStr::new("-c"),
-        OsStr::new("-matomics"),
-        OsStr::new("-mbulk-memory"),
-        OsStr::new("-mmutable-globals"),
-        OsStr::new("-pthread"),
-        OsStr::new("-mthread-model"),
-        OsStr::new("posix"),
-        OsStr::new("-fno-trapping-math"),
-        OsStr::new("-D_WASI_EMULATED_MMAN"),
-        OsStr::new("-D_WASI_EMULATED_SIGNAL"),
-        OsStr::new("-D_WASI_EMULATED_PROCESS_CLOCKS"),
+// Arguments that configure clang for the target and build configuration,
+// used for everything we compile.
+fn target_compiler_args(state: &State, sysroot_path: &Path) -> Vec<OsString> {
+    let mut command_args: Vec<OsString> = vec![
+        "--sysroot".into(),
+        sysroot_path.into(),
+        target_flag(&state.user_settings).into(),
+        "-mthread-model".into(),
+        "posix".into(),
     ];
+    if !state.build_settings.preprocess_only {
+        command_args.push("-c".into());
+    }
+
+    command_args.extend(
+        injected_compiler_flags(&state.user_settings)
+            .into_iter()
+            .map(OsString::from),
+    );
 
     if state.user_settings.wasm_exceptions {
-        command_args.push(OsStr::new("-fwasm-exceptions"));
-        command_args.push(OsStr::new("-mllvm"));
-        command_args.push(OsStr::new("--wasm-enable-sjlj"));
+        command_args.push("-mllvm".into());
+        command_args.push("--wasm-enable-sjlj".into());
         if state.cxx {
             // Enable C++ exceptions as well
-            command_args.push(OsStr::new("-mllvm"));
-            command_args.push(OsStr::new("--wasm-enable-eh"));
+            command_args.push("-mllvm".into());
+            command_args.push("--wasm-enable-eh".into());
         }
     }
 
-    if state.user_settings.module_kind().requires_pic() || state.user_settings.pic {
-        command_args.push(OsStr::new("-fPIC"));
-        command_args.push(OsStr::new("-ftls-model=global-dynamic"));
-        command_args.push(OsStr::new("-fvisibility=default"));
-    } else {
-        command_args.push(OsStr::new("-ftls-model=local-exec"));
-    }
+    command_args
+}
 
-    match state.build_settings.debug_level {
-        DebugLevel::G0 => (),
-        DebugLevel::G1 => command_args.push(OsStr::new("-g1")),
-        DebugLevel::G2 => command_args.push(OsStr::new("-g2")),
-        DebugLevel::G3 => command_args.push(OsStr::new("-g3")),
+// The flags passed to clang by default for the build configuration, which can
+// be dropped individually with SUPPRESS_DEFAULT_CFLAGS.
+pub(crate) fn default_compiler_flags(user_settings: &UserSettings) -> Vec<&'static str> {
+    let mut flags = vec![
+        "-matomics",
+        "-mbulk-memory",
+        "-mmutable-globals",
+        "-pthread",
+        "-fno-trapping-math",
+        "-D_WASI_EMULATED_MMAN",
+        "-D_WASI_EMULATED_SIGNAL",
+        "-D_WASI_EMULATED_PROCESS_CLOCKS",
+    ];
+
+    if user_settings.wasm_exceptions {
+        flags.push("-fwasm-exceptions");
     }
 
-    for arg in &state.args.compiler_args {
-        command_args.push(OsStr::new(arg.as_str()));
+    if user_settings.module_kind().requires_pic() || user_settings.pic {
+        flags.extend([
+            "-fPIC",
+            "-ftls-model=global-dynamic",
+            "-fvisibility=default",
+        ]);
+    } else {
+        flags.push("-ftls-model=local-exec");
     }
 
-    if state.user_settings.module_kind().is_binary() {
-        // If we're linking later, we should compile each input separately
+    flags
+}
 
-        let mut filename_counter = HashMap::new();
+// The default flags that are actually passed, i.e. the ones not suppressed.
+pub(crate) fn injected_compiler_flags(user_settings: &UserSettings) -> Vec<&'static str> {
+    default_compiler_flags(user_settings)
+        .into_iter()
+        .filter(|flag| !is_suppressed(flag, &user_settings.suppress_default_cflags))
+        .collect()
+}
 
-        for input in &state.args.compiler_inputs {
-            let mut command = Command::new(&compiler_path);
-            command.env("PATH", &path_env);
+// The arguments wasixtidy passes to clang-tidy. The flags are added with
+// `--extra-arg-before`, so they apply to the commands from a compilation
+// database as well as the ones given after `--`, and can still be overridden
+// by either.
+pub(crate) fn tidy_args(user_settings: &UserSettings, sysroot_path: &Path) -> Vec<String> {
+    let mut flags = vec![
+        format!("--sysroot={}", sysroot_path.display()),
+        target_flag(user_settings),
+    ];
+    flags.extend(
+        injected_compiler_flags(user_settings)
+            .into_iter()
+            .map(ToOwned::to_owned),
+    );
+    flags.extend(user_settings.extra_compiler_flags.iter().cloned());
+    flags
+        .into_iter()
+        .map(|flag| format!("--extra-arg-before={flag}"))
+        .collect()
+}
 
-            command.args(&command_args);
+// Tools can't tell they're writing to a terminal since their stderr is
+// captured to recognize failures, so they're told explicitly.
+fn wants_color_diagnostics(state: &State, user_args: &[String]) -> bool {
+    use std::io::IsTerminal;
 
-            command.arg(input);
+    std::io::stderr().is_terminal()
+        && state.user_settings.diagnostics_format == DiagnosticsFormat::Text
+        && !user_args
+            .iter()
+            .any(|arg| arg.contains("color-diagnostics"))
+}
 
-            let output_path = {
-                let input_name = input.file_name().unwrap_or_else(|| OsStr::new("output"));
-                let counter = filename_counter.entry(input_name.to_owned()).or_insert(0);
-                let mut output_name = input_name.to_owned();
-                output_name.push(format!(".{}.o", counter));
-                *counter += 1;
-                state.temp_dir.join(output_name)
-            };
+// The arguments assembly inputs are assembled with instead. Assembly only
+// needs the target and its features; the defines are kept for `.S` files,
+// which are preprocessed, but the other C flags would go unused.
+fn assembler_args(state: &State, sysroot_path: &Path, preprocessed: bool) -> Vec<OsString> {
+    let mut command_args: Vec<OsString> = vec![
+        "--sysroot".into(),
+        sysroot_path.into(),
+        target_flag(&state.user_settings).into(),
+    ];
+    if !state.build_settings.preprocess_only {
+        command_args.push("-c".into());
+    }
 
-            command.arg("-o").arg(&output_path);
-            state.args.linker_inputs.push(output_path);
+    command_args.extend(
+        injected_compiler_flags(&state.user_settings)
+            .into_iter()
+            .filter(|flag| flag.starts_with("-m") || (preprocessed && flag.starts_with("-D")))
+            .map(OsString::from),
+    );
 
-            run_command(command)?;
-        }
-    } else {
-        // If we're not linking, just push all inputs to clang to get one output
+    if wants_color_diagnostics(state, &state.args.compiler_args) {
+        command_args.push("-fcolor-diagnostics".into());
+    }
+    command_args.extend(
+        diagnostics::compiler_flags(state.user_settings.diagnostics_format)
+            .iter()
+            .map(OsString::from),
+    );
 
-        let mut command = Command::new(&compiler_path);
-        command.env("PATH", &path_env);
+    command_args.extend(state.args.compiler_args.iter().map(OsString::from));
+    command_args
+}
 
-        command.args(&command_args);
-        command.args(&state.args.compiler_inputs);
-        if let Some(output_path) = state.args.output.as_ref() {
-            command.arg("-o").arg(output_path);
+// Whether an input is C++, going by the last -x given before the inputs, or
+// else by its extension.
+fn is_cxx_input(compiler_args: &[String], input: &Path) -> bool {
+    let mut language = None;
+    let mut args = compiler_args.iter();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("-x") {
+            Some("") => language = args.next().map(String::as_str),
+            Some(joined) => language = Some(joined),
+            None => (),
         }
-
-        run_command(command)?;
     }
-
-    Ok(())
+    match language {
+        Some("none") | None => is_cxx_source(input),
+        Some(language) => language.starts_with("c++"),
+    }
 }
 
-fn link_inputs(state: &State) -> Result<()> {
-    let linker_path = state.user_settings.llvm_location.get_tool_path("wasm-ld");
+// The variables clang reads include directories from, as it would with -I or
+// -isystem.
+const INCLUDE_PATH_VARS: &[&str] = &[
+    "CPATH",
+    "C_INCLUDE_PATH",
+    "CPLUS_INCLUDE_PATH",
+    "OBJC_INCLUDE_PATH",
+];
 
-    let sysroot_path = state.user_settings.ensure_sysroot_location()?;
-    let sysroot_lib_path = sysroot_path.join("lib");
-    let sysroot_lib_wasm32_path = sysroot_lib_path.join("wasm32-wasi");
+// Clang would search the directories from CPATH and the like ahead of the
+// sysroot, so they're left out of its environment and passed after the other
+// arguments instead, if ENV_SEARCH_PATHS is enabled.
+fn add_env_include_args(command: &mut Command, user_settings: &UserSettings, cxx: bool) {
+    for var in INCLUDE_PATH_VARS {
+        command.env_remove(var);
+    }
+    command.args(env_include_args(user_settings, cxx));
+}
 
-    let mut command = Command::new(linker_path);
+fn env_include_args(user_settings: &UserSettings, cxx: bool) -> Vec<OsString> {
+    if !user_settings.env_search_paths {
+        return Vec::new();
+    }
+    let language_var = if cxx {
+        "CPLUS_INCLUDE_PATH"
+    } else {
+        "C_INCLUDE_PATH"
+    };
+    search_path_args(
+        "-isystem",
+        ["CPATH", language_var].into_iter().filter_map(env::var_os),
+    )
+}
 
-    command.args(&state.args.linker_args);
+// The directories of PATH-style lists as arguments of a search path flag,
+// skipping empty entries.
+fn search_path_args(flag: &str, lists: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    lists
+        .into_iter()
+        .flat_map(|list| env::split_paths(&list).collect::<Vec<_>>())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .flat_map(|dir| [OsString::from(flag), dir.into_os_string()])
+        .collect()
+}
+
+fn is_assembly_source(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "s" || ext == "S")
+}
 
-    command.args([
-        "--extra-features=atomics",
-        "--extra-features=bulk-memory",
-        "--extra-features=mutable-globals",
-        "--shared-memory",
-        "--max-memory=4294967296", // TODO: make configurable
-        "--import-memory",
-        "--export-dynamic",
-        "--export=__wasm_call_ctors",
-    ]);
+fn is_preprocessed_assembly_source(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "S")
+}
 
-    command.args(&state.user_settings.extra_linker_flags);
+// The arguments every input is compiled with, before per-input arguments
+// such as directory flags and the input itself.
+fn compiler_args(state: &State, sysroot_path: &Path) -> Vec<OsString> {
+    let mut command_args = target_compiler_args(state, sysroot_path);
 
-    if state.user_settings.wasm_exceptions {
-        command.args(["-mllvm", "--wasm-enable-sjlj"]);
-        if state.cxx {
-            command.args(["-mllvm", "--wasm-enable-eh"]);
-        }
+    if state.user_settings.instrument_functions {
+        command_args.push("-finstrument-functions".into());
     }
 
-    let module_kind = state.user_settings.module_kind();
-
-    command.args([
-        "--export=__wasm_init_tls",
-        "--export=__wasm_signal",
-        "--export=__tls_size",
-        "--export=__tls_align",
-        "--export=__tls_base",
-        "--export-if-defined=__indirect_function_table", // needed for reflection and call_dynamic
-    ]);
+    if state.user_settings.profiling {
+        command_args.push("-fno-omit-frame-pointer".into());
+        command_args.push("-fno-optimize-sibling-calls".into());
+    }
 
-    if module_kind.is_executable() {
-        command.args([
-            "--export-if-defined=__stack_pointer",
-            "--export-if-defined=__heap_base",
-            "--export-if-defined=__data_end",
-        ]);
+    if wants_color_diagnostics(state, &state.args.compiler_args) {
+        command_args.push("-fcolor-diagnostics".into());
     }
+    command_args.extend(
+        diagnostics::compiler_flags(state.user_settings.diagnostics_format)
+            .iter()
+            .map(OsString::from),
+    );
 
-    if matches!(module_kind, ModuleKind::DynamicMain) {
-        command.args(["--whole-archive", "--export-all"]);
+    match state.build_settings.debug_level {
+        // Source maps are generated from DWARF line tables
+        DebugLevel::G0 if state.build_settings.source_map => command_args.push("-g".into()),
+        DebugLevel::G0 => (),
+        DebugLevel::G1 => command_args.push("-g1".into()),
+        DebugLevel::G2 => command_args.push("-g2".into()),
+        DebugLevel::G3 => command_args.push("-g3".into()),
+    }
+
+    command_args.extend(state.args.compiler_args.iter().map(OsString::from));
+    command_args
+}
+
+fn compile_inputs(state: &mut State) -> Result<()> {
+    // The C++ driver compiles everything as C++, like g++. Otherwise, each
+    // input gets the driver for its language, and C++ inputs make the link
+    // include the C++ libraries.
+    let run_cxx = state.cxx;
+    let cxx_inputs = state
+        .args
+        .compiler_inputs
+        .iter()
+        .map(|input| run_cxx || is_cxx_input(&state.args.compiler_args, input))
+        .collect::<Vec<_>>();
+    state.cxx = cxx_inputs.contains(&true);
+
+    let clang_path = tool_path(state, "clang");
+    let clangxx_path = tool_path(state, "clang++");
+    let compiler_path = |cxx: bool| if cxx { &clangxx_path } else { &clang_path };
+    let binaryen_bin_path = state.user_settings.binaryen_location.get_bin_path();
+    let path_env = if let Some(binaryen_bin_path) = &binaryen_bin_path {
+        format!(
+            "{}:{}",
+            absolute(binaryen_bin_path).unwrap().display(),
+            env::var("PATH").unwrap_or_default()
+        )
+    } else {
+        env::var("PATH").unwrap_or_default()
+    };
+
+    state.user_settings.ensure_llvm_location(false)?;
+    let sysroot_path = state.user_settings.ensure_sysroot_location()?;
+    compat::check(&state.user_settings, &sysroot_path)?;
+
+    let command_args = compiler_args(state, &sysroot_path);
+
+    if state.user_settings.module_kind().is_binary() || state.args.relocatable {
+        // If we're linking later, we should compile each input separately
+
+        let object_dir = object_dir(state)?;
+        // Concurrent builds can compile the same object into OUT_DIR/obj, so
+        // it's written to a file of our own first and renamed into place.
+        let shared_objects = state.user_settings.out_dir.is_some();
+
+        let mut objects = Vec::new();
+        let inputs = state.args.compiler_inputs.iter().zip(&cxx_inputs);
+        for (index, (input, &cxx)) in inputs.enumerate() {
+            let mut command = Command::new(compiler_path(cxx));
+            command.env("PATH", &path_env);
+
+            let assembly = is_assembly_source(input);
+            if assembly {
+                command.args(assembler_args(
+                    state,
+                    &sysroot_path,
+                    is_preprocessed_assembly_source(input),
+                ));
+            } else {
+                command.args(&command_args);
+            }
+            command.args(&state.args.directory_flags[index]);
+            add_env_include_args(&mut command, &state.user_settings, cxx);
+
+            command.arg(input);
+
+            let object_path = object_dir.join(object_name(input, command.get_args())?);
+            let output_path = if shared_objects {
+                let mut path = object_path.clone().into_os_string();
+                path.push(format!(".tmp-{}", std::process::id()));
+                if wants_depfile_target(&state.args.compiler_args) {
+                    command.arg("-MT").arg(&object_path);
+                }
+                PathBuf::from(path)
+            } else {
+                object_path.clone()
+            };
+            add_depfile_arg(&mut command, depfile_path(state, &object_path))?;
+
+            command.arg("-o").arg(&output_path);
+            if state.user_settings.opt_report.is_some() && !assembly {
+                let record = optreport::record_path(
+                    &state.temp_dir.join(
+                        object_path
+                            .file_name()
+                            .context("Object path has no file name")?,
+                    ),
+                );
+                optreport::add_compiler_args(&mut command, &record);
+                state.opt_records.push((input.clone(), record));
+            }
+            compile_object(
+                &state.user_settings,
+                &state.temp_dir,
+                command,
+                &output_path,
+                &input.display().to_string(),
+            )?;
+            if output_path != object_path {
+                std::fs::rename(&output_path, &object_path).with_context(|| {
+                    format!("Failed to move object into {}", object_path.display())
+                })?;
+            }
+            objects.push(object_path);
+        }
+        for (index, object) in objects.into_iter().enumerate() {
+            insert_compiled_object(&mut state.args, index, object);
+        }
+    } else {
+        let directory_flags = &state.args.directory_flags;
+
+        let inputs = &state.args.compiler_inputs;
+        let all_assembly = inputs.iter().all(|input| is_assembly_source(input));
+        let any_assembly = inputs.iter().any(|input| is_assembly_source(input));
+
+        if directory_flags.windows(2).all(|w| w[0] == w[1])
+            && all_assembly == any_assembly
+            && cxx_inputs.windows(2).all(|w| w[0] == w[1])
+        {
+            // If we're not linking, just push all inputs to clang to get one output
+
+            let mut command = Command::new(compiler_path(state.cxx));
+            command.env("PATH", &path_env);
+
+            if all_assembly {
+                let preprocessed = inputs
+                    .iter()
+                    .any(|input| is_preprocessed_assembly_source(input));
+                command.args(assembler_args(state, &sysroot_path, preprocessed));
+            } else {
+                command.args(&command_args);
+            }
+            match state.args.compiler_inputs.as_slice() {
+                _ if state.user_settings.opt_report.is_none() || all_assembly => (),
+                [input] => {
+                    let record = add_opt_record_args(&state.temp_dir, &mut command, input);
+                    state.opt_records.push(record);
+                }
+                // Clang can only be given a record file for a single input
+                _ => eprintln!(
+                    "Warning: OPT_REPORT does not include remarks when compiling multiple files \
+                     at once"
+                ),
+            }
+            command.args(directory_flags.first().into_iter().flatten());
+            add_env_include_args(&mut command, &state.user_settings, state.cxx);
+            command.args(&state.args.compiler_inputs);
+            if let Some(output_path) = state.args.output.as_ref() {
+                command.arg("-o").arg(output_path);
+            }
+
+            let detail = output_path(state).display().to_string();
+            match (&state.args.output, state.args.compiler_inputs.len()) {
+                (Some(output), 1) if produces_object(&state.args.compiler_args) => {
+                    compile_object(
+                        &state.user_settings,
+                        &state.temp_dir,
+                        command,
+                        output,
+                        &detail,
+                    )?;
+                }
+                _ => run_stage(Stage::Compile, &detail, command)?,
+            }
+        } else {
+            // Inputs need different flags or drivers, so they must be compiled
+            // one by one.
+            // Without -o, clang names each object after its input either way.
+            if state.args.output.is_some() {
+                bail!("cannot specify -o when generating multiple output files");
+            }
+
+            let inputs = state.args.compiler_inputs.iter().zip(&cxx_inputs);
+            for ((input, &cxx), flags) in inputs.zip(directory_flags) {
+                let mut command = Command::new(compiler_path(cxx));
+                command.env("PATH", &path_env);
+
+                let assembly = is_assembly_source(input);
+                if assembly {
+                    command.args(assembler_args(
+                        state,
+                        &sysroot_path,
+                        is_preprocessed_assembly_source(input),
+                    ));
+                } else {
+                    command.args(&command_args);
+                }
+                command.args(flags);
+                add_env_include_args(&mut command, &state.user_settings, cxx);
+                if state.user_settings.opt_report.is_some() && !assembly {
+                    let record = add_opt_record_args(&state.temp_dir, &mut command, input);
+                    state.opt_records.push(record);
+                }
+                command.arg(input);
+
+                run_stage(Stage::Compile, &input.display().to_string(), command)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Compiles a single input to an object, through the object cache if it's
+// enabled. Remarks for OPT_REPORT only come from actually compiling.
+fn compile_object(
+    user_settings: &UserSettings,
+    temp_dir: &Path,
+    command: Command,
+    output: &Path,
+    detail: &str,
+) -> Result<()> {
+    // Stdin can only be read once, so it can't be hashed for the cache first
+    let reads_stdin = command.get_args().any(|arg| arg == "-");
+    if user_settings.object_cache && user_settings.opt_report.is_none() && !reads_stdin {
+        objcache::compile(command, output, detail, temp_dir)
+    } else {
+        run_stage(Stage::Compile, detail, command)
+    }
+}
+
+// Whether compiling with these arguments writes an object file, rather than
+// e.g. preprocessed source or assembly.
+fn produces_object(compiler_args: &[String]) -> bool {
+    !compiler_args.iter().any(|arg| {
+        [
+            "-E",
+            "-S",
+            "-M",
+            "-MM",
+            "-fsyntax-only",
+            "-emit-llvm",
+            "--analyze",
+        ]
+        .contains(&arg.as_str())
+    })
+}
+
+// Records remarks for an input compiled straight to its final object file,
+// naming the record after the input since the object isn't temporary.
+fn add_opt_record_args(temp_dir: &Path, command: &mut Command, input: &Path) -> (PathBuf, PathBuf) {
+    let record = optreport::record_path(
+        &temp_dir.join(input.file_name().unwrap_or_else(|| OsStr::new("input"))),
+    );
+    optreport::add_compiler_args(command, &record);
+    (input.to_owned(), record)
+}
+
+fn write_opt_report(state: &State) -> Result<()> {
+    let Some(report_path) = &state.user_settings.opt_report else {
+        return Ok(());
+    };
+
+    let wasm_opt_output = state.temp_dir.join(optreport::WASM_OPT_OUTPUT_FILE);
+    optreport::write(
+        report_path,
+        &state.opt_records,
+        wasm_opt_output
+            .is_file()
+            .then_some(wasm_opt_output.as_path()),
+    )
+}
+
+// Flags from the `.wasixcc-flags` file closest to the input, searching from
+// the input's directory upwards. Only the closest file is used.
+fn find_directory_flags(input: &Path) -> Result<Vec<String>> {
+    let input = absolute(input)
+        .with_context(|| format!("Failed to get absolute path of {}", input.display()))?;
+
+    let fs = vfs::get();
+    let Some(flags_file) = input
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join(DIRECTORY_FLAGS_FILE_NAME))
+        .find(|path| fs.is_file(path))
+    else {
+        return Ok(vec![]);
+    };
+
+    let contents = fs
+        .read_to_string(&flags_file)
+        .with_context(|| format!("Failed to read {}", flags_file.display()))?;
+    let flags = parse_directory_flags(&contents);
+    tracing::debug!(
+        "Using flags from {} for {}: {flags:?}",
+        flags_file.display(),
+        input.display()
+    );
+    Ok(flags)
+}
+
+// Flags from `.wasixcc-flags` are classified like the ones on the command
+// line, so that e.g. -O3 or -fwasm-exceptions there also set the wasm-opt
+// level or select the sysroot. Returns the flags that are passed to clang.
+fn classify_directory_flags(
+    flags: Vec<String>,
+    build_settings: &mut BuildSettings,
+    user_settings: &mut UserSettings,
+) -> Result<Vec<String>> {
+    let mut kept = Vec::new();
+    for flag in flags {
+        if !flag.starts_with('-')
+            || update_build_settings_from_arg(&flag, build_settings, user_settings)?
+        {
+            kept.push(flag);
+        }
+    }
+    Ok(kept)
+}
+
+// Flags are separated by whitespace; lines starting with '#' are comments.
+fn parse_directory_flags(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+// The tool from the first -B directory that has it, or else the configured one.
+fn tool_path(state: &State, tool: &str) -> PathBuf {
+    prefixed_path(state, tool, &state.user_settings.tool_path(tool))
+}
+
+// The file from the first -B directory that has it, or else the default.
+fn prefixed_path(state: &State, name: &str, default: &Path) -> PathBuf {
+    state
+        .args
+        .prefix_dirs
+        .iter()
+        .map(|dir| dir.join(name))
+        .find(|path| vfs::get().exists(path))
+        .unwrap_or_else(|| default.to_owned())
+}
+
+// The sysroot directories libraries are looked up in, in order.
+pub(crate) fn sysroot_library_dirs(sysroot_path: &Path) -> [PathBuf; 2] {
+    let lib_path = sysroot_path.join("lib");
+    let lib_wasm32_path = lib_path.join("wasm32-wasi");
+    [lib_path, lib_wasm32_path]
+}
+
+// The file in the sysroot library directories, for -print-file-name. Like
+// with clang, the name is returned as is if it's not found.
+pub(crate) fn find_library_file(sysroot_path: &Path, name: &str) -> PathBuf {
+    sysroot_library_dirs(sysroot_path)
+        .into_iter()
+        .map(|dir| dir.join(name))
+        .find(|path| vfs::get().exists(path))
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+// Whether to export all global symbols. Modules that are linked against need
+// to, but a static main only exports what it's asked to by default.
+fn exports_dynamic(user_settings: &UserSettings, linker_args: &[String]) -> bool {
+    user_settings
+        .export_dynamic
+        .unwrap_or_else(|| user_settings.module_kind().requires_pic())
+        && !linker_args.iter().any(|arg| arg == "--no-export-dynamic")
+}
+
+fn links_cxx_runtime(state: &State) -> bool {
+    state.cxx || state.user_settings.include_cpp_symbols
+}
+
+fn link_inputs(state: &State) -> Result<()> {
+    state.user_settings.ensure_llvm_location(true)?;
+    let linker_path = tool_path(state, "wasm-ld");
+
+    let sysroot_path = state.user_settings.ensure_sysroot_location()?;
+    let [sysroot_lib_path, sysroot_lib_wasm32_path] = sysroot_library_dirs(&sysroot_path);
+
+    let mut command = Command::new(linker_path);
+
+    command.args(without_strip_flags(
+        &state.args.linker_args,
+        &state.user_settings,
+    ));
+
+    let version_script = state
+        .args
+        .version_script
+        .as_deref()
+        .map(exports::read_version_script)
+        .transpose()?;
+    // A version script with `local: *;` limits the exports to its globals
+    let export_everything = !version_script
+        .as_ref()
+        .is_some_and(|script| script.hides_others);
+
+    add_default_linker_args(
+        &mut command,
+        &state.user_settings,
+        &[
+            "--extra-features=atomics",
+            "--extra-features=bulk-memory",
+            "--extra-features=mutable-globals",
+            "--shared-memory",
+            "--max-memory=4294967296", // TODO: make configurable
+            "--import-memory",
+        ],
+    );
+    if export_everything && exports_dynamic(&state.user_settings, &state.args.linker_args) {
+        add_default_linker_args(&mut command, &state.user_settings, &["--export-dynamic"]);
+    }
+    add_default_linker_args(
+        &mut command,
+        &state.user_settings,
+        &["--export=__wasm_call_ctors"],
+    );
+
+    if let Some(script) = &version_script {
+        for symbol in &script.globals {
+            command.arg(format!("--export-if-defined={symbol}"));
+        }
+    }
+    if let Some(export_list) = &state.user_settings.export_list {
+        for symbol in exports::read_export_list(export_list)? {
+            command.arg(format!("--export={symbol}"));
+        }
+    }
+
+    command.args(without_strip_flags(
+        &state.user_settings.extra_linker_flags,
+        &state.user_settings,
+    ));
+
+    if state.user_settings.wasm_exceptions {
+        command.args(["-mllvm", "--wasm-enable-sjlj"]);
+        if links_cxx_runtime(state) {
+            command.args(["-mllvm", "--wasm-enable-eh"]);
+        }
+    }
+
+    let module_kind = state.user_settings.module_kind();
+    check_layout_settings(&state.user_settings, module_kind)?;
+
+    add_default_linker_args(
+        &mut command,
+        &state.user_settings,
+        &[
+            "--export=__wasm_init_tls",
+            "--export=__wasm_signal",
+            "--export=__tls_size",
+            "--export=__tls_align",
+            "--export=__tls_base",
+            "--export-if-defined=__indirect_function_table", // needed for reflection and call_dynamic
+        ],
+    );
+
+    if module_kind.is_executable() {
+        add_default_linker_args(
+            &mut command,
+            &state.user_settings,
+            &[
+                "--export-if-defined=__stack_pointer",
+                "--export-if-defined=__heap_base",
+                "--export-if-defined=__data_end",
+            ],
+        );
+    }
+
+    if matches!(module_kind, ModuleKind::DynamicMain) {
+        add_default_linker_args(&mut command, &state.user_settings, &["--whole-archive"]);
+        if export_everything {
+            add_default_linker_args(&mut command, &state.user_settings, &["--export-all"]);
+        }
+    }
+
+    for dir in &state.args.prefix_dirs {
+        let mut lib_arg = OsString::new();
+        lib_arg.push("-L");
+        lib_arg.push(dir);
+        command.arg(lib_arg);
+    }
+
+    if state.user_settings.env_search_paths {
+        command.args(search_path_args("-L", env::var_os("LIBRARY_PATH")));
     }
 
     // Make sysroots libs available to all modules so they can optionally
@@ -440,57 +1522,91 @@ fn link_inputs(state: &State) -> Result<()> {
     command.arg(lib_arg);
 
     if module_kind.is_executable() {
-        command.args([
-            "-lwasi-emulated-getpid",
-            "-lwasi-emulated-mman",
-            "-lwasi-emulated-process-clocks",
-            "-lc",
-            "-lresolv",
-            "-lrt",
-            "-lm",
-            "-lpthread",
-            "-lutil",
-        ]);
+        add_default_linker_args(
+            &mut command,
+            &state.user_settings,
+            &[
+                "-lwasi-emulated-getpid",
+                "-lwasi-emulated-mman",
+                "-lwasi-emulated-process-clocks",
+                "-lc",
+                "-lresolv",
+                "-lrt",
+                "-lm",
+                "-lpthread",
+                "-lutil",
+            ],
+        );
 
-        if state.cxx || state.user_settings.include_cpp_symbols {
-            command.args(["-lc++", "-lc++abi"]);
+        // In a dynamic main, these are within --whole-archive, so all of
+        // the C++ runtime is there for side modules even if the main is C.
+        if links_cxx_runtime(state) {
+            add_default_linker_args(&mut command, &state.user_settings, &["-lc++", "-lc++abi"]);
             if state.user_settings.wasm_exceptions {
-                command.arg("-lunwind");
+                add_default_linker_args(&mut command, &state.user_settings, &["-lunwind"]);
             }
         }
     }
 
+    command.args(
+        state
+            .user_settings
+            .extra_libs
+            .iter()
+            .map(|lib| format!("-l{}", library_name(lib))),
+    );
+
     if matches!(module_kind, ModuleKind::DynamicMain) {
-        command.args(["--no-whole-archive"]);
+        add_default_linker_args(&mut command, &state.user_settings, &["--no-whole-archive"]);
     }
 
     // Link as much as needed out of libclang_rt.builtins regardless of module kind.
-    command.arg("-lclang_rt.builtins-wasm32");
+    add_default_linker_args(
+        &mut command,
+        &state.user_settings,
+        &["-lclang_rt.builtins-wasm32"],
+    );
 
     if state.user_settings.module_kind().requires_pic() {
-        command.args([
-            "--experimental-pic",
-            "--export-if-defined=__wasm_apply_data_relocs",
-            "--export-if-defined=__wasm_apply_tls_relocs",
-        ]);
+        add_default_linker_args(
+            &mut command,
+            &state.user_settings,
+            &[
+                "--experimental-pic",
+                "--export-if-defined=__wasm_apply_data_relocs",
+                "--export-if-defined=__wasm_apply_tls_relocs",
+            ],
+        );
     }
 
     match module_kind {
         ModuleKind::StaticMain => {
             // TODO: make configurable
-            command.args(["-z", "stack-size=8388608"]);
+            add_default_linker_args(
+                &mut command,
+                &state.user_settings,
+                &["-z stack-size=8388608"],
+            );
         }
 
         ModuleKind::DynamicMain => {
-            command.args(["-pie", "-lcommon-tag-stubs"]);
+            add_default_linker_args(
+                &mut command,
+                &state.user_settings,
+                &["-pie", "-lcommon-tag-stubs"],
+            );
         }
 
         ModuleKind::SharedLibrary => {
-            command.args([
-                "-shared",
-                "--no-entry",
-                "--unresolved-symbols=import-dynamic",
-            ]);
+            add_default_linker_args(
+                &mut command,
+                &state.user_settings,
+                &[
+                    "-shared",
+                    "--no-entry",
+                    "--unresolved-symbols=import-dynamic",
+                ],
+            );
             if state.user_settings.link_symbolic {
                 command.arg("-Bsymbolic");
             }
@@ -499,27 +1615,322 @@ fn link_inputs(state: &State) -> Result<()> {
         ModuleKind::ObjectFile => panic!("Internal error: object files can't be linked"),
     }
 
-    command.args(&state.args.linker_inputs);
+    if state.user_settings.export_table {
+        if module_kind.requires_pic() {
+            // wasm-ld rejects --export-table with -pie and -shared, where the
+            // table is imported; exporting it again has the same effect
+            command.arg("--export=__indirect_function_table");
+        } else {
+            command.arg("--export-table");
+        }
+    }
+    if state.user_settings.growable_table {
+        command.arg("--growable-table");
+    }
+    if let Some(table_base) = state.user_settings.table_base {
+        command.arg(format!("--table-base={table_base}"));
+    }
+    if let Some(global_base) = state.user_settings.global_base {
+        command.arg(format!("--global-base={global_base}"));
+    }
+
+    if wants_color_diagnostics(state, &state.args.linker_args) {
+        command.arg("--color-diagnostics");
+    }
+
+    command.args(ordered_linker_inputs(&state.args));
 
-    if module_kind.is_executable() {
-        command.arg(sysroot_lib_wasm32_path.join("crt1.o"));
-    } else {
-        command.arg(sysroot_lib_wasm32_path.join("scrt1.o"));
+    if state.user_settings.instrument_functions {
+        command.arg(compile_instrument_functions_runtime(state, &sysroot_path)?);
+    }
+
+    match (&state.user_settings.crt_object, state.user_settings.no_crt) {
+        (Some(_), true) => bail!("CRT_OBJECT and NO_CRT can't be used together"),
+        (Some(crt_object), false) => {
+            command.arg(crt_object);
+        }
+        (None, true) => (),
+        (None, false) if module_kind.is_executable() => {
+            command.arg(prefixed_path(
+                state,
+                "crt1.o",
+                &sysroot_lib_wasm32_path.join("crt1.o"),
+            ));
+        }
+        (None, false) => {
+            command.arg(prefixed_path(
+                state,
+                "scrt1.o",
+                &sysroot_lib_wasm32_path.join("scrt1.o"),
+            ));
+        }
     }
 
     command.arg("-o");
     command.arg(output_path(state));
 
-    run_command(command)
-}
+    match state.user_settings.allow_multiple_definition {
+        MultipleDefinitions::Error => (),
+        MultipleDefinitions::Allow => {
+            command.arg("--allow-multiple-definition");
+        }
+        MultipleDefinitions::Report => {
+            report_multiple_definitions(&command)?;
+            command.arg("--allow-multiple-definition");
+        }
+    }
 
-fn run_wasm_opt(state: &State) -> Result<()> {
-    let mut command = Command::new(
+    let demangle = |stderr: &str| {
+        inspect::demangle_symbol_names(stderr, |names| demangle_names(names, &state.user_settings))
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to demangle linker output: {e:?}");
+                stderr.to_owned()
+            })
+    };
+    run_filtered_stage(
+        Stage::Link,
+        &output_path(state).display().to_string(),
+        command,
         state
             .user_settings
-            .binaryen_location
-            .get_tool_path("wasm-opt"),
+            .demangle_linker_errors
+            .then_some(&demangle as StderrFilter),
+    )
+}
+
+// Modules built with PIC get their memory and table bases from the dynamic
+// loader, so they can't be given fixed ones.
+fn check_layout_settings(user_settings: &UserSettings, module_kind: ModuleKind) -> Result<()> {
+    let settings = [
+        ("GLOBAL_BASE", user_settings.global_base),
+        ("TABLE_BASE", user_settings.table_base),
+    ];
+    for (name, value) in settings {
+        if value.is_some() && module_kind.requires_pic() {
+            bail!("{name} can't be used with module kind {module_kind:?}, which is relocated when loaded");
+        }
+    }
+    Ok(())
+}
+
+// Runs the link without --allow-multiple-definition first, to list the
+// duplicate definitions wasm-ld would otherwise choose between silently.
+fn report_multiple_definitions(command: &Command) -> Result<()> {
+    let mut probe = Command::new(command.get_program());
+    probe
+        .args(command.get_args())
+        .args(["--error-limit=0", "--no-color-diagnostics"]);
+    tracing::debug!("Looking for multiple definitions: {probe:?}");
+
+    let (_, stderr) = run_capturing_stderr(&mut probe, false)?;
+    let duplicates = muldefs::parse_duplicates(&stderr);
+    if !duplicates.is_empty() {
+        muldefs::print_report(&duplicates);
+    }
+    Ok(())
+}
+
+// Links the inputs into a single object file, for `-r`. The result is linked
+// again later, so none of the default libraries or flags are added and the
+// post-link steps don't apply.
+fn link_relocatable(state: &State) -> Result<()> {
+    state.user_settings.ensure_llvm_location(true)?;
+
+    let mut command = Command::new(tool_path(state, "wasm-ld"));
+    command.arg("--relocatable");
+    command.args(without_strip_flags(
+        &state.args.linker_args,
+        &state.user_settings,
+    ));
+    if wants_color_diagnostics(state, &state.args.linker_args) {
+        command.arg("--color-diagnostics");
+    }
+    command.args(ordered_linker_inputs(&state.args));
+    command.arg("-o");
+    command.arg(output_path(state));
+
+    run_stage(
+        Stage::Link,
+        &output_path(state).display().to_string(),
+        command,
+    )
+}
+
+// Adds flags the linker is passed by default, leaving out the ones suppressed
+// with SUPPRESS_DEFAULT_LDFLAGS. Flags taking a separate value are given as
+// one string, e.g. "-z stack-size=8388608".
+fn add_default_linker_args(command: &mut Command, user_settings: &UserSettings, args: &[&str]) {
+    for arg in args {
+        if is_suppressed(arg, &user_settings.suppress_default_ldflags) {
+            tracing::debug!("Not passing default linker flag {arg}");
+        } else if is_omitted_library(arg, &user_settings.omit_default_libs) {
+            tracing::debug!("Not linking default library {arg}");
+        } else {
+            command.args(arg.split_whitespace());
+        }
+    }
+}
+
+// A default flag is suppressed if it's listed as is, or by its name only if
+// it takes a value, e.g. "--max-memory" for "--max-memory=4294967296".
+fn is_suppressed(flag: &str, suppressed: &[String]) -> bool {
+    suppressed.iter().any(|suppressed| {
+        flag == suppressed
+            || flag
+                .strip_prefix(suppressed.as_str())
+                .is_some_and(|value| value.starts_with('='))
+    })
+}
+
+// Libraries can be named in settings with or without -l.
+fn library_name(lib: &str) -> &str {
+    lib.strip_prefix("-l").unwrap_or(lib)
+}
+
+fn is_omitted_library(flag: &str, omitted: &[String]) -> bool {
+    flag.strip_prefix("-l")
+        .is_some_and(|name| omitted.iter().any(|lib| library_name(lib) == name))
+}
+
+// Lists the shared libraries given as inputs as NEEDED in the output's
+// dylink.0 section, by their soname if they have one, and records the
+// output's own soname. Inputs that aren't shared modules are rejected, as the
+// dynamic linker couldn't load them.
+fn record_dylink_metadata(state: &State) -> Result<()> {
+    let module_kind = state.user_settings.module_kind();
+    if !module_kind.requires_pic() {
+        return Ok(());
+    }
+
+    let mut needed = Vec::new();
+    for input in &state.args.linker_inputs {
+        if input.extension().is_none_or(|ext| ext != "so") {
+            continue;
+        }
+        let bytes =
+            std::fs::read(input).with_context(|| format!("Failed to read {}", input.display()))?;
+        if !dylink::is_shared_module(&bytes)? {
+            bail!(
+                "{} is not a shared library: it has no {} section",
+                input.display(),
+                dylink::DYLINK_SECTION
+            );
+        }
+        needed.push(match dylink::read_soname(&bytes)? {
+            Some(soname) => soname,
+            None => input.file_name().unwrap().to_string_lossy().into_owned(),
+        });
+    }
+
+    let soname = match (&state.args.soname, module_kind) {
+        (Some(soname), ModuleKind::SharedLibrary) => Some(soname),
+        (Some(_), _) => {
+            eprintln!("Warning: ignoring -soname, which only applies to shared libraries");
+            None
+        }
+        (None, _) => None,
+    };
+    if needed.is_empty() && soname.is_none() {
+        return Ok(());
+    }
+
+    let output_path = output_path(state);
+    let mut bytes = std::fs::read(output_path)
+        .with_context(|| format!("Failed to read {}", output_path.display()))?;
+    if !needed.is_empty() {
+        bytes = dylink::add_needed(&bytes, &needed)?;
+    }
+    if let Some(soname) = soname {
+        bytes.extend(dylink::encode_soname_section(soname));
+    }
+    std::fs::write(output_path, bytes)
+        .with_context(|| format!("Failed to write {}", output_path.display()))
+}
+
+fn prune_threads_if_enabled(state: &State) -> Result<()> {
+    if !state.user_settings.auto_prune_threads {
+        return Ok(());
+    }
+
+    // Dynamically linked modules must keep the threads ABI, since other
+    // modules they're linked with may need it.
+    if state.user_settings.module_kind() != ModuleKind::StaticMain {
+        tracing::debug!("Not pruning thread support from dynamically linked module");
+        return Ok(());
+    }
+
+    let output_path = output_path(state);
+    if threads::prune_threads(output_path)? {
+        tracing::info!(
+            "Removed thread support from {} as it doesn't use it",
+            output_path.display()
+        );
+    }
+    Ok(())
+}
+
+// Compiles the runtime recording the events of -finstrument-functions, to be
+// linked into the module.
+fn compile_instrument_functions_runtime(state: &State, sysroot_path: &Path) -> Result<PathBuf> {
+    let source_path = state.temp_dir.join("wasixcc_instrument_functions.c");
+    let object_path = state.temp_dir.join("wasixcc_instrument_functions.o");
+    std::fs::write(&source_path, INSTRUMENT_FUNCTIONS_RUNTIME)
+        .with_context(|| format!("Failed to write {}", source_path.display()))?;
+
+    let mut command = Command::new(tool_path(state, "clang"));
+    command.args(target_compiler_args(state, sysroot_path));
+    command.arg("-O2");
+    command.arg(&source_path);
+    command.arg("-o").arg(&object_path);
+    run_stage(Stage::Compile, "instrumentation runtime", command)?;
+
+    Ok(object_path)
+}
+
+// KEEP_NAMES wins over linker flags that would strip the name section.
+fn without_strip_flags<'a>(args: &'a [String], user_settings: &UserSettings) -> Vec<&'a String> {
+    args.iter()
+        .filter(|arg| {
+            let strip = matches!(arg.as_str(), "-s" | "-S" | "--strip-all" | "--strip-debug");
+            if strip && user_settings.keep_names {
+                eprintln!("Warning: ignoring linker flag {arg} because KEEP_NAMES is enabled");
+            }
+            !(strip && user_settings.keep_names)
+        })
+        .collect()
+}
+
+// Snapshots the output after running its initialization function. Runs
+// before wasm-opt, which then optimizes the snapshot.
+fn run_wizer(state: &State) -> Result<()> {
+    let user_settings = &state.user_settings;
+    let output_path = output_path(state);
+    let input_path = state.temp_dir.join("pre-wizer.wasm");
+    std::fs::copy(output_path, &input_path)
+        .with_context(|| format!("Failed to copy {}", output_path.display()))?;
+
+    let mut command = Command::new("wizer");
+    command.arg("--init-func").arg(
+        user_settings
+            .wizer_init_func
+            .as_deref()
+            .unwrap_or("wizer.initialize"),
     );
+    if user_settings.wizer_allow_wasi || !user_settings.wizer_dirs.is_empty() {
+        command.arg("--allow-wasi");
+    }
+    for dir in &user_settings.wizer_dirs {
+        command.arg("--dir").arg(dir);
+    }
+    command.args(&user_settings.wizer_flags);
+    command.arg("-o").arg(output_path).arg(&input_path);
+
+    run_stage(Stage::Wizer, &output_path.display().to_string(), command)
+}
+
+// Returns whether wasm-opt was actually run.
+fn run_wasm_opt(state: &State) -> Result<bool> {
+    let mut command = Command::new(tool_path(state, "wasm-opt"));
 
     if !state.user_settings.wasm_opt_suppress_default {
         if state.user_settings.wasm_exceptions {
@@ -563,16 +1974,47 @@ fn run_wasm_opt(state: &State) -> Result<()> {
 
     if command.get_args().next().is_none() {
         tracing::info!("Skipping wasm-opt as no passes were specified or needed");
-        return Ok(());
+        return Ok(false);
+    }
+
+    if state.user_settings.opt_report.is_some() {
+        // Metrics of the final module, and which functions asyncify
+        // instrumented; both are printed to stdout.
+        command.arg("--metrics");
+        command.arg("--pass-arg=asyncify-verbose");
+        let output_path = state.temp_dir.join(optreport::WASM_OPT_OUTPUT_FILE);
+        let output = std::fs::File::create(&output_path)
+            .with_context(|| format!("Failed to create {}", output_path.display()))?;
+        command.stdout(output);
     }
 
     match state.build_settings.debug_level {
+        DebugLevel::G0 if state.build_settings.source_map || state.user_settings.keep_names => {
+            // Only names or source locations were asked for, not DWARF
+            command.arg("-g");
+            command.arg("--strip-dwarf");
+        }
         DebugLevel::G0 => (),
         DebugLevel::G1 | DebugLevel::G2 | DebugLevel::G3 => {
             command.arg("-g");
         }
     }
 
+    if state.user_settings.profiling {
+        // Inlined functions disappear from profiles
+        command.arg("--no-inline=*");
+    }
+
+    if state.build_settings.source_map {
+        let map_path = sidecar_path(state, ".map");
+        command.arg("--input-source-map");
+        command.arg(&map_path);
+        command.arg("--output-source-map");
+        command.arg(&map_path);
+        command.arg("--output-source-map-url");
+        command.arg(source_map_url(state));
+    }
+
     command.arg("--no-validation");
 
     command.args(WASM_OPT_ENABLED_FEATURES);
@@ -583,14 +2025,16 @@ fn run_wasm_opt(state: &State) -> Result<()> {
     command.arg(output_path);
 
     if state.user_settings.wasm_opt_preserve_unoptimized {
-        let tempdir = tempfile::TempDir::new()
+        let tempdir = state
+            .user_settings
+            .create_temp_dir()
             .context("Failed to create temporary directory for wasm-opt")?;
         let unoptimized_path = tempdir.path().join("unoptimized.wasm");
         std::fs::copy(output_path, &unoptimized_path)
             .context("Failed to create copy of unoptimized artifact before running wasm-opt")?;
         command.arg(&unoptimized_path);
-        match run_command(command) {
-            Ok(()) => Ok(()),
+        match run_stage(Stage::WasmOpt, &output_path.display().to_string(), command) {
+            Ok(()) => Ok(true),
             Err(e) => {
                 let kept_path = tempdir.keep();
                 eprintln!(
@@ -602,7 +2046,249 @@ fn run_wasm_opt(state: &State) -> Result<()> {
         }
     } else {
         command.arg(output_path);
-        run_command(command)
+        run_stage(Stage::WasmOpt, &output_path.display().to_string(), command)?;
+        Ok(true)
+    }
+}
+
+// Splits the optimized output with wasm-split. Without a profile, this writes
+// an instrumented build that records which functions run; with one, the
+// functions that never ran move to a deferred module, loaded on first call
+// through placeholders listed in `<output>.placeholders`.
+fn run_wasm_split(state: &State) -> Result<()> {
+    let output_path = output_path(state);
+    let mut command = Command::new(tool_path(state, "wasm-split"));
+    command.args(WASM_OPT_ENABLED_FEATURES);
+
+    let Some(profile) = &state.user_settings.split_profile else {
+        let instrumented_path = sidecar_path(state, ".instrumented.wasm");
+        command
+            .arg("--instrument")
+            .arg(output_path)
+            .arg("-o")
+            .arg(&instrumented_path);
+        run_command(command)?;
+        eprintln!(
+            "Wrote an instrumented build to {}; run it, call its `__write_profile` export \
+            to save a profile, then rebuild with SPLIT_PROFILE pointing at the profile",
+            instrumented_path.display()
+        );
+        return Ok(());
+    };
+
+    let input_path = state.temp_dir.join("pre-split.wasm");
+    std::fs::copy(output_path, &input_path)
+        .with_context(|| format!("Failed to copy {}", output_path.display()))?;
+    command
+        .arg("--split")
+        .arg(&input_path)
+        .arg(format!("--profile={}", profile.display()))
+        .arg("--placeholdermap")
+        .arg("-o1")
+        .arg(output_path)
+        .arg("-o2")
+        .arg(sidecar_path(state, ".deferred.wasm"));
+    run_command(command)
+}
+
+// Wraps the core module into a component, adapting its WASI preview 1
+// imports. Runs last, since the other steps only understand core modules.
+fn make_component(state: &State) -> Result<()> {
+    let adapter_name = if state.user_settings.module_kind().is_executable() {
+        "wasi_snapshot_preview1.command.wasm"
+    } else {
+        "wasi_snapshot_preview1.reactor.wasm"
+    };
+    let Some(adapter) = &state.user_settings.component_adapter else {
+        bail!(
+            "COMPONENT requires COMPONENT_ADAPTER to point at the {adapter_name} adapter \
+            from a Wasmtime release"
+        );
+    };
+
+    let output_path = output_path(state);
+    let core_path = state.temp_dir.join("core.wasm");
+    std::fs::copy(output_path, &core_path)
+        .with_context(|| format!("Failed to copy {}", output_path.display()))?;
+
+    let mut command = Command::new("wasm-tools");
+    command
+        .args(["component", "new"])
+        .arg(&core_path)
+        .arg("--adapt")
+        .arg(format!("wasi_snapshot_preview1={}", adapter.display()))
+        .arg("-o")
+        .arg(output_path);
+    run_command(command)
+}
+
+// The wasm modules a build wrote, for `--emit-wat`. Without -o, clang names
+// each object after its input, in the current directory.
+fn written_modules(state: &State) -> Vec<PathBuf> {
+    if state.user_settings.module_kind().is_binary() || state.args.relocatable {
+        vec![output_path(state).to_owned()]
+    } else if !produces_object(&state.args.compiler_args) {
+        eprintln!("Warning: --emit-wat only applies to object files and linked modules");
+        vec![]
+    } else if let Some(output) = &state.args.output {
+        vec![output.clone()]
+    } else {
+        state
+            .args
+            .compiler_inputs
+            .iter()
+            .map(|input| {
+                let mut name = input.file_stem().unwrap_or_default().to_owned();
+                name.push(".o");
+                PathBuf::from(name)
+            })
+            .collect()
+    }
+}
+
+// Writes the text format of a module next to it, e.g. `app.wasm.wat`.
+fn emit_wat(module: &Path) -> Result<()> {
+    let mut wat_path = module.as_os_str().to_owned();
+    wat_path.push(".wat");
+
+    let mut command = Command::new("wasm-tools");
+    command.arg("print").arg(module).arg("-o").arg(wat_path);
+    run_command(command)
+}
+
+// Marks the output as a profiling build, so Wasmer's profiler can rely on
+// the names of its functions.
+fn add_profiling_section(state: &State) -> Result<()> {
+    let payload = format!(
+        "{{\"version\":1,\"producer\":\"wasixcc {}\"}}",
+        env!("CARGO_PKG_VERSION")
+    );
+    wasm::append_custom_section(output_path(state), PROFILING_SECTION, payload.as_bytes())
+}
+
+/// How a module was built, recorded by EMBED_BUILD_INFO for support triage.
+#[derive(Debug, serde::Serialize)]
+struct BuildInfo {
+    version: u32,
+    producer: String,
+    llvm: Option<String>,
+    sysroot: Option<String>,
+    binaryen: String,
+    module_kind: ModuleKind,
+    target: String,
+    wasm_exceptions: bool,
+    pic: bool,
+    default_flags: Vec<&'static str>,
+}
+
+// Releases are only known for installations downloaded by wasixcc; binaryen
+// doesn't record one, so the caller passes the version wasm-opt reports.
+fn build_info(user_settings: &UserSettings, binaryen: String) -> Result<BuildInfo> {
+    let sysroot = user_settings.sysroot_location()?;
+    Ok(BuildInfo {
+        version: 1,
+        producer: format!("wasixcc {}", env!("CARGO_PKG_VERSION")),
+        llvm: user_settings
+            .llvm_location
+            .install_path()
+            .and_then(compat::read_release_tag),
+        sysroot: compat::read_release_tag(&sysroot),
+        binaryen,
+        module_kind: user_settings.module_kind(),
+        target: user_settings.target_triple().to_owned(),
+        wasm_exceptions: user_settings.wasm_exceptions,
+        pic: user_settings.pic,
+        default_flags: default_compiler_flags(user_settings),
+    })
+}
+
+// Added after stripping, so STRIP can't remove it.
+fn add_build_info_section(state: &State) -> Result<()> {
+    let binaryen = status::tool_version(&state.user_settings.tool_path("wasm-opt"));
+    let payload = serde_json::to_string(&build_info(&state.user_settings, binaryen)?)
+        .context("Failed to serialize build info")?;
+    wasm::append_custom_section(output_path(state), BUILD_INFO_SECTION, payload.as_bytes())
+}
+
+// Printed once the output is final, after wasm-opt and any other rewriting.
+fn print_link_summary(state: &State) -> Result<()> {
+    let output = output_path(state);
+    let bytes =
+        std::fs::read(output).with_context(|| format!("Failed to read {}", output.display()))?;
+    eprintln!("Link summary for {}:", output.display());
+    eprint!("{}", inspect::link_summary(&bytes)?);
+    Ok(())
+}
+
+// How many of the largest items --size-report lists.
+const SIZE_REPORT_ITEMS: usize = 30;
+
+fn print_size_report(state: &State) -> Result<()> {
+    let output = output_path(state);
+    let bytes =
+        std::fs::read(output).with_context(|| format!("Failed to read {}", output.display()))?;
+    eprintln!("Size report for {}:", output.display());
+    eprint!("{}", inspect::size_report(&bytes, SIZE_REPORT_ITEMS)?);
+    Ok(())
+}
+
+// Moves the DWARF sections of the output into `<output>.debug.wasm`, which is
+// otherwise a full copy of the output so the DWARF's code offsets stay valid.
+fn separate_dwarf(state: &State) -> Result<()> {
+    let output_path = output_path(state);
+    let debug_path = sidecar_path(state, ".debug.wasm");
+
+    let module = std::fs::read(output_path)
+        .with_context(|| format!("Failed to read {}", output_path.display()))?;
+    std::fs::write(&debug_path, &module)
+        .with_context(|| format!("Failed to write {}", debug_path.display()))?;
+
+    let mut stripped = wasm::remove_custom_sections(&module, |name| name.starts_with(".debug_"))?;
+    let mut payload = Vec::new();
+    wasm::write_name(
+        &mut payload,
+        &debug_path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default(),
+    );
+    stripped.extend(wasm::encode_custom_section(
+        EXTERNAL_DEBUG_INFO_SECTION,
+        &payload,
+    ));
+
+    std::fs::write(output_path, stripped)
+        .with_context(|| format!("Failed to write {}", output_path.display()))
+}
+
+// Removes the sections STRIP asks for from the output once it's optimized.
+// KEEP_NAMES wins over stripping the name section, as with linker flags.
+fn strip_output(state: &State) -> Result<()> {
+    remove_output_sections(state, |name| {
+        is_stripped_section(
+            name,
+            state.user_settings.strip,
+            state.user_settings.keep_names,
+        )
+    })
+}
+
+fn remove_output_sections(state: &State, is_removed: impl Fn(&str) -> bool) -> Result<()> {
+    let output_path = output_path(state);
+    let module = std::fs::read(output_path)
+        .with_context(|| format!("Failed to read {}", output_path.display()))?;
+    let stripped = wasm::remove_custom_sections(&module, is_removed)?;
+    std::fs::write(output_path, stripped)
+        .with_context(|| format!("Failed to write {}", output_path.display()))
+}
+
+fn is_stripped_section(name: &str, strip: Strip, keep_names: bool) -> bool {
+    match strip {
+        Strip::None => false,
+        Strip::Debug => name.starts_with(".debug_"),
+        Strip::All => {
+            name.starts_with(".debug_") || name == "producers" || (name == "name" && !keep_names)
+        }
     }
 }
 
@@ -616,12 +2302,26 @@ fn prepare_compiler_args(
         linker_args: Vec::new(),
         compiler_inputs: Vec::new(),
         linker_inputs: Vec::new(),
+        positional_linker_args: Vec::new(),
+        compiler_input_positions: Vec::new(),
+        directory_flags: Vec::new(),
         output: None,
+        soname: None,
+        version_script: None,
+        relocatable: false,
+        prefix_dirs: Vec::new(),
     };
     let mut build_settings = BuildSettings {
         opt_level: OptLevel::O0,
         debug_level: DebugLevel::G0,
         use_wasm_opt: true,
+        source_map: false,
+        run: false,
+        size_report: false,
+        syntax_only: false,
+        preprocess_only: false,
+        analyze: false,
+        emit_wat: false,
     };
 
     let mut extra_flags = vec![];
@@ -656,17 +2356,34 @@ fn prepare_compiler_args(
         .chain(args)
         .chain(extra_post_flags)
         .chain(extra_post_flags2);
+    let mut sysroot_arg = None;
 
     while let Some(arg) = iter.next() {
         if let Some(arg) = arg.strip_prefix("-Wl,") {
             for split in arg.split(',') {
-                result.linker_args.push(split.to_owned());
+                push_linker_arg(&mut result, split.to_owned());
             }
         } else if arg == "-Xlinker" {
             let Some(next_arg) = iter.next() else {
                 bail!("Expected argument after -Xlinker");
             };
-            result.linker_args.push(next_arg);
+            push_linker_arg(&mut result, next_arg);
+        } else if arg == "-r" {
+            // Not a compiler flag, clang passes it on to the linker
+            result.linker_args.push(arg);
+        } else if arg == "-s" {
+            // Strips the output, as with gcc
+            user_settings.strip = Strip::All;
+        } else if arg == "-Bstatic" || arg == "-Bdynamic" {
+            // Linker flags, unlike -B<dir>
+            push_linker_arg(&mut result, arg);
+        } else if arg == "-B" {
+            let Some(next_arg) = iter.next() else {
+                bail!("Expected argument after -B");
+            };
+            result.prefix_dirs.push(PathBuf::from(next_arg));
+        } else if let Some(dir) = arg.strip_prefix("-B") {
+            result.prefix_dirs.push(PathBuf::from(dir));
         } else if arg == "-z" {
             let Some(next_arg) = iter.next() else {
                 bail!("Expected argument after -z");
@@ -684,6 +2401,9 @@ fn prepare_compiler_args(
                 }
             }
             result.output = Some(output);
+        } else if arg == "-" {
+            // Source from stdin, in the language given with -x
+            push_compiler_input(&mut result, PathBuf::from(arg));
         } else if arg.starts_with('-') {
             if update_build_settings_from_arg(&arg, &mut build_settings, user_settings)? {
                 // Read the value early so it's also discarded if we discard the flag
@@ -693,6 +2413,16 @@ fn prepare_compiler_args(
                     None
                 };
 
+                // Decided once all flags are known, since the configured
+                // sysroot depends on them
+                match arg.strip_prefix("--sysroot") {
+                    Some("") => sysroot_arg = next_arg.clone().map(PathBuf::from),
+                    Some(value) if value.starts_with('=') => {
+                        sysroot_arg = Some(PathBuf::from(&value[1..]));
+                    }
+                    _ => (),
+                }
+
                 if CLANG_FLAGS_TO_DISCARD.iter().any(|flag| {
                     arg.strip_prefix(flag)
                         .is_some_and(|value| value.is_empty() || value.starts_with('='))
@@ -700,18 +2430,19 @@ fn prepare_compiler_args(
                     continue;
                 }
 
-                let args_list = if CLANG_FLAGS_TO_FORWARD_TO_WASM_LD
+                if CLANG_FLAGS_TO_FORWARD_TO_WASM_LD
                     .iter()
                     .any(|flag| arg.starts_with(flag))
                 {
-                    &mut result.linker_args
+                    if let (Some(library), "-l") = (&next_arg, arg.as_str()) {
+                        push_linker_arg(&mut result, format!("-l{library}"));
+                    } else {
+                        push_linker_arg(&mut result, arg);
+                        result.linker_args.extend(next_arg);
+                    }
                 } else {
-                    &mut result.compiler_args
-                };
-
-                args_list.push(arg);
-                if let Some(next_arg) = next_arg {
-                    args_list.push(next_arg);
+                    result.compiler_args.push(arg);
+                    result.compiler_args.extend(next_arg);
                 }
             }
         } else {
@@ -722,24 +2453,62 @@ fn prepare_compiler_args(
                     result.linker_inputs.push(PathBuf::from(arg));
                 }
                 _ => {
-                    result.compiler_inputs.push(PathBuf::from(arg));
+                    push_compiler_input(&mut result, PathBuf::from(arg));
                 }
             }
         }
     }
 
+    nativeflags::translate_compiler_args(
+        &mut result.compiler_args,
+        &mut result.linker_args,
+        user_settings.strict_flags,
+    )?;
+    nativeflags::translate_linker_args(&mut result.linker_args, user_settings.strict_flags)?;
+    result.soname = take_soname(&mut result.linker_args)?;
+    result.version_script = take_version_script(&mut result.linker_args)?;
+    check_link_groups(&result.positional_linker_args)?;
+    take_relocatable(&mut result, user_settings)?;
+
+    if let Some(sysroot) = sysroot_arg {
+        if user_settings.allow_sysroot_override {
+            user_settings.sysroot_location = Some(sysroot);
+        } else if user_settings.sysroot_location().ok().as_ref() != Some(&sysroot) {
+            eprintln!(
+                "Warning: ignoring --sysroot {} in favor of the configured sysroot; \
+                 set ALLOW_SYSROOT_OVERRIDE=yes to use it instead",
+                sysroot.display()
+            );
+        }
+    }
+
+    for input in &result.compiler_inputs {
+        let flags = find_directory_flags(input)?;
+        let flags = classify_directory_flags(flags, &mut build_settings, user_settings)?;
+        result.directory_flags.push(flags);
+    }
+
     if user_settings.module_kind.is_none() {
         for arg in &result.compiler_args {
             if arg == "-shared" {
                 user_settings.module_kind = Some(ModuleKind::SharedLibrary);
                 break;
-            } else if arg == "-c" || arg == "-S" || arg == "-E" {
+            } else if ["-c", "-S", "-E", "-emit-llvm"].contains(&arg.as_str()) {
                 user_settings.module_kind = Some(ModuleKind::ObjectFile);
                 break;
             }
         }
     }
 
+    if result.compiler_args.iter().any(|arg| arg == "-emit-llvm")
+        && user_settings.module_kind().is_binary()
+    {
+        bail!(
+            "-emit-llvm produces LLVM IR, which can't be linked into a {:?}",
+            user_settings.module_kind()
+        );
+    }
+
     if user_settings.module_kind.is_none() {
         for arg in &result.linker_args {
             if arg == "-shared" {
@@ -764,7 +2533,14 @@ fn prepare_linker_args(
         linker_args: Vec::new(),
         compiler_inputs: Vec::new(),
         linker_inputs: Vec::new(),
+        positional_linker_args: Vec::new(),
+        compiler_input_positions: Vec::new(),
+        directory_flags: Vec::new(),
         output: None,
+        soname: None,
+        version_script: None,
+        relocatable: false,
+        prefix_dirs: Vec::new(),
     };
 
     let mut iter = args.into_iter();
@@ -781,9 +2557,14 @@ fn prepare_linker_args(
                 }
             }
             result.output = Some(output);
+        } else if arg == "-l" {
+            let Some(next_arg) = iter.next() else {
+                bail!("Expected argument after -l");
+            };
+            push_linker_arg(&mut result, format!("-l{next_arg}"));
         } else if arg.starts_with('-') {
             let has_next_arg = WASM_LD_FLAGS_WITH_ARGS.contains(&arg[..]);
-            result.linker_args.push(arg);
+            push_linker_arg(&mut result, arg);
             if has_next_arg {
                 if let Some(next_arg) = iter.next() {
                     result.linker_args.push(next_arg);
@@ -795,23 +2576,167 @@ fn prepare_linker_args(
         }
     }
 
-    if user_settings.module_kind.is_none() {
-        for arg in &result.linker_args {
-            if arg == "-shared" {
-                user_settings.module_kind = Some(ModuleKind::SharedLibrary);
-                break;
-            } else if arg == "-pie" {
-                user_settings.module_kind = Some(ModuleKind::DynamicMain);
-                break;
-            }
+    nativeflags::translate_linker_args(&mut result.linker_args, user_settings.strict_flags)?;
+    result.soname = take_soname(&mut result.linker_args)?;
+    result.version_script = take_version_script(&mut result.linker_args)?;
+    check_link_groups(&result.positional_linker_args)?;
+    take_relocatable(&mut result, user_settings)?;
+
+    if user_settings.module_kind.is_none() {
+        for arg in &result.linker_args {
+            if arg == "-shared" {
+                user_settings.module_kind = Some(ModuleKind::SharedLibrary);
+                break;
+            } else if arg == "-pie" {
+                user_settings.module_kind = Some(ModuleKind::DynamicMain);
+                break;
+            }
+        }
+    }
+
+    if user_settings.module_kind().requires_pic() {
+        user_settings.pic = true;
+    }
+
+    Ok(result)
+}
+
+// Removes `-r` from the linker arguments, making the output an object file.
+fn take_relocatable(result: &mut PreparedArgs, user_settings: &mut UserSettings) -> Result<()> {
+    let len = result.linker_args.len();
+    result
+        .linker_args
+        .retain(|arg| !matches!(arg.as_str(), "-r" | "--relocatable"));
+    result.relocatable = result.linker_args.len() != len;
+
+    if result.relocatable {
+        match user_settings.module_kind {
+            None | Some(ModuleKind::ObjectFile) => {
+                user_settings.module_kind = Some(ModuleKind::ObjectFile)
+            }
+            Some(module_kind) => {
+                bail!("-r produces an object file, but the module kind is {module_kind:?}")
+            }
+        }
+    }
+    Ok(())
+}
+
+// Adds a compiler input, recording where it sits among the linker inputs and
+// the positional linker flags so its object can be linked in that place.
+fn push_compiler_input(result: &mut PreparedArgs, input: PathBuf) {
+    result.compiler_input_positions.push((
+        result.linker_inputs.len(),
+        result.positional_linker_args.len(),
+    ));
+    result.compiler_inputs.push(input);
+}
+
+// Adds the object compiled from the compiler input at `index` to the linker
+// inputs where the input was given. Objects must be added in the order of
+// their inputs.
+fn insert_compiled_object(args: &mut PreparedArgs, index: usize, object: PathBuf) {
+    let (inputs_before, flags_before) = args.compiler_input_positions[index];
+    args.linker_inputs.insert(inputs_before + index, object);
+    for (position, _) in &mut args.positional_linker_args[flags_before..] {
+        *position += 1;
+    }
+}
+
+// Adds a linker flag, keeping the ones that apply to the inputs after them in
+// position among the inputs.
+fn push_linker_arg(result: &mut PreparedArgs, arg: String) {
+    let arg = match arg.as_str() {
+        "-(" => "--start-group".to_owned(),
+        "-)" => "--end-group".to_owned(),
+        _ => arg,
+    };
+    let is_library = arg.len() > 2 && arg.starts_with("-l");
+    if is_library || POSITIONAL_LINKER_FLAGS.contains(arg.as_str()) {
+        result
+            .positional_linker_args
+            .push((result.linker_inputs.len(), arg));
+    } else {
+        result.linker_args.push(arg);
+    }
+}
+
+// Groups of archives can't be nested, and each must be closed.
+fn check_link_groups(positional_linker_args: &[(usize, String)]) -> Result<()> {
+    let mut in_group = false;
+    for (_, arg) in positional_linker_args {
+        match arg.as_str() {
+            "--start-group" if in_group => bail!("Nested --start-group"),
+            "--end-group" if !in_group => bail!("--end-group without --start-group"),
+            "--start-group" | "--end-group" => in_group = !in_group,
+            _ => (),
+        }
+    }
+    if in_group {
+        bail!("--start-group without --end-group");
+    }
+    Ok(())
+}
+
+// The linker inputs, with the flags that apply to them in the order they were
+// given.
+fn ordered_linker_inputs(args: &PreparedArgs) -> Vec<OsString> {
+    let mut result = Vec::new();
+    let mut positional = args.positional_linker_args.iter().peekable();
+    for (index, input) in args.linker_inputs.iter().enumerate() {
+        while let Some((_, arg)) = positional.next_if(|(position, _)| *position <= index) {
+            result.push(arg.into());
         }
+        result.push(input.into());
     }
+    result.extend(positional.map(|(_, arg)| arg.into()));
+    result
+}
 
-    if user_settings.module_kind().requires_pic() {
-        user_settings.pic = true;
+// Removes `-soname <name>` and its variants from the linker arguments,
+// returning the name.
+fn take_soname(linker_args: &mut Vec<String>) -> Result<Option<String>> {
+    let mut soname = None;
+    let mut args = std::mem::take(linker_args).into_iter();
+    while let Some(arg) = args.next() {
+        if ["-soname", "--soname", "-h"].contains(&arg.as_str()) {
+            let Some(name) = args.next() else {
+                bail!("Expected argument after {arg}");
+            };
+            soname = Some(name);
+        } else if let Some(name) = arg
+            .strip_prefix("-soname=")
+            .or_else(|| arg.strip_prefix("--soname="))
+        {
+            soname = Some(name.to_owned());
+        } else {
+            linker_args.push(arg);
+        }
     }
+    Ok(soname)
+}
 
-    Ok(result)
+// Removes `--version-script <file>` and its variants from the linker
+// arguments, returning the file.
+fn take_version_script(linker_args: &mut Vec<String>) -> Result<Option<PathBuf>> {
+    let mut version_script = None;
+    let mut args = std::mem::take(linker_args).into_iter();
+    while let Some(arg) = args.next() {
+        if ["--version-script", "-version-script"].contains(&arg.as_str()) {
+            let Some(path) = args.next() else {
+                bail!("Expected argument after {arg}");
+            };
+            version_script = Some(PathBuf::from(path));
+        } else if let Some(path) = arg
+            .strip_prefix("--version-script=")
+            .or_else(|| arg.strip_prefix("-version-script="))
+        {
+            version_script = Some(PathBuf::from(path));
+        } else {
+            linker_args.push(arg);
+        }
+    }
+    Ok(version_script)
 }
 
 // The returned bool indicated whether the argument should be kept in the
@@ -822,7 +2747,29 @@ fn update_build_settings_from_arg(
     build_settings: &mut BuildSettings,
     user_settings: &mut UserSettings,
 ) -> Result<bool> {
-    if let Some(opt_level) = arg.strip_prefix("-O") {
+    if arg == "-gsource-map" {
+        // Not a clang flag; debug info is enabled when compiling instead.
+        build_settings.source_map = true;
+        Ok(false)
+    } else if arg == "--run" {
+        build_settings.run = true;
+        Ok(false)
+    } else if arg == "--size-report" {
+        build_settings.size_report = true;
+        Ok(false)
+    } else if arg == "--emit-wat" {
+        build_settings.emit_wat = true;
+        Ok(false)
+    } else if arg == "-fsyntax-only" {
+        build_settings.syntax_only = true;
+        Ok(true)
+    } else if arg == "-E" {
+        build_settings.preprocess_only = true;
+        Ok(true)
+    } else if arg == "--analyze" {
+        build_settings.analyze = true;
+        Ok(true)
+    } else if let Some(opt_level) = arg.strip_prefix("-O") {
         build_settings.opt_level = match opt_level {
             "0" => OptLevel::O0,
             "" | "1" => OptLevel::O1,
@@ -872,7 +2819,7 @@ fn update_build_settings_from_arg(
 
 fn deduce_module_kind(extension: &OsStr) -> Option<ModuleKind> {
     match extension.to_str() {
-        Some("o") | Some("obj") => Some(ModuleKind::ObjectFile),
+        Some("o") | Some("obj") | Some("bc") | Some("ll") => Some(ModuleKind::ObjectFile),
         Some("so") => Some(ModuleKind::SharedLibrary),
         _ => None, // Default to static main if no extension matches
     }
@@ -894,6 +2841,10 @@ mod tests {
             deduce_module_kind(OsStr::new("so")),
             Some(ModuleKind::SharedLibrary)
         );
+        assert_eq!(
+            deduce_module_kind(OsStr::new("bc")),
+            Some(ModuleKind::ObjectFile)
+        );
         assert_eq!(deduce_module_kind(OsStr::new("unknown")), None);
     }
 
@@ -903,6 +2854,13 @@ mod tests {
             opt_level: OptLevel::O0,
             debug_level: DebugLevel::G0,
             use_wasm_opt: true,
+            source_map: false,
+            run: false,
+            size_report: false,
+            syntax_only: false,
+            preprocess_only: false,
+            analyze: false,
+            emit_wat: false,
         };
         let mut us = UserSettings::default();
         assert!(update_build_settings_from_arg("-O3", &mut bs, &mut us).unwrap());
@@ -916,6 +2874,34 @@ mod tests {
         assert!(!us.wasm_exceptions);
     }
 
+    #[test]
+    fn test_source_map() {
+        let mut us = UserSettings::default();
+        let args = ["-g1", "-gsource-map", "a.c"].map(String::from).to_vec();
+        let (pa, bs) = prepare_compiler_args(args, &mut us, false).unwrap();
+        assert!(bs.source_map);
+        assert_eq!(bs.debug_level, DebugLevel::G1);
+        assert_eq!(pa.compiler_args, ["-g1"]);
+    }
+
+    #[test]
+    fn test_run() {
+        let mut us = UserSettings::default();
+        let args = ["--run", "a.c"].map(String::from).to_vec();
+        let (pa, bs) = prepare_compiler_args(args, &mut us, false).unwrap();
+        assert!(bs.run);
+        assert!(pa.compiler_args.is_empty());
+    }
+
+    #[test]
+    fn test_size_report() {
+        let mut us = UserSettings::default();
+        let args = ["--size-report", "a.c"].map(String::from).to_vec();
+        let (pa, bs) = prepare_compiler_args(args, &mut us, false).unwrap();
+        assert!(bs.size_report);
+        assert!(pa.compiler_args.is_empty());
+    }
+
     #[test]
     fn test_prepare_compiler_args_and_build_settings() {
         let mut us = UserSettings::default();
@@ -980,6 +2966,635 @@ mod tests {
         assert_eq!(us.module_kind, Some(ModuleKind::SharedLibrary));
     }
 
+    #[test]
+    fn test_ordered_linker_inputs() {
+        let mut us = UserSettings::default();
+        let args = [
+            "-lfirst",
+            "main.c",
+            "-Wl,--whole-archive",
+            "libfoo.a",
+            "-Wl,--no-whole-archive",
+            "-l",
+            "bar",
+            "-L",
+            "libs",
+            "baz.o",
+            "-lm",
+        ];
+        let (mut pa, _) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert_eq!(pa.linker_args, ["-L", "libs"]);
+        insert_compiled_object(&mut pa, 0, PathBuf::from("main.o"));
+        assert_eq!(
+            ordered_linker_inputs(&pa),
+            [
+                "-lfirst",
+                "main.o",
+                "--whole-archive",
+                "libfoo.a",
+                "--no-whole-archive",
+                "-lbar",
+                "baz.o",
+                "-lm"
+            ]
+        );
+
+        let mut us = UserSettings::default();
+        let args = ["a.o", "b.c", "-lx", "c.o", "d.c"];
+        let (mut pa, _) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        insert_compiled_object(&mut pa, 0, PathBuf::from("b.o"));
+        insert_compiled_object(&mut pa, 1, PathBuf::from("d.o"));
+        assert_eq!(
+            ordered_linker_inputs(&pa),
+            ["a.o", "b.o", "-lx", "c.o", "d.o"]
+        );
+
+        let mut us = UserSettings::default();
+        let args = ["a.o", "-l", "c", "--whole-archive", "libd.a", "-m", "x"];
+        let pa = prepare_linker_args(args.map(String::from).to_vec(), &mut us).unwrap();
+        assert_eq!(pa.linker_args, ["-m", "x"]);
+        assert_eq!(
+            ordered_linker_inputs(&pa),
+            ["a.o", "-lc", "--whole-archive", "libd.a"]
+        );
+    }
+
+    #[test]
+    fn test_link_groups() {
+        let mut us = UserSettings::default();
+        let args = ["a.o", "-Wl,-(", "liba.a", "libb.a", "-Wl,-)", "-lc"];
+        let (pa, _) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert_eq!(
+            ordered_linker_inputs(&pa),
+            [
+                "a.o",
+                "--start-group",
+                "liba.a",
+                "libb.a",
+                "--end-group",
+                "-lc"
+            ]
+        );
+
+        for args in [
+            &["--start-group", "liba.a"][..],
+            &["liba.a", "--end-group"],
+            &[
+                "--start-group",
+                "--start-group",
+                "--end-group",
+                "--end-group",
+            ],
+        ] {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            assert!(prepare_linker_args(args, &mut UserSettings::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_relocatable() {
+        let mut us = UserSettings::default();
+        let args = ["-r", "a.o", "b.c", "-o", "combined"];
+        let (pa, _) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert!(pa.relocatable);
+        assert!(pa.linker_args.is_empty());
+        assert!(pa.compiler_args.is_empty());
+        assert_eq!(us.module_kind, Some(ModuleKind::ObjectFile));
+
+        let mut us = UserSettings::default();
+        let args = ["--relocatable", "a.o", "-o", "combined.o"];
+        let pa = prepare_linker_args(args.map(String::from).to_vec(), &mut us).unwrap();
+        assert!(pa.relocatable);
+        assert_eq!(us.module_kind, Some(ModuleKind::ObjectFile));
+
+        let mut us = UserSettings::default();
+        let args = ["-Wl,-r", "a.o", "-o", "libfoo.so"];
+        assert!(prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).is_err());
+    }
+
+    #[test]
+    fn test_check_layout_settings() {
+        let mut us = UserSettings {
+            global_base: Some(1024),
+            table_base: Some(1),
+            ..Default::default()
+        };
+        check_layout_settings(&us, ModuleKind::StaticMain).unwrap();
+        assert!(check_layout_settings(&us, ModuleKind::DynamicMain).is_err());
+        us.global_base = None;
+        assert!(check_layout_settings(&us, ModuleKind::SharedLibrary).is_err());
+    }
+
+    #[test]
+    fn test_emit_llvm() {
+        let mut us = UserSettings::default();
+        let args = ["-emit-llvm", "a.c", "-o", "a.bc"];
+        let (pa, _) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert_eq!(us.module_kind, Some(ModuleKind::ObjectFile));
+        assert_eq!(pa.output, Some(PathBuf::from("a.bc")));
+
+        let mut us = UserSettings::default();
+        let args = ["-shared", "-emit-llvm", "a.c"];
+        assert!(prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).is_err());
+    }
+
+    #[test]
+    fn test_stdin_input() {
+        let mut us = UserSettings::default();
+        let args = ["-x", "c", "-", "-o", "out"];
+        let (pa, _) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert_eq!(pa.compiler_args, ["-x", "c"]);
+        assert_eq!(pa.compiler_inputs, [PathBuf::from("-")]);
+        assert!(pa.linker_inputs.is_empty());
+    }
+
+    #[test]
+    fn test_syntax_only() {
+        let mut us = UserSettings::default();
+        let args = ["-fsyntax-only", "-Wall", "a.c", "b.c"];
+        let (pa, bs) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert!(bs.syntax_only);
+        assert_eq!(pa.compiler_args, ["-fsyntax-only", "-Wall"]);
+    }
+
+    #[test]
+    fn test_preprocess_only() {
+        let mut us = UserSettings::default();
+        let args = ["-E", "-DFOO", "a.c"];
+        let (pa, bs) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert!(bs.preprocess_only);
+        assert_eq!(pa.compiler_args, ["-E", "-DFOO"]);
+        assert_eq!(us.module_kind, Some(ModuleKind::ObjectFile));
+    }
+
+    #[test]
+    fn test_analyze() {
+        let mut us = UserSettings::default();
+        let args = ["--analyze", "--analyzer-output", "html", "a.c", "-o", "a"];
+        let (pa, bs) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert!(bs.analyze);
+        assert_eq!(pa.compiler_args, ["--analyze", "--analyzer-output", "html"]);
+        assert_eq!(pa.compiler_inputs, [PathBuf::from("a.c")]);
+        assert!(!produces_object(&pa.compiler_args));
+    }
+
+    #[test]
+    fn test_take_soname() {
+        let mut us = UserSettings::default();
+        let args = [
+            "-shared",
+            "-Wl,-soname,libfoo.so.1",
+            "-Wl,--gc-sections",
+            "foo.o",
+        ];
+        let (pa, _) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert_eq!(pa.soname.as_deref(), Some("libfoo.so.1"));
+        assert_eq!(pa.linker_args, ["--gc-sections"]);
+
+        let mut args = vec!["--soname=libbar.so".to_string(), "-h".to_string()];
+        assert!(take_soname(&mut args).is_err());
+        let mut args = vec!["--soname=libbar.so".to_string()];
+        assert_eq!(
+            take_soname(&mut args).unwrap().as_deref(),
+            Some("libbar.so")
+        );
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn test_take_version_script() {
+        let mut us = UserSettings::default();
+        let args = ["-shared", "-Wl,--version-script=foo.map", "foo.o"];
+        let (pa, _) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert_eq!(pa.version_script, Some(PathBuf::from("foo.map")));
+        assert!(pa.linker_args.is_empty());
+        assert_eq!(pa.linker_inputs, [PathBuf::from("foo.o")]);
+
+        let mut us = UserSettings::default();
+        let args = ["--version-script", "bar.map", "-shared", "bar.o"];
+        let pa = prepare_linker_args(args.map(String::from).to_vec(), &mut us).unwrap();
+        assert_eq!(pa.version_script, Some(PathBuf::from("bar.map")));
+        assert_eq!(pa.linker_args, ["-shared"]);
+        assert_eq!(pa.linker_inputs, [PathBuf::from("bar.o")]);
+    }
+
+    #[test]
+    fn test_apply_out_dir() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let out_dir = tmp.path().join("out");
+        let us = UserSettings {
+            out_dir: Some(out_dir.clone()),
+            ..Default::default()
+        };
+
+        let mut pa = prepare_linker_args(
+            vec!["-o".to_string(), "build/app.wasm".to_string()],
+            &mut UserSettings::default(),
+        )
+        .unwrap();
+        apply_out_dir(&mut pa, &us).unwrap();
+        assert_eq!(pa.output, Some(out_dir.join("app.wasm")));
+        assert!(out_dir.is_dir());
+
+        pa.output = None;
+        apply_out_dir(&mut pa, &us).unwrap();
+        assert_eq!(pa.output, Some(out_dir.join("a.out")));
+
+        let mut us = UserSettings {
+            out_dir: Some(out_dir.clone()),
+            ..Default::default()
+        };
+        let args = ["-c", "src/a/util.c", "-o", "build/a/util.o"];
+        let (mut pa, _) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        apply_out_dir(&mut pa, &us).unwrap();
+        assert_eq!(pa.output, Some(PathBuf::from("build/a/util.o")));
+    }
+
+    #[test]
+    fn test_directory_flags() {
+        assert_eq!(
+            parse_directory_flags("# comment\n-DFOO=1  -Wno-error\n\n  -O3\n"),
+            vec!["-DFOO=1", "-Wno-error", "-O3"]
+        );
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let nested = tmp.path().join("a/b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(tmp.path().join(DIRECTORY_FLAGS_FILE_NAME), "-DOUTER").unwrap();
+        std::fs::write(
+            tmp.path().join("a").join(DIRECTORY_FLAGS_FILE_NAME),
+            "-DINNER",
+        )
+        .unwrap();
+
+        assert_eq!(
+            find_directory_flags(&nested.join("x.c")).unwrap(),
+            vec!["-DINNER"]
+        );
+        assert_eq!(
+            find_directory_flags(&tmp.path().join("y.c")).unwrap(),
+            vec!["-DOUTER"]
+        );
+    }
+
+    #[test]
+    fn test_classify_directory_flags() {
+        let mut us = UserSettings::default();
+        let (_, mut bs) = prepare_compiler_args(vec![], &mut us, false).unwrap();
+        let flags = ["-O3", "-DFOO", "-fwasm-exceptions", "-include", "a.h"].map(String::from);
+        assert_eq!(
+            classify_directory_flags(flags.to_vec(), &mut bs, &mut us).unwrap(),
+            ["-O3", "-DFOO", "-include", "a.h"]
+        );
+        assert_eq!(bs.opt_level, OptLevel::O3);
+        assert!(us.wasm_exceptions);
+    }
+
+    #[test]
+    fn test_query_compile_command() {
+        let command = vfs::with_file_system(vfs::MemoryFileSystem::default(), || {
+            query_compile_command(
+                vec!["-O2".to_owned(), "-DFOO".to_owned()],
+                UserSettings::default(),
+                false,
+                Path::new("src/foo.c"),
+            )
+        })
+        .unwrap();
+
+        assert_eq!(command.file, PathBuf::from("src/foo.c"));
+        assert!(command.arguments[0].contains("clang"));
+        assert!(command.arguments.contains(&"--sysroot".to_owned()));
+        assert!(command.arguments.contains(&"-O2".to_owned()));
+        assert!(command.arguments.contains(&"-DFOO".to_owned()));
+        assert_eq!(command.arguments.last().unwrap(), "src/foo.c");
+    }
+
+    #[test]
+    fn test_sysroot_override() {
+        let args = ["--sysroot=/opt/sysroot", "a.c"].map(String::from).to_vec();
+        let mut us = UserSettings::default();
+        let (pa, _) = prepare_compiler_args(args.clone(), &mut us, false).unwrap();
+        assert!(pa.compiler_args.is_empty());
+        assert_eq!(us.sysroot_location, None);
+
+        let mut us = UserSettings {
+            allow_sysroot_override: true,
+            ..Default::default()
+        };
+        prepare_compiler_args(args, &mut us, false).unwrap();
+        assert_eq!(us.sysroot_location, Some(PathBuf::from("/opt/sysroot")));
+
+        let args = ["--sysroot", "/opt/other", "a.c"]
+            .map(String::from)
+            .to_vec();
+        prepare_compiler_args(args, &mut us, false).unwrap();
+        assert_eq!(us.sysroot_location, Some(PathBuf::from("/opt/other")));
+    }
+
+    #[test]
+    fn test_prefix_dirs() {
+        let mut us = UserSettings::default();
+        let args = ["-B", "/opt/a", "-B/opt/b", "-Bstatic", "-lfoo", "a.c"];
+        let (pa, _) =
+            prepare_compiler_args(args.map(String::from).to_vec(), &mut us, false).unwrap();
+        assert_eq!(
+            pa.prefix_dirs,
+            [PathBuf::from("/opt/a"), PathBuf::from("/opt/b")]
+        );
+        assert!(pa.compiler_args.is_empty());
+        assert_eq!(
+            pa.positional_linker_args,
+            [(0, "-Bstatic".to_owned()), (0, "-lfoo".to_owned())]
+        );
+    }
+
+    #[test]
+    fn test_search_path_args() {
+        let lists = [OsString::from("/a:/b"), OsString::from(":/c:")];
+        assert_eq!(
+            search_path_args("-isystem", lists),
+            ["-isystem", "/a", "-isystem", "/b", "-isystem", "/c"].map(OsString::from)
+        );
+        assert!(search_path_args("-L", None).is_empty());
+    }
+
+    #[test]
+    fn test_target_flag() {
+        let mut user_settings = UserSettings::default();
+        assert_eq!(target_flag(&user_settings), "--target=wasm32-wasi");
+        user_settings.target_triple = Some("wasm32-wasip1-threads".to_owned());
+        assert_eq!(
+            target_flag(&user_settings),
+            "--target=wasm32-wasip1-threads"
+        );
+    }
+
+    #[test]
+    fn test_find_library_file() {
+        let fs = vfs::MemoryFileSystem::default().with_file("/sysroot/lib/wasm32-wasi/libc.a", "");
+        vfs::with_file_system(fs, || {
+            assert_eq!(
+                find_library_file(Path::new("/sysroot"), "libc.a"),
+                PathBuf::from("/sysroot/lib/wasm32-wasi/libc.a")
+            );
+            assert_eq!(
+                find_library_file(Path::new("/sysroot"), "libfoo.a"),
+                PathBuf::from("libfoo.a")
+            );
+        });
+    }
+
+    #[test]
+    fn test_is_suppressed() {
+        let suppressed = ["--import-memory".to_owned(), "-z stack-size".to_owned()];
+        assert!(is_suppressed("--import-memory", &suppressed));
+        assert!(is_suppressed("-z stack-size=8388608", &suppressed));
+        assert!(!is_suppressed("--import-memory-other", &suppressed));
+        assert!(!is_suppressed("--export-dynamic", &suppressed));
+    }
+
+    #[test]
+    fn test_strip() {
+        assert!(is_stripped_section(".debug_info", Strip::Debug, false));
+        assert!(!is_stripped_section("name", Strip::Debug, false));
+        assert!(is_stripped_section("name", Strip::All, false));
+        assert!(!is_stripped_section("name", Strip::All, true));
+        assert!(!is_stripped_section("dylink.0", Strip::All, false));
+        assert!(!is_stripped_section(".debug_line", Strip::None, false));
+
+        let mut us = UserSettings::default();
+        let args = ["-s", "a.c"].map(String::from).to_vec();
+        let (pa, _) = prepare_compiler_args(args, &mut us, false).unwrap();
+        assert_eq!(us.strip, Strip::All);
+        assert!(pa.compiler_args.is_empty());
+    }
+
+    #[test]
+    fn test_build_info() {
+        let us = UserSettings {
+            module_kind: Some(ModuleKind::SharedLibrary),
+            wasm_exceptions: true,
+            pic: true,
+            sysroot_prefix: PathBuf::from("/wasixcc"),
+            ..Default::default()
+        };
+        let fs = vfs::MemoryFileSystem::default()
+            .with_file("/wasixcc/sysroot-ehpic/.wasixcc-release", "v2025-01-01\n");
+        let info = vfs::with_file_system(fs, || {
+            build_info(&us, "wasm-opt version 123".to_owned()).unwrap()
+        });
+        assert_eq!(info.module_kind, ModuleKind::SharedLibrary);
+        assert_eq!(info.target, "wasm32-wasi");
+        assert!(info.wasm_exceptions && info.pic);
+        assert!(info.default_flags.contains(&"-fPIC"));
+        assert_eq!(info.sysroot.as_deref(), Some("v2025-01-01"));
+        assert_eq!(info.binaryen, "wasm-opt version 123");
+    }
+
+    #[test]
+    fn test_exports_dynamic() {
+        let mut us = UserSettings::default();
+        assert!(!exports_dynamic(&us, &[]));
+        us.module_kind = Some(ModuleKind::SharedLibrary);
+        assert!(exports_dynamic(&us, &[]));
+        assert!(!exports_dynamic(&us, &["--no-export-dynamic".to_owned()]));
+        us.export_dynamic = Some(false);
+        assert!(!exports_dynamic(&us, &[]));
+        us.module_kind = Some(ModuleKind::StaticMain);
+        us.export_dynamic = Some(true);
+        assert!(exports_dynamic(&us, &[]));
+    }
+
+    #[test]
+    fn test_is_omitted_library() {
+        let omitted = ["rt".to_owned(), "-lutil".to_owned()];
+        assert!(is_omitted_library("-lrt", &omitted));
+        assert!(is_omitted_library("-lutil", &omitted));
+        assert!(!is_omitted_library("-lresolv", &omitted));
+        assert!(!is_omitted_library("--rt", &omitted));
+    }
+
+    #[test]
+    fn test_injected_compiler_flags() {
+        let mut user_settings = UserSettings {
+            suppress_default_cflags: vec![
+                "-fno-trapping-math".to_owned(),
+                "-D_WASI_EMULATED_SIGNAL".to_owned(),
+            ],
+            ..Default::default()
+        };
+        let flags = injected_compiler_flags(&user_settings);
+        assert!(flags.contains(&"-matomics"));
+        assert!(flags.contains(&"-ftls-model=local-exec"));
+        assert!(!flags.contains(&"-fno-trapping-math"));
+        assert!(!flags.contains(&"-D_WASI_EMULATED_SIGNAL"));
+
+        user_settings.wasm_exceptions = true;
+        user_settings.pic = true;
+        let flags = injected_compiler_flags(&user_settings);
+        assert!(flags.contains(&"-fwasm-exceptions"));
+        assert!(flags.contains(&"-fPIC"));
+        assert!(!flags.contains(&"-ftls-model=local-exec"));
+    }
+
+    #[test]
+    fn test_tidy_args() {
+        let user_settings = UserSettings {
+            extra_compiler_flags: vec!["-DFOO".to_owned()],
+            ..Default::default()
+        };
+        let args = tidy_args(&user_settings, Path::new("/sysroot"));
+        assert_eq!(args[0], "--extra-arg-before=--sysroot=/sysroot");
+        assert_eq!(args[1], "--extra-arg-before=--target=wasm32-wasi");
+        assert!(args.contains(&"--extra-arg-before=-D_WASI_EMULATED_MMAN".to_owned()));
+        assert_eq!(args.last().unwrap(), "--extra-arg-before=-DFOO");
+    }
+
+    #[test]
+    fn test_wants_depfile_target() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert!(wants_depfile_target(&args(&["-c", "-MD"])));
+        assert!(!wants_depfile_target(&args(&["-c"])));
+        assert!(!wants_depfile_target(&args(&["-MMD", "-MT", "out.o"])));
+    }
+
+    #[test]
+    fn test_object_name() {
+        let args = [OsStr::new("-O2"), OsStr::new("-DFOO")];
+        let name = object_name(Path::new("src/main.c"), args).unwrap();
+        let name = name.to_str().unwrap();
+        assert!(name.starts_with("main.c."));
+        assert!(name.ends_with(".o"));
+        assert_eq!(name.len(), "main.c.".len() + 16 + ".o".len());
+
+        // Stable for the same input and arguments only
+        assert_eq!(object_name(Path::new("src/main.c"), args).unwrap(), name);
+        assert_ne!(
+            object_name(Path::new("src/main.c"), [OsStr::new("-O2")]).unwrap(),
+            name
+        );
+        assert_ne!(object_name(Path::new("test/main.c"), args).unwrap(), name);
+    }
+
+    #[test]
+    fn test_without_strip_flags() {
+        let args = vec!["-s".to_string(), "--gc-sections".to_string()];
+        let mut us = UserSettings::default();
+        assert_eq!(without_strip_flags(&args, &us), vec!["-s", "--gc-sections"]);
+        us.keep_names = true;
+        assert_eq!(without_strip_flags(&args, &us), vec!["--gc-sections"]);
+    }
+
+    #[test]
+    fn test_separate_dwarf() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let output = tmp.path().join("app.wasm");
+        let mut module = b"\0asm\x01\0\0\0\x0a\x01\x00".to_vec();
+        module.extend(wasm::encode_custom_section(".debug_info", b"dwarf"));
+        module.extend(wasm::encode_custom_section("name", b"names"));
+        std::fs::write(&output, &module).unwrap();
+
+        let mut args = prepare_linker_args(vec![], &mut UserSettings::default()).unwrap();
+        args.output = Some(output.clone());
+        let state = State {
+            user_settings: UserSettings::default(),
+            build_settings: BuildSettings {
+                opt_level: OptLevel::O0,
+                debug_level: DebugLevel::G2,
+                use_wasm_opt: false,
+                source_map: false,
+                run: false,
+                size_report: false,
+                syntax_only: false,
+                preprocess_only: false,
+                analyze: false,
+                emit_wat: false,
+            },
+            args,
+            cxx: false,
+            temp_dir: tmp.path().to_owned(),
+            opt_records: vec![],
+        };
+        separate_dwarf(&state).unwrap();
+
+        assert_eq!(
+            std::fs::read(tmp.path().join("app.wasm.debug.wasm")).unwrap(),
+            module
+        );
+        let stripped = std::fs::read(&output).unwrap();
+        let names = wasm::read_sections(&stripped)
+            .unwrap()
+            .into_iter()
+            .filter_map(|section| section.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["name", EXTERNAL_DEBUG_INFO_SECTION]);
+    }
+
+    #[test]
+    fn test_is_cxx_input() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert!(is_cxx_input(&args(&["-O2"]), Path::new("a.cpp")));
+        assert!(!is_cxx_input(&args(&["-O2"]), Path::new("a.c")));
+        assert!(is_cxx_input(&args(&["-x", "c++"]), Path::new("a.c")));
+        assert!(is_cxx_input(&args(&["-xc++-header"]), Path::new("a.h")));
+        assert!(!is_cxx_input(
+            &args(&["-x", "c++", "-xc"]),
+            Path::new("a.cc")
+        ));
+        assert!(is_cxx_input(
+            &args(&["-x", "c", "-x", "none"]),
+            Path::new("a.cc")
+        ));
+    }
+
+    #[test]
+    fn test_assembler_args() {
+        assert!(is_assembly_source(Path::new("start.s")));
+        assert!(is_preprocessed_assembly_source(Path::new("start.S")));
+        assert!(!is_preprocessed_assembly_source(Path::new("start.s")));
+        assert!(!is_assembly_source(Path::new("main.c")));
+
+        let mut user_settings = UserSettings::default();
+        let (args, build_settings) = prepare_compiler_args(
+            ["-c", "-DFOO", "start.S"].map(String::from).to_vec(),
+            &mut user_settings,
+            false,
+        )
+        .unwrap();
+        let state = State {
+            user_settings,
+            build_settings,
+            args,
+            cxx: false,
+            temp_dir: PathBuf::new(),
+            opt_records: vec![],
+        };
+        let has_arg = |args: &[OsString], arg: &str| args.iter().any(|a| a == arg);
+
+        let args = assembler_args(&state, Path::new("/sysroot"), true);
+        assert!(has_arg(&args, "-matomics"));
+        assert!(has_arg(&args, "-D_WASI_EMULATED_MMAN"));
+        assert!(has_arg(&args, "-DFOO"));
+        assert!(!has_arg(&args, "-fno-trapping-math"));
+        assert!(!has_arg(&args, "-ftls-model=local-exec"));
+
+        let args = assembler_args(&state, Path::new("/sysroot"), false);
+        assert!(!has_arg(&args, "-D_WASI_EMULATED_MMAN"));
+    }
+
     #[test]
     fn test_sysroot_prefix() {
         let mut us = UserSettings {
@@ -0,0 +1,79 @@
+//! Conan 2 profile for building packages with wasixcc, generated by
+//! `wasixccenv gen-conan-profile`.
+
+use std::collections::BTreeMap;
+
+// The LLVM major version wasixcc's toolchain is based on.
+const CLANG_VERSION: &str = "21";
+
+const BUILD_ENV: [(&str, &str); 6] = [
+    ("CC", "wasixcc"),
+    ("CXX", "wasix++"),
+    ("AR", "wasixar"),
+    ("RANLIB", "wasixranlib"),
+    ("NM", "wasixnm"),
+    ("LD", "wasixld"),
+];
+
+/// Generates a host profile. The given settings are passed to every compile
+/// and link as `-s` arguments.
+pub(crate) fn profile(settings: &BTreeMap<String, String>) -> String {
+    let mut contents = format!(
+        "# Generated by `wasixccenv gen-conan-profile`.\n\
+        [settings]\n\
+        os=WASI\n\
+        arch=wasm\n\
+        compiler=clang\n\
+        compiler.version={CLANG_VERSION}\n\
+        compiler.libcxx=libc++\n\
+        compiler.cppstd=17\n\
+        build_type=Release\n\
+        \n[conf]\n\
+        tools.build:compiler_executables={{\"c\": \"wasixcc\", \"cpp\": \"wasix++\"}}\n\
+        tools.cmake.cmaketoolchain:system_name=WASI\n\
+        tools.cmake.cmaketoolchain:system_processor=wasm32\n\
+        tools.gnu:host_triplet=wasm32-wasi\n"
+    );
+
+    if !settings.is_empty() {
+        let flags = settings
+            .iter()
+            .map(|(name, value)| quote(&format!("-s{name}={value}")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        for conf in ["cflags", "cxxflags", "sharedlinkflags", "exelinkflags"] {
+            contents.push_str(&format!("tools.build:{conf}=[{flags}]\n"));
+        }
+    }
+
+    contents.push_str("\n[buildenv]\n");
+    for (name, tool) in BUILD_ENV {
+        contents.push_str(&format!("{name}={tool}\n"));
+    }
+
+    contents
+}
+
+// Conf values are Python literals.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let contents = profile(&BTreeMap::new());
+        assert!(contents.contains("os=WASI\narch=wasm\n"));
+        assert!(contents.contains(
+            "tools.build:compiler_executables={\"c\": \"wasixcc\", \"cpp\": \"wasix++\"}\n"
+        ));
+        assert!(!contents.contains("tools.build:cflags"));
+        assert!(contents.ends_with("LD=wasixld\n"));
+
+        let settings = BTreeMap::from([("WASM_EXCEPTIONS".to_owned(), "yes".to_owned())]);
+        assert!(profile(&settings).contains("tools.build:cxxflags=[\"-sWASM_EXCEPTIONS=yes\"]\n"));
+    }
+}
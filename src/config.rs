@@ -0,0 +1,30 @@
+//! Project configuration, read from a `wasixcc.toml` file.
+
+use super::*;
+
+pub(crate) const CONFIG_FILE_NAME: &str = "wasixcc.toml";
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ProjectConfig {
+    pub matrix: Option<matrix::MatrixConfig>,
+    pub build: Option<project::BuildConfig>,
+}
+
+/// Looks for `wasixcc.toml` in the current directory and its ancestors.
+pub(crate) fn find_config_file() -> Option<PathBuf> {
+    let current_dir = std::env::current_dir().ok()?;
+    let fs = vfs::get();
+    current_dir
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|path| fs.is_file(path))
+}
+
+pub(crate) fn load_config(path: &Path) -> Result<ProjectConfig> {
+    let contents = vfs::get()
+        .read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse config file {}", path.display()))
+}
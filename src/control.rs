@@ -0,0 +1,179 @@
+//! Progress reporting over a control socket, configured through the
+//! CONTROL_SOCKET setting.
+//!
+//! The socket is expected to be a unix domain socket that an IDE or build
+//! server is already listening on. wasixcc connects to it and writes one JSON
+//! object per line for every build event. The other side may write a line
+//! containing `cancel` at any time, which kills the running tool and fails
+//! the build.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, ExitStatus},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::Stage;
+
+static CONTROL_SOCKET: OnceLock<ControlSocket> = OnceLock::new();
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub(crate) enum Event<'a> {
+    StageStarted { stage: Stage, detail: &'a str },
+    StageFinished { stage: Stage, success: bool },
+    Cancelled,
+    BuildFinished { success: bool },
+}
+
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+
+struct ControlSocket {
+    #[cfg(unix)]
+    writer: Mutex<Stream>,
+    cancelled: AtomicBool,
+}
+
+impl ControlSocket {
+    #[cfg(unix)]
+    fn connect(path: &Path) -> Result<(Self, Stream)> {
+        let stream = Stream::connect(path).with_context(|| {
+            format!("Failed to connect to control socket at {}", path.display())
+        })?;
+        let reader = stream
+            .try_clone()
+            .context("Failed to clone control socket")?;
+        let socket = Self {
+            writer: Mutex::new(stream),
+            cancelled: AtomicBool::new(false),
+        };
+        Ok((socket, reader))
+    }
+
+    #[cfg(not(unix))]
+    fn connect(path: &Path) -> Result<(Self, std::io::Empty)> {
+        bail!(
+            "Control sockets are only supported on unix systems; cannot connect to {}",
+            path.display()
+        )
+    }
+
+    fn send(&self, event: &Event) {
+        #[cfg(unix)]
+        {
+            let result = serde_json::to_string(event)
+                .context("Failed to serialize event")
+                .and_then(|mut line| {
+                    line.push('\n');
+                    let mut writer = self.writer.lock().unwrap();
+                    writer
+                        .write_all(line.as_bytes())
+                        .context("Failed to write to control socket")
+                });
+
+            // A client that went away shouldn't break the build.
+            if let Err(e) = result {
+                tracing::warn!("Failed to report {event:?}: {e:?}");
+            }
+        }
+    }
+
+    fn listen(&self, reader: impl std::io::Read) {
+        for line in BufReader::new(reader).lines() {
+            let Ok(line) = line else { break };
+            if line.trim() == "cancel" {
+                tracing::info!("Cancel requested through control socket");
+                self.cancelled.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Connects to the control socket. Events reported before this is called, or
+/// when it's never called, are silently dropped.
+pub(crate) fn connect(path: &Path) -> Result<()> {
+    let (socket, reader) = ControlSocket::connect(path)?;
+    if CONTROL_SOCKET.set(socket).is_err() {
+        bail!("Internal error: control socket connected twice");
+    }
+
+    let socket = CONTROL_SOCKET.get().unwrap();
+    std::thread::spawn(move || socket.listen(reader));
+
+    Ok(())
+}
+
+pub(crate) fn report(event: Event) {
+    if let Some(socket) = CONTROL_SOCKET.get() {
+        socket.send(&event);
+    }
+}
+
+pub(crate) fn is_connected() -> bool {
+    CONTROL_SOCKET.get().is_some()
+}
+
+/// Waits for the child to exit, killing it if a cancel message arrives in
+/// the meantime.
+pub(crate) fn wait_cancellable(child: &mut Child) -> Result<ExitStatus> {
+    let socket = CONTROL_SOCKET
+        .get()
+        .context("Internal error: control socket not connected")?;
+
+    loop {
+        if let Some(status) = child.try_wait().context("Failed to wait for command")? {
+            return Ok(status);
+        }
+
+        if socket.is_cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            socket.send(&Event::Cancelled);
+            bail!("Build cancelled through control socket");
+        }
+
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_control_socket_reports_and_cancels() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("control.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let (socket, reader) = ControlSocket::connect(&path).unwrap();
+        let (mut client, _) = listener.accept().unwrap();
+
+        socket.send(&Event::BuildFinished { success: true });
+        let mut line = String::new();
+        BufReader::new(client.try_clone().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        assert!(line.ends_with('\n'));
+
+        assert!(!socket.is_cancelled());
+        client.write_all(b"cancel\n").unwrap();
+        drop(client);
+        socket.listen(reader);
+        assert!(socket.is_cancelled());
+    }
+}
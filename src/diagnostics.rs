@@ -0,0 +1,66 @@
+//! Machine-readable diagnostics, configured through the DIAGNOSTICS_FORMAT
+//! setting.
+//!
+//! In JSON mode, clang reports its diagnostics as SARIF, and errors from
+//! wasixcc itself are written to stderr as a single JSON object per line
+//! instead of the usual human-readable message.
+
+use std::str::FromStr;
+
+use anyhow::{bail, Error};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DiagnosticsFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for DiagnosticsFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => bail!("Unknown diagnostics format: {s}; expected text or json"),
+        }
+    }
+}
+
+/// The clang flags to produce diagnostics in the given format.
+pub(crate) fn compiler_flags(format: DiagnosticsFormat) -> &'static [&'static str] {
+    match format {
+        DiagnosticsFormat::Text => &[],
+        // clang warns that the SARIF output is still subject to change on
+        // every invocation otherwise
+        DiagnosticsFormat::Json => &["-fdiagnostics-format=sarif", "-Wno-sarif-format-unstable"],
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ErrorRecord {
+    source: &'static str,
+    level: &'static str,
+    message: String,
+    /// The underlying errors, outermost first.
+    causes: Vec<String>,
+}
+
+pub(crate) fn print_error(format: DiagnosticsFormat, error: &Error) {
+    match format {
+        DiagnosticsFormat::Text => eprintln!("Error: {error:?}"),
+        DiagnosticsFormat::Json => {
+            let record = ErrorRecord {
+                source: "wasixcc",
+                level: "error",
+                message: error.to_string(),
+                causes: error.chain().skip(1).map(ToString::to_string).collect(),
+            };
+            match serde_json::to_string(&record) {
+                Ok(line) => eprintln!("{line}"),
+                Err(_) => eprintln!("Error: {error:?}"),
+            }
+        }
+    }
+}
@@ -1,24 +1,97 @@
-use std::{fmt::Display, fs, path::Path, str::FromStr};
+use std::{
+    fmt::Display,
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
 
 use anyhow::{bail, Context};
 use fs_extra::dir::CopyOptions;
 use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256};
 
-use crate::UserSettings;
+use crate::{compat, UserSettings};
 
 const LLVM_REPO: &str = "wasix-org/llvm-project";
 const SYSROOT_REPO: &str = "wasix-org/wasix-libc";
 const BINARYEN_REPO: &str = "WebAssembly/binaryen";
 
+const GITHUB_API_URL: &str = "https://api.github.com";
+const GITHUB_DOWNLOAD_URL: &str = "https://github.com";
+
+const SYSROOT_ASSET_NAMES: [&str; 3] = [
+    "sysroot.tar.gz",
+    "sysroot-eh.tar.gz",
+    "sysroot-ehpic.tar.gz",
+];
+
+// Release assets listing the checksums of the other assets, in the format
+// of `sha256sum`.
+const CHECKSUM_ASSET_NAMES: [&str; 3] = ["SHA256SUMS", "sha256sums.txt", "checksums.txt"];
+
+// Digests of release assets that can't be verified otherwise.
+const KNOWN_DIGESTS: &str = include_str!("known_digests.txt");
+
+// Failed downloads are retried with exponential backoff, starting from
+// DOWNLOAD_RETRY_DELAY.
+const DOWNLOAD_ATTEMPTS: u32 = 5;
+const DOWNLOAD_RETRY_DELAY: Duration = Duration::from_secs(1);
+// A download lock this old was left behind by a process that died.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(30 * 60);
+
 #[derive(serde::Deserialize)]
 struct GithubReleaseData {
+    tag_name: String,
     assets: Vec<GithubAsset>,
 }
 
+#[derive(serde::Deserialize)]
+struct GithubReleaseSummary {
+    tag_name: String,
+    #[serde(default)]
+    published_at: Option<String>,
+    #[serde(default)]
+    prerelease: bool,
+}
+
 #[derive(serde::Deserialize)]
 struct GithubAsset {
     browser_download_url: String,
     name: String,
+    /// Digest computed by GitHub, e.g. `sha256:<hex>`. Only available for
+    /// assets uploaded after GitHub started computing them.
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+/// A toolchain component that can be downloaded from GitHub releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Component {
+    Sysroot,
+    Llvm,
+    Binaryen,
+}
+
+impl Component {
+    pub(crate) const ALL: [Component; 3] = [Self::Sysroot, Self::Llvm, Self::Binaryen];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Sysroot => "sysroot",
+            Self::Llvm => "LLVM",
+            Self::Binaryen => "Binaryen",
+        }
+    }
+
+    fn repo(self) -> &'static str {
+        match self {
+            Self::Sysroot => SYSROOT_REPO,
+            Self::Llvm => LLVM_REPO,
+            Self::Binaryen => BINARYEN_REPO,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -33,6 +106,7 @@ fn get_llvm_asset_name() -> anyhow::Result<&'static str> {
         ("linux", "aarch64") => Ok("LLVM-Linux-aarch64.tar.gz"),
         ("macos", "x86_64") => Ok("LLVM-MacOS-x86_64.tar.gz"),
         ("macos", "aarch64") => Ok("LLVM-MacOS-aarch64.tar.gz"),
+        ("windows", "x86_64") => Ok("LLVM-Windows-x86_64.zip"),
         (os, arch) => {
             bail!("LLVM download for {} on {} is not supported", os, arch)
         }
@@ -45,6 +119,7 @@ fn get_binaryen_asset_suffix() -> anyhow::Result<&'static str> {
         ("linux", "aarch64") => Ok("-aarch64-linux.tar.gz"),
         ("macos", "x86_64") => Ok("-x86_64-macos.tar.gz"),
         ("macos", "aarch64") => Ok("-arm64-macos.tar.gz"),
+        ("windows", "x86_64") => Ok("-x86_64-windows.tar.gz"),
         (os, arch) => {
             bail!("Binaryen download for {} on {} is not supported", os, arch)
         }
@@ -84,14 +159,7 @@ impl Display for TagSpecGithubUrlPostfix<'_> {
     }
 }
 
-pub(crate) fn download_sysroot(
-    tag_spec: TagSpec,
-    user_settings: &UserSettings,
-) -> anyhow::Result<()> {
-    if user_settings.sysroot_location.is_some() {
-        tracing::warn!("SYSROOT_LOCATION is ignored when downloading sysroot");
-    }
-
+fn github_client(user_settings: &UserSettings) -> anyhow::Result<reqwest::blocking::Client> {
     let mut headers = HeaderMap::new();
 
     // Use API token if specified via env var.
@@ -101,23 +169,59 @@ pub(crate) fn download_sysroot(
         .map(|x| x.trim().to_string())
         .filter(|x| !x.is_empty());
 
-    if let Some(token) = gh_token {
+    // The token is for GitHub only, never send it to a mirror.
+    if let Some(token) = gh_token.filter(|_| user_settings.download_base_url.is_none()) {
         headers.insert("authorization", format!("Bearer {token}").parse()?);
     }
 
-    let client = reqwest::blocking::Client::builder()
+    let mut builder = reqwest::blocking::Client::builder()
         .default_headers(headers)
-        .user_agent("wasixcc")
-        .build()?;
+        .user_agent("wasixcc");
+
+    // Without an explicit proxy, reqwest uses the standard proxy env vars.
+    if let Some(proxy) = &user_settings.http_proxy {
+        builder = builder.proxy(
+            reqwest::Proxy::all(proxy).with_context(|| format!("Invalid HTTP_PROXY: {proxy}"))?,
+        );
+    }
+
+    let ca_bundle = user_settings
+        .ca_bundle
+        .clone()
+        .or_else(|| std::env::var_os("SSL_CERT_FILE").map(PathBuf::from));
+    if let Some(path) = ca_bundle {
+        let pem = fs::read(&path)
+            .with_context(|| format!("Failed to read CA bundle {}", path.display()))?;
+        let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Invalid CA bundle {}", path.display()))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
+fn fetch_release(
+    client: &reqwest::blocking::Client,
+    user_settings: &UserSettings,
+    repo: &str,
+    tag_spec: &TagSpec,
+) -> anyhow::Result<GithubReleaseData> {
+    let base_url = user_settings
+        .download_base_url
+        .as_deref()
+        .map(|url| url.trim_end_matches('/'));
 
     let release_url = format!(
-        "https://api.github.com/repos/{SYSROOT_REPO}/releases/{}",
+        "{}/repos/{repo}/releases/{}",
+        base_url.unwrap_or(GITHUB_API_URL),
         tag_spec.display_github_url_postfix()
     );
 
     eprintln!("Retrieving release info from {release_url} ...");
 
-    let release: GithubReleaseData = client
+    let mut release: GithubReleaseData = client
         .get(&release_url)
         .send()?
         .error_for_status()
@@ -125,18 +229,114 @@ pub(crate) fn download_sysroot(
         .json()
         .context("Could not deserialize release info")?;
 
-    for asset_name in [
-        "sysroot.tar.gz",
-        "sysroot-eh.tar.gz",
-        "sysroot-ehpic.tar.gz",
-    ] {
+    if let Some(base_url) = base_url {
+        for asset in &mut release.assets {
+            asset.browser_download_url = mirror_url(&asset.browser_download_url, base_url);
+        }
+    }
+
+    Ok(release)
+}
+
+// Mirrors serve assets under the same paths as github.com. URLs that already
+// point elsewhere (e.g. rewritten by the mirror itself) are left alone.
+fn mirror_url(url: &str, base_url: &str) -> String {
+    match url.strip_prefix(GITHUB_DOWNLOAD_URL) {
+        Some(path) => format!("{base_url}{path}"),
+        None => url.to_owned(),
+    }
+}
+
+// Number of releases listed per component; GitHub returns the newest first.
+const LISTED_RELEASES: u32 = 20;
+
+/// Prints the most recent release tags of each component, along with their
+/// publication dates.
+pub(crate) fn list_versions(
+    components: &[Component],
+    user_settings: &UserSettings,
+) -> anyhow::Result<()> {
+    let client = github_client(user_settings)?;
+    let base_url = user_settings
+        .download_base_url
+        .as_deref()
+        .map(|url| url.trim_end_matches('/'))
+        .unwrap_or(GITHUB_API_URL);
+
+    for (i, component) in components.iter().enumerate() {
+        let releases_url = format!(
+            "{base_url}/repos/{}/releases?per_page={LISTED_RELEASES}",
+            component.repo()
+        );
+        let releases: Vec<GithubReleaseSummary> = client
+            .get(&releases_url)
+            .send()?
+            .error_for_status()
+            .with_context(|| format!("Could not list {} releases", component.name()))?
+            .json()
+            .context("Could not deserialize release list")?;
+
+        if i > 0 {
+            println!();
+        }
+        println!("{} ({}):", component.name(), component.repo());
+        for line in release_lines(&releases) {
+            println!("  {line}");
+        }
+    }
+
+    Ok(())
+}
+
+// The first non-prerelease is what 'latest' resolves to.
+fn release_lines(releases: &[GithubReleaseSummary]) -> Vec<String> {
+    let tag_width = releases
+        .iter()
+        .map(|r| r.tag_name.len())
+        .max()
+        .unwrap_or_default();
+    let latest = releases.iter().position(|r| !r.prerelease);
+
+    releases
+        .iter()
+        .enumerate()
+        .map(|(i, release)| {
+            // Dates are in RFC 3339 format; the day is enough here.
+            let date = release
+                .published_at
+                .as_deref()
+                .map(|date| date.get(..10).unwrap_or(date))
+                .unwrap_or("unpublished");
+            let mut line = format!("{:tag_width$}  {date}", release.tag_name);
+            if release.prerelease {
+                line.push_str("  (prerelease)");
+            } else if Some(i) == latest {
+                line.push_str("  (latest)");
+            }
+            line
+        })
+        .collect()
+}
+
+pub(crate) fn download_sysroot(
+    tag_spec: TagSpec,
+    user_settings: &UserSettings,
+) -> anyhow::Result<()> {
+    if user_settings.sysroot_location.is_some() {
+        tracing::warn!("SYSROOT_LOCATION is ignored when downloading sysroot");
+    }
+
+    let client = github_client(user_settings)?;
+    let release = fetch_release(&client, user_settings, SYSROOT_REPO, &tag_spec)?;
+
+    for asset_name in SYSROOT_ASSET_NAMES {
         let asset = release
             .assets
             .iter()
             .find(|a| a.name == asset_name)
             .with_context(|| format!("Could not find asset '{asset_name}' in release"))?;
 
-        download_and_unpack_sysroot(asset, &user_settings.sysroot_prefix, &client).with_context(
+        download_and_unpack_sysroot(asset, &release, user_settings, &client).with_context(
             || format!("Failed to download and unpack sysroot asset '{asset_name}'"),
         )?;
     }
@@ -148,9 +348,11 @@ pub(crate) fn download_llvm(tag_spec: TagSpec, user_settings: &UserSettings) ->
     // Determine the asset name based on OS and architecture
     let asset_name = get_llvm_asset_name()?;
 
-    let target_dir = match user_settings.llvm_location {
-        crate::LlvmLocation::DefaultPath(ref path)
-        | crate::LlvmLocation::UserProvided(ref path) => path,
+    let Some(target_dir) = user_settings.llvm_location.install_path() else {
+        bail!(
+            "LLVM_SYSTEM_VERSION is set, so the system's LLVM is used; install it with \
+            the system's package manager instead"
+        );
     };
 
     if !target_dir.exists() {
@@ -163,38 +365,8 @@ pub(crate) fn download_llvm(tag_spec: TagSpec, user_settings: &UserSettings) ->
     }
     let target_dir = target_dir.to_path_buf();
 
-    let mut headers = HeaderMap::new();
-
-    // Use API token if specified via env var.
-    // Prevents 403 errors when IP is throttled by Github API.
-    let gh_token = std::env::var("GITHUB_TOKEN")
-        .ok()
-        .map(|x| x.trim().to_string())
-        .filter(|x| !x.is_empty());
-
-    if let Some(token) = gh_token {
-        headers.insert("authorization", format!("Bearer {token}").parse()?);
-    }
-
-    let client = reqwest::blocking::Client::builder()
-        .default_headers(headers)
-        .user_agent("wasixcc")
-        .build()?;
-
-    let release_url = format!(
-        "https://api.github.com/repos/{LLVM_REPO}/releases/{}",
-        tag_spec.display_github_url_postfix()
-    );
-
-    eprintln!("Retrieving release info from {release_url} ...");
-
-    let release: GithubReleaseData = client
-        .get(&release_url)
-        .send()?
-        .error_for_status()
-        .context("Could not download release info")?
-        .json()
-        .context("Could not deserialize release info")?;
+    let client = github_client(user_settings)?;
+    let release = fetch_release(&client, user_settings, LLVM_REPO, &tag_spec)?;
 
     let asset = release
         .assets
@@ -202,9 +374,12 @@ pub(crate) fn download_llvm(tag_spec: TagSpec, user_settings: &UserSettings) ->
         .find(|a| a.name == asset_name)
         .with_context(|| format!("Could not find asset '{asset_name}' in release"))?;
 
-    download_asset(asset, &target_dir, &client)
+    download_asset(asset, &release, &target_dir, user_settings, &client)
         .with_context(|| format!("Failed to download and unpack sysroot asset '{asset_name}'"))?;
+    compat::write_release_tag(&target_dir, &release.tag_name)?;
 
+    // Windows has no executable bits to fix up
+    #[cfg(unix)]
     {
         use std::os::unix::fs::PermissionsExt;
         for entry in
@@ -253,38 +428,8 @@ pub(crate) fn download_binaryen(
     }
     let target_dir = target_dir.to_path_buf();
 
-    let mut headers = HeaderMap::new();
-
-    // Use API token if specified via env var.
-    // Prevents 403 errors when IP is throttled by Github API.
-    let gh_token = std::env::var("GITHUB_TOKEN")
-        .ok()
-        .map(|x| x.trim().to_string())
-        .filter(|x| !x.is_empty());
-
-    if let Some(token) = gh_token {
-        headers.insert("authorization", format!("Bearer {token}").parse()?);
-    }
-
-    let client = reqwest::blocking::Client::builder()
-        .default_headers(headers)
-        .user_agent("wasixcc")
-        .build()?;
-
-    let release_url = format!(
-        "https://api.github.com/repos/{BINARYEN_REPO}/releases/{}",
-        tag_spec.display_github_url_postfix()
-    );
-
-    eprintln!("Retrieving release info from {release_url} ...");
-
-    let release: GithubReleaseData = client
-        .get(&release_url)
-        .send()?
-        .error_for_status()
-        .context("Could not download release info")?
-        .json()
-        .context("Could not deserialize release info")?;
+    let client = github_client(user_settings)?;
+    let release = fetch_release(&client, user_settings, BINARYEN_REPO, &tag_spec)?;
 
     // Find the asset that matches our platform
     // Asset names are like: binaryen-version_124-x86_64-linux.tar.gz
@@ -292,11 +437,9 @@ pub(crate) fn download_binaryen(
         .assets
         .iter()
         .find(|a| a.name.ends_with(&asset_suffix))
-        .with_context(|| {
-            format!("Could not find binaryen asset for the current platform in release")
-        })?;
+        .context("Could not find binaryen asset for the current platform in release")?;
 
-    download_asset(asset, &target_dir, &client)
+    download_asset(asset, &release, &target_dir, user_settings, &client)
         .with_context(|| format!("Failed to download and unpack asset '{}'", asset.name))?;
 
     // Extract version from the asset name to know the directory name
@@ -308,7 +451,7 @@ pub(crate) fn download_binaryen(
         .with_context(|| format!("Could not extract version from asset name '{}'", asset.name))?;
 
     // Move files from the binaryen-version_{version} to the binaryen target dir.
-    let entries = fs::read_dir(target_dir.join(format!("binaryen-version_{}", version_str)))
+    let entries = fs::read_dir(target_dir.join(format!("binaryen-version_{version_str}")))
         .with_context(|| "Failed to read bin directory")?;
     for entry in entries {
         let entry = entry.with_context(|| "Failed to read bin directory entry")?;
@@ -317,15 +460,17 @@ pub(crate) fn download_binaryen(
         fs::rename(entry.path(), target_dir.join(entry.file_name()))
             .with_context(|| "Failed to move binaryen file to target directory")?;
     }
-    fs::remove_dir_all(target_dir.join(format!("binaryen-version_{}", version_str)))
+    fs::remove_dir_all(target_dir.join(format!("binaryen-version_{version_str}")))
         .with_context(|| "Failed to remove temporary binaryen directory")?;
 
+    // Windows has no executable bits to fix up
+    #[cfg(unix)]
     {
         use std::os::unix::fs::PermissionsExt;
         eprintln!("Target dir: {}", target_dir.display());
 
-        for entry in std::fs::read_dir(target_dir.join(format!("bin")))
-            .context("Failed to read bin directory")?
+        for entry in
+            std::fs::read_dir(target_dir.join("bin")).context("Failed to read bin directory")?
         {
             let entry = entry.context("Failed to read bin directory entry")?;
             if entry
@@ -351,40 +496,431 @@ pub(crate) fn download_binaryen(
 
 fn download_asset(
     asset: &GithubAsset,
+    release: &GithubReleaseData,
     target_dir: &Path,
+    user_settings: &UserSettings,
     client: &reqwest::blocking::Client,
 ) -> anyhow::Result<()> {
+    let (mut archive, _) = download_verified_asset(asset, release, user_settings, client)?;
+    let format = ArchiveFormat::detect(&asset.name, &mut archive)?;
+
+    format.unpack(archive, target_dir)?;
+
+    #[cfg(target_os = "macos")]
+    clear_quarantine(target_dir);
+
+    Ok(())
+}
+
+// Gatekeeper refuses to run unsigned binaries carrying the quarantine
+// attribute, which archives fetched through a quarantining proxy or a
+// mirror synced by a browser pass on to their contents.
+#[cfg(target_os = "macos")]
+fn clear_quarantine(dir: &Path) {
+    match std::process::Command::new("xattr")
+        .args(["-dr", "com.apple.quarantine"])
+        .arg(dir)
+        .output()
+    {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => tracing::debug!(
+            "Failed to clear quarantine attributes in {}: {}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        Err(e) => tracing::debug!("Failed to run xattr: {e}"),
+    }
+}
+
+/// The archive formats release assets come in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    TarGz,
+    TarXz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The format of a downloaded asset, from its name or, failing that, from
+    /// the first bytes of the file, which is rewound afterwards.
+    fn detect(asset_name: &str, file: &mut fs::File) -> anyhow::Result<Self> {
+        if let Some(format) = Self::from_extension(asset_name) {
+            return Ok(format);
+        }
+
+        let mut magic = Vec::new();
+        file.take(6)
+            .read_to_end(&mut magic)
+            .context("Failed to read downloaded asset")?;
+        file.seek(SeekFrom::Start(0))
+            .context("Failed to read downloaded asset")?;
+        Self::from_magic(&magic)
+            .with_context(|| format!("Unsupported archive format of asset '{asset_name}'"))
+    }
+
+    fn from_extension(asset_name: &str) -> Option<Self> {
+        if asset_name.ends_with(".tar.gz") || asset_name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if asset_name.ends_with(".tar.xz") || asset_name.ends_with(".txz") {
+            Some(Self::TarXz)
+        } else if asset_name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+
+    fn from_magic(magic: &[u8]) -> Option<Self> {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Some(Self::TarGz)
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Self::TarXz)
+        } else if magic.starts_with(b"PK\x03\x04") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+
+    fn unpack(self, archive: fs::File, target_dir: &Path) -> anyhow::Result<()> {
+        match self {
+            Self::TarGz => tar::Archive::new(flate2::read::GzDecoder::new(archive))
+                .unpack(target_dir)
+                .context("Failed to unpack asset"),
+            Self::TarXz => tar::Archive::new(xz2::read::XzDecoder::new(archive))
+                .unpack(target_dir)
+                .context("Failed to unpack asset"),
+            Self::Zip => zip::ZipArchive::new(archive)
+                .and_then(|mut archive| archive.extract(target_dir))
+                .context("Failed to unpack asset"),
+        }
+    }
+}
+
+// Downloads an asset into the download cache and verifies its checksum before
+// anything is unpacked from it. Returns the file, rewound to the start, along
+// with the asset's SHA-256 digest.
+fn download_verified_asset(
+    asset: &GithubAsset,
+    release: &GithubReleaseData,
+    user_settings: &UserSettings,
+    client: &reqwest::blocking::Client,
+) -> anyhow::Result<(fs::File, String)> {
+    let expected_digest = expected_digest(asset, release, client)?;
+    if expected_digest.is_none() {
+        if user_settings.require_checksums {
+            bail!(
+                "No checksum is known for asset '{}' of release {}, so it can't be verified \
+                as REQUIRE_CHECKSUMS asks",
+                asset.name,
+                release.tag_name
+            );
+        }
+        eprintln!(
+            "Warning: no checksum is known for asset '{}', installing it unverified",
+            asset.name
+        );
+    }
+
+    let cache_dir = download_cache_dir();
+    fs::create_dir_all(&cache_dir)
+        .with_context(|| format!("Failed to create download cache at {}", cache_dir.display()))?;
+    let partial_path = cache_dir.join(partial_download_name(asset));
+
     eprintln!(
         "Downloading asset '{}' from url '{}'...",
         asset.name, asset.browser_download_url
     );
-    let res = client
-        .get(&asset.browser_download_url)
-        .send()?
-        .error_for_status()?;
 
-    let decoder = flate2::read::GzDecoder::new(res);
-    let mut archive = tar::Archive::new(decoder);
+    let resumed = partial_path.exists();
+    download_with_retries(client, &asset.browser_download_url, &partial_path)?;
+    let (mut file, mut digest) = open_and_hash(&partial_path)?;
+
+    if resumed && expected_digest.as_ref().is_some_and(|d| *d != digest) {
+        // The partial download may have been left by a different version of
+        // the asset, so start over before giving up.
+        eprintln!(
+            "Resumed download of asset '{}' is corrupted, downloading it again...",
+            asset.name
+        );
+        fs::remove_file(&partial_path).context("Failed to remove partial download")?;
+        download_with_retries(client, &asset.browser_download_url, &partial_path)?;
+        (file, digest) = open_and_hash(&partial_path)?;
+    }
+
+    match expected_digest {
+        Some(expected) if expected != digest => {
+            let _ = fs::remove_file(&partial_path);
+            bail!(
+                "Checksum mismatch for asset '{}': expected SHA-256 {expected}, got {digest}. \
+                The download may be corrupted or tampered with; please try again.",
+                asset.name
+            )
+        }
+        Some(_) => tracing::info!("Verified checksum of asset '{}'", asset.name),
+        None => (),
+    }
+
+    // The open file stays readable on unix. Elsewhere, the complete download
+    // stays in the cache, and the next download of it is a no-op resume.
+    let _ = fs::remove_file(&partial_path);
+
+    file.seek(SeekFrom::Start(0))
+        .context("Failed to read downloaded asset")?;
+    Ok((file, digest))
+}
+
+/// Held while downloading on demand, so that concurrent builds (e.g. under
+/// `make -j`) take turns instead of downloading into the same place at once.
+pub(crate) struct DownloadLock(PathBuf);
+
+impl DownloadLock {
+    pub(crate) fn acquire() -> anyhow::Result<Self> {
+        let dir = download_cache_dir();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create download cache at {}", dir.display()))?;
+        let path = dir.join("download.lock");
+
+        let mut waiting = false;
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(_) => return Ok(Self(path)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let age = fs::metadata(&path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+                    if age.is_some_and(|age| age > STALE_LOCK_AGE) {
+                        let _ = fs::remove_file(&path);
+                        continue;
+                    }
+                    if !waiting {
+                        eprintln!("Waiting for another build to finish downloading...");
+                        waiting = true;
+                    }
+                    std::thread::sleep(Duration::from_millis(500));
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create {}", path.display()))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for DownloadLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+pub(crate) fn download_cache_dir() -> PathBuf {
+    std::env::home_dir()
+        .map(|home| home.join(".wasixcc/cache/downloads"))
+        .unwrap_or_else(|| std::env::temp_dir().join("wasixcc-downloads"))
+}
+
+// Partial downloads are named after the asset and its URL, so different
+// releases of an asset never resume each other.
+fn partial_download_name(asset: &GithubAsset) -> String {
+    let url_hash = format!(
+        "{:x}",
+        Sha256::digest(asset.browser_download_url.as_bytes())
+    );
+    format!("{}.{}.part", asset.name, &url_hash[..16])
+}
+
+fn open_and_hash(path: &Path) -> anyhow::Result<(fs::File, String)> {
+    let mut reader = HashingReader {
+        inner: fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?,
+        hasher: Sha256::new(),
+    };
+    std::io::copy(&mut reader, &mut std::io::sink()).context("Failed to read downloaded asset")?;
+    Ok((reader.inner, format!("{:x}", reader.hasher.finalize())))
+}
+
+fn download_with_retries(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let mut delay = DOWNLOAD_RETRY_DELAY;
+    for attempt in 1.. {
+        match download_to_file(client, url, path) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < DOWNLOAD_ATTEMPTS && is_retryable(&e) => {
+                eprintln!(
+                    "Download failed ({e:#}), retrying in {} seconds...",
+                    delay.as_secs()
+                );
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+// Downloads `url` into `path`, resuming from the end of `path` if it exists.
+fn download_to_file(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let existing_len = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+
+    let mut request = client.get(url);
+    if existing_len > 0 {
+        request = request.header("Range", format!("bytes={existing_len}-"));
+    }
+    let res = request.send()?;
 
-    archive
-        .unpack(target_dir)
-        .context("Failed to unpack asset")?;
+    // 416 Range Not Satisfiable: the file is already complete
+    if existing_len > 0 && res.status().as_u16() == 416 {
+        return Ok(());
+    }
+    let mut res = res.error_for_status()?;
+
+    // 206 Partial Content: the server honored the range
+    let mut file = if existing_len > 0 && res.status().as_u16() == 206 {
+        eprintln!("Resuming download after {existing_len} bytes");
+        fs::OpenOptions::new().append(true).open(path)
+    } else {
+        fs::File::create(path)
+    }
+    .with_context(|| format!("Failed to open {}", path.display()))?;
 
+    std::io::copy(&mut res, &mut file).context("Failed to download asset")?;
     Ok(())
 }
 
+// Errors are worth retrying unless the server rejected the request itself.
+fn is_retryable(error: &anyhow::Error) -> bool {
+    let status = error
+        .downcast_ref::<reqwest::Error>()
+        .and_then(|e| e.status());
+    match status {
+        Some(status) => {
+            let code = status.as_u16();
+            !(400..500).contains(&code) || code == 408 || code == 429
+        }
+        None => true,
+    }
+}
+
+// The expected SHA-256 of an asset, from the built-in manifest, GitHub's own
+// digest of it or a checksum asset in the same release.
+fn expected_digest(
+    asset: &GithubAsset,
+    release: &GithubReleaseData,
+    client: &reqwest::blocking::Client,
+) -> anyhow::Result<Option<String>> {
+    if let Some(digest) = known_digest(KNOWN_DIGESTS, &release.tag_name, &asset.name) {
+        return Ok(Some(digest));
+    }
+
+    if let Some(digest) = asset
+        .digest
+        .as_deref()
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+    {
+        return Ok(Some(digest.to_lowercase()));
+    }
+
+    let sidecar_name = format!("{}.sha256", asset.name);
+    for checksum_asset in release
+        .assets
+        .iter()
+        .filter(|a| a.name == sidecar_name || CHECKSUM_ASSET_NAMES.contains(&a.name.as_str()))
+    {
+        let contents = client
+            .get(&checksum_asset.browser_download_url)
+            .send()?
+            .error_for_status()?
+            .text()
+            .with_context(|| format!("Failed to download checksums '{}'", checksum_asset.name))?;
+        if let Some(digest) = parse_checksums(&contents, &asset.name) {
+            return Ok(Some(digest));
+        }
+    }
+
+    Ok(None)
+}
+
+// Finds the checksum of `asset_name` in the output of `sha256sum`. A file
+// with a lone checksum is taken to be for the asset.
+fn parse_checksums(contents: &str, asset_name: &str) -> Option<String> {
+    let is_digest = |s: &str| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit());
+
+    contents.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let digest = parts.next().filter(|digest| is_digest(digest))?;
+        match parts.next() {
+            // `*` marks files hashed in binary mode
+            Some(name) => {
+                let name = name.trim_start_matches('*');
+                (name == asset_name || name.ends_with(&format!("/{asset_name}")))
+                    .then(|| digest.to_lowercase())
+            }
+            None => Some(digest.to_lowercase()),
+        }
+    })
+}
+
+fn known_digest(manifest: &str, tag: &str, asset_name: &str) -> Option<String> {
+    parse_checksums(manifest, &format!("{tag}/{asset_name}"))
+}
+
 fn download_and_unpack_sysroot(
     asset: &GithubAsset,
-    target_dir: &Path,
+    release: &GithubReleaseData,
+    user_settings: &UserSettings,
     client: &reqwest::blocking::Client,
 ) -> anyhow::Result<()> {
+    let target_dir = &user_settings.sysroot_prefix;
+
     // Unpack to a temp dir, since we need to re-organize the contents.
-    let temp_dir = tempfile::TempDir::new().context("Failed to create temporary directory")?;
+    let temp_dir = user_settings.create_temp_dir()?;
 
-    download_asset(asset, temp_dir.path(), client)?;
+    download_asset(asset, release, temp_dir.path(), user_settings, client)?;
 
+    let (postfix, asset_dir) = find_unpacked_sysroot(temp_dir.path())?;
+
+    std::fs::create_dir_all(target_dir).context("Failed to create target directory")?;
+
+    let final_dir = target_dir.join(format!("sysroot{postfix}"));
+    if final_dir.exists() {
+        std::fs::remove_dir_all(&final_dir).with_context(|| {
+            format!(
+                "Failed to remove existing sysroot directory at {}",
+                final_dir.display(),
+            )
+        })?;
+    }
+
+    move_dir(asset_dir.join("sysroot"), &final_dir)?;
+    compat::write_release_tag(&final_dir, &release.tag_name)?;
+
+    eprintln!(
+        "Downloaded sysroot asset '{}' to '{}'",
+        asset.name,
+        final_dir.display()
+    );
+
+    Ok(())
+}
+
+// Sysroot assets contain a single `wasix-sysroot<postfix>` directory, where
+// the postfix identifies the variant (e.g. `-eh`).
+fn find_unpacked_sysroot(unpacked_dir: &Path) -> anyhow::Result<(String, PathBuf)> {
     // A few sanity checks can't hurt...
-    let dirs = std::fs::read_dir(temp_dir.path())
+    let dirs = std::fs::read_dir(unpacked_dir)
         .context("Failed to read unpacked asset directory")?
         .collect::<Result<Vec<_>, _>>()
         .context("Failed to collect unpacked asset entries")?;
@@ -406,27 +942,96 @@ fn download_and_unpack_sysroot(
             )
         })?;
 
-    std::fs::create_dir_all(target_dir).context("Failed to create target directory")?;
+    Ok((postfix.to_owned(), dirs[0].path()))
+}
 
-    let final_dir = target_dir.join(format!("sysroot{postfix}"));
+/// A sysroot variant stored in the content-addressed store.
+#[derive(Debug)]
+pub(crate) struct StoredSysroot {
+    /// Name of the variant, e.g. `sysroot-eh`.
+    pub name: String,
+    /// SHA-256 digest of the variant's release asset, which is also the
+    /// name of its directory in the store.
+    pub digest: String,
+}
+
+/// Downloads all sysroot variants of a release into `store_dir`. Returns the
+/// release's tag along with the stored variants.
+pub(crate) fn download_sysroot_to_store(
+    tag_spec: &TagSpec,
+    store_dir: &Path,
+    user_settings: &UserSettings,
+) -> anyhow::Result<(String, Vec<StoredSysroot>)> {
+    let client = github_client(user_settings)?;
+    let release = fetch_release(&client, user_settings, SYSROOT_REPO, tag_spec)?;
+
+    let mut stored = Vec::new();
+    for asset_name in SYSROOT_ASSET_NAMES {
+        let asset = release
+            .assets
+            .iter()
+            .find(|a| a.name == asset_name)
+            .with_context(|| format!("Could not find asset '{asset_name}' in release"))?;
+
+        stored.push(
+            download_sysroot_asset_to_store(asset, &release, store_dir, user_settings, &client)
+                .with_context(|| {
+                    format!("Failed to download sysroot asset '{asset_name}' into the store")
+                })?,
+        );
+    }
+
+    Ok((release.tag_name, stored))
+}
+
+fn download_sysroot_asset_to_store(
+    asset: &GithubAsset,
+    release: &GithubReleaseData,
+    store_dir: &Path,
+    user_settings: &UserSettings,
+    client: &reqwest::blocking::Client,
+) -> anyhow::Result<StoredSysroot> {
+    std::fs::create_dir_all(store_dir)
+        .with_context(|| format!("Failed to create sysroot store at {}", store_dir.display()))?;
+
+    // Unpack inside the store, so moving the result into place is a rename.
+    let temp_dir =
+        tempfile::TempDir::new_in(store_dir).context("Failed to create temporary directory")?;
+
+    let (mut archive, digest) = download_verified_asset(asset, release, user_settings, client)?;
+    ArchiveFormat::detect(&asset.name, &mut archive)?.unpack(archive, temp_dir.path())?;
+
+    let (postfix, asset_dir) = find_unpacked_sysroot(temp_dir.path())?;
+    let final_dir = store_dir.join(&digest);
     if final_dir.exists() {
-        std::fs::remove_dir_all(&final_dir).with_context(|| {
-            format!(
-                "Failed to remove existing sysroot directory at {}",
-                final_dir.display(),
-            )
-        })?;
+        eprintln!("Sysroot asset '{}' is already in the store", asset.name);
+    } else {
+        move_dir(asset_dir.join("sysroot"), &final_dir)?;
+        compat::write_release_tag(&final_dir, &release.tag_name)?;
+        eprintln!(
+            "Stored sysroot asset '{}' at '{}'",
+            asset.name,
+            final_dir.display()
+        );
     }
 
-    move_dir(dirs[0].path().join("sysroot"), &final_dir)?;
+    Ok(StoredSysroot {
+        name: format!("sysroot{postfix}"),
+        digest,
+    })
+}
 
-    eprintln!(
-        "Downloaded sysroot asset '{}' to '{}'",
-        asset.name,
-        final_dir.display()
-    );
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
 
-    Ok(())
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
 }
 
 fn move_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> anyhow::Result<()> {
@@ -453,3 +1058,141 @@ fn move_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> anyhow::Result<()>
         Err(e) => Err(e).context("Failed to move directory"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_macos_asset_names() {
+        assert!(get_llvm_asset_name().unwrap().starts_with("LLVM-MacOS-"));
+        assert!(get_binaryen_asset_suffix()
+            .unwrap()
+            .ends_with("-macos.tar.gz"));
+    }
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(
+            ArchiveFormat::from_extension("LLVM-Linux-x86_64.tar.gz"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_extension("LLVM-Linux-x86_64.tar.xz"),
+            Some(ArchiveFormat::TarXz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_extension("LLVM-Windows-x86_64.zip"),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(ArchiveFormat::from_extension("sysroot"), None);
+
+        assert_eq!(
+            ArchiveFormat::from_magic(&[0x1f, 0x8b, 0x08, 0x00]),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_magic(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+            Some(ArchiveFormat::TarXz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_magic(b"PK\x03\x04\x14\x00"),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(ArchiveFormat::from_magic(b"\x28\xb5\x2f\xfd"), None);
+    }
+
+    #[test]
+    fn test_partial_download_name() {
+        let asset = |url: &str| GithubAsset {
+            browser_download_url: url.to_owned(),
+            name: "sysroot.tar.gz".to_owned(),
+            digest: None,
+        };
+        let name = partial_download_name(&asset("https://example.com/v1/sysroot.tar.gz"));
+        assert!(name.starts_with("sysroot.tar.gz."));
+        assert!(name.ends_with(".part"));
+        assert_eq!(
+            partial_download_name(&asset("https://example.com/v1/sysroot.tar.gz")),
+            name
+        );
+        assert_ne!(
+            partial_download_name(&asset("https://example.com/v2/sysroot.tar.gz")),
+            name
+        );
+    }
+
+    #[test]
+    fn test_release_lines() {
+        let release = |tag: &str, date: Option<&str>, prerelease| GithubReleaseSummary {
+            tag_name: tag.to_owned(),
+            published_at: date.map(str::to_owned),
+            prerelease,
+        };
+        let releases = [
+            release("v2025-02-01.1-rc", Some("2025-02-01T10:00:00Z"), true),
+            release("v2025-01-01.1", Some("2025-01-01T10:00:00Z"), false),
+            release("v2024-12-01.1", None, false),
+        ];
+
+        assert_eq!(
+            release_lines(&releases),
+            [
+                "v2025-02-01.1-rc  2025-02-01  (prerelease)",
+                "v2025-01-01.1     2025-01-01  (latest)",
+                "v2024-12-01.1     unpublished",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mirror_url() {
+        assert_eq!(
+            mirror_url(
+                "https://github.com/wasix-org/wasix-libc/releases/download/v1/sysroot.tar.gz",
+                "https://mirror.example.com/github"
+            ),
+            "https://mirror.example.com/github/wasix-org/wasix-libc/releases/download/v1/sysroot.tar.gz"
+        );
+        assert_eq!(
+            mirror_url(
+                "https://cdn.example.com/sysroot.tar.gz",
+                "https://mirror.example.com"
+            ),
+            "https://cdn.example.com/sysroot.tar.gz"
+        );
+    }
+
+    #[test]
+    fn test_parse_checksums() {
+        let a = "a".repeat(64);
+        let b = "B".repeat(64);
+        let sums = format!("{a}  sysroot.tar.gz\n{b} *dist/sysroot-eh.tar.gz\n");
+        assert_eq!(parse_checksums(&sums, "sysroot.tar.gz"), Some(a.clone()));
+        assert_eq!(
+            parse_checksums(&sums, "sysroot-eh.tar.gz"),
+            Some(b.to_lowercase())
+        );
+        assert_eq!(parse_checksums(&sums, "sysroot-ehpic.tar.gz"), None);
+
+        // A `.sha256` file with just the checksum
+        assert_eq!(parse_checksums(&format!("{a}\n"), "llvm.tar.gz"), Some(a));
+        assert_eq!(parse_checksums("not a checksum\n", "llvm.tar.gz"), None);
+    }
+
+    #[test]
+    fn test_known_digest() {
+        let a = "a".repeat(64);
+        let manifest = format!("# comment\n{a}  v1/LLVM-Linux-x86_64.tar.gz\n");
+        assert_eq!(
+            known_digest(&manifest, "v1", "LLVM-Linux-x86_64.tar.gz"),
+            Some(a)
+        );
+        assert_eq!(
+            known_digest(&manifest, "v2", "LLVM-Linux-x86_64.tar.gz"),
+            None
+        );
+        assert_eq!(known_digest(KNOWN_DIGESTS, "v1", "sysroot.tar.gz"), None);
+    }
+}
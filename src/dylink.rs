@@ -0,0 +1,148 @@
+//! The `dylink.0` section of dynamically linked modules, see
+//! https://github.com/WebAssembly/tool-conventions/blob/main/DynamicLinking.md
+
+use super::*;
+
+pub(crate) const DYLINK_SECTION: &str = "dylink.0";
+
+pub(crate) const MEM_INFO: u8 = 1;
+pub(crate) const NEEDED: u8 = 2;
+pub(crate) const EXPORT_INFO: u8 = 3;
+pub(crate) const IMPORT_INFO: u8 = 4;
+pub(crate) const RUNTIME_PATH: u8 = 5;
+
+/// Holds the soname given to a shared library with `-soname`, which
+/// `dylink.0` has no room for. Modules linked against the library list it
+/// as NEEDED by this name rather than by its file name.
+pub(crate) const SONAME_SECTION: &str = "wasixcc.soname";
+
+pub(crate) fn is_shared_module(bytes: &[u8]) -> Result<bool> {
+    Ok(wasm::read_sections(bytes)?
+        .iter()
+        .any(|section| section.name == Some(DYLINK_SECTION)))
+}
+
+pub(crate) fn read_soname(bytes: &[u8]) -> Result<Option<String>> {
+    let sections = wasm::read_sections(bytes)?;
+    let Some(section) = sections.iter().find(|s| s.name == Some(SONAME_SECTION)) else {
+        return Ok(None);
+    };
+    let mut reader = wasm::Reader::new(section.contents);
+    reader.read_name()?;
+    Ok(Some(reader.read_name()?.to_owned()))
+}
+
+pub(crate) fn encode_soname_section(soname: &str) -> Vec<u8> {
+    let mut payload = Vec::new();
+    wasm::write_name(&mut payload, soname);
+    wasm::encode_custom_section(SONAME_SECTION, &payload)
+}
+
+/// Splits the payload of a `dylink.0` section into its subsections.
+pub(crate) fn read_subsections(contents: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+    let mut reader = wasm::Reader::new(contents);
+    reader.read_name()?;
+
+    let mut subsections = Vec::new();
+    while !reader.is_empty() {
+        let kind = reader.read_u8()?;
+        let size = reader.read_leb128_u32()?;
+        subsections.push((kind, reader.read_bytes(size as usize)?));
+    }
+    Ok(subsections)
+}
+
+pub(crate) fn read_names(reader: &mut wasm::Reader) -> Result<Vec<String>> {
+    (0..reader.read_leb128_u32()?)
+        .map(|_| Ok(reader.read_name()?.to_owned()))
+        .collect()
+}
+
+/// Adds libraries to the NEEDED list of the module's `dylink.0` section,
+/// keeping the ones already listed.
+pub(crate) fn add_needed(bytes: &[u8], libraries: &[String]) -> Result<Vec<u8>> {
+    if !is_shared_module(bytes)? {
+        bail!("Module has no {DYLINK_SECTION} section");
+    }
+
+    wasm::rebuild_module(bytes, |section| {
+        if section.name != Some(DYLINK_SECTION) {
+            return Ok(None);
+        }
+
+        let mut subsections = read_subsections(section.contents)?;
+        let mut needed = match subsections.iter().find(|(kind, _)| *kind == NEEDED) {
+            Some((_, payload)) => read_names(&mut wasm::Reader::new(payload))?,
+            None => Vec::new(),
+        };
+        for library in libraries {
+            if !needed.contains(library) {
+                needed.push(library.clone());
+            }
+        }
+
+        let mut needed_payload = Vec::new();
+        wasm::write_leb128_u64(&mut needed_payload, needed.len() as u64);
+        for library in &needed {
+            wasm::write_name(&mut needed_payload, library);
+        }
+
+        // Subsections are ordered by kind
+        subsections.retain(|(kind, _)| *kind != NEEDED);
+        let position = subsections
+            .iter()
+            .position(|(kind, _)| *kind > NEEDED)
+            .unwrap_or(subsections.len());
+        subsections.insert(position, (NEEDED, &needed_payload));
+
+        let mut contents = Vec::new();
+        wasm::write_name(&mut contents, DYLINK_SECTION);
+        for (kind, payload) in subsections {
+            contents.push(kind);
+            wasm::write_leb128_u64(&mut contents, payload.len() as u64);
+            contents.extend_from_slice(payload);
+        }
+        Ok(Some(contents))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_needed() {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        let mut needed = vec![0x01];
+        wasm::write_name(&mut needed, "libc.so");
+        let mut dylink = vec![MEM_INFO, 0x04, 0x10, 0x02, 0x01, 0x00];
+        dylink.extend([NEEDED, needed.len() as u8]);
+        dylink.extend(needed);
+        dylink.extend([EXPORT_INFO, 0x01, 0x00]);
+        module.extend(wasm::encode_custom_section(DYLINK_SECTION, &dylink));
+        module.extend(encode_soname_section("libfoo.so.1"));
+
+        let module = add_needed(&module, &["libc.so".to_owned(), "libbar.so".to_owned()]).unwrap();
+        let sections = wasm::read_sections(&module).unwrap();
+        let subsections = read_subsections(sections[0].contents).unwrap();
+        assert_eq!(
+            subsections
+                .iter()
+                .map(|(kind, _)| *kind)
+                .collect::<Vec<_>>(),
+            [MEM_INFO, NEEDED, EXPORT_INFO]
+        );
+        assert_eq!(
+            read_names(&mut wasm::Reader::new(subsections[1].1)).unwrap(),
+            ["libc.so", "libbar.so"]
+        );
+        assert_eq!(
+            read_soname(&module).unwrap().as_deref(),
+            Some("libfoo.so.1")
+        );
+
+        let static_module = b"\0asm\x01\0\0\0".to_vec();
+        assert!(add_needed(&static_module, &[]).is_err());
+        assert_eq!(read_soname(&static_module).unwrap(), None);
+    }
+}
@@ -0,0 +1,133 @@
+//! Export control for wasm-ld, which takes neither version scripts nor export
+//! lists: both are turned into `--export-if-defined=` flags.
+
+use super::*;
+
+/// The symbols a GNU ld version script exports.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct VersionScript {
+    pub globals: Vec<String>,
+    /// Whether a `local: *;` hides everything not listed as global.
+    pub hides_others: bool,
+}
+
+/// Parses the parts of a version script that matter for wasm: the global and
+/// local symbols of each version node. Version names and dependencies are
+/// dropped, since wasm has no symbol versioning.
+pub(crate) fn parse_version_script(contents: &str) -> Result<VersionScript> {
+    let contents = strip_comments(contents);
+    let mut script = VersionScript::default();
+    let mut local = false;
+    let mut depth = 0;
+
+    let separated = contents
+        .replace('{', " { ")
+        .replace('}', " } ")
+        .replace(';', " ; ");
+    for token in separated.split_whitespace() {
+        match token {
+            "{" => depth += 1,
+            "}" => {
+                depth -= 1;
+                local = false;
+            }
+            ";" => (),
+            // Nested blocks such as extern "C++" { ... } would need demangling
+            "extern" => bail!("extern blocks in version scripts are not supported"),
+            "global:" if depth > 0 => local = false,
+            "local:" if depth > 0 => local = true,
+            // The name of a version node, or of the one it depends on
+            _ if depth == 0 => (),
+            "*" if local => script.hides_others = true,
+            _ if local => (),
+            symbol if symbol.contains(['*', '?', '[']) => {
+                eprintln!("Warning: ignoring wildcard pattern {symbol} in version script");
+            }
+            symbol => script.globals.push(symbol.to_owned()),
+        }
+    }
+    if depth != 0 {
+        bail!("Unbalanced braces in version script");
+    }
+    Ok(script)
+}
+
+/// Parses an EXPORT_LIST file: one symbol per line, with `#` starting a
+/// comment.
+pub(crate) fn parse_export_list(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+pub(crate) fn read_version_script(path: &Path) -> Result<VersionScript> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read version script {}", path.display()))?;
+    parse_version_script(&contents)
+        .with_context(|| format!("Failed to parse version script {}", path.display()))
+}
+
+pub(crate) fn read_export_list(path: &Path) -> Result<Vec<String>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read export list {}", path.display()))?;
+    Ok(parse_export_list(&contents))
+}
+
+// Removes `#` and `/* */` comments.
+fn strip_comments(contents: &str) -> String {
+    let mut result = String::new();
+    let mut rest = contents;
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, rest)| rest);
+            result.push(' ');
+        } else if rest.starts_with('#') {
+            rest = rest.split_once('\n').map_or("", |(_, rest)| rest);
+            result.push('\n');
+        } else {
+            let c = rest.chars().next().unwrap();
+            result.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version_script() {
+        let script = "# Exported API\n\
+                      FOO_1.0 {\n\
+                        global: foo_init; /* setup */ foo_run;\n\
+                        foo_*;\n\
+                        local: *;\n\
+                      };\n\
+                      FOO_2.0 { foo_stop; } FOO_1.0;\n";
+        assert_eq!(
+            parse_version_script(script).unwrap(),
+            VersionScript {
+                globals: vec!["foo_init".into(), "foo_run".into(), "foo_stop".into()],
+                hides_others: true,
+            }
+        );
+
+        let script = "{ global: a; };";
+        assert!(!parse_version_script(script).unwrap().hides_others);
+        assert!(parse_version_script("{ global: a;").is_err());
+        assert!(parse_version_script("{ extern \"C++\" { foo::*; }; };").is_err());
+    }
+
+    #[test]
+    fn test_parse_export_list() {
+        assert_eq!(
+            parse_export_list("# API\nfoo\n  bar  # the bar\n\n"),
+            ["foo", "bar"]
+        );
+    }
+}
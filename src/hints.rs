@@ -0,0 +1,99 @@
+//! Hints for common build failures, recognized in the output of the tools
+//! wasixcc runs and appended to the error it reports.
+
+// Headers that are always in the sysroot. When one of these can't be found,
+// the sysroot is missing or broken rather than the project misconfigured.
+const SYSROOT_HEADERS: &[&str] = &[
+    "stdio.h",
+    "stdlib.h",
+    "string.h",
+    "stddef.h",
+    "stdint.h",
+    "unistd.h",
+    "pthread.h",
+    "errno.h",
+    "iostream",
+    "string",
+    "vector",
+    "cstdio",
+    "cstdlib",
+];
+
+/// Hints for the failure described by a tool's stderr output, if any of
+/// the common failures is recognized.
+pub(crate) fn for_output(output: &str) -> Vec<&'static str> {
+    let mut hints = Vec::new();
+    let mut add = |hint| {
+        if !hints.contains(&hint) {
+            hints.push(hint);
+        }
+    };
+
+    for line in output.lines() {
+        if let Some(header) = missing_header(line) {
+            if SYSROOT_HEADERS.contains(&header) {
+                add(
+                    "A standard header is missing, so the sysroot may be missing or \
+                    incomplete; run `wasixccenv download-sysroot` or check the SYSROOT \
+                    and SYSROOT_PREFIX settings",
+                );
+            }
+        } else if let Some(symbol) = line.split("undefined symbol: ").nth(1) {
+            if symbol.starts_with("__cxa_")
+                || symbol.starts_with("_ZNSt")
+                || symbol.starts_with("_Znwm")
+                || symbol.starts_with("_Znwj")
+                || symbol.starts_with("_ZdlPv")
+            {
+                add("The C++ runtime isn't linked; link with wasixcc++, or add \
+                    -sINCLUDE_CPP_SYMBOLS=yes when linking C++ code with wasixcc");
+            } else if symbol.starts_with("__wasm_setjmp")
+                || symbol.starts_with("__wasm_longjmp")
+                || symbol.starts_with("__cpp_exception")
+            {
+                add(
+                    "setjmp/longjmp and C++ exceptions need wasm exception handling; \
+                    build and link everything with -sWASM_EXCEPTIONS=yes",
+                );
+            }
+        } else if line.contains("recompile with -fPIC") {
+            add(
+                "Objects linked into a dynamic module must be position-independent; \
+                rebuild them with -sPIC=yes",
+            );
+        } else if line.contains("--shared-memory is disallowed") {
+            add(
+                "Some objects were compiled without atomics and bulk memory; rebuild \
+                them with wasixcc, or pass -matomics -mbulk-memory",
+            );
+        }
+    }
+
+    hints
+}
+
+// The header named in clang's `fatal error: 'foo.h' file not found`.
+fn missing_header(line: &str) -> Option<&str> {
+    let rest = line.split("fatal error: '").nth(1)?;
+    rest.strip_suffix("' file not found")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hints() {
+        let output = "a.c:1:10: fatal error: 'stdio.h' file not found\n";
+        assert_eq!(for_output(output).len(), 1);
+        assert!(for_output(output)[0].contains("download-sysroot"));
+
+        let output = "wasm-ld: error: a.o: undefined symbol: __cxa_throw\n\
+                      wasm-ld: error: a.o: undefined symbol: __cxa_begin_catch\n";
+        assert_eq!(for_output(output).len(), 1);
+        assert!(for_output(output)[0].contains("INCLUDE_CPP_SYMBOLS"));
+
+        assert!(for_output("a.c:1:10: fatal error: 'project.h' file not found\n").is_empty());
+        assert!(for_output("wasm-ld: error: a.o: undefined symbol: foo\n").is_empty());
+    }
+}
@@ -0,0 +1,799 @@
+//! Summaries of wasm files for the binary inspection tools, printed without
+//! needing any external tools.
+
+use super::*;
+
+fn section_name(section: &wasm::Section) -> String {
+    match (section.id, section.name) {
+        (_, Some(name)) => format!("custom \"{name}\""),
+        (1, _) => "type".to_owned(),
+        (2, _) => "import".to_owned(),
+        (3, _) => "function".to_owned(),
+        (4, _) => "table".to_owned(),
+        (5, _) => "memory".to_owned(),
+        (6, _) => "global".to_owned(),
+        (7, _) => "export".to_owned(),
+        (8, _) => "start".to_owned(),
+        (9, _) => "element".to_owned(),
+        (10, _) => "code".to_owned(),
+        (11, _) => "data".to_owned(),
+        (12, _) => "datacount".to_owned(),
+        (13, _) => "tag".to_owned(),
+        (id, _) => format!("unknown ({id})"),
+    }
+}
+
+fn external_kind_name(kind: u8) -> &'static str {
+    match kind {
+        wasm::EXTERNAL_FUNC => "func",
+        wasm::EXTERNAL_TABLE => "table",
+        wasm::EXTERNAL_MEMORY => "memory",
+        wasm::EXTERNAL_GLOBAL => "global",
+        wasm::EXTERNAL_TAG => "tag",
+        _ => "unknown",
+    }
+}
+
+/// Lists the sections, imports and exports of a wasm module or object.
+pub(crate) fn summary(bytes: &[u8]) -> Result<String> {
+    let sections = wasm::read_sections(bytes)?;
+    let mut out = String::from("Sections:\n");
+
+    let names = sections.iter().map(section_name).collect::<Vec<_>>();
+    let name_width = names.iter().map(String::len).max().unwrap_or_default();
+    for (section, name) in sections.iter().zip(&names) {
+        out.push_str(&format!(
+            "  {name:name_width$}  offset {:#010x}  size {:>10}\n",
+            section.range.start,
+            section.contents.len()
+        ));
+    }
+
+    if let Some(section) = sections.iter().find(|s| s.id == wasm::SECTION_IMPORT) {
+        out.push_str("\nImports:\n");
+        for import in wasm::read_imports(section.contents)? {
+            out.push_str(&format!(
+                "  {:6}  {}.{}\n",
+                external_kind_name(import.kind),
+                import.module,
+                import.field
+            ));
+        }
+    }
+
+    if let Some(section) = sections.iter().find(|s| s.id == wasm::SECTION_EXPORT) {
+        out.push_str("\nExports:\n");
+        for export in wasm::read_exports(section.contents)? {
+            out.push_str(&format!(
+                "  {:6}  {} (index {})\n",
+                external_kind_name(export.kind),
+                export.name,
+                export.index
+            ));
+        }
+    }
+
+    if let Some(section) = sections
+        .iter()
+        .find(|s| s.name == Some(compiler::BUILD_INFO_SECTION))
+    {
+        let mut reader = wasm::Reader::new(section.contents);
+        reader.read_name()?;
+        out.push_str(&format!(
+            "\nBuild info:\n  {}\n",
+            String::from_utf8_lossy(&section.contents[reader.pos()..])
+        ));
+    }
+
+    Ok(out)
+}
+
+/// Summarizes what a linked module imports and exports, its memory and table
+/// limits and its dylink section, for LINK_SUMMARY.
+pub(crate) fn link_summary(bytes: &[u8]) -> Result<String> {
+    let sections = wasm::read_sections(bytes)?;
+    let find = |id| sections.iter().find(|section| section.id == id);
+    let mut out = String::new();
+
+    let imports = match find(wasm::SECTION_IMPORT) {
+        Some(section) => wasm::read_imports(section.contents)?,
+        None => Vec::new(),
+    };
+    let mut imports_by_module = std::collections::BTreeMap::<_, Vec<_>>::new();
+    for import in &imports {
+        imports_by_module
+            .entry(import.module)
+            .or_default()
+            .push(import);
+    }
+    out.push_str(&format!("Imports ({}):\n", imports.len()));
+    for (module, imports) in &imports_by_module {
+        out.push_str(&format!("  {module} ({})\n", imports.len()));
+        for import in imports {
+            out.push_str(&format!(
+                "    {:6}  {}\n",
+                external_kind_name(import.kind),
+                import.field
+            ));
+        }
+    }
+
+    let exports = match find(wasm::SECTION_EXPORT) {
+        Some(section) => wasm::read_exports(section.contents)?,
+        None => Vec::new(),
+    };
+    out.push_str(&format!("\nExports ({}):\n", exports.len()));
+    for export in &exports {
+        out.push_str(&format!(
+            "  {:6}  {}\n",
+            external_kind_name(export.kind),
+            export.name
+        ));
+    }
+
+    // Imported memories and tables come first in the index space
+    let mut memories = Vec::new();
+    let mut tables = Vec::new();
+    if let Some(section) = find(wasm::SECTION_IMPORT) {
+        for import in &imports {
+            let mut reader = wasm::Reader::new(&section.contents[import.descriptor_offset..]);
+            let origin = format!("imported from {}.{}", import.module, import.field);
+            match import.kind {
+                wasm::EXTERNAL_MEMORY => memories.push((reader.read_limits()?, origin)),
+                wasm::EXTERNAL_TABLE => {
+                    reader.read_value_type()?;
+                    tables.push((reader.read_limits()?, origin));
+                }
+                _ => (),
+            }
+        }
+    }
+    if let Some(section) = find(wasm::SECTION_MEMORY) {
+        let mut reader = wasm::Reader::new(section.contents);
+        for _ in 0..reader.read_leb128_u32()? {
+            memories.push((reader.read_limits()?, "defined".to_owned()));
+        }
+    }
+    if let Some(section) = find(wasm::SECTION_TABLE) {
+        let mut reader = wasm::Reader::new(section.contents);
+        for _ in 0..reader.read_leb128_u32()? {
+            reader.read_value_type()?;
+            tables.push((reader.read_limits()?, "defined".to_owned()));
+        }
+    }
+
+    out.push_str("\nMemory:\n");
+    if memories.is_empty() {
+        out.push_str("  none\n");
+    }
+    for (limits, origin) in &memories {
+        out.push_str(&format!(
+            "  {} ({origin})\n",
+            format_limits(limits, "pages")
+        ));
+    }
+    out.push_str("\nTables:\n");
+    if tables.is_empty() {
+        out.push_str("  none\n");
+    }
+    for (limits, origin) in &tables {
+        out.push_str(&format!(
+            "  {} ({origin})\n",
+            format_limits(limits, "entries")
+        ));
+    }
+
+    if let Some(section) = sections
+        .iter()
+        .find(|s| s.name == Some(dylink::DYLINK_SECTION))
+    {
+        out.push_str("\nDylink:\n");
+        if let Some(soname) = dylink::read_soname(bytes)? {
+            out.push_str(&format!("  soname: {soname}\n"));
+        }
+        out.push_str(&dylink_summary(section.contents)?);
+    }
+
+    Ok(out)
+}
+
+fn format_limits(limits: &wasm::Limits, unit: &str) -> String {
+    let mut out = format!("min {} {unit}", limits.min);
+    match limits.max {
+        Some(max) => out.push_str(&format!(", max {max} {unit}")),
+        None => out.push_str(", no max"),
+    }
+    if limits.flags & wasm::LIMITS_SHARED != 0 {
+        out.push_str(", shared");
+    }
+    out
+}
+
+fn dylink_summary(contents: &[u8]) -> Result<String> {
+    let mut out = String::new();
+    for (kind, payload) in dylink::read_subsections(contents)? {
+        let mut payload = wasm::Reader::new(payload);
+        match kind {
+            dylink::MEM_INFO => {
+                let memory_size = payload.read_leb128_u32()?;
+                let memory_align = payload.read_leb128_u32()?;
+                let table_size = payload.read_leb128_u32()?;
+                let table_align = payload.read_leb128_u32()?;
+                out.push_str(&format!(
+                    "  memory size {memory_size} (align {}), table size {table_size} (align {})\n",
+                    1u64 << memory_align,
+                    1u64 << table_align
+                ));
+            }
+            dylink::NEEDED => {
+                let needed = dylink::read_names(&mut payload)?;
+                if !needed.is_empty() {
+                    out.push_str(&format!("  needed: {}\n", needed.join(", ")));
+                }
+            }
+            dylink::RUNTIME_PATH => {
+                let paths = dylink::read_names(&mut payload)?;
+                out.push_str(&format!("  runtime path: {}\n", paths.join(", ")));
+            }
+            dylink::EXPORT_INFO | dylink::IMPORT_INFO => {
+                let what = if kind == dylink::EXPORT_INFO {
+                    "exports"
+                } else {
+                    "imports"
+                };
+                out.push_str(&format!(
+                    "  symbol info for {} {what}\n",
+                    payload.read_leb128_u32()?
+                ));
+            }
+            _ => (),
+        }
+    }
+    Ok(out)
+}
+
+/// Lists the sections of a module by size, then its largest functions, data
+/// segments and other sections, for `--size-report`. Functions and data
+/// segments are named from the name section when there is one.
+pub(crate) fn size_report(bytes: &[u8], top: usize) -> Result<String> {
+    let sections = wasm::read_sections(bytes)?;
+    let percent = |size: usize| 100.0 * size as f64 / bytes.len() as f64;
+    let mut out = String::from("Sections:\n");
+
+    let names = sections.iter().map(section_name).collect::<Vec<_>>();
+    let name_width = names.iter().map(String::len).max().unwrap_or_default();
+    let mut by_size = sections.iter().zip(&names).collect::<Vec<_>>();
+    by_size.sort_by_key(|(section, _)| std::cmp::Reverse(section.range.len()));
+    for (section, name) in by_size {
+        out.push_str(&format!(
+            "  {name:name_width$}  {:>10}  {:>5.1}%\n",
+            section.range.len(),
+            percent(section.range.len())
+        ));
+    }
+
+    let (function_names, data_names) = match sections.iter().find(|s| s.name == Some("name")) {
+        Some(section) => read_name_section(section.contents).unwrap_or_default(),
+        None => Default::default(),
+    };
+
+    let mut items = Vec::new();
+    let imported_functions = match sections.iter().find(|s| s.id == wasm::SECTION_IMPORT) {
+        Some(section) => wasm::read_imports(section.contents)?
+            .iter()
+            .filter(|import| import.kind == wasm::EXTERNAL_FUNC)
+            .count() as u32,
+        None => 0,
+    };
+    for (section, name) in sections.iter().zip(&names) {
+        match section.id {
+            wasm::SECTION_CODE => {
+                let mut reader = wasm::Reader::new(section.contents);
+                for index in 0..reader.read_leb128_u32()? {
+                    let size = reader.read_leb128_u32()? as usize;
+                    reader.read_bytes(size)?;
+                    let index = imported_functions + index;
+                    let name = match function_names.get(&index) {
+                        Some(name) => format!("func {name}"),
+                        None => format!("func[{index}]"),
+                    };
+                    items.push((size, name));
+                }
+            }
+            wasm::SECTION_DATA => {
+                let mut reader = wasm::Reader::new(section.contents);
+                for index in 0..reader.read_leb128_u32()? {
+                    let flags = reader.read_leb128_u32()?;
+                    if flags == 2 {
+                        reader.read_leb128_u32()?;
+                    }
+                    // Active segments have an offset expression
+                    if flags != 1 {
+                        while reader.skip_instruction()? != 0x0b {}
+                    }
+                    let size = reader.read_leb128_u32()? as usize;
+                    reader.read_bytes(size)?;
+                    let name = match data_names.get(&index) {
+                        Some(name) => format!("data {name}"),
+                        None => format!("data[{index}]"),
+                    };
+                    items.push((size, name));
+                }
+            }
+            _ => items.push((section.range.len(), format!("section {name}"))),
+        }
+    }
+
+    items.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    out.push_str(&format!(
+        "\nLargest items ({} of {}):\n",
+        top.min(items.len()),
+        items.len()
+    ));
+    for (size, name) in items.iter().take(top) {
+        out.push_str(&format!("  {size:>10}  {:>5.1}%  {name}\n", percent(*size)));
+    }
+    if function_names.is_empty() {
+        out.push_str(
+            "\nThe module has no function names; build with -sKEEP_NAMES=yes to see them.\n",
+        );
+    }
+
+    Ok(out)
+}
+
+// Subsections of the name section, see
+// https://github.com/WebAssembly/extended-name-section
+const NAME_FUNCTIONS: u8 = 1;
+const NAME_DATA_SEGMENTS: u8 = 9;
+
+type NameMap = std::collections::HashMap<u32, String>;
+
+// The function and data segment names from a name section.
+fn read_name_section(contents: &[u8]) -> Result<(NameMap, NameMap)> {
+    let mut reader = wasm::Reader::new(contents);
+    reader.read_name()?;
+
+    let mut functions = NameMap::new();
+    let mut data = NameMap::new();
+    while !reader.is_empty() {
+        let kind = reader.read_u8()?;
+        let size = reader.read_leb128_u32()?;
+        let mut payload = wasm::Reader::new(reader.read_bytes(size as usize)?);
+        let names = match kind {
+            NAME_FUNCTIONS => &mut functions,
+            NAME_DATA_SEGMENTS => &mut data,
+            _ => continue,
+        };
+        for _ in 0..payload.read_leb128_u32()? {
+            let index = payload.read_leb128_u32()?;
+            names.insert(index, payload.read_name()?.to_owned());
+        }
+    }
+    Ok((functions, data))
+}
+
+/// Sizes of a wasm file's contents, by kind of section.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Sizes {
+    pub code: usize,
+    pub data: usize,
+    pub custom: usize,
+    /// All other sections, and section headers.
+    pub other: usize,
+    pub total: usize,
+}
+
+pub(crate) fn sizes(bytes: &[u8]) -> Result<Sizes> {
+    let mut sizes = Sizes {
+        total: bytes.len(),
+        ..Default::default()
+    };
+    for section in wasm::read_sections(bytes)? {
+        match section.id {
+            wasm::SECTION_CODE => sizes.code += section.contents.len(),
+            wasm::SECTION_DATA => sizes.data += section.contents.len(),
+            wasm::SECTION_CUSTOM => sizes.custom += section.contents.len(),
+            _ => (),
+        }
+    }
+    sizes.other = sizes.total - sizes.code - sizes.data - sizes.custom;
+    Ok(sizes)
+}
+
+/// Formats sizes as a table in the style of `size`, with a totals row when
+/// there's more than one file.
+pub(crate) fn size_table(files: &[(&str, Sizes)]) -> String {
+    let mut out = format!(
+        "{:>10} {:>10} {:>10} {:>10} {:>10}  filename\n",
+        "code", "data", "custom", "other", "total"
+    );
+    let row = |sizes: &Sizes, name: &str| {
+        format!(
+            "{:>10} {:>10} {:>10} {:>10} {:>10}  {name}\n",
+            sizes.code, sizes.data, sizes.custom, sizes.other, sizes.total
+        )
+    };
+
+    let mut totals = Sizes::default();
+    for (name, sizes) in files {
+        out.push_str(&row(sizes, name));
+        totals.code += sizes.code;
+        totals.data += sizes.data;
+        totals.custom += sizes.custom;
+        totals.other += sizes.other;
+        totals.total += sizes.total;
+    }
+    if files.len() > 1 {
+        out.push_str(&row(&totals, "(TOTALS)"));
+    }
+
+    out
+}
+
+/// Offset of the code section's contents in a module. DWARF addresses in
+/// wasm are relative to it, whereas runtimes such as Wasmer report code
+/// locations as offsets into the whole module.
+pub(crate) fn code_section_offset(bytes: &[u8]) -> Result<usize> {
+    wasm::read_sections(bytes)?
+        .iter()
+        .find(|section| section.id == wasm::SECTION_CODE)
+        .map(|section| section.offset)
+        .context("Module has no code section")
+}
+
+// addr2line options that take a value as the next argument.
+const ADDR2LINE_VALUE_OPTIONS: [&str; 5] = ["-e", "--exe", "--obj", "-j", "--section"];
+
+/// Splits addr2line's arguments into options and addresses.
+pub(crate) fn split_addr2line_args(args: Vec<String>) -> (Vec<String>, Vec<String>) {
+    let mut options = Vec::new();
+    let mut addresses = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg.starts_with('-') {
+            let takes_value = ADDR2LINE_VALUE_OPTIONS.contains(&arg.as_str());
+            options.push(arg);
+            if takes_value {
+                options.extend(args.next());
+            }
+        } else {
+            addresses.push(arg);
+        }
+    }
+    (options, addresses)
+}
+
+/// The module given to addr2line with `-e`, `--exe` or `--obj`.
+pub(crate) fn addr2line_module(options: &[String]) -> Option<&str> {
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        if ["-e", "--exe", "--obj"].contains(&option.as_str()) {
+            return options.next().map(String::as_str);
+        }
+        for prefix in ["--exe=", "--obj=", "-e"] {
+            if let Some(module) = option.strip_prefix(prefix) {
+                return Some(module);
+            }
+        }
+    }
+    None
+}
+
+/// Converts addresses from module offsets to code section offsets.
+pub(crate) fn translate_addresses(addresses: &[String], code_offset: usize) -> Result<Vec<String>> {
+    addresses
+        .iter()
+        .map(|address| {
+            // Addresses are hexadecimal, with or without a prefix
+            let digits = address.strip_prefix("0x").unwrap_or(address);
+            let value = usize::from_str_radix(digits, 16)
+                .with_context(|| format!("Invalid address: {address}"))?;
+            let code_address = value.checked_sub(code_offset).with_context(|| {
+                format!(
+                    "Address {address} is before the code section at {code_offset:#x}; pass \
+                    --code-offsets if the addresses are already relative to it"
+                )
+            })?;
+            Ok(format!("{code_address:#x}"))
+        })
+        .collect()
+}
+
+/// Demangles Itanium-mangled names embedded in other symbol names, such as
+/// `__imported_env__Z3foov`, which `llvm-nm -C` leaves alone since the
+/// whole name isn't mangled. `demangle` maps a list of names to their
+/// demangled forms, or the name itself if it can't be demangled.
+pub(crate) fn demangle_embedded_names(
+    output: &str,
+    demangle: impl FnOnce(&[&str]) -> Result<Vec<String>>,
+) -> Result<String> {
+    // Candidates run from a `_Z` inside a word to the end of the word
+    let candidates = output
+        .split_whitespace()
+        .filter_map(|word| {
+            let start = word.get(1..)?.find("_Z")? + 1;
+            Some(&word[start..])
+        })
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return Ok(output.to_owned());
+    }
+
+    let demangled = demangle(&candidates)?;
+    let mut result = output.to_owned();
+    for (candidate, demangled) in candidates.iter().zip(demangled) {
+        if *candidate != demangled {
+            result = result.replace(candidate, &demangled);
+        }
+    }
+    Ok(result)
+}
+
+/// Demangles the Itanium-mangled symbol names in tool output such as linker
+/// errors, where they appear as separate identifiers, e.g.
+/// `undefined symbol: _Z3foov`. `demangle` is as for
+/// `demangle_embedded_names`.
+pub(crate) fn demangle_symbol_names(
+    output: &str,
+    demangle: impl FnOnce(&[&str]) -> Result<Vec<String>>,
+) -> Result<String> {
+    let is_symbol_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$');
+
+    // Splits the output into alternating runs of symbol and other characters
+    let mut tokens = Vec::new();
+    let mut rest = output;
+    while let Some(first) = rest.chars().next() {
+        let in_symbol = is_symbol_char(first);
+        let end = rest
+            .find(|c: char| is_symbol_char(c) != in_symbol)
+            .unwrap_or(rest.len());
+        tokens.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    let mut candidates = tokens
+        .iter()
+        .copied()
+        .filter(|token| token.starts_with("_Z"))
+        .collect::<Vec<_>>();
+    candidates.sort_unstable();
+    candidates.dedup();
+    if candidates.is_empty() {
+        return Ok(output.to_owned());
+    }
+
+    let demangled = demangle(&candidates)?;
+    Ok(tokens
+        .into_iter()
+        .map(|token| match candidates.binary_search(&token) {
+            Ok(index) => demangled[index].as_str(),
+            Err(_) => token,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut imports = vec![0x01];
+        wasm::write_name(&mut imports, "wasix_32v1");
+        wasm::write_name(&mut imports, "fd_write");
+        imports.extend([wasm::EXTERNAL_FUNC, 0x00]);
+        module.extend(wasm::encode_section(wasm::SECTION_IMPORT, &imports));
+
+        let mut exports = vec![0x01];
+        wasm::write_name(&mut exports, "_start");
+        exports.extend([wasm::EXTERNAL_FUNC, 0x01]);
+        module.extend(wasm::encode_section(wasm::SECTION_EXPORT, &exports));
+        module.extend(wasm::encode_custom_section("name", b""));
+
+        let summary = summary(&module).unwrap();
+        assert!(summary.contains("  import         offset 0x00000008  size         23\n"));
+        assert!(summary.contains("  custom \"name\"  offset"));
+        assert!(summary.contains("Imports:\n  func    wasix_32v1.fd_write\n"));
+        assert!(summary.contains("Exports:\n  func    _start (index 1)\n"));
+    }
+
+    #[test]
+    fn test_summary_build_info() {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        module.extend(wasm::encode_custom_section(
+            compiler::BUILD_INFO_SECTION,
+            b"{\"version\":1}",
+        ));
+
+        let summary = summary(&module).unwrap();
+        assert!(summary.ends_with("Build info:\n  {\"version\":1}\n"));
+    }
+
+    #[test]
+    fn test_link_summary() {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut imports = vec![0x02];
+        wasm::write_name(&mut imports, "env");
+        wasm::write_name(&mut imports, "memory");
+        imports.extend([wasm::EXTERNAL_MEMORY, 0x03, 0x11, 0x80, 0x80, 0x04]);
+        wasm::write_name(&mut imports, "wasix_32v1");
+        wasm::write_name(&mut imports, "fd_write");
+        imports.extend([wasm::EXTERNAL_FUNC, 0x00]);
+        module.extend(wasm::encode_section(wasm::SECTION_IMPORT, &imports));
+
+        // A funcref table with min 2 and no max
+        module.extend(wasm::encode_section(
+            wasm::SECTION_TABLE,
+            &[0x01, 0x70, 0x00, 0x02],
+        ));
+
+        let mut exports = vec![0x01];
+        wasm::write_name(&mut exports, "_start");
+        exports.extend([wasm::EXTERNAL_FUNC, 0x01]);
+        module.extend(wasm::encode_section(wasm::SECTION_EXPORT, &exports));
+
+        let mut needed = vec![0x01];
+        wasm::write_name(&mut needed, "libc.so");
+        let mut dylink = vec![dylink::MEM_INFO, 0x04, 0x10, 0x02, 0x01, 0x00];
+        dylink.extend([dylink::NEEDED, needed.len() as u8]);
+        dylink.extend(needed);
+        module.extend(wasm::encode_custom_section(dylink::DYLINK_SECTION, &dylink));
+        module.extend(dylink::encode_soname_section("libapp.so"));
+
+        let summary = link_summary(&module).unwrap();
+        assert!(summary.starts_with(
+            "Imports (2):\n  env (1)\n    memory  memory\n  wasix_32v1 (1)\n    func    fd_write\n"
+        ));
+        assert!(summary.contains("Exports (1):\n  func    _start\n"));
+        assert!(summary.contains(
+            "Memory:\n  min 17 pages, max 65536 pages, shared (imported from env.memory)\n"
+        ));
+        assert!(summary.contains("Tables:\n  min 2 entries, no max (defined)\n"));
+        assert!(summary.contains(
+            "Dylink:\n  soname: libapp.so\n  memory size 16 (align 4), table size 1 (align 1)\n  needed: libc.so\n"
+        ));
+    }
+
+    #[test]
+    fn test_size_report() {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut imports = vec![0x01];
+        wasm::write_name(&mut imports, "env");
+        wasm::write_name(&mut imports, "f");
+        imports.extend([wasm::EXTERNAL_FUNC, 0x00]);
+        module.extend(wasm::encode_section(wasm::SECTION_IMPORT, &imports));
+
+        // Two functions of 2 and 6 bytes
+        module.extend(wasm::encode_section(
+            wasm::SECTION_CODE,
+            &[
+                0x02, 0x02, 0x00, 0x0b, 0x06, 0x00, 0x41, 0x00, 0x1a, 0x01, 0x0b,
+            ],
+        ));
+        // An active segment of 4 bytes and a passive one of 1 byte
+        module.extend(wasm::encode_section(
+            wasm::SECTION_DATA,
+            &[
+                0x02, 0x00, 0x41, 0x00, 0x0b, 0x04, 1, 2, 3, 4, 0x01, 0x01, 5,
+            ],
+        ));
+
+        let mut function_names = vec![0x01, 0x02];
+        wasm::write_name(&mut function_names, "main");
+        let mut data_names = vec![0x01, 0x00];
+        wasm::write_name(&mut data_names, ".rodata");
+        let mut names = vec![NAME_FUNCTIONS, function_names.len() as u8];
+        names.extend(function_names);
+        names.extend([NAME_DATA_SEGMENTS, data_names.len() as u8]);
+        names.extend(data_names);
+        module.extend(wasm::encode_custom_section("name", &names));
+
+        let report = size_report(&module, 3).unwrap();
+        let items = report
+            .split("Largest items (3 of 6):\n")
+            .nth(1)
+            .unwrap()
+            .lines()
+            .map(|line| line.split("%  ").nth(1).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            ["section custom \"name\"", "section import", "func main"]
+        );
+        assert!(report.contains("      6  "));
+        assert!(!report.contains("KEEP_NAMES"));
+
+        let report = size_report(&module, 10).unwrap();
+        assert!(report.contains("%  data .rodata\n"));
+        assert!(report.contains("%  data[1]\n"));
+        assert!(report.contains("%  func[1]\n"));
+    }
+
+    #[test]
+    fn test_sizes() {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        module.extend(wasm::encode_section(
+            wasm::SECTION_CODE,
+            &[0x01, 0x02, 0x00, 0x0b],
+        ));
+        module.extend(wasm::encode_section(wasm::SECTION_DATA, &[0x00]));
+        module.extend(wasm::encode_custom_section("name", b"xyz"));
+
+        let sizes = sizes(&module).unwrap();
+        assert_eq!(
+            sizes,
+            Sizes {
+                code: 4,
+                data: 1,
+                custom: 8,
+                other: 14,
+                total: 27,
+            }
+        );
+
+        let table = size_table(&[("a.wasm", sizes)]);
+        assert_eq!(
+            table.lines().nth(1).unwrap(),
+            "         4          1          8         14         27  a.wasm"
+        );
+    }
+
+    #[test]
+    fn test_addr2line_args() {
+        let args = ["-f", "-e", "app.wasm", "0x1a2b", "1000"]
+            .map(str::to_owned)
+            .to_vec();
+        let (options, addresses) = split_addr2line_args(args);
+        assert_eq!(options, ["-f", "-e", "app.wasm"]);
+        assert_eq!(addr2line_module(&options), Some("app.wasm"));
+        assert_eq!(
+            translate_addresses(&addresses, 0x100).unwrap(),
+            ["0x192b", "0xf00"]
+        );
+
+        assert_eq!(
+            addr2line_module(&["--exe=app.wasm".to_owned()]),
+            Some("app.wasm")
+        );
+        assert!(translate_addresses(&["0x10".to_owned()], 0x100).is_err());
+    }
+
+    #[test]
+    fn test_demangle_embedded_names() {
+        let output = "00000001 T _Z3foov\n         U __imported_env__Z3barv\n";
+        let demangled = demangle_embedded_names(output, |names| {
+            assert_eq!(names, ["_Z3barv"]);
+            Ok(vec!["bar()".to_owned()])
+        })
+        .unwrap();
+        assert_eq!(
+            demangled,
+            "00000001 T _Z3foov\n         U __imported_env_bar()\n"
+        );
+
+        let output = "00000001 T main\n";
+        let unchanged = demangle_embedded_names(output, |_| unreachable!()).unwrap();
+        assert_eq!(unchanged, output);
+    }
+
+    #[test]
+    fn test_demangle_symbol_names() {
+        let output = "wasm-ld: error: a.o: undefined symbol: _ZNSt3__14coutE\n\
+                      wasm-ld: error: b.o: undefined symbol: _Z3foov, _Z3foov\n";
+        let demangled = demangle_symbol_names(output, |names| {
+            assert_eq!(names, ["_Z3foov", "_ZNSt3__14coutE"]);
+            Ok(vec!["foo()".to_owned(), "std::__1::cout".to_owned()])
+        })
+        .unwrap();
+        assert_eq!(
+            demangled,
+            "wasm-ld: error: a.o: undefined symbol: std::__1::cout\n\
+             wasm-ld: error: b.o: undefined symbol: foo(), foo()\n"
+        );
+    }
+}
@@ -0,0 +1,77 @@
+// Runtime for INSTRUMENT_FUNCTIONS, linked into modules compiled with
+// -finstrument-functions.
+//
+// Function entries and exits are recorded into a ring buffer which the host
+// reads through the exported accessors below. Function addresses are indices
+// into the module's indirect function table, so they can be resolved to names
+// through the table and the name section.
+
+#include <stdint.h>
+
+#define NO_INSTRUMENT __attribute__((no_instrument_function))
+#define EXPORT(name) __attribute__((export_name(name), used))
+
+#define TRACE_CAPACITY 65536
+
+enum {
+  TRACE_ENTER = 0,
+  TRACE_EXIT = 1,
+};
+
+struct trace_event {
+  uint32_t function;
+  uint32_t call_site;
+  uint32_t kind;
+  uint32_t reserved;
+};
+
+static struct trace_event trace_events[TRACE_CAPACITY];
+
+// Total number of events recorded; once it exceeds the capacity, the oldest
+// events have been overwritten.
+static _Atomic uint32_t trace_count;
+
+static _Atomic uint32_t trace_enabled = 1;
+
+NO_INSTRUMENT static void record(void *function, void *call_site, uint32_t kind) {
+  if (!trace_enabled) {
+    return;
+  }
+  uint32_t index = trace_count++ % TRACE_CAPACITY;
+  trace_events[index] = (struct trace_event){
+      .function = (uint32_t)(uintptr_t)function,
+      .call_site = (uint32_t)(uintptr_t)call_site,
+      .kind = kind,
+  };
+}
+
+NO_INSTRUMENT void __cyg_profile_func_enter(void *function, void *call_site) {
+  record(function, call_site, TRACE_ENTER);
+}
+
+NO_INSTRUMENT void __cyg_profile_func_exit(void *function, void *call_site) {
+  record(function, call_site, TRACE_EXIT);
+}
+
+// Address of the ring buffer of 16-byte events in linear memory.
+EXPORT("wasixcc_trace_buffer") NO_INSTRUMENT struct trace_event *wasixcc_trace_buffer(void) {
+  return trace_events;
+}
+
+// Number of events the buffer holds.
+EXPORT("wasixcc_trace_capacity") NO_INSTRUMENT uint32_t wasixcc_trace_capacity(void) {
+  return TRACE_CAPACITY;
+}
+
+// Total number of events recorded so far.
+EXPORT("wasixcc_trace_count") NO_INSTRUMENT uint32_t wasixcc_trace_count(void) {
+  return trace_count;
+}
+
+EXPORT("wasixcc_trace_set_enabled") NO_INSTRUMENT void wasixcc_trace_set_enabled(uint32_t enabled) {
+  trace_enabled = enabled;
+}
+
+EXPORT("wasixcc_trace_reset") NO_INSTRUMENT void wasixcc_trace_reset(void) {
+  trace_count = 0;
+}
@@ -1,24 +1,98 @@
 #![cfg_attr(target_vendor = "wasmer", allow(unexpected_cfgs))]
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashSet,
     ffi::{OsStr, OsString},
+    io::{Read, Write},
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, ExitStatus, Stdio},
     sync::LazyLock,
 };
 
 use anyhow::{bail, Context, Result};
 
-use crate::{compiler::ModuleKind, download::TagSpec};
+use crate::{
+    compat::CompatCheck,
+    compiler::{ModuleKind, Strip},
+    diagnostics::DiagnosticsFormat,
+    download::TagSpec,
+    muldefs::MultipleDefinitions,
+};
 
+mod ar;
+mod autotools;
+mod bazel;
+mod builddb;
+mod ccenv;
+mod clean;
+mod compat;
 mod compiler;
+mod conan;
+mod config;
+mod control;
+mod diagnostics;
 pub mod download;
+mod dylink;
+mod exports;
+mod hints;
+mod inspect;
+mod matrix;
+mod meson;
+mod muldefs;
+mod nativeflags;
+mod objcache;
+mod optreport;
+mod project;
+mod reproducer;
+mod selftest;
+mod shellenv;
+mod sourcemap;
+mod status;
+mod store;
+mod threads;
+mod timing;
+mod toolchains;
+mod trace;
+pub mod vfs;
+mod wasm;
+mod watch;
+
+/// The commands installed as `wasix<command>` by `--install-executables`.
+pub const COMMANDS: &[&str] = &[
+    "cc",
+    "++",
+    "cc++",
+    "ar",
+    "nm",
+    "ranlib",
+    "ld",
+    "ccenv",
+    "objdump",
+    "objcopy",
+    "size",
+    "c++filt",
+    "dwarfdump",
+    "addr2line",
+    "tidy",
+    "run",
+];
+
+/// A step of the build pipeline, as reported to the control socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Stage {
+    Compile,
+    Link,
+    Wizer,
+    WasmOpt,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum LlvmLocation {
     UserProvided(PathBuf),
     DefaultPath(PathBuf),
+    /// Versioned binaries from PATH, such as `clang-22`.
+    FromSystem(u32),
 }
 
 impl LlvmLocation {
@@ -29,7 +103,7 @@ impl LlvmLocation {
 
             // ... but a default path with fallbacks is generally acceptable.
             Self::DefaultPath(path) => {
-                if path.join("bin").exists() {
+                if vfs::get().exists(&path.join("bin")) {
                     path.join("bin").join(tool)
                 } else {
                     // Default to running LLVM 21 binaries if the custom toolchain is not
@@ -44,6 +118,16 @@ impl LlvmLocation {
                     PathBuf::from(tool_path)
                 }
             }
+
+            Self::FromSystem(version) => PathBuf::from(format!("{tool}-{version}")),
+        }
+    }
+
+    /// The installation directory, unless the system's LLVM is used.
+    pub fn install_path(&self) -> Option<&Path> {
+        match self {
+            Self::UserProvided(path) | Self::DefaultPath(path) => Some(path),
+            Self::FromSystem(_) => None,
         }
     }
 }
@@ -69,7 +153,7 @@ impl BinaryenLocation {
 
             // ... but a default path with fallbacks is generally acceptable.
             Self::DefaultPath(path) => {
-                if path.join("bin").exists() {
+                if vfs::get().exists(&path.join("bin")) {
                     path.join("bin").join(tool)
                 } else {
                     // Default to running system binaryen if the custom toolchain is not
@@ -89,7 +173,7 @@ impl BinaryenLocation {
         match self {
             Self::UserProvided(path) => Some(path.join("bin")),
             Self::DefaultPath(path) => {
-                if path.join("bin").exists() {
+                if vfs::get().exists(&path.join("bin")) {
                     Some(path.join("bin"))
                 } else {
                     // Default to running system binaryen if the custom toolchain is not
@@ -113,26 +197,89 @@ impl Default for BinaryenLocation {
 #[derive(Debug)]
 #[cfg_attr(test, derive(Default))]
 struct UserSettings {
-    sysroot_location: Option<PathBuf>,          // key name: SYSROOT
-    sysroot_prefix: PathBuf,                    // key name: SYSROOT_PREFIX
-    llvm_location: LlvmLocation,                // key name: LLVM_LOCATION
-    binaryen_location: BinaryenLocation,        // key name: BINARYEN_LOCATION
-    extra_compiler_flags: Vec<String>,          // key name: COMPILER_FLAGS
-    extra_compiler_post_flags: Vec<String>,     // key name: COMPILER_POST_FLAGS
-    extra_compiler_flags_c: Vec<String>,        // key name: COMPILER_FLAGS_C
-    extra_compiler_post_flags_c: Vec<String>,   // key name: COMPILER_POST_FLAGS_C
-    extra_compiler_flags_cxx: Vec<String>,      // key name: COMPILER_FLAGS_CXX
-    extra_compiler_post_flags_cxx: Vec<String>, // key name: COMPILER_POST_FLAGS_CXX
-    extra_linker_flags: Vec<String>,            // key name: LINKER_FLAGS
-    include_cpp_symbols: bool,                  // key name: INCLUDE_CPP_SYMBOLS
-    run_wasm_opt: Option<bool>,                 // key name: RUN_WASM_OPT
-    wasm_opt_flags: Vec<String>,                // key name: WASM_OPT_FLAGS
-    wasm_opt_suppress_default: bool,            // key name: WASM_OPT_SUPPRESS_DEFAULT
-    wasm_opt_preserve_unoptimized: bool,        // key name: WASM_OPT_PRESERVE_UNOPTIMIZED
-    module_kind: Option<ModuleKind>,            // key name: MODULE_KIND
-    wasm_exceptions: bool,                      // key name: WASM_EXCEPTIONS
-    pic: bool,                                  // key name: PIC
-    link_symbolic: bool,                        // key name: LINK_SYMBOLIC
+    sysroot_location: Option<PathBuf>,              // key name: SYSROOT
+    sysroot_prefix: PathBuf,                        // key name: SYSROOT_PREFIX
+    llvm_location: LlvmLocation,                    // key name: LLVM_LOCATION
+    binaryen_location: BinaryenLocation,            // key name: BINARYEN_LOCATION
+    extra_compiler_flags: Vec<String>,              // key name: COMPILER_FLAGS
+    extra_compiler_post_flags: Vec<String>,         // key name: COMPILER_POST_FLAGS
+    extra_compiler_flags_c: Vec<String>,            // key name: COMPILER_FLAGS_C
+    extra_compiler_post_flags_c: Vec<String>,       // key name: COMPILER_POST_FLAGS_C
+    extra_compiler_flags_cxx: Vec<String>,          // key name: COMPILER_FLAGS_CXX
+    extra_compiler_post_flags_cxx: Vec<String>,     // key name: COMPILER_POST_FLAGS_CXX
+    extra_linker_flags: Vec<String>,                // key name: LINKER_FLAGS
+    include_cpp_symbols: bool,                      // key name: INCLUDE_CPP_SYMBOLS
+    run_wasm_opt: Option<bool>,                     // key name: RUN_WASM_OPT
+    wasm_opt_flags: Vec<String>,                    // key name: WASM_OPT_FLAGS
+    wasm_opt_suppress_default: bool,                // key name: WASM_OPT_SUPPRESS_DEFAULT
+    wasm_opt_preserve_unoptimized: bool,            // key name: WASM_OPT_PRESERVE_UNOPTIMIZED
+    module_kind: Option<ModuleKind>,                // key name: MODULE_KIND
+    wasm_exceptions: bool,                          // key name: WASM_EXCEPTIONS
+    pic: bool,                                      // key name: PIC
+    link_symbolic: bool,                            // key name: LINK_SYMBOLIC
+    control_socket: Option<PathBuf>,                // key name: CONTROL_SOCKET
+    out_dir: Option<PathBuf>,                       // key name: OUT_DIR
+    separate_dwarf: bool,                           // key name: SEPARATE_DWARF
+    sysroot_store: PathBuf,                         // key name: SYSROOT_STORE
+    keep_names: bool,                               // key name: KEEP_NAMES
+    auto_prune_threads: bool,                       // key name: AUTO_PRUNE_THREADS
+    temp_dir: Option<PathBuf>,                      // key name: TEMP_DIR
+    opt_report: Option<PathBuf>,                    // key name: OPT_REPORT
+    auto_download: Option<bool>,                    // key name: AUTO_DOWNLOAD
+    profiling: bool,                                // key name: PROFILING
+    instrument_functions: bool,                     // key name: INSTRUMENT_FUNCTIONS
+    suppress_default_cflags: Vec<String>,           // key name: SUPPRESS_DEFAULT_CFLAGS
+    download_base_url: Option<String>,              // key name: DOWNLOAD_BASE_URL
+    suppress_default_ldflags: Vec<String>,          // key name: SUPPRESS_DEFAULT_LDFLAGS
+    nm_demangle: Option<bool>,                      // key name: NM_DEMANGLE
+    ar_deterministic: bool,                         // key name: AR_DETERMINISTIC
+    ar_thin: bool,                                  // key name: AR_THIN
+    ar_ranlib: bool,                                // key name: AR_RANLIB
+    object_cache: bool,                             // key name: OBJECT_CACHE
+    time_report: bool,                              // key name: TIME_REPORT
+    trace_file: Option<PathBuf>,                    // key name: TRACE_FILE
+    diagnostics_format: DiagnosticsFormat,          // key name: DIAGNOSTICS_FORMAT
+    demangle_linker_errors: bool,                   // key name: DEMANGLE_LINKER_ERRORS
+    reproducer: Option<PathBuf>,                    // key name: REPRODUCER
+    strict_toolchain: Option<bool>,                 // key name: STRICT_TOOLCHAIN
+    clang_path: Option<PathBuf>,                    // key name: CLANG_PATH
+    clangxx_path: Option<PathBuf>,                  // key name: CLANGXX_PATH
+    wasm_ld_path: Option<PathBuf>,                  // key name: WASM_LD_PATH
+    wasm_opt_path: Option<PathBuf>,                 // key name: WASM_OPT_PATH
+    compat_check: CompatCheck,                      // key name: COMPAT_CHECK
+    toolchain: Option<String>,                      // key name: TOOLCHAIN
+    http_proxy: Option<String>,                     // key name: HTTP_PROXY
+    ca_bundle: Option<PathBuf>,                     // key name: CA_BUNDLE
+    runner: Option<String>,                         // key name: RUNNER
+    runner_args: Vec<String>,                       // key name: RUNNER_ARGS
+    link_summary: bool,                             // key name: LINK_SUMMARY
+    wizer: bool,                                    // key name: WIZER
+    wizer_init_func: Option<String>,                // key name: WIZER_INIT_FUNC
+    wizer_allow_wasi: bool,                         // key name: WIZER_ALLOW_WASI
+    wizer_dirs: Vec<String>,                        // key name: WIZER_DIRS
+    wizer_flags: Vec<String>,                       // key name: WIZER_FLAGS
+    split_module: bool,                             // key name: SPLIT_MODULE
+    split_profile: Option<PathBuf>,                 // key name: SPLIT_PROFILE
+    component: bool,                                // key name: COMPONENT
+    component_adapter: Option<PathBuf>,             // key name: COMPONENT_ADAPTER
+    export_list: Option<PathBuf>,                   // key name: EXPORT_LIST
+    allow_multiple_definition: MultipleDefinitions, // key name: ALLOW_MULTIPLE_DEFINITION
+    export_table: bool,                             // key name: EXPORT_TABLE
+    growable_table: bool,                           // key name: GROWABLE_TABLE
+    table_base: Option<u32>,                        // key name: TABLE_BASE
+    global_base: Option<u32>,                       // key name: GLOBAL_BASE
+    crt_object: Option<PathBuf>,                    // key name: CRT_OBJECT
+    no_crt: bool,                                   // key name: NO_CRT
+    extra_libs: Vec<String>,                        // key name: EXTRA_LIBS
+    omit_default_libs: Vec<String>,                 // key name: OMIT_DEFAULT_LIBS
+    target_triple: Option<String>,                  // key name: TARGET_TRIPLE
+    env_search_paths: bool,                         // key name: ENV_SEARCH_PATHS
+    allow_sysroot_override: bool,                   // key name: ALLOW_SYSROOT_OVERRIDE
+    strict_flags: bool,                             // key name: STRICT_FLAGS
+    export_dynamic: Option<bool>,                   // key name: EXPORT_DYNAMIC
+    strip: Strip,                                   // key name: STRIP
+    embed_build_info: bool,                         // key name: EMBED_BUILD_INFO
+    require_checksums: bool,                        // key name: REQUIRE_CHECKSUMS
 }
 
 impl UserSettings {
@@ -151,14 +298,142 @@ impl UserSettings {
         }
     }
 
+    // The path of the compiler, linker or wasm-opt, after the per-tool
+    // overrides such as CLANG_PATH.
+    pub fn tool_path(&self, tool: &str) -> PathBuf {
+        let override_path = match tool {
+            "clang" => &self.clang_path,
+            "clang++" => &self.clangxx_path,
+            "wasm-ld" => &self.wasm_ld_path,
+            "wasm-opt" => &self.wasm_opt_path,
+            _ => &None,
+        };
+        match override_path {
+            Some(path) => path.clone(),
+            None if matches!(tool, "wasm-opt" | "wasm-split") => {
+                self.binaryen_location.get_tool_path(tool)
+            }
+            None => self.llvm_location.get_tool_path(tool),
+        }
+    }
+
     pub fn ensure_sysroot_location(&self) -> Result<PathBuf> {
+        if self.sysroot_location.is_none() {
+            let check_view =
+                || store::check_project_view(&self.sysroot_prefix, &self.sysroot_store);
+            if let Err(e) = check_view() {
+                let Some(_lock) = self.lock_auto_download()? else {
+                    return Err(e);
+                };
+                // Another build may have synced the view while we waited
+                if let Err(e) = check_view() {
+                    if !self.confirm_auto_download(&format!("{e:#}"))? {
+                        return Err(e);
+                    }
+                    store::sync_sysroot(self)?;
+                }
+            }
+        }
+
         let sysroot = self.sysroot_location()?;
-        if !sysroot.is_dir() {
-            bail!("sysroot does not exist: {}", sysroot.display());
+        if !vfs::get().is_dir(&sysroot) {
+            let message = format!("sysroot does not exist: {}", sysroot.display());
+            // Never download into a sysroot the user pointed us at
+            if self.sysroot_location.is_some() {
+                bail!("{message}");
+            }
+            let Some(_lock) = self.lock_auto_download()? else {
+                bail!("{message}");
+            };
+            if !vfs::get().is_dir(&sysroot) {
+                if !self.confirm_auto_download(&message)? {
+                    bail!("{message}");
+                }
+                download::download_sysroot(compat::matching_sysroot_tag(self), self)?;
+                if !vfs::get().is_dir(&sysroot) {
+                    bail!("{message}");
+                }
+            }
         }
         Ok(sysroot)
     }
 
+    // Takes the download lock when downloading may be allowed, so concurrent
+    // builds ask and download one at a time, and the ones that waited find
+    // the sysroot already there.
+    fn lock_auto_download(&self) -> Result<Option<download::DownloadLock>> {
+        use std::io::IsTerminal;
+
+        let allowed = self
+            .auto_download
+            .unwrap_or_else(|| std::io::stdin().is_terminal() && std::io::stderr().is_terminal());
+        if !allowed {
+            return Ok(None);
+        }
+        download::DownloadLock::acquire().map(Some)
+    }
+
+    // Fails if LLVM isn't installed in the default location and the
+    // fallback to a system clang-21 isn't allowed. STRICT_TOOLCHAIN defaults
+    // to strict for link steps, since a mismatched wasm-ld is the most likely
+    // to silently produce broken modules.
+    pub fn ensure_llvm_location(&self, linking: bool) -> Result<()> {
+        let LlvmLocation::DefaultPath(path) = &self.llvm_location else {
+            return Ok(());
+        };
+        if vfs::get().exists(&path.join("bin")) || !self.strict_toolchain.unwrap_or(linking) {
+            return Ok(());
+        }
+
+        bail!(
+            "No LLVM installation found at {}; run `wasixccenv download-llvm` to install \
+             it, or set LLVM_LOCATION to an existing installation. Pass \
+             -sSTRICT_TOOLCHAIN=no to use the system's LLVM 21 instead, which may \
+             produce broken output.",
+            path.display()
+        )
+    }
+
+    // Whether to download a missing sysroot, as set by AUTO_DOWNLOAD or
+    // confirmed by the user when running on a terminal.
+    fn confirm_auto_download(&self, problem: &str) -> Result<bool> {
+        use std::io::IsTerminal;
+
+        if let Some(auto_download) = self.auto_download {
+            if auto_download {
+                eprintln!("{problem}; downloading the sysroot");
+            }
+            return Ok(auto_download);
+        }
+
+        if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+            return Ok(false);
+        }
+
+        eprint!("{problem}\nDownload the sysroot now? [y/N] ");
+        let mut answer = String::new();
+        std::io::stdin()
+            .read_line(&mut answer)
+            .context("Failed to read answer")?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    pub fn create_temp_dir(&self) -> Result<tempfile::TempDir> {
+        match &self.temp_dir {
+            Some(temp_dir) => {
+                std::fs::create_dir_all(temp_dir).with_context(|| {
+                    format!(
+                        "Failed to create temporary directory root {}",
+                        temp_dir.display()
+                    )
+                })?;
+                tempfile::TempDir::new_in(temp_dir)
+            }
+            None => tempfile::TempDir::new(),
+        }
+        .context("Failed to create temporary directory")
+    }
+
     pub fn module_kind(&self) -> ModuleKind {
         match (self.module_kind, self.pic) {
             (Some(kind), _) => kind,
@@ -166,6 +441,10 @@ impl UserSettings {
             (None, false) => ModuleKind::StaticMain,
         }
     }
+
+    pub fn target_triple(&self) -> &str {
+        self.target_triple.as_deref().unwrap_or("wasm32-wasi")
+    }
 }
 
 fn get_args_and_user_settings() -> Result<(Vec<String>, UserSettings)> {
@@ -175,19 +454,130 @@ fn get_args_and_user_settings() -> Result<(Vec<String>, UserSettings)> {
     Ok((args, user_settings))
 }
 
-fn run_command(mut command: Command) -> Result<()> {
-    tracing::debug!("Executing build command: {command:?}");
+fn run_command(command: Command) -> Result<()> {
+    run_filtered_command(command, None)
+}
 
-    let status = command
-        .status()
-        .with_context(|| format!("Failed to run command: {command:?}"))?;
+// Rewrites a command's stderr output before it's written out. Commands run
+// with a filter have their stderr written once they exit, rather than as
+// it's produced.
+type StderrFilter<'a> = &'a dyn Fn(&str) -> String;
+
+fn run_filtered_command(mut command: Command, filter: Option<StderrFilter>) -> Result<()> {
+    tracing::debug!("Executing build command: {command:?}");
+    reproducer::record_command(&command);
+
+    let start = std::time::SystemTime::now();
+    let started = std::time::Instant::now();
+    let result = run_capturing_stderr(&mut command, filter.is_none());
+    trace::record(
+        &command,
+        start,
+        started.elapsed(),
+        matches!(&result, Ok((status, _)) if status.success()),
+    );
+    let (status, stderr) = result?;
+    if let Some(filter) = filter {
+        eprint!("{}", filter(&stderr));
+    }
     if !status.success() {
-        bail!("Command failed with status: {status}; the command was: {command:?}");
+        let mut message =
+            format!("Command failed with status: {status}; the command was: {command:?}");
+        for hint in hints::for_output(&stderr) {
+            message.push_str(&format!("\nhint: {hint}"));
+        }
+        bail!(message);
     }
 
     Ok(())
 }
 
+// Runs the command, capturing its stderr so common failures can be
+// recognized afterwards. With `pass_through`, stderr is also written out as
+// the command runs.
+fn run_capturing_stderr(command: &mut Command, pass_through: bool) -> Result<(ExitStatus, String)> {
+    let mut child = command
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run command: {command:?}"))?;
+
+    let mut stderr = child
+        .stderr
+        .take()
+        .context("Internal error: stderr not captured")?;
+    let tee = std::thread::spawn(move || {
+        let mut captured = Vec::new();
+        let mut buffer = [0; 4096];
+        while let Ok(read @ 1..) = stderr.read(&mut buffer) {
+            if pass_through {
+                let _ = std::io::stderr().write_all(&buffer[..read]);
+            }
+            captured.extend_from_slice(&buffer[..read]);
+        }
+        captured
+    });
+
+    let status = if control::is_connected() {
+        control::wait_cancellable(&mut child)
+    } else {
+        child.wait().context("Failed to wait for command")
+    };
+    let captured = tee.join().unwrap_or_default();
+    Ok((status?, String::from_utf8_lossy(&captured).into_owned()))
+}
+
+fn run_stage(stage: Stage, detail: &str, command: Command) -> Result<()> {
+    run_filtered_stage(stage, detail, command, None)
+}
+
+fn run_filtered_stage(
+    stage: Stage,
+    detail: &str,
+    command: Command,
+    filter: Option<StderrFilter>,
+) -> Result<()> {
+    control::report(control::Event::StageStarted { stage, detail });
+    let command_line = reproducer::is_enabled().then(|| reproducer::CommandLine::of(&command));
+    let start = std::time::Instant::now();
+    let result = run_filtered_command(command, filter);
+    if let (Err(_), Some(command_line)) = (&result, &command_line) {
+        reproducer::write(stage, command_line);
+    }
+    timing::record(stage, detail, start.elapsed());
+    control::report(control::Event::StageFinished {
+        stage,
+        success: result.is_ok(),
+    });
+    result
+}
+
+fn connect_control_socket(user_settings: &UserSettings) -> Result<()> {
+    match &user_settings.control_socket {
+        Some(path) => control::connect(path),
+        None => Ok(()),
+    }
+}
+
+fn enable_build_reports(user_settings: &UserSettings) {
+    if user_settings.time_report {
+        timing::enable();
+    }
+    if let Some(path) = &user_settings.trace_file {
+        trace::enable(path.clone());
+    }
+    if let Some(dir) = &user_settings.reproducer {
+        reproducer::enable(dir.clone(), format!("{user_settings:#?}\n"));
+    }
+}
+
+fn report_build_finished(result: Result<()>) -> Result<()> {
+    timing::print_report();
+    control::report(control::Event::BuildFinished {
+        success: result.is_ok(),
+    });
+    result
+}
+
 fn run_tool_with_passthrough_args(
     tool: &str,
     args: Vec<String>,
@@ -199,32 +589,196 @@ fn run_tool_with_passthrough_args(
     run_command(command)
 }
 
+/// Prints an error that ended the program, in the format configured with
+/// DIAGNOSTICS_FORMAT.
+pub fn print_error(error: &anyhow::Error) {
+    // The error may well be an invalid setting, so only this one is read
+    let args = std::env::args().collect::<Vec<_>>();
+    let format = try_get_user_setting_value("DIAGNOSTICS_FORMAT", &args)
+        .ok()
+        .flatten()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_default();
+    diagnostics::print_error(format, error);
+}
+
+/// The exit code of a program run with `--run` that failed, to be forwarded
+/// as wasixcc's own.
+#[derive(Debug)]
+pub struct ProgramExit(pub i32);
+
+impl std::fmt::Display for ProgramExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Program exited with code {}", self.0)
+    }
+}
+
+impl std::error::Error for ProgramExit {}
+
 pub fn run_compiler(run_cxx: bool) -> Result<()> {
     tracing::info!("Starting in compiler mode");
 
     let (args, user_settings) = get_args_and_user_settings()?;
-    compiler::run(args, user_settings, run_cxx)
+    connect_control_socket(&user_settings)?;
+    enable_build_reports(&user_settings);
+    report_build_finished(compiler::run(args, user_settings, run_cxx))
+}
+
+/// Builds like `run_compiler`, then rebuilds whenever an input changes, for
+/// `--watch`.
+pub fn run_watch(run_cxx: bool) -> Result<()> {
+    tracing::info!("Starting in watch mode");
+
+    let args = std::env::args()
+        .skip(1)
+        .filter(|arg| arg != "--watch")
+        .collect();
+    let (settings_args, args) = separate_user_settings_args(args);
+    watch::run(args, &settings_args, run_cxx)
+}
+
+/// Builds the inputs like wasixcc (or wasix++, given C++ sources) with
+/// `--run`, for the wasixrun command.
+pub fn run_program() -> Result<()> {
+    tracing::info!("Starting in run mode");
+
+    let (mut args, user_settings) = get_args_and_user_settings()?;
+    let run_cxx = args.iter().any(|arg| is_cxx_source(Path::new(arg)));
+    args.push("--run".to_owned());
+    connect_control_socket(&user_settings)?;
+    enable_build_reports(&user_settings);
+    report_build_finished(compiler::run(args, user_settings, run_cxx))
+}
+
+/// Builds the project described by the `[build]` section of `wasixcc.toml`,
+/// for `wasixcc build`.
+pub fn run_project_build() -> Result<()> {
+    tracing::info!("Starting in project build mode");
+
+    let args = std::env::args().skip(2).collect();
+    let (settings_args, args) = separate_user_settings_args(args);
+    let config_path = match args.as_slice() {
+        [] => config::find_config_file()
+            .with_context(|| format!("Could not find {}", config::CONFIG_FILE_NAME))?,
+        [path] => PathBuf::from(path),
+        _ => bail!("Usage: wasixcc build [CONFIG]"),
+    };
+    let config = config::load_config(&config_path)?;
+    let build = config
+        .build
+        .with_context(|| format!("No [build] section in {}", config_path.display()))?;
+    let base_dir = config_path.parent().unwrap_or(Path::new("."));
+    project::build(&build, base_dir, &settings_args)
+}
+
+/// Compiles every entry of a `compile_commands.json` database with wasixcc,
+/// for `wasixcc build-db`.
+pub fn run_build_db() -> Result<()> {
+    tracing::info!("Starting in build-db mode");
+
+    let args = std::env::args().skip(2).collect();
+    let (settings_args, args) = separate_user_settings_args(args);
+    let usage = "Usage: wasixcc build-db [--out-dir <DIR>] <compile_commands.json>";
+    let (out_dir, database) = match args.as_slice() {
+        [database] => (PathBuf::from("target/wasixcc-build-db"), database),
+        [flag, out_dir, database] if flag == "--out-dir" => (PathBuf::from(out_dir), database),
+        _ => bail!(usage),
+    };
+    let out_dir = std::path::absolute(&out_dir)
+        .with_context(|| format!("Failed to get absolute path of {}", out_dir.display()))?;
+    builddb::run(Path::new(database), &out_dir, &settings_args)
+}
+
+fn is_cxx_source(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ["cc", "cpp", "cxx", "c++", "C"].contains(&&*ext.to_string_lossy()))
 }
 
 pub fn run_linker() -> Result<()> {
     tracing::info!("Starting in linker mode");
 
     let (args, user_settings) = get_args_and_user_settings()?;
-    compiler::link_only(args, user_settings)
+    connect_control_socket(&user_settings)?;
+    enable_build_reports(&user_settings);
+    report_build_finished(compiler::link_only(args, user_settings))
 }
 
 pub fn run_ar() -> Result<()> {
     tracing::info!("Starting in ar mode");
 
     let (args, user_settings) = get_args_and_user_settings()?;
-    run_tool_with_passthrough_args("llvm-ar", args, user_settings)
+    let command = ar::adjust_args(args, user_settings.ar_deterministic, user_settings.ar_thin);
+
+    let mut ar = Command::new(user_settings.llvm_location.get_tool_path("llvm-ar"));
+    ar.args(command.args);
+    run_command(ar)?;
+
+    if let Some(archive) = command.modified_archive.filter(|_| user_settings.ar_ranlib) {
+        let mut ranlib = Command::new(user_settings.llvm_location.get_tool_path("llvm-ranlib"));
+        ranlib.arg(archive);
+        run_command(ranlib)?;
+    }
+
+    Ok(())
 }
 
 pub fn run_nm() -> Result<()> {
+    use std::io::IsTerminal;
+
     tracing::info!("Starting in nm mode");
 
-    let (args, user_settings) = get_args_and_user_settings()?;
-    run_tool_with_passthrough_args("llvm-nm", args, user_settings)
+    let (mut args, user_settings) = get_args_and_user_settings()?;
+    let demangle = user_settings
+        .nm_demangle
+        .unwrap_or_else(|| std::io::stdout().is_terminal());
+    if !demangle || args.iter().any(|arg| arg == "--no-demangle") {
+        return run_tool_with_passthrough_args("llvm-nm", args, user_settings);
+    }
+
+    if !args.iter().any(|arg| arg == "-C" || arg == "--demangle") {
+        args.insert(0, "-C".to_owned());
+    }
+    let mut command = Command::new(user_settings.llvm_location.get_tool_path("llvm-nm"));
+    command.args(&args);
+    tracing::debug!("Executing command: {command:?}");
+    let output = command
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .with_context(|| format!("Failed to run command: {command:?}"))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout =
+        inspect::demangle_embedded_names(&stdout, |names| demangle_names(names, &user_settings))?;
+    print!("{stdout}");
+
+    if !output.status.success() {
+        bail!("Command failed with status: {}", output.status);
+    }
+    Ok(())
+}
+
+// Demangles the names with llvm-cxxfilt, one per line.
+fn demangle_names(names: &[&str], user_settings: &UserSettings) -> Result<Vec<String>> {
+    let mut command = Command::new(user_settings.llvm_location.get_tool_path("llvm-cxxfilt"));
+    command.args(names);
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run command: {command:?}"))?;
+    if !output.status.success() {
+        bail!(
+            "Command failed with status: {}; the command was: {command:?}",
+            output.status
+        );
+    }
+
+    let demangled = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    if demangled.len() != names.len() {
+        bail!("Unexpected output from llvm-cxxfilt");
+    }
+    Ok(demangled)
 }
 
 pub fn run_ranlib() -> Result<()> {
@@ -234,11 +788,186 @@ pub fn run_ranlib() -> Result<()> {
     run_tool_with_passthrough_args("llvm-ranlib", args, user_settings)
 }
 
+pub fn run_objdump() -> Result<()> {
+    tracing::info!("Starting in objdump mode");
+
+    let (args, user_settings) = get_args_and_user_settings()?;
+    if !args.iter().any(|arg| arg == "--wasm-summary") {
+        return run_tool_with_passthrough_args("llvm-objdump", args, user_settings);
+    }
+
+    let files = args
+        .iter()
+        .filter(|arg| !arg.starts_with('-'))
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        bail!("--wasm-summary requires at least one file");
+    }
+    for (i, file) in files.iter().enumerate() {
+        let bytes = std::fs::read(file).with_context(|| format!("Failed to read {file}"))?;
+        let summary =
+            inspect::summary(&bytes).with_context(|| format!("Failed to read module {file}"))?;
+        if i > 0 {
+            println!();
+        }
+        if files.len() > 1 {
+            println!("{file}:");
+        }
+        print!("{summary}");
+    }
+    Ok(())
+}
+
+pub fn run_objcopy() -> Result<()> {
+    tracing::info!("Starting in objcopy mode");
+
+    let (args, user_settings) = get_args_and_user_settings()?;
+    run_tool_with_passthrough_args("llvm-objcopy", args, user_settings)
+}
+
+pub fn run_size() -> Result<()> {
+    tracing::info!("Starting in size mode");
+
+    // Options are llvm-size's; without any, sizes are reported per kind of
+    // wasm section instead.
+    let (args, user_settings) = get_args_and_user_settings()?;
+    if args.is_empty() || args.iter().any(|arg| arg.starts_with('-')) {
+        return run_tool_with_passthrough_args("llvm-size", args, user_settings);
+    }
+
+    let mut files = Vec::new();
+    for file in &args {
+        let bytes = std::fs::read(file).with_context(|| format!("Failed to read {file}"))?;
+        let sizes =
+            inspect::sizes(&bytes).with_context(|| format!("Failed to read module {file}"))?;
+        files.push((file.as_str(), sizes));
+    }
+    print!("{}", inspect::size_table(&files));
+    Ok(())
+}
+
+pub fn run_cxxfilt() -> Result<()> {
+    tracing::info!("Starting in c++filt mode");
+
+    let (args, user_settings) = get_args_and_user_settings()?;
+    run_tool_with_passthrough_args("llvm-cxxfilt", args, user_settings)
+}
+
+pub fn run_dwarfdump() -> Result<()> {
+    tracing::info!("Starting in dwarfdump mode");
+
+    let (args, user_settings) = get_args_and_user_settings()?;
+    run_tool_with_passthrough_args("llvm-dwarfdump", args, user_settings)
+}
+
+pub fn run_addr2line() -> Result<()> {
+    tracing::info!("Starting in addr2line mode");
+
+    let (args, user_settings) = get_args_and_user_settings()?;
+    let (mut options, mut addresses) = inspect::split_addr2line_args(args);
+
+    let code_offsets = options.iter().any(|option| option == "--code-offsets");
+    options.retain(|option| option != "--code-offsets");
+
+    // Addresses read from stdin need translating too, so they're passed on
+    // as arguments.
+    if addresses.is_empty() {
+        for line in std::io::stdin().lines() {
+            let line = line.context("Failed to read addresses from stdin")?;
+            addresses.extend(line.split_whitespace().map(ToOwned::to_owned));
+        }
+    }
+
+    if !code_offsets {
+        if let Some(module) = inspect::addr2line_module(&options) {
+            let bytes =
+                std::fs::read(module).with_context(|| format!("Failed to read {module}"))?;
+            if bytes.starts_with(b"\0asm") {
+                let code_offset = inspect::code_section_offset(&bytes)?;
+                addresses = inspect::translate_addresses(&addresses, code_offset)?;
+            }
+        }
+    }
+
+    options.extend(addresses);
+    run_tool_with_passthrough_args("llvm-addr2line", options, user_settings)
+}
+
+pub fn run_tidy() -> Result<()> {
+    tracing::info!("Starting in tidy mode");
+
+    let (args, user_settings) = get_args_and_user_settings()?;
+    let sysroot_path = user_settings.ensure_sysroot_location()?;
+    let mut command = Command::new(user_settings.llvm_location.get_tool_path("clang-tidy"));
+    command.args(compiler::tidy_args(&user_settings, &sysroot_path));
+    command.args(args);
+    run_command(command)
+}
+
+pub fn run_ccenv() -> Result<()> {
+    tracing::info!("Starting in ccenv mode");
+
+    let (args, user_settings) = get_args_and_user_settings()?;
+    ccenv::run(args, user_settings)
+}
+
 pub fn get_sysroot() -> Result<PathBuf> {
     let (_, user_settings) = get_args_and_user_settings()?;
     user_settings.ensure_sysroot_location()
 }
 
+/// The tools wasixcc runs with the first line of their `--version` output.
+pub fn get_tool_versions() -> Result<Vec<(&'static str, String)>> {
+    let (_, user_settings) = get_args_and_user_settings()?;
+    Ok(["clang", "wasm-ld", "wasm-opt"]
+        .into_iter()
+        .map(|tool| (tool, status::tool_version(&user_settings.tool_path(tool))))
+        .collect())
+}
+
+/// The directories libraries are looked up in, for `-print-search-dirs`.
+pub fn get_library_dirs() -> Result<Vec<PathBuf>> {
+    Ok(compiler::sysroot_library_dirs(&get_sysroot()?).into())
+}
+
+/// The path of a file in the library directories, for `-print-file-name`.
+pub fn get_library_file(name: &str) -> Result<PathBuf> {
+    Ok(compiler::find_library_file(&get_sysroot()?, name))
+}
+
+/// The command the file following `--query-flags` would be compiled with,
+/// given the other arguments and the current configuration, as JSON in the
+/// format of `compile_commands.json` entries.
+pub fn query_compile_flags(run_cxx: bool) -> Result<String> {
+    let (args, user_settings) = get_args_and_user_settings()?;
+
+    let mut input = None;
+    let mut compiler_args = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--query-flags" {
+            input = Some(args.next().context("--query-flags requires a file")?);
+        } else {
+            compiler_args.push(arg);
+        }
+    }
+    let input = input.context("--query-flags requires a file")?;
+
+    let command =
+        compiler::query_compile_command(compiler_args, user_settings, run_cxx, Path::new(&input))?;
+    serde_json::to_string_pretty(&command).context("Failed to serialize compile command")
+}
+
+/// The default compiler flags passed for the current configuration, without
+/// the ones suppressed with SUPPRESS_DEFAULT_CFLAGS.
+pub fn get_default_compiler_flags() -> Result<Vec<String>> {
+    let (_, user_settings) = get_args_and_user_settings()?;
+    Ok(compiler::injected_compiler_flags(&user_settings)
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
 pub fn download_sysroot(tag_spec: TagSpec) -> Result<()> {
     tracing::info!("Downloading sysroot: {:?}", tag_spec);
 
@@ -281,30 +1010,37 @@ fn separate_user_settings_args(args: Vec<String>) -> (Vec<String>, Vec<String>)
 }
 
 fn gather_user_settings(args: &[String]) -> Result<UserSettings> {
-    let llvm_location = match try_get_user_setting_value("LLVM_LOCATION", args)? {
-        Some(path) => LlvmLocation::UserProvided(PathBuf::from(path)),
-        None => LlvmLocation::DefaultPath(
-            std::env::home_dir()
-                .map(|home| home.join(".wasixcc/llvm"))
-                .unwrap_or_else(|| PathBuf::from("/lib/wasixcc/llvm")),
+    // Components are found in the selected toolchain's directory by default
+    let toolchain = toolchains::selected(try_get_user_setting_value("TOOLCHAIN", args)?)?;
+    let base_dir = toolchains::base_dir(toolchain.as_deref());
+
+    let llvm_system_version = try_get_user_setting_value("LLVM_SYSTEM_VERSION", args)?;
+    let llvm_location = match (
+        try_get_user_setting_value("LLVM_LOCATION", args)?,
+        llvm_system_version,
+    ) {
+        (Some(_), Some(_)) => bail!("LLVM_LOCATION and LLVM_SYSTEM_VERSION can't both be set"),
+        (None, Some(version)) => LlvmLocation::FromSystem(
+            version
+                .parse()
+                .with_context(|| format!("Invalid value {version} for LLVM_SYSTEM_VERSION"))?,
         ),
+        (Some(path), None) => LlvmLocation::UserProvided(PathBuf::from(path)),
+        (None, None) => LlvmLocation::DefaultPath(base_dir.join("llvm")),
     };
 
     let binaryen_location = match try_get_user_setting_value("BINARYEN_LOCATION", args)? {
         Some(path) => BinaryenLocation::UserProvided(PathBuf::from(path)),
-        None => BinaryenLocation::DefaultPath(
-            std::env::home_dir()
-                .map(|home| home.join(".wasixcc/binaryen"))
-                .unwrap_or_else(|| PathBuf::from("/lib/wasixcc/binaryen")),
-        ),
+        None => BinaryenLocation::DefaultPath(base_dir.join("binaryen")),
     };
 
     let sysroot_location = try_get_user_setting_value("SYSROOT", args)?;
 
+    // Projects with a lockfile use the sysroots pinned by it
     let sysroot_prefix = try_get_user_setting_value("SYSROOT_PREFIX", args)?
         .map(PathBuf::from)
-        .or_else(|| std::env::home_dir().map(|home| home.join(".wasixcc/sysroot")))
-        .unwrap_or_else(|| PathBuf::from("/lib/wasixcc/sysroot"));
+        .or_else(|| store::find_lockfile().map(|lockfile| store::view_dir(&lockfile)))
+        .unwrap_or_else(|| base_dir.join("sysroot"));
 
     let extra_compiler_flags = match try_get_user_setting_value("COMPILER_FLAGS", args)? {
         Some(flags) => read_string_list_user_setting(&flags),
@@ -384,15 +1120,11 @@ fn gather_user_settings(args: &[String]) -> Result<UserSettings> {
             None => false,
         };
 
-    let module_kind = match try_get_user_setting_value("MODULE_KIND", args)? {
-        Some(kind) => Some(match kind.as_str() {
-            "static-main" => ModuleKind::StaticMain,
-            "dynamic-main" => ModuleKind::DynamicMain,
-            "shared-library" => ModuleKind::SharedLibrary,
-            "object-file" => ModuleKind::ObjectFile,
-            _ => bail!("Unknown module kind: {}", kind),
-        }),
-        None => None, // Default to static main
+    // Components are made from a static main module, see COMPONENT
+    let (module_kind, component_kind) = match try_get_user_setting_value("MODULE_KIND", args)? {
+        Some(kind) if kind == "component" => (Some(ModuleKind::StaticMain), true),
+        Some(kind) => (Some(kind.parse::<ModuleKind>()?), false),
+        None => (None, false), // Default to static main
     };
 
     let wasm_exceptions = match try_get_user_setting_value("WASM_EXCEPTIONS", args)? {
@@ -413,6 +1145,312 @@ fn gather_user_settings(args: &[String]) -> Result<UserSettings> {
         None => true,
     };
 
+    let control_socket = try_get_user_setting_value("CONTROL_SOCKET", args)?.map(PathBuf::from);
+
+    let out_dir = try_get_user_setting_value("OUT_DIR", args)?.map(PathBuf::from);
+
+    let separate_dwarf = match try_get_user_setting_value("SEPARATE_DWARF", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for SEPARATE_DWARF"))?,
+        None => false,
+    };
+
+    let sysroot_store = try_get_user_setting_value("SYSROOT_STORE", args)?
+        .map(PathBuf::from)
+        .or_else(|| std::env::home_dir().map(|home| home.join(".wasixcc/store")))
+        .unwrap_or_else(|| PathBuf::from("/lib/wasixcc/store"));
+
+    let keep_names = match try_get_user_setting_value("KEEP_NAMES", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for KEEP_NAMES"))?,
+        None => false,
+    };
+
+    let auto_prune_threads = match try_get_user_setting_value("AUTO_PRUNE_THREADS", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for AUTO_PRUNE_THREADS"))?,
+        None => false,
+    };
+
+    let temp_dir = try_get_user_setting_value("TEMP_DIR", args)?.map(PathBuf::from);
+
+    let opt_report = try_get_user_setting_value("OPT_REPORT", args)?.map(PathBuf::from);
+
+    let auto_download = match try_get_user_setting_value("AUTO_DOWNLOAD", args)? {
+        Some(value) => Some(
+            read_bool_user_setting(&value)
+                .with_context(|| format!("Invalid value {value} for AUTO_DOWNLOAD"))?,
+        ),
+        None => None,
+    };
+
+    let profiling = match try_get_user_setting_value("PROFILING", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for PROFILING"))?,
+        None => false,
+    };
+
+    let instrument_functions = match try_get_user_setting_value("INSTRUMENT_FUNCTIONS", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for INSTRUMENT_FUNCTIONS"))?,
+        None => false,
+    };
+
+    let suppress_default_cflags = match try_get_user_setting_value("SUPPRESS_DEFAULT_CFLAGS", args)?
+    {
+        Some(value) => read_string_list_user_setting(&value),
+        None => vec![],
+    };
+
+    let download_base_url = try_get_user_setting_value("DOWNLOAD_BASE_URL", args)?;
+
+    let suppress_default_ldflags =
+        match try_get_user_setting_value("SUPPRESS_DEFAULT_LDFLAGS", args)? {
+            Some(value) => read_string_list_user_setting(&value),
+            None => vec![],
+        };
+
+    let nm_demangle = match try_get_user_setting_value("NM_DEMANGLE", args)? {
+        Some(value) => Some(
+            read_bool_user_setting(&value)
+                .with_context(|| format!("Invalid value {value} for NM_DEMANGLE"))?,
+        ),
+        None => None,
+    };
+
+    let ar_deterministic = match try_get_user_setting_value("AR_DETERMINISTIC", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for AR_DETERMINISTIC"))?,
+        None => false,
+    };
+
+    let ar_thin = match try_get_user_setting_value("AR_THIN", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for AR_THIN"))?,
+        None => false,
+    };
+
+    let ar_ranlib = match try_get_user_setting_value("AR_RANLIB", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for AR_RANLIB"))?,
+        None => false,
+    };
+
+    let object_cache = match try_get_user_setting_value("OBJECT_CACHE", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for OBJECT_CACHE"))?,
+        None => false,
+    };
+
+    let time_report = match try_get_user_setting_value("TIME_REPORT", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for TIME_REPORT"))?,
+        None => false,
+    };
+
+    let trace_file = try_get_user_setting_value("TRACE_FILE", args)?.map(PathBuf::from);
+
+    let diagnostics_format = match try_get_user_setting_value("DIAGNOSTICS_FORMAT", args)? {
+        Some(value) => value
+            .parse()
+            .with_context(|| format!("Invalid value {value} for DIAGNOSTICS_FORMAT"))?,
+        None => DiagnosticsFormat::Text,
+    };
+
+    let demangle_linker_errors = match try_get_user_setting_value("DEMANGLE_LINKER_ERRORS", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for DEMANGLE_LINKER_ERRORS"))?,
+        None => true,
+    };
+
+    let reproducer = try_get_user_setting_value("REPRODUCER", args)?.map(PathBuf::from);
+
+    let strict_toolchain = match try_get_user_setting_value("STRICT_TOOLCHAIN", args)? {
+        Some(value) => Some(
+            read_bool_user_setting(&value)
+                .with_context(|| format!("Invalid value {value} for STRICT_TOOLCHAIN"))?,
+        ),
+        None => None,
+    };
+
+    let clang_path = try_get_user_setting_value("CLANG_PATH", args)?.map(PathBuf::from);
+
+    let clangxx_path = try_get_user_setting_value("CLANGXX_PATH", args)?.map(PathBuf::from);
+
+    let wasm_ld_path = try_get_user_setting_value("WASM_LD_PATH", args)?.map(PathBuf::from);
+
+    let wasm_opt_path = try_get_user_setting_value("WASM_OPT_PATH", args)?.map(PathBuf::from);
+
+    let compat_check = match try_get_user_setting_value("COMPAT_CHECK", args)? {
+        Some(value) => value
+            .parse()
+            .with_context(|| format!("Invalid value {value} for COMPAT_CHECK"))?,
+        None => CompatCheck::Warn,
+    };
+
+    let http_proxy = try_get_user_setting_value("HTTP_PROXY", args)?;
+
+    let ca_bundle = try_get_user_setting_value("CA_BUNDLE", args)?.map(PathBuf::from);
+
+    let runner = try_get_user_setting_value("RUNNER", args)?;
+
+    let runner_args = match try_get_user_setting_value("RUNNER_ARGS", args)? {
+        Some(value) => read_string_list_user_setting(&value),
+        None => vec![],
+    };
+
+    let link_summary = match try_get_user_setting_value("LINK_SUMMARY", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for LINK_SUMMARY"))?,
+        None => false,
+    };
+
+    let wizer = match try_get_user_setting_value("WIZER", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for WIZER"))?,
+        None => false,
+    };
+
+    let wizer_init_func = try_get_user_setting_value("WIZER_INIT_FUNC", args)?;
+
+    let wizer_allow_wasi = match try_get_user_setting_value("WIZER_ALLOW_WASI", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for WIZER_ALLOW_WASI"))?,
+        None => false,
+    };
+
+    let wizer_dirs = match try_get_user_setting_value("WIZER_DIRS", args)? {
+        Some(value) => read_string_list_user_setting(&value),
+        None => vec![],
+    };
+
+    let wizer_flags = match try_get_user_setting_value("WIZER_FLAGS", args)? {
+        Some(value) => read_string_list_user_setting(&value),
+        None => vec![],
+    };
+
+    let split_module = match try_get_user_setting_value("SPLIT_MODULE", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for SPLIT_MODULE"))?,
+        None => false,
+    };
+
+    let split_profile = try_get_user_setting_value("SPLIT_PROFILE", args)?.map(PathBuf::from);
+
+    let component = match try_get_user_setting_value("COMPONENT", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for COMPONENT"))?,
+        None => component_kind,
+    };
+
+    let component_adapter =
+        try_get_user_setting_value("COMPONENT_ADAPTER", args)?.map(PathBuf::from);
+
+    let export_list = try_get_user_setting_value("EXPORT_LIST", args)?.map(PathBuf::from);
+
+    let allow_multiple_definition =
+        match try_get_user_setting_value("ALLOW_MULTIPLE_DEFINITION", args)? {
+            Some(value) => value
+                .parse()
+                .with_context(|| format!("Invalid value {value} for ALLOW_MULTIPLE_DEFINITION"))?,
+            None => MultipleDefinitions::Error,
+        };
+
+    let export_table = match try_get_user_setting_value("EXPORT_TABLE", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for EXPORT_TABLE"))?,
+        None => false,
+    };
+
+    let growable_table = match try_get_user_setting_value("GROWABLE_TABLE", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for GROWABLE_TABLE"))?,
+        None => false,
+    };
+
+    let table_base = match try_get_user_setting_value("TABLE_BASE", args)? {
+        Some(value) => Some(
+            value
+                .parse::<u32>()
+                .with_context(|| format!("Invalid value {value} for TABLE_BASE"))?,
+        ),
+        None => None,
+    };
+
+    let global_base = match try_get_user_setting_value("GLOBAL_BASE", args)? {
+        Some(value) => Some(
+            value
+                .parse::<u32>()
+                .with_context(|| format!("Invalid value {value} for GLOBAL_BASE"))?,
+        ),
+        None => None,
+    };
+
+    let crt_object = try_get_user_setting_value("CRT_OBJECT", args)?.map(PathBuf::from);
+
+    let no_crt = match try_get_user_setting_value("NO_CRT", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for NO_CRT"))?,
+        None => false,
+    };
+
+    let extra_libs = match try_get_user_setting_value("EXTRA_LIBS", args)? {
+        Some(value) => read_string_list_user_setting(&value),
+        None => vec![],
+    };
+
+    let omit_default_libs = match try_get_user_setting_value("OMIT_DEFAULT_LIBS", args)? {
+        Some(value) => read_string_list_user_setting(&value),
+        None => vec![],
+    };
+
+    let target_triple = try_get_user_setting_value("TARGET_TRIPLE", args)?;
+
+    let env_search_paths = match try_get_user_setting_value("ENV_SEARCH_PATHS", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for ENV_SEARCH_PATHS"))?,
+        None => true,
+    };
+
+    let allow_sysroot_override = match try_get_user_setting_value("ALLOW_SYSROOT_OVERRIDE", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for ALLOW_SYSROOT_OVERRIDE"))?,
+        None => false,
+    };
+
+    let strict_flags = match try_get_user_setting_value("STRICT_FLAGS", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for STRICT_FLAGS"))?,
+        None => false,
+    };
+
+    let export_dynamic = match try_get_user_setting_value("EXPORT_DYNAMIC", args)? {
+        Some(value) => Some(
+            read_bool_user_setting(&value)
+                .with_context(|| format!("Invalid value {value} for EXPORT_DYNAMIC"))?,
+        ),
+        None => None,
+    };
+
+    let strip = match try_get_user_setting_value("STRIP", args)? {
+        Some(value) => value
+            .parse()
+            .with_context(|| format!("Invalid value {value} for STRIP"))?,
+        None => Strip::None,
+    };
+
+    let embed_build_info = match try_get_user_setting_value("EMBED_BUILD_INFO", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for EMBED_BUILD_INFO"))?,
+        None => false,
+    };
+
+    let require_checksums = match try_get_user_setting_value("REQUIRE_CHECKSUMS", args)? {
+        Some(value) => read_bool_user_setting(&value)
+            .with_context(|| format!("Invalid value {value} for REQUIRE_CHECKSUMS"))?,
+        None => false,
+    };
+
     Ok(UserSettings {
         sysroot_location: sysroot_location.map(Into::into),
         sysroot_prefix,
@@ -434,6 +1472,69 @@ fn gather_user_settings(args: &[String]) -> Result<UserSettings> {
         wasm_exceptions,
         pic,
         link_symbolic,
+        control_socket,
+        out_dir,
+        separate_dwarf,
+        sysroot_store,
+        keep_names,
+        auto_prune_threads,
+        temp_dir,
+        opt_report,
+        auto_download,
+        profiling,
+        instrument_functions,
+        suppress_default_cflags,
+        download_base_url,
+        suppress_default_ldflags,
+        nm_demangle,
+        ar_deterministic,
+        ar_thin,
+        ar_ranlib,
+        object_cache,
+        time_report,
+        trace_file,
+        diagnostics_format,
+        demangle_linker_errors,
+        reproducer,
+        strict_toolchain,
+        clang_path,
+        clangxx_path,
+        wasm_ld_path,
+        wasm_opt_path,
+        compat_check,
+        toolchain,
+        http_proxy,
+        ca_bundle,
+        runner,
+        runner_args,
+        link_summary,
+        wizer,
+        wizer_init_func,
+        wizer_allow_wasi,
+        wizer_dirs,
+        wizer_flags,
+        split_module,
+        split_profile,
+        component,
+        component_adapter,
+        export_list,
+        allow_multiple_definition,
+        export_table,
+        growable_table,
+        table_base,
+        global_base,
+        crt_object,
+        no_crt,
+        extra_libs,
+        omit_default_libs,
+        target_triple,
+        env_search_paths,
+        allow_sysroot_override,
+        strict_flags,
+        export_dynamic,
+        strip,
+        embed_build_info,
+        require_checksums,
     })
 }
 
@@ -578,6 +1679,15 @@ mod tests {
         assert_eq!(settings.module_kind, Some(ModuleKind::SharedLibrary));
         assert!(settings.wasm_exceptions);
         assert!(!settings.pic);
+        assert!(!settings.component);
+
+        let settings = gather_user_settings(&["-sMODULE_KIND=component".to_string()]).unwrap();
+        assert_eq!(settings.module_kind, Some(ModuleKind::StaticMain));
+        assert!(settings.component);
+
+        let settings = gather_user_settings(&["-sTABLE_BASE=16".to_string()]).unwrap();
+        assert_eq!(settings.table_base, Some(16));
+        assert!(gather_user_settings(&["-sTABLE_BASE=-1".to_string()]).is_err());
     }
 
     #[test]
@@ -608,4 +1718,83 @@ mod tests {
         run_tool_with_passthrough_args("dummytool", vec!["X".into(), "Y".into()], user_settings)
             .unwrap();
     }
+
+    #[test]
+    fn test_probing_uses_vfs() {
+        let fs = vfs::MemoryFileSystem::default()
+            .with_dir("/wasix/sysroot-eh/lib")
+            .with_dir("/wasix/llvm/bin");
+
+        vfs::with_file_system(fs, || {
+            let mut us = UserSettings {
+                sysroot_prefix: PathBuf::from("/wasix"),
+                llvm_location: LlvmLocation::DefaultPath(PathBuf::from("/wasix/llvm")),
+                ..Default::default()
+            };
+            assert!(us.ensure_sysroot_location().is_err());
+            us.wasm_exceptions = true;
+            assert_eq!(
+                us.ensure_sysroot_location().unwrap(),
+                PathBuf::from("/wasix/sysroot-eh")
+            );
+            assert_eq!(
+                us.llvm_location.get_tool_path("clang"),
+                PathBuf::from("/wasix/llvm/bin/clang")
+            );
+        });
+    }
+
+    #[test]
+    fn test_tool_path_overrides() {
+        let us = UserSettings {
+            llvm_location: LlvmLocation::UserProvided(PathBuf::from("/llvm")),
+            binaryen_location: BinaryenLocation::UserProvided(PathBuf::from("/binaryen")),
+            wasm_ld_path: Some(PathBuf::from("/build/bin/wasm-ld")),
+            ..Default::default()
+        };
+        assert_eq!(us.tool_path("wasm-ld"), PathBuf::from("/build/bin/wasm-ld"));
+        assert_eq!(us.tool_path("clang"), PathBuf::from("/llvm/bin/clang"));
+        assert_eq!(
+            us.tool_path("wasm-opt"),
+            PathBuf::from("/binaryen/bin/wasm-opt")
+        );
+    }
+
+    #[test]
+    fn test_strict_toolchain() {
+        vfs::with_file_system(vfs::MemoryFileSystem::default(), || {
+            let mut us = UserSettings {
+                llvm_location: LlvmLocation::DefaultPath(PathBuf::from("/wasix/llvm")),
+                ..Default::default()
+            };
+            assert!(us.ensure_llvm_location(false).is_ok());
+            assert!(us.ensure_llvm_location(true).is_err());
+
+            us.strict_toolchain = Some(true);
+            assert!(us.ensure_llvm_location(false).is_err());
+            us.strict_toolchain = Some(false);
+            assert!(us.ensure_llvm_location(true).is_ok());
+
+            us.llvm_location = LlvmLocation::FromSystem(22);
+            us.strict_toolchain = Some(true);
+            assert!(us.ensure_llvm_location(true).is_ok());
+            assert_eq!(
+                us.llvm_location.get_tool_path("wasm-ld"),
+                PathBuf::from("wasm-ld-22")
+            );
+        });
+    }
+
+    #[test]
+    fn test_create_temp_dir() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("nested/tmp");
+        let us = UserSettings {
+            temp_dir: Some(root.clone()),
+            ..Default::default()
+        };
+        let temp_dir = us.create_temp_dir().unwrap();
+        assert!(temp_dir.path().starts_with(&root));
+        assert!(temp_dir.path().is_dir());
+    }
 }
@@ -5,18 +5,21 @@ use tracing::level_filters::LevelFilter;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 use wasixcc::download::TagSpec;
 
-#[cfg(unix)]
-const COMMANDS: &[&str] = &["cc", "++", "cc++", "ar", "nm", "ranlib", "ld"];
-
 enum WasixccCommand {
     Help,
     Version,
+    DumpVersion,
     InstallExecutables(PathBuf),
     DownloadSysroot(TagSpec),
     DownloadLlvm(TagSpec),
     DownloadBinaryen(TagSpec),
     DownloadAll,
     PrintSysroot,
+    PrintDefaultFlags,
+    PrintSearchDirs,
+    PrintFileName(String),
+    QueryFlags,
+    Watch,
     RunTool,
 }
 
@@ -47,6 +50,8 @@ fn get_executable_name() -> Result<String> {
         .context("Failed to get executable file name")?
         .to_str()
         .context("Non-UTF8 characters in executable name")?
+        // Installed as wasixcc.exe etc. on Windows
+        .trim_end_matches(".exe")
         .to_owned())
 }
 
@@ -66,9 +71,9 @@ fn get_command(exe_name: &str) -> Result<String> {
 
 #[cfg_attr(target_vendor = "wasmer", allow(unused_variables))]
 fn install_executables(path: PathBuf) -> Result<()> {
-    #[cfg(not(unix))]
+    #[cfg(not(any(unix, windows)))]
     {
-        bail!("wasixcc only supports installation on unix systems at this time");
+        bail!("wasixcc only supports installation on unix systems and Windows at this time");
     }
 
     #[cfg(unix)]
@@ -80,7 +85,7 @@ fn install_executables(path: PathBuf) -> Result<()> {
 
         let exe_path = env::current_exe().context("Failed to get current executable path")?;
 
-        for command in COMMANDS {
+        for command in wasixcc::COMMANDS {
             let target = path.join(format!("wasix{}", command));
 
             if fs::metadata(&target).is_ok() {
@@ -99,12 +104,51 @@ fn install_executables(path: PathBuf) -> Result<()> {
 
         Ok(())
     }
+
+    #[cfg(windows)]
+    {
+        use std::{env, fs, os::windows::fs as windows_fs};
+
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create directory at {path:?}"))?;
+
+        let exe_path = env::current_exe().context("Failed to get current executable path")?;
+
+        for command in wasixcc::COMMANDS {
+            let target = path.join(format!("wasix{command}.exe"));
+
+            if fs::symlink_metadata(&target).is_ok() {
+                fs::remove_file(&target)
+                    .with_context(|| format!("Failed to remove existing file at {target:?}"))?;
+            }
+
+            // Symlinks need Developer Mode or an elevated prompt, so fall back to
+            // hard links, which need the same volume, and then to copies.
+            let kind = if windows_fs::symlink_file(&exe_path, &target).is_ok() {
+                "symlink"
+            } else if fs::hard_link(&exe_path, &target).is_ok() {
+                "hard link"
+            } else {
+                fs::copy(&exe_path, &target)
+                    .with_context(|| format!("Failed to copy executable to {target:?}"))?;
+                "copy"
+            };
+
+            println!("Created command {target:?} ({kind})");
+        }
+
+        Ok(())
+    }
 }
 
-fn print_version(exe_name: &str) {
+fn print_version(exe_name: &str) -> Result<()> {
     let version = env!("CARGO_PKG_VERSION");
 
     println!("{exe_name} version: {version}");
+    for (tool, tool_version) in wasixcc::get_tool_versions()? {
+        println!("  {:<10}{tool_version}", format!("{tool}:"));
+    }
+    Ok(())
 }
 
 fn print_sysroot() -> Result<()> {
@@ -113,13 +157,37 @@ fn print_sysroot() -> Result<()> {
     Ok(())
 }
 
+// In the format of GCC and clang, which libtool parses
+fn print_search_dirs() -> Result<()> {
+    let dirs = wasixcc::get_library_dirs()?
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect::<Vec<_>>();
+    println!("libraries: ={}", dirs.join(":"));
+    Ok(())
+}
+
+fn print_file_name(name: &str) -> Result<()> {
+    println!("{}", wasixcc::get_library_file(name)?.display());
+    Ok(())
+}
+
+fn print_default_flags() -> Result<()> {
+    for flag in wasixcc::get_default_compiler_flags()? {
+        println!("{flag}");
+    }
+    Ok(())
+}
+
 fn print_help(exe_name: &str) {
     println!(
         r#"Usage: {exe_name} [OPTIONS] -- [PASS-THROUGH OPTIONS]
 
 Options:
   --help, -h                     Print this help message
-  --version, -v                  Print version information
+  --version, -v                  Print the version of wasixcc and of the
+                                 tools it runs
+  -dumpversion                   Print the version of wasixcc only
   -s[CONFIG]=[VALUE]             Set a configuration value, see list below
   --install-executables <PATH>   Install executables to the specified path
   --download-sysroot <TAG>       Download and install the wasix-libc sysroot.
@@ -136,10 +204,40 @@ Options:
                                  downloaded. The downloaded toolchain will be
                                  unpacked into the directory pointed to by
                                  the LLVM_LOCATION setting.
-  --download-all                 Download the latest version of both the 
-                                 sysroot and the LLVM toolchain.
+  --download-binaryen <TAG>      Download and install Binaryen. The tag can
+                                 be 'latest' or a specific tag such as
+                                 'version_124'. The downloaded binaries will
+                                 be unpacked into the directory pointed to by
+                                 the BINARYEN_LOCATION setting.
+  --download-all                 Download the latest version of the sysroot,
+                                 the LLVM toolchain and Binaryen.
   --print-sysroot                Print sysroot location corresponding to
                                  current build configuration
+  -print-search-dirs             Print the sysroot library directories
+  -print-file-name=<FILE>        Print the path of a file in the sysroot
+                                 library directories
+  --print-default-flags          Print the default flags passed to the
+                                 compiler for the current build
+                                 configuration, one per line. These can be
+                                 left out with SUPPRESS_DEFAULT_CFLAGS.
+  --query-flags <FILE>           Print the command the file would be compiled
+                                 with, given the other arguments and the
+                                 current configuration, as a JSON object in
+                                 the format of compile_commands.json entries.
+  --watch                        Build with the other arguments, then rebuild
+                                 whenever a source file, a header it includes
+                                 or a linker input changes, until interrupted.
+  build [CONFIG]                 Build the project described by the [build]
+                                 section of wasixcc.toml, recompiling only
+                                 the sources that changed since the last
+                                 build. Must be the first argument.
+  build-db [--out-dir <DIR>] <COMPILE_COMMANDS>
+                                 Compile every entry of a compile_commands.json
+                                 database with wasixcc instead of the
+                                 compiler it was recorded with, and report
+                                 which ones fail. Objects are written to
+                                 DIR, target/wasixcc-build-db by default.
+                                 Must be the first argument.
 
 Configuration options can be provided on the command line using the
 '-s' flag, or using environment variables prefixed with 'WASIXCC_'.
@@ -155,6 +253,22 @@ The following configuration options are available:");
                            will be executed from LLVM_LOCATION/bin/tool-name.
                            If this option is left out, LLVM binaries will be
                            invoked with a -21 version suffix (e.g. clang-21).
+  LLVM_SYSTEM_VERSION=<N>  Run LLVM binaries from PATH with this version
+                           suffix, e.g. clang-22 and wasm-ld-22 for 22,
+                           instead of an LLVM installation. Can't be
+                           combined with LLVM_LOCATION.
+  BINARYEN_LOCATION=<PATH> Set the location of the Binaryen installation
+                           used to run `wasm-opt`. Similarly to LLVM_LOCATION,
+                           tools will be executed from
+                           BINARYEN_LOCATION/bin/tool-name. If this option is
+                           left out, the Binaryen installation downloaded by
+                           `wasixccenv download-binaryen` is used, falling
+                           back to `wasm-opt` from PATH if there is none.
+  TOOLCHAIN=<NAME>         Use the toolchain installed in
+                           ~/.wasixcc/toolchains/NAME: LLVM, the sysroot
+                           and Binaryen are found (and downloaded) there
+                           unless their locations are set. Defaults to the
+                           toolchain selected with `wasixccenv use`.
   COMPILER_FLAGS=<FLAGS>   Extra flags to pass to the compiler, separated
                            by colons (':')
   COMPILER_POST_FLAGS=<FLAGS>
@@ -224,6 +338,8 @@ The following configuration options are available:");
                            * shared-library: A dynamically-linked side module
                                  which can be loaded by a dynamic main
                            * object-file: An object file
+                           * component: A static-main module wrapped into
+                                 a component, see COMPONENT
   WASM_EXCEPTIONS=<BOOL>   Whether to enable WebAssembly exception handling
                            support. This value can be deduced from the
                            `-fwasm-exceptions`/`-fno-wasm-exceptions` flags
@@ -240,6 +356,294 @@ The following configuration options are available:");
                            this option to `false`. This option is only
                            relevant for dynamic main modules and shared
                            libraries.
+  CONTROL_SOCKET=<PATH>    Path of a unix domain socket to report build
+                           progress to, for IDE and build server integration.
+                           {exe_name} connects to the socket and writes one
+                           JSON object per line as each stage (compile, link,
+                           wasm-opt) starts and finishes. Writing a `cancel`
+                           line to the socket kills the running tool and
+                           fails the build.
+  OUT_DIR=<PATH>           Directory to collect build artifacts in. The
+                           linked artifact is placed directly inside it,
+                           keeping only the file name given to `-o`, and
+                           sidecar files are placed next to it with
+                           predictable names. Outputs of -c, -S and -E stay
+                           where `-o` puts them. Objects compiled for
+                           linking are kept in OUT_DIR/obj, named after the
+                           input and a hash of its path and flags, with
+                           depfiles requested with `-MD` or `-MMD` named the
+                           same way in OUT_DIR/deps.
+  SEPARATE_DWARF=<BOOL>    Move DWARF debug info out of the output into a
+                           companion '<output>.debug.wasm' file, and add an
+                           `external_debug_info` custom section pointing at it
+                           to the output. Implies `-g` if no debug level was
+                           specified. Defaults to false.
+  SYSROOT_STORE=<PATH>     Location of the content-addressed store that
+                           sysroots pinned in a project's wasixcc.lock are
+                           downloaded to. Defaults to `~/.wasixcc/store`.
+  KEEP_NAMES=<BOOL>        Keep the name section (function names) in the
+                           output even at -O2 and above, without shipping
+                           DWARF, so stack traces stay symbolicated. Linker
+                           flags that strip symbols (-s, -S, --strip-all,
+                           --strip-debug) are ignored. Defaults to false.
+  AUTO_PRUNE_THREADS=<BOOL>
+                           After linking a static main module, check whether
+                           it contains any atomic instructions or TLS data. If
+                           it has neither, remove the TLS exports and make its
+                           memory non-shared, so it runs on engines without
+                           threads support. Defaults to false.
+  TEMP_DIR=<PATH>          Directory to create temporary files in, such as
+                           intermediate object files and downloads being
+                           unpacked. Defaults to the system's temporary
+                           directory (e.g. $TMPDIR or /tmp).
+  OPT_REPORT=<PATH>        Write an optimization report to this path: the
+                           optimization remarks from clang for each compiled
+                           file, followed by wasm-opt's metrics and the
+                           functions instrumented by asyncify, as YAML.
+  AUTO_DOWNLOAD=<BOOL>     Download the sysroot when it is missing, then
+                           continue the build. When not set, asks first if
+                           running on a terminal, and fails otherwise. Only
+                           applies when SYSROOT is not set.
+  PROFILING=<BOOL>         Build for profiling with Wasmer: implies
+                           KEEP_NAMES, compiles with frame pointers and
+                           without sibling call optimization, prevents
+                           wasm-opt from inlining, and marks the module
+                           with a 'wasmer.profiling' custom section.
+  INSTRUMENT_FUNCTIONS=<BOOL>
+                           Compile with -finstrument-functions and link a
+                           small runtime recording function entries and
+                           exits into a ring buffer, which the host reads
+                           through the exported wasixcc_trace_* functions.
+  SUPPRESS_DEFAULT_CFLAGS=<FLAGS>
+                           Colon-separated list of default flags that
+                           shouldn't be passed to clang, e.g.
+                           '-fno-trapping-math:-D_WASI_EMULATED_SIGNAL'.
+                           Use --print-default-flags to list the defaults
+                           for the current configuration.
+  DOWNLOAD_BASE_URL=<URL>  Download release info and assets from a mirror
+                           instead of GitHub. Release info is fetched from
+                           <URL>/repos/<repo>/releases/<tag>, as with
+                           api.github.com, and asset URLs on github.com are
+                           rewritten to start with <URL> instead.
+  REQUIRE_CHECKSUMS=<BOOL> Refuse to install downloaded LLVM, sysroot and
+                           Binaryen releases without a known checksum,
+                           rather than installing them with a warning.
+                           Defaults to no.
+  HTTP_PROXY=<URL>         Proxy to use for all downloads, e.g.
+                           'http://proxy.example.com:3128'. Without it, the
+                           standard HTTPS_PROXY, HTTP_PROXY, ALL_PROXY and
+                           NO_PROXY environment variables are used.
+  CA_BUNDLE=<PATH>         PEM file with additional CA certificates to
+                           trust for downloads, such as a corporate CA.
+                           Defaults to SSL_CERT_FILE from the environment.
+  SUPPRESS_DEFAULT_LDFLAGS=<FLAGS>
+                           Colon-separated list of default flags that
+                           shouldn't be passed to wasm-ld, such as
+                           '--import-memory', '--export=__tls_base' or
+                           '-lutil'. Flags with values can be given by name
+                           only, e.g. '--max-memory' or '-z stack-size'.
+  NM_DEMANGLE=<BOOL>       Demangle C++ symbol names in the output of
+                           wasixnm. Defaults to yes when its output is
+                           a terminal, and no otherwise.
+  AR_DETERMINISTIC=<BOOL>  Always create archives in deterministic mode
+                           with wasixar (zero timestamps, UIDs and GIDs),
+                           overriding a 'U' modifier given by the build.
+  AR_THIN=<BOOL>           Create thin archives with wasixar, which
+                           reference the object files instead of
+                           containing copies of them.
+  AR_RANLIB=<BOOL>         Run llvm-ranlib on archives after wasixar
+                           creates or modifies them.
+  OBJECT_CACHE=<BOOL>      Cache compiled objects in
+                           ~/.wasixcc/cache/objects, keyed on the
+                           preprocessed source, the compiler flags and
+                           the compiler version, and reuse them when
+                           compiling the same source again. Manage the
+                           cache with `wasixccenv cache`.
+  TIME_REPORT=<BOOL>       Print the wall time of each compiler, wasm-ld
+                           and wasm-opt invocation, and the total build
+                           time, when the build finishes.
+  TRACE_FILE=<PATH>        Append an event for every subprocess run
+                           during the build (compiler, wasm-ld,
+                           wasm-opt, ...) with its start time and
+                           duration to this file, in Chrome trace-event
+                           format. Builds running in parallel can share
+                           the file. Load it in Perfetto
+                           (ui.perfetto.dev) to see where build time
+                           goes.
+  DIAGNOSTICS_FORMAT=<text|json>
+                           How to report diagnostics. With `json`, clang
+                           reports diagnostics in SARIF format, and
+                           errors from {exe_name} itself (invalid
+                           settings, missing sysroot, failed commands)
+                           are written to stderr as one JSON object with
+                           `message` and `causes` fields, for IDE and CI
+                           integration. Defaults to `text`.
+  DEMANGLE_LINKER_ERRORS=<BOOL>
+                           Pass the linker's error output through
+                           llvm-cxxfilt, so mangled C++ symbol names in
+                           errors such as undefined symbols are readable.
+                           Enabled by default; disable it for scripts
+                           that parse the raw linker output.
+  REPRODUCER=<PATH>        When a compile, link or wasm-opt step fails,
+                           write a tarball to this directory with the
+                           command lines, the failing step's input files
+                           (and preprocessed source, for compile
+                           failures), the effective settings and tool
+                           versions, to attach to bug reports.
+  STRICT_TOOLCHAIN=<BOOL>  Fail with instructions to install LLVM when
+                           LLVM_LOCATION isn't set and no installation
+                           is found in the default location, instead of
+                           falling back to LLVM 21 binaries from PATH
+                           (e.g. clang-21), which may produce broken
+                           output. Defaults to yes for link steps and
+                           no for compile steps.
+  CLANG_PATH=<PATH>        Path of the clang binary to run, instead of
+                           the one found through LLVM_LOCATION or
+                           LLVM_SYSTEM_VERSION.
+  CLANGXX_PATH=<PATH>      Path of the clang++ binary to run, instead of
+                           the one found through LLVM_LOCATION or
+                           LLVM_SYSTEM_VERSION.
+  WASM_LD_PATH=<PATH>      Path of the wasm-ld binary to run, instead of
+                           the one found through LLVM_LOCATION or
+                           LLVM_SYSTEM_VERSION, e.g. to test a locally
+                           built linker with a released toolchain.
+  WASM_OPT_PATH=<PATH>     Path of the wasm-opt binary to run, instead
+                           of the one found through BINARYEN_LOCATION.
+  COMPAT_CHECK=<off|warn|error>
+                           Whether to check that the sysroot and LLVM
+                           come from compatible releases before
+                           compiling, and whether a mismatch is a
+                           warning or an error. Only checks toolchains
+                           downloaded by {exe_name}. Defaults to `warn`.
+  RUNNER=<COMMAND>         Command that runs the output when --run is
+                           passed, e.g. 'wasmer run --dir=.' or 'wasmtime
+                           run'. The output path is appended to it.
+                           Defaults to 'wasmer run'.
+  RUNNER_ARGS=<ARGS>       Colon-separated list of extra arguments for the
+                           RUNNER command, passed before the output path,
+                           e.g. '--net:--env=RUST_LOG=debug'.
+  LINK_SUMMARY=<BOOL>      After linking, print the imports grouped by
+                           module, the exports, the memory and table
+                           limits and the dylink section of the output.
+                           Defaults to no.
+  WIZER=<BOOL>             Run wizer on the linked output before
+                           wasm-opt, snapshotting the state left by its
+                           initialization function for faster startup.
+                           The wizer binary is looked up in PATH.
+                           Defaults to no.
+  WIZER_INIT_FUNC=<NAME>   Exported function wizer runs before taking
+                           the snapshot. Defaults to 'wizer.initialize'.
+  WIZER_ALLOW_WASI=<BOOL>  Allow the initialization function to make
+                           WASI calls. Defaults to no.
+  WIZER_DIRS=<DIRS>        Colon-separated list of directories to
+                           preopen during initialization; implies
+                           WIZER_ALLOW_WASI.
+  WIZER_FLAGS=<FLAGS>      Colon-separated list of extra flags to pass
+                           to wizer.
+  SPLIT_MODULE=<BOOL>      Run Binaryen's wasm-split on the optimized
+                           output. Without SPLIT_PROFILE, this writes an
+                           instrumented build to <output>.instrumented.wasm
+                           for collecting a profile. With it, functions
+                           the profile didn't see run are moved to
+                           <output>.deferred.wasm, and the output becomes
+                           the primary module. Defaults to no.
+  SPLIT_PROFILE=<PATH>     Profile written by the instrumented build of
+                           SPLIT_MODULE, listing the functions to keep
+                           in the primary module.
+  COMPONENT=<BOOL>         After linking, wrap the core module into a
+                           component with `wasm-tools component new`,
+                           adapting its WASI imports with
+                           COMPONENT_ADAPTER. Also enabled by
+                           MODULE_KIND=component. Defaults to no.
+  COMPONENT_ADAPTER=<PATH> Path of the wasi_snapshot_preview1 adapter
+                           module used by COMPONENT, from a Wasmtime
+                           release: the .command.wasm adapter for
+                           executables, .reactor.wasm for libraries.
+  EXPORT_LIST=<PATH>       File listing symbols to export from the output, one
+                           per line, with # starting a comment. Each becomes an
+                           --export flag for wasm-ld, so linking fails if one
+                           isn't defined.
+  ALLOW_MULTIPLE_DEFINITION=<BOOL|report>
+                           Let the first definition of a symbol defined more
+                           than once win instead of failing the link, like
+                           --allow-multiple-definition. With `report`, the
+                           duplicates are also listed with the definition kept.
+  EXPORT_TABLE=<BOOL>      Export the indirect function table, so the host or
+                           a dynamic loader can add functions to it at runtime.
+  GROWABLE_TABLE=<BOOL>    Leave the indirect function table without a maximum
+                           size, so it can grow at runtime.
+  TABLE_BASE=<NUMBER>      Index of the first function in the indirect function
+                           table, leaving the slots below it free. Only for
+                           static mains, like GLOBAL_BASE.
+  GLOBAL_BASE=<NUMBER>     Address in linear memory where static data starts,
+                           leaving the memory below it free. Only for static
+                           mains, as other modules are placed by the loader.
+  CRT_OBJECT=<PATH>        Startup object to link instead of the sysroot's
+                           crt1.o (or scrt1.o for shared libraries), for custom
+                           startup code.
+  NO_CRT=<BOOL>            Don't link a startup object, e.g. when the inputs
+                           define _start themselves.
+  EXTRA_LIBS=<LIST>        Libraries to link along with the default libc ones,
+                           by name with or without -l, e.g.
+                           wasi-emulated-signal.
+  OMIT_DEFAULT_LIBS=<LIST> Default libraries not to link, by name with or
+                           without -l, e.g. rt:util to provide those yourself.
+  TARGET_TRIPLE=<TRIPLE>   Target triple clang compiles for, and that
+                           -dumpmachine reports. Defaults to wasm32-wasi.
+  ENV_SEARCH_PATHS=<BOOL>  Add the directories in CPATH, C_INCLUDE_PATH
+                           and CPLUS_INCLUDE_PATH as -isystem and the
+                           ones in LIBRARY_PATH as -L, after the flags
+                           given. When disabled, they're ignored.
+                           Defaults to yes.
+  ALLOW_SYSROOT_OVERRIDE=<BOOL>
+                           Use the sysroot given with --sysroot, e.g. by
+                           CMake for CMAKE_SYSROOT, for compiling and
+                           linking. Otherwise, --sysroot is ignored with a
+                           warning. Defaults to no.
+  STRICT_FLAGS=<BOOL>      Fail on flags for native targets that don't
+                           apply to wasm, such as -m64, -march=native or
+                           -Wl,-rpath, instead of ignoring them with a
+                           warning. Defaults to no.
+  EXPORT_DYNAMIC=<BOOL>    Whether to link with --export-dynamic, which
+                           exports all global symbols. Defaults to yes for
+                           dynamic mains and side modules and no for static
+                           mains. -Wl,--no-export-dynamic also turns it off.
+  STRIP=<MODE>             What to strip from the output after it's
+                           optimized: `none`, `debug` for the DWARF
+                           sections, or `all` for the name and producers
+                           sections as well. Passing -s is the same as
+                           `all`. Defaults to none.
+  EMBED_BUILD_INFO=<BOOL>  Records the wasixcc version, the LLVM, sysroot
+                           and binaryen releases, the module kind and the
+                           default compiler flags in a `wasixcc.meta` custom
+                           section of the output. Defaults to no.
+
+Per-directory flags: when compiling a source file, {exe_name} looks for a
+'.wasixcc-flags' file in the file's directory and its ancestors. Flags in the
+closest such file (separated by whitespace, with '#' starting a comment line)
+are appended to the compiler command line for that file. Flags such as -O3, -g
+or -fwasm-exceptions also configure the rest of the build, as on the command
+line.
+
+Source maps: passing `-gsource-map` compiles with debug info and generates a
+source map from it next to the output (e.g. 'app.wasm.map'). The map is kept
+in sync when wasm-opt runs, and a `sourceMappingURL` custom section pointing
+at it is added to the module. DWARF info is removed from the output unless
+`-g` is also passed.
+
+Running: passing `--run` runs the output after building it, with the
+command in RUNNER ('wasmer run' by default) and any RUNNER_ARGS, and exits
+with the program's exit code. `wasixrun` is a shorthand for this, picking
+the C++ driver for C++ sources.
+
+Size reports: passing `--size-report` prints the sections of the final
+output by size, followed by its largest functions, data segments and other
+sections. Functions are named from the name section, so combine it with
+-sKEEP_NAMES=yes for optimized builds.
+
+Text output: passing `--emit-wat` writes the WebAssembly text format of each
+object file or linked module next to it, e.g. app.wasm.wat, using
+`wasm-tools print`. For assembly, use -S as usual.
 
 Note: Pass-through options are passed directly to the underlying
 LLVM executables (e.g., clang, wasm-ld, etc.). This is useful for
@@ -258,6 +662,8 @@ fn get_wasixcc_command(exe_name: &str) -> WasixccCommand {
 
             "--version" | "-v" => WasixccCommand::Version,
 
+            "-dumpversion" => WasixccCommand::DumpVersion,
+
             "--install-executables" => {
                 let Some(path) = args.next() else {
                     println!("Usage: {exe_name} --install-executables <PATH>");
@@ -310,7 +716,22 @@ fn get_wasixcc_command(exe_name: &str) -> WasixccCommand {
 
             "--download-all" => WasixccCommand::DownloadAll,
 
-            "--print-sysroot" => WasixccCommand::PrintSysroot,
+            "--print-sysroot" | "-print-sysroot" => WasixccCommand::PrintSysroot,
+
+            "--print-default-flags" => WasixccCommand::PrintDefaultFlags,
+
+            "-print-search-dirs" | "--print-search-dirs" => WasixccCommand::PrintSearchDirs,
+
+            arg if arg.starts_with("-print-file-name=")
+                || arg.starts_with("--print-file-name=") =>
+            {
+                let (_, name) = arg.split_once('=').unwrap();
+                WasixccCommand::PrintFileName(name.to_owned())
+            }
+
+            "--query-flags" => WasixccCommand::QueryFlags,
+
+            "--watch" => WasixccCommand::Watch,
 
             "--" => WasixccCommand::RunTool,
 
@@ -331,8 +752,9 @@ fn run() -> Result<()> {
             print_help(&exe_name);
             Ok(())
         }
-        WasixccCommand::Version => {
-            print_version(&exe_name);
+        WasixccCommand::Version => print_version(&exe_name),
+        WasixccCommand::DumpVersion => {
+            println!("{}", env!("CARGO_PKG_VERSION"));
             Ok(())
         }
         WasixccCommand::InstallExecutables(path) => install_executables(path),
@@ -346,15 +768,40 @@ fn run() -> Result<()> {
             Ok(())
         }
         WasixccCommand::PrintSysroot => print_sysroot(),
+        WasixccCommand::PrintDefaultFlags => print_default_flags(),
+        WasixccCommand::PrintSearchDirs => print_search_dirs(),
+        WasixccCommand::PrintFileName(name) => print_file_name(&name),
+        WasixccCommand::QueryFlags => {
+            let run_cxx = matches!(get_command(&exe_name)?.as_str(), "++" | "cc++");
+            println!("{}", wasixcc::query_compile_flags(run_cxx)?);
+            Ok(())
+        }
+        WasixccCommand::Watch => {
+            let run_cxx = matches!(get_command(&exe_name)?.as_str(), "++" | "cc++");
+            wasixcc::run_watch(run_cxx)
+        }
         WasixccCommand::RunTool => {
             let command_name = get_command(&exe_name)?;
             match command_name.as_str() {
-                "cc" => wasixcc::run_compiler(false),
+                "cc" => match std::env::args().nth(1).as_deref() {
+                    Some("build") => wasixcc::run_project_build(),
+                    Some("build-db") => wasixcc::run_build_db(),
+                    _ => wasixcc::run_compiler(false),
+                },
                 "++" | "cc++" => wasixcc::run_compiler(true),
                 "ld" => wasixcc::run_linker(),
+                "run" => wasixcc::run_program(),
                 "ar" => wasixcc::run_ar(),
                 "nm" => wasixcc::run_nm(),
                 "ranlib" => wasixcc::run_ranlib(),
+                "ccenv" => wasixcc::run_ccenv(),
+                "objdump" => wasixcc::run_objdump(),
+                "objcopy" => wasixcc::run_objcopy(),
+                "size" => wasixcc::run_size(),
+                "c++filt" => wasixcc::run_cxxfilt(),
+                "dwarfdump" => wasixcc::run_dwarfdump(),
+                "addr2line" => wasixcc::run_addr2line(),
+                "tidy" => wasixcc::run_tidy(),
                 cmd => bail!("Unknown command {cmd}"),
             }
         }
@@ -369,7 +816,11 @@ fn main() {
     match run() {
         Ok(()) => (),
         Err(e) => {
-            eprintln!("Error: {:?}", e);
+            // A program run with --run already reported its own failure
+            if let Some(wasixcc::ProgramExit(code)) = e.downcast_ref() {
+                std::process::exit(*code);
+            }
+            wasixcc::print_error(&e);
             std::process::exit(1);
         }
     }
@@ -0,0 +1,316 @@
+//! Matrix builds, driven by the `[matrix]` section of `wasixcc.toml`.
+//!
+//! Each variant runs the configured build command with the variant's
+//! settings exported as `WASIXCC_*` environment variables, and `{out_dir}`
+//! in the command replaced by a per-variant build directory.
+
+use std::{
+    fs::File,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::*;
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct MatrixConfig {
+    /// The build command to run for each variant, e.g.
+    /// `["make", "-j8", "BUILD_DIR={out_dir}"]`. It must mention `{out_dir}`,
+    /// so that variants built in parallel don't share intermediate files.
+    command: Vec<String>,
+    #[serde(default = "default_wasm_exceptions")]
+    wasm_exceptions: Vec<bool>,
+    #[serde(default = "default_opt_levels")]
+    opt_levels: Vec<String>,
+    #[serde(default = "default_module_kinds")]
+    module_kinds: Vec<String>,
+    /// Base output directory; each variant gets a subdirectory named after it.
+    #[serde(default = "default_out_dir")]
+    out_dir: PathBuf,
+    /// Number of variants to build in parallel, defaults to the number of CPUs.
+    jobs: Option<usize>,
+}
+
+fn default_wasm_exceptions() -> Vec<bool> {
+    vec![false, true]
+}
+
+fn default_opt_levels() -> Vec<String> {
+    vec!["-O2".to_owned()]
+}
+
+fn default_module_kinds() -> Vec<String> {
+    vec!["static-main".to_owned()]
+}
+
+fn default_out_dir() -> PathBuf {
+    PathBuf::from("target/wasixcc-matrix")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Variant {
+    name: String,
+    wasm_exceptions: bool,
+    opt_level: String,
+    module_kind: ModuleKind,
+    module_kind_name: String,
+}
+
+enum VariantStatus {
+    Succeeded(Duration),
+    Failed(Duration),
+}
+
+/// Expands the matrix into the list of variants to build, along with the
+/// variants that were skipped because they're not a valid build configuration.
+fn expand_matrix(config: &MatrixConfig) -> Result<(Vec<Variant>, Vec<Variant>)> {
+    if config.command.is_empty() {
+        bail!("The matrix build command must not be empty");
+    }
+    if !config
+        .command
+        .iter()
+        .any(|arg| arg.contains(OUT_DIR_PLACEHOLDER))
+    {
+        bail!(
+            "The matrix build command must build into {OUT_DIR_PLACEHOLDER}, so variants built \
+             in parallel don't share intermediate files"
+        );
+    }
+
+    let mut variants = Vec::new();
+    let mut skipped = Vec::new();
+
+    for &wasm_exceptions in &config.wasm_exceptions {
+        for opt_level in &config.opt_levels {
+            let opt_level = normalize_opt_level(opt_level)?;
+            for module_kind_name in &config.module_kinds {
+                let module_kind = module_kind_name.parse::<ModuleKind>()?;
+                let variant = Variant {
+                    name: format!(
+                        "{}{opt_level}-{module_kind_name}",
+                        if wasm_exceptions { "eh" } else { "noeh" }
+                    ),
+                    wasm_exceptions,
+                    opt_level: opt_level.clone(),
+                    module_kind,
+                    module_kind_name: module_kind_name.clone(),
+                };
+
+                // PIC requires wasm exceptions, see UserSettings::sysroot_location.
+                if module_kind.requires_pic() && !wasm_exceptions {
+                    skipped.push(variant);
+                } else {
+                    variants.push(variant);
+                }
+            }
+        }
+    }
+
+    Ok((variants, skipped))
+}
+
+const OUT_DIR_PLACEHOLDER: &str = "{out_dir}";
+
+fn variant_command(config: &MatrixConfig, out_dir: &Path) -> Vec<String> {
+    let out_dir = out_dir.to_string_lossy();
+    config
+        .command
+        .iter()
+        .map(|arg| arg.replace(OUT_DIR_PLACEHOLDER, &out_dir))
+        .collect()
+}
+
+fn normalize_opt_level(opt_level: &str) -> Result<String> {
+    let level = opt_level.trim_start_matches('-').trim_start_matches('O');
+    match level {
+        "0" | "1" | "2" | "3" | "4" | "s" | "z" => Ok(format!("-O{level}")),
+        _ => bail!("Invalid optimization level in matrix: {opt_level}"),
+    }
+}
+
+fn run_variant(config: &MatrixConfig, variant: &Variant, base_dir: &Path) -> Result<bool> {
+    let out_dir = base_dir.join(&config.out_dir).join(&variant.name);
+    std::fs::create_dir_all(&out_dir)
+        .with_context(|| format!("Failed to create output directory {}", out_dir.display()))?;
+
+    let log_path = out_dir.join("build.log");
+    let log = File::create(&log_path)
+        .with_context(|| format!("Failed to create log file {}", log_path.display()))?;
+
+    // Append to existing post flags rather than replacing them, so the
+    // variant's optimization level wins over the user's.
+    let post_flags = match std::env::var("WASIXCC_COMPILER_POST_FLAGS") {
+        Ok(flags) if !flags.is_empty() => format!("{flags}:{}", variant.opt_level),
+        _ => variant.opt_level.clone(),
+    };
+
+    let bool_value = |value: bool| if value { "yes" } else { "no" };
+
+    let args = variant_command(config, &out_dir);
+    let mut command = Command::new(&args[0]);
+    command
+        .args(&args[1..])
+        .current_dir(base_dir)
+        .env(
+            "WASIXCC_WASM_EXCEPTIONS",
+            bool_value(variant.wasm_exceptions),
+        )
+        .env(
+            "WASIXCC_PIC",
+            bool_value(variant.module_kind.requires_pic()),
+        )
+        .env("WASIXCC_MODULE_KIND", &variant.module_kind_name)
+        .env("WASIXCC_COMPILER_POST_FLAGS", post_flags)
+        .stdout(log.try_clone().context("Failed to clone log file")?)
+        .stderr(log);
+
+    tracing::debug!("Building variant {}: {command:?}", variant.name);
+
+    let status = command
+        .status()
+        .with_context(|| format!("Failed to run command: {command:?}"))?;
+    Ok(status.success())
+}
+
+pub(crate) fn run_matrix(config: &MatrixConfig, base_dir: &Path) -> Result<()> {
+    let (variants, skipped) = expand_matrix(config)?;
+
+    let jobs = config
+        .jobs
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        })
+        .clamp(1, variants.len().max(1));
+
+    eprintln!(
+        "Building {} variants with {jobs} parallel jobs...",
+        variants.len()
+    );
+
+    let queue = Mutex::new(variants.iter().enumerate());
+    let results = Mutex::new(variants.iter().map(|_| None).collect::<Vec<_>>());
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let Some((index, variant)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+
+                let start = Instant::now();
+                let status = match run_variant(config, variant, base_dir) {
+                    Ok(true) => VariantStatus::Succeeded(start.elapsed()),
+                    Ok(false) => VariantStatus::Failed(start.elapsed()),
+                    Err(e) => {
+                        eprintln!("Failed to build variant {}: {e:?}", variant.name);
+                        VariantStatus::Failed(start.elapsed())
+                    }
+                };
+                eprintln!("Finished variant {}", variant.name);
+                results.lock().unwrap()[index] = Some(status);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+
+    let name_width = variants
+        .iter()
+        .chain(&skipped)
+        .map(|v| v.name.len())
+        .max()
+        .unwrap_or(0)
+        .max("Variant".len());
+
+    println!("{:name_width$}  {:8}  Time", "Variant", "Status");
+    let mut failed = 0;
+    for (variant, status) in variants.iter().zip(&results) {
+        let (status, time) = match status {
+            Some(VariantStatus::Succeeded(time)) => ("ok", *time),
+            Some(VariantStatus::Failed(time)) => {
+                failed += 1;
+                ("FAILED", *time)
+            }
+            // Only reachable if a worker thread panicked
+            None => {
+                failed += 1;
+                ("FAILED", Duration::ZERO)
+            }
+        };
+        let time = format!("{:.1}s", time.as_secs_f64());
+        println!("{:name_width$}  {status:8}  {time}", variant.name);
+    }
+    for variant in &skipped {
+        println!(
+            "{:name_width$}  {:8}  PIC requires wasm exceptions",
+            variant.name, "skipped"
+        );
+    }
+
+    if failed > 0 {
+        bail!(
+            "{failed} of {} variants failed; see build.log in each variant's output directory",
+            variants.len()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_matrix() {
+        let config = MatrixConfig {
+            command: vec!["make".to_owned(), "BUILD_DIR={out_dir}".to_owned()],
+            wasm_exceptions: vec![false, true],
+            opt_levels: vec!["-O0".to_owned(), "Oz".to_owned()],
+            module_kinds: vec!["static-main".to_owned(), "shared-library".to_owned()],
+            out_dir: default_out_dir(),
+            jobs: None,
+        };
+
+        let (variants, skipped) = expand_matrix(&config).unwrap();
+        let names = variants.iter().map(|v| v.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "noeh-O0-static-main",
+                "noeh-Oz-static-main",
+                "eh-O0-static-main",
+                "eh-O0-shared-library",
+                "eh-Oz-static-main",
+                "eh-Oz-shared-library",
+            ]
+        );
+        let skipped = skipped.iter().map(|v| v.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            skipped,
+            vec!["noeh-O0-shared-library", "noeh-Oz-shared-library"]
+        );
+        assert_eq!(
+            variant_command(&config, Path::new("out/eh-O0-static-main")),
+            ["make", "BUILD_DIR=out/eh-O0-static-main"]
+        );
+
+        let config = MatrixConfig {
+            command: vec!["make".to_owned()],
+            ..config
+        };
+        assert!(expand_matrix(&config).is_err());
+    }
+
+    #[test]
+    fn test_normalize_opt_level() {
+        assert_eq!(normalize_opt_level("-O2").unwrap(), "-O2");
+        assert_eq!(normalize_opt_level("Os").unwrap(), "-Os");
+        assert_eq!(normalize_opt_level("3").unwrap(), "-O3");
+        assert!(normalize_opt_level("-O9").is_err());
+    }
+}
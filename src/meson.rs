@@ -0,0 +1,69 @@
+//! Meson cross file for building with wasixcc, generated by
+//! `wasixccenv gen-meson-cross`.
+
+use std::collections::BTreeMap;
+
+const BINARIES: [(&str, &str); 6] = [
+    ("c", "wasixcc"),
+    ("cpp", "wasix++"),
+    ("ar", "wasixar"),
+    ("nm", "wasixnm"),
+    ("ranlib", "wasixranlib"),
+    ("c_ld", "wasixld"),
+];
+
+/// Generates a cross file which passes the given settings to every
+/// compiler and linker invocation as `-s` arguments, so builds don't depend
+/// on the environment they're run from.
+pub(crate) fn cross_file(settings: &BTreeMap<String, String>) -> String {
+    let mut contents = String::from("[binaries]\n");
+    for (name, binary) in BINARIES {
+        contents.push_str(&format!("{name} = {}\n", quote(binary)));
+    }
+
+    contents.push_str(
+        "\n[host_machine]\n\
+        system = 'wasi'\n\
+        cpu_family = 'wasm32'\n\
+        cpu = 'wasm32'\n\
+        endian = 'little'\n\
+        \n[properties]\n\
+        needs_exe_wrapper = true\n",
+    );
+
+    let args = settings
+        .iter()
+        .map(|(name, value)| quote(&format!("-s{name}={value}")))
+        .collect::<Vec<_>>()
+        .join(", ");
+    contents.push_str("\n[built-in options]\n");
+    for option in ["c_args", "cpp_args", "c_link_args", "cpp_link_args"] {
+        contents.push_str(&format!("{option} = [{args}]\n"));
+    }
+
+    contents
+}
+
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cross_file() {
+        let settings = BTreeMap::from([
+            ("COMPILER_FLAGS".to_owned(), "-DNAME='x'".to_owned()),
+            ("WASM_EXCEPTIONS".to_owned(), "yes".to_owned()),
+        ]);
+        let contents = cross_file(&settings);
+
+        assert!(contents.contains("c = 'wasixcc'\n"));
+        assert!(contents.contains("cpu_family = 'wasm32'\n"));
+        assert!(contents
+            .contains(r"c_args = ['-sCOMPILER_FLAGS=-DNAME=\'x\'', '-sWASM_EXCEPTIONS=yes']"));
+        assert!(contents.contains("cpp_link_args = ["));
+    }
+}
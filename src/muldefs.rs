@@ -0,0 +1,128 @@
+//! Handling of symbols defined more than once, configured through the
+//! ALLOW_MULTIPLE_DEFINITION setting.
+//!
+//! wasm-ld rejects duplicate definitions unless it's passed
+//! `--allow-multiple-definition`, in which case it silently keeps the first
+//! one it sees. In report mode, the link is first attempted without the flag
+//! so the duplicates wasm-ld reports can be listed along with the definition
+//! that's kept.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use anyhow::bail;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MultipleDefinitions {
+    #[default]
+    Error,
+    Allow,
+    Report,
+}
+
+impl FromStr for MultipleDefinitions {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match super::read_bool_user_setting(s) {
+            Some(true) => Ok(Self::Allow),
+            Some(false) => Ok(Self::Error),
+            None if s == "report" => Ok(Self::Report),
+            None => bail!("Expected a boolean or report, got: {s}"),
+        }
+    }
+}
+
+/// A symbol wasm-ld found more than one definition of.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Duplicate {
+    /// Where the definition wasm-ld keeps is, which is the first one it saw.
+    pub kept: String,
+    pub ignored: Vec<String>,
+}
+
+/// Collects the duplicate symbol errors from wasm-ld's output, which look
+/// like:
+///
+/// ```text
+/// wasm-ld: error: duplicate symbol: foo
+/// >>> defined in a.o
+/// >>> defined in b.o
+/// ```
+pub(crate) fn parse_duplicates(stderr: &str) -> BTreeMap<String, Duplicate> {
+    let mut duplicates = BTreeMap::<String, Duplicate>::new();
+    let mut lines = stderr.lines();
+    while let Some(line) = lines.next() {
+        let Some((_, symbol)) = line.split_once("error: duplicate symbol: ") else {
+            continue;
+        };
+        let mut definitions = lines
+            .clone()
+            .map_while(|line| line.strip_prefix(">>> defined in "));
+        let (Some(kept), Some(ignored)) = (definitions.next(), definitions.next()) else {
+            continue;
+        };
+        let duplicate = duplicates
+            .entry(symbol.trim().to_owned())
+            .or_insert_with(|| Duplicate {
+                kept: kept.to_owned(),
+                ignored: Vec::new(),
+            });
+        duplicate.ignored.push(ignored.to_owned());
+    }
+    duplicates
+}
+
+pub(crate) fn print_report(duplicates: &BTreeMap<String, Duplicate>) {
+    eprintln!("Symbols defined more than once, keeping the first definition:");
+    for (symbol, duplicate) in duplicates {
+        eprintln!("  {symbol}");
+        eprintln!("    kept:    {}", duplicate.kept);
+        for ignored in &duplicate.ignored {
+            eprintln!("    ignored: {ignored}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duplicates() {
+        let stderr = "wasm-ld: error: duplicate symbol: foo\n\
+                      >>> defined in a.o\n\
+                      >>> defined in b.o\n\
+                      \n\
+                      wasm-ld: error: duplicate symbol: foo\n\
+                      >>> defined in a.o\n\
+                      >>> defined in libc.a(c.o)\n\
+                      \n\
+                      wasm-ld: error: undefined symbol: bar\n";
+        let duplicates = parse_duplicates(stderr);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(
+            duplicates["foo"],
+            Duplicate {
+                kept: "a.o".into(),
+                ignored: vec!["b.o".into(), "libc.a(c.o)".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_multiple_definitions() {
+        assert_eq!(
+            "yes".parse::<MultipleDefinitions>().unwrap(),
+            MultipleDefinitions::Allow
+        );
+        assert_eq!(
+            "0".parse::<MultipleDefinitions>().unwrap(),
+            MultipleDefinitions::Error
+        );
+        assert_eq!(
+            "report".parse::<MultipleDefinitions>().unwrap(),
+            MultipleDefinitions::Report
+        );
+        assert!("sometimes".parse::<MultipleDefinitions>().is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Flags for native targets that ports commonly pass along, which are
+//! meaningless or fatal for wasm.
+//!
+//! They're left out with a warning, or rejected with STRICT_FLAGS so that
+//! configure probes for them fail rather than pass with a flag that has no
+//! effect. Flags with a wasm equivalent are replaced with it instead.
+
+use anyhow::{bail, Result};
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Drop,
+    /// Drop the flag along with the value following it.
+    DropWithValue,
+    /// Replace the compiler flag with a linker flag to the same effect.
+    Linker(&'static str),
+}
+
+fn compiler_flag_action(arg: &str) -> Option<(Action, &'static str)> {
+    match arg {
+        "-m32" | "-m64" => Some((Action::Drop, "wasm32 is the only target")),
+        "-rdynamic" => Some((Action::Linker("--export-dynamic"), "")),
+        _ if arg.starts_with("-march=") || arg.starts_with("-mtune=") => {
+            Some((Action::Drop, "there is no native CPU to build for"))
+        }
+        _ if ["-msse", "-mavx", "-mfpmath="]
+            .iter()
+            .any(|prefix| arg.starts_with(prefix)) =>
+        {
+            Some((Action::Drop, "it only applies to x86"))
+        }
+        _ => None,
+    }
+}
+
+fn linker_flag_action(arg: &str) -> Option<(Action, &'static str)> {
+    match arg {
+        "--as-needed" | "--no-as-needed" => Some((
+            Action::Drop,
+            "wasm-ld has no shared library dependencies to prune",
+        )),
+        "-rpath" | "--rpath" | "-rpath-link" | "--rpath-link" => Some((
+            Action::DropWithValue,
+            "shared libraries are found through the runtime's search paths",
+        )),
+        "--enable-new-dtags" | "--disable-new-dtags" => {
+            Some((Action::Drop, "wasm modules have no dynamic section"))
+        }
+        _ if ["-rpath=", "--rpath=", "-rpath-link=", "--rpath-link="]
+            .iter()
+            .any(|prefix| arg.starts_with(prefix)) =>
+        {
+            Some((
+                Action::Drop,
+                "shared libraries are found through the runtime's search paths",
+            ))
+        }
+        _ if arg.starts_with("--hash-style=") => {
+            Some((Action::Drop, "wasm modules have no symbol hash table"))
+        }
+        _ => None,
+    }
+}
+
+fn unsupported(arg: &str, reason: &str, strict: bool) -> Result<()> {
+    if strict {
+        bail!("{arg} is not supported for wasm, as {reason}");
+    }
+    eprintln!("Warning: ignoring {arg}, as {reason}; set STRICT_FLAGS=yes to make this an error");
+    Ok(())
+}
+
+/// Removes the native-only flags from the compiler arguments, moving the ones
+/// with a linker equivalent to the linker arguments.
+pub(crate) fn translate_compiler_args(
+    compiler_args: &mut Vec<String>,
+    linker_args: &mut Vec<String>,
+    strict: bool,
+) -> Result<()> {
+    for arg in std::mem::take(compiler_args) {
+        match compiler_flag_action(&arg) {
+            None => compiler_args.push(arg),
+            Some((Action::Linker(flag), _)) => linker_args.push(flag.to_owned()),
+            Some((_, reason)) => unsupported(&arg, reason, strict)?,
+        }
+    }
+    Ok(())
+}
+
+/// Removes the native-only flags from the linker arguments.
+pub(crate) fn translate_linker_args(linker_args: &mut Vec<String>, strict: bool) -> Result<()> {
+    let mut args = std::mem::take(linker_args).into_iter();
+    while let Some(arg) = args.next() {
+        match linker_flag_action(&arg) {
+            None => linker_args.push(arg),
+            Some((action, reason)) => {
+                unsupported(&arg, reason, strict)?;
+                if action == Action::DropWithValue {
+                    args.next();
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_translate_compiler_args() {
+        let mut compiler_args = strings(&["-m64", "-O2", "-march=native", "-msse4.2", "-rdynamic"]);
+        let mut linker_args = Vec::new();
+        translate_compiler_args(&mut compiler_args, &mut linker_args, false).unwrap();
+        assert_eq!(compiler_args, ["-O2"]);
+        assert_eq!(linker_args, ["--export-dynamic"]);
+
+        let mut compiler_args = strings(&["-m64"]);
+        assert!(translate_compiler_args(&mut compiler_args, &mut Vec::new(), true).is_err());
+
+        // Replacements aren't an error in strict mode
+        let mut compiler_args = strings(&["-rdynamic"]);
+        translate_compiler_args(&mut compiler_args, &mut Vec::new(), true).unwrap();
+    }
+
+    #[test]
+    fn test_translate_linker_args() {
+        let mut linker_args = strings(&[
+            "--as-needed",
+            "-rpath",
+            "/usr/lib",
+            "--rpath=/opt/lib",
+            "--gc-sections",
+        ]);
+        translate_linker_args(&mut linker_args, false).unwrap();
+        assert_eq!(linker_args, ["--gc-sections"]);
+
+        let mut linker_args = strings(&["-rpath", "/usr/lib"]);
+        assert!(translate_linker_args(&mut linker_args, true).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+//! Content-addressed cache of compiled objects, enabled with OBJECT_CACHE.
+//!
+//! Objects are keyed on the preprocessed source, the compiler arguments that
+//! affect code generation and the compiler's version, so a cached object is
+//! only reused when compiling would produce the same result.
+
+use std::{fs, io::Write};
+
+use sha2::{Digest, Sha256};
+
+use super::*;
+
+// One byte is appended to this file per cached compilation: 'h' for a hit
+// and 'm' for a miss. Appends this small are atomic, so concurrent builds
+// can share it.
+const STATS_FILE_NAME: &str = "stats";
+
+pub(crate) fn cache_dir() -> PathBuf {
+    std::env::home_dir()
+        .map(|home| home.join(".wasixcc/cache/objects"))
+        .unwrap_or_else(|| std::env::temp_dir().join("wasixcc-objects"))
+}
+
+/// Compiles an object with `command`, which must write it to `output`,
+/// unless the cache has an object for the same inputs.
+pub(crate) fn compile(
+    command: Command,
+    output: &Path,
+    detail: &str,
+    temp_dir: &Path,
+) -> Result<()> {
+    let dir = cache_dir();
+    let key = match cache_key(&command, output, temp_dir) {
+        Ok(key) => key,
+        Err(e) => {
+            // Let the compiler report problems with the source
+            tracing::debug!("Not using the object cache: {e:?}");
+            return run_stage(Stage::Compile, detail, command);
+        }
+    };
+    let entry = dir.join(&key[..2]).join(format!("{key}.o"));
+
+    if entry.is_file() {
+        control::report(control::Event::StageStarted {
+            stage: Stage::Compile,
+            detail,
+        });
+        let start = std::time::Instant::now();
+        let result = fs::copy(&entry, output)
+            .map(|_| ())
+            .with_context(|| format!("Failed to copy cached object to {}", output.display()));
+        timing::record(Stage::Compile, detail, start.elapsed());
+        control::report(control::Event::StageFinished {
+            stage: Stage::Compile,
+            success: result.is_ok(),
+        });
+        result?;
+        record_stat(&dir, b'h');
+        return Ok(());
+    }
+
+    run_stage(Stage::Compile, detail, command)?;
+    record_stat(&dir, b'm');
+
+    // Failing to fill the cache shouldn't fail the build
+    if let Err(e) = store(output, &entry) {
+        tracing::warn!("Failed to store object in cache: {e:?}");
+    }
+    Ok(())
+}
+
+fn cache_key(command: &Command, output: &Path, temp_dir: &Path) -> Result<String> {
+    let version = Command::new(command.get_program())
+        .arg("--version")
+        .output()
+        .context("Failed to get compiler version")?;
+
+    let preprocessed = temp_dir.join("objcache.i");
+    let mut preprocess = Command::new(command.get_program());
+    for (name, value) in command.get_envs() {
+        match value {
+            Some(value) => preprocess.env(name, value),
+            None => preprocess.env_remove(name),
+        };
+    }
+    preprocess.args(preprocess_args(command, output));
+    preprocess.arg("-E").arg("-o").arg(&preprocessed);
+    run_command(preprocess)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update(&version.stdout);
+    for arg in key_args(command) {
+        hasher.update([0]);
+        hasher.update(arg.as_encoded_bytes());
+    }
+    if embeds_working_dir(command) {
+        let dir = match command.get_current_dir() {
+            Some(dir) => dir.to_owned(),
+            None => std::env::current_dir().context("Failed to get the current directory")?,
+        };
+        hasher.update([0]);
+        hasher.update(dir.as_os_str().as_encoded_bytes());
+    }
+    hasher.update([0]);
+    hasher.update(
+        fs::read(&preprocessed)
+            .with_context(|| format!("Failed to read {}", preprocessed.display()))?,
+    );
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// The compile arguments, without the output. Depfiles are still written
+// when preprocessing, so they're up to date even when the object comes from
+// the cache, but they need to name the real output.
+fn preprocess_args(command: &Command, output: &Path) -> Vec<OsString> {
+    let mut args = Vec::new();
+    let mut has_target = false;
+    let mut wants_depfile = false;
+
+    let mut command_args = command.get_args();
+    while let Some(arg) = command_args.next() {
+        if arg == "-o" {
+            command_args.next();
+            continue;
+        }
+        has_target |= arg == "-MT" || arg == "-MQ";
+        wants_depfile |= arg == "-MD" || arg == "-MMD";
+        args.push(arg.to_owned());
+    }
+
+    if wants_depfile && !has_target {
+        args.push("-MT".into());
+        args.push(output.as_os_str().to_owned());
+    }
+    args
+}
+
+/// The arguments that can affect the object, i.e. all but the output and
+/// the depfile options.
+pub(crate) fn key_args(command: &Command) -> Vec<&OsStr> {
+    let mut args = Vec::new();
+    let mut command_args = command.get_args();
+    while let Some(arg) = command_args.next() {
+        if ["-o", "-MF", "-MT", "-MQ"]
+            .iter()
+            .any(|option| arg == *option)
+        {
+            command_args.next();
+        } else if !["-MD", "-MMD", "-MP"].iter().any(|option| arg == *option) {
+            args.push(arg);
+        }
+    }
+    args
+}
+
+// Debug info records the directory the compiler ran in, unless it's
+// overridden, so objects compiled in different directories differ.
+fn embeds_working_dir(command: &Command) -> bool {
+    let mut debug_info = false;
+    for arg in command.get_args().filter_map(|arg| arg.to_str()) {
+        if arg.starts_with("-fdebug-compilation-dir") || arg.starts_with("-ffile-compilation-dir") {
+            return false;
+        }
+        if arg.starts_with("-g") {
+            debug_info = arg != "-g0";
+        }
+    }
+    debug_info
+}
+
+// Copies through a temporary file, so concurrent builds never see a
+// partially written entry.
+fn store(object: &Path, entry: &Path) -> Result<()> {
+    let dir = entry
+        .parent()
+        .context("Cache entry has no parent directory")?;
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let temp_path = dir.join(format!(".tmp-{}", std::process::id()));
+    fs::copy(object, &temp_path).with_context(|| format!("Failed to copy {}", object.display()))?;
+    fs::rename(&temp_path, entry).with_context(|| format!("Failed to create {}", entry.display()))
+}
+
+fn record_stat(dir: &Path, stat: u8) {
+    let result = fs::create_dir_all(dir).and_then(|_| {
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(STATS_FILE_NAME))?
+            .write_all(&[stat])
+    });
+    if let Err(e) = result {
+        tracing::warn!("Failed to update object cache statistics: {e}");
+    }
+}
+
+/// Prints the number and size of cached objects, and the hit rate since
+/// the cache was last cleared.
+pub(crate) fn print_stats() -> Result<()> {
+    let dir = cache_dir();
+
+    let (mut entries, mut size) = (0, 0);
+    if dir.is_dir() {
+        for shard in
+            fs::read_dir(&dir).with_context(|| format!("Failed to read {}", dir.display()))?
+        {
+            let shard = shard?.path();
+            if !shard.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&shard)? {
+                let metadata = entry?.metadata()?;
+                entries += 1;
+                size += metadata.len();
+            }
+        }
+    }
+
+    let stats = fs::read(dir.join(STATS_FILE_NAME)).unwrap_or_default();
+    let hits = stats.iter().filter(|&&stat| stat == b'h').count();
+    let misses = stats.iter().filter(|&&stat| stat == b'm').count();
+
+    println!("Cache directory: {}", dir.display());
+    println!("Cached objects:  {entries}");
+    println!(
+        "Cache size:      {:.1} MiB",
+        size as f64 / (1024.0 * 1024.0)
+    );
+    println!("Hits:            {hits}");
+    println!("Misses:          {misses}");
+    if hits + misses > 0 {
+        println!(
+            "Hit rate:        {:.1}%",
+            hits as f64 * 100.0 / (hits + misses) as f64
+        );
+    }
+    Ok(())
+}
+
+pub(crate) fn clear() -> Result<()> {
+    let dir = cache_dir();
+    if dir.exists() {
+        fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
+    }
+    eprintln!("Cleared the object cache at {}", dir.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_args() {
+        let mut command = Command::new("clang");
+        command.args(["-c", "-O2", "-MD", "-MF", "deps/a.d", "a.c", "-o", "a.o"]);
+
+        assert_eq!(key_args(&command), ["-c", "-O2", "a.c"]);
+        assert_eq!(
+            preprocess_args(&command, Path::new("out/a.o")),
+            ["-c", "-O2", "-MD", "-MF", "deps/a.d", "a.c", "-MT", "out/a.o"]
+        );
+    }
+
+    #[test]
+    fn test_embeds_working_dir() {
+        let embeds = |args: &[&str]| embeds_working_dir(Command::new("clang").args(args));
+
+        assert!(!embeds(&["-c", "a.c"]));
+        assert!(embeds(&["-c", "-g", "a.c"]));
+        assert!(!embeds(&["-c", "-g", "-g0", "a.c"]));
+        assert!(!embeds(&["-c", "-g", "-fdebug-compilation-dir=.", "a.c"]));
+    }
+}
@@ -0,0 +1,104 @@
+//! Optimization reports for OPT_REPORT.
+//!
+//! Clang writes its optimization remarks for each compiled file to a YAML
+//! record, and wasm-opt prints its metrics (and, for asyncify, the functions
+//! it instrumented) to stdout. The report is a single YAML stream made of all
+//! the clang records followed by a `!WasmOpt` document holding wasm-opt's
+//! output.
+
+use std::io::Write;
+
+use super::*;
+
+/// The file wasm-opt's output is captured to, inside the temporary directory.
+pub(crate) const WASM_OPT_OUTPUT_FILE: &str = "wasm-opt-report.txt";
+
+/// Where clang writes the remarks when compiling to `object`.
+pub(crate) fn record_path(object: &Path) -> PathBuf {
+    let mut path = object.as_os_str().to_owned();
+    path.push(".opt.yaml");
+    PathBuf::from(path)
+}
+
+pub(crate) fn add_compiler_args(command: &mut Command, record: &Path) {
+    let mut record_arg = OsString::from("-foptimization-record-file=");
+    record_arg.push(record);
+    command
+        .arg("-fsave-optimization-record=yaml")
+        .arg(record_arg);
+}
+
+/// Writes the report to `report_path`. `records` holds the source file and
+/// remarks record of each compiled input.
+pub(crate) fn write(
+    report_path: &Path,
+    records: &[(PathBuf, PathBuf)],
+    wasm_opt_output: Option<&Path>,
+) -> Result<()> {
+    let mut report = Vec::new();
+
+    for (input, record) in records {
+        // Clang doesn't create the record if there were no remarks
+        let Ok(contents) = std::fs::read_to_string(record) else {
+            continue;
+        };
+        writeln!(report, "# Remarks from clang for {}", input.display())?;
+        report.extend_from_slice(contents.as_bytes());
+        if !contents.ends_with('\n') {
+            report.push(b'\n');
+        }
+    }
+
+    if let Some(wasm_opt_output) = wasm_opt_output {
+        let contents = std::fs::read_to_string(wasm_opt_output)
+            .with_context(|| format!("Failed to read {}", wasm_opt_output.display()))?;
+        writeln!(report, "--- !WasmOpt\nOutput: |")?;
+        for line in contents.lines() {
+            writeln!(report, "  {line}")?;
+        }
+        writeln!(report, "...")?;
+    }
+
+    if let Some(parent) = report_path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    std::fs::write(report_path, report)
+        .with_context(|| format!("Failed to write {}", report_path.display()))?;
+
+    tracing::info!("Wrote optimization report to {}", report_path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let record = record_path(&tmp.path().join("a.c.0.o"));
+        assert_eq!(record, tmp.path().join("a.c.0.o.opt.yaml"));
+        std::fs::write(&record, "--- !Passed\nPass: inline\n...").unwrap();
+        let wasm_opt_output = tmp.path().join(WASM_OPT_OUTPUT_FILE);
+        std::fs::write(&wasm_opt_output, "total\n [funcs] : 3\n").unwrap();
+
+        let report_path = tmp.path().join("reports/report.yaml");
+        write(
+            &report_path,
+            &[
+                (PathBuf::from("a.c"), record),
+                (PathBuf::from("b.c"), tmp.path().join("missing.opt.yaml")),
+            ],
+            Some(&wasm_opt_output),
+        )
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(report_path).unwrap(),
+            "# Remarks from clang for a.c\n\
+            --- !Passed\nPass: inline\n...\n\
+            --- !WasmOpt\nOutput: |\n  total\n   [funcs] : 3\n...\n"
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! `wasixcc build`: incremental builds of simple projects described by the
+//! `[build]` section of `wasixcc.toml`, without Make or CMake.
+//!
+//! Each source is compiled to an object under the build directory, with a
+//! depfile listing the headers it includes. A source is only recompiled when
+//! it or one of those headers is newer than its object, or when the flags
+//! changed; the output is only relinked when one of the objects is newer.
+
+use std::{fs, time::SystemTime};
+
+use super::*;
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct BuildConfig {
+    /// Glob patterns of the sources to build, e.g. `"src/**/*.c"`.
+    sources: Vec<String>,
+    #[serde(default)]
+    include_dirs: Vec<PathBuf>,
+    /// Preprocessor definitions, as `NAME` or `NAME=VALUE`.
+    #[serde(default)]
+    defines: Vec<String>,
+    /// Extra compiler flags, e.g. `["-O2", "-Wall"]`.
+    #[serde(default)]
+    flags: Vec<String>,
+    /// Extra linker flags, e.g. `["-lm"]`.
+    #[serde(default)]
+    link_flags: Vec<String>,
+    output: PathBuf,
+    #[serde(default = "default_module_kind")]
+    module_kind: String,
+    /// Where objects and depfiles are kept between builds.
+    #[serde(default = "default_build_dir")]
+    build_dir: PathBuf,
+}
+
+fn default_module_kind() -> String {
+    "static-main".to_owned()
+}
+
+fn default_build_dir() -> PathBuf {
+    PathBuf::from("target/wasixcc-build")
+}
+
+// Remembers the flags of the last build, so changing them rebuilds everything.
+const FLAGS_FILE_NAME: &str = "flags";
+
+pub(crate) fn build(config: &BuildConfig, base_dir: &Path, settings_args: &[String]) -> Result<()> {
+    let module_kind = config.module_kind.parse::<ModuleKind>()?;
+    if !module_kind.is_binary() {
+        bail!("module-kind must be static-main, dynamic-main or shared-library");
+    }
+
+    let sources = find_sources(base_dir, &config.sources)?;
+    if sources.is_empty() {
+        bail!("No sources match {:?}", config.sources);
+    }
+    let run_cxx = sources.iter().any(|source| is_cxx_source(source));
+
+    let build_dir = base_dir.join(&config.build_dir);
+    let object_dir = build_dir.join("obj");
+    fs::create_dir_all(&object_dir)
+        .with_context(|| format!("Failed to create build directory {}", build_dir.display()))?;
+
+    let mut compile_args = Vec::new();
+    for dir in &config.include_dirs {
+        compile_args.push(format!("-I{}", base_dir.join(dir).display()));
+    }
+    for define in &config.defines {
+        compile_args.push(format!("-D{define}"));
+    }
+    compile_args.extend(config.flags.iter().cloned());
+
+    // Objects are compiled as PIC when the output needs it
+    let mut compile_settings_args = vec!["-sMODULE_KIND=object-file".to_owned()];
+    if module_kind.requires_pic() {
+        compile_settings_args.push("-sPIC=yes".to_owned());
+    }
+    compile_settings_args.extend(settings_args.iter().cloned());
+
+    let flags_path = build_dir.join(FLAGS_FILE_NAME);
+    let flags = compile_settings_args
+        .iter()
+        .chain(&compile_args)
+        .map(|arg| format!("{arg}\n"))
+        .collect::<String>();
+    let flags_changed = fs::read_to_string(&flags_path).ok().as_deref() != Some(&*flags);
+
+    let mut objects = Vec::new();
+    let mut compiled = 0;
+    for source in &sources {
+        let relative = source.strip_prefix(base_dir).unwrap_or(source);
+        let mut object = object_dir.join(relative).into_os_string();
+        object.push(".o");
+        let object = PathBuf::from(object);
+        let depfile = object.with_extension("d");
+
+        if flags_changed || needs_rebuild(&object, &depfile) {
+            eprintln!("Compiling {}", relative.display());
+            if let Some(parent) = object.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+            }
+
+            let mut args = compile_args.clone();
+            args.extend([
+                "-c".to_owned(),
+                source.to_string_lossy().into_owned(),
+                "-o".to_owned(),
+                object.to_string_lossy().into_owned(),
+                "-MD".to_owned(),
+                "-MF".to_owned(),
+                depfile.to_string_lossy().into_owned(),
+            ]);
+            compiler::run(
+                args,
+                build_settings(&compile_settings_args)?,
+                is_cxx_source(source),
+            )
+            .with_context(|| format!("Failed to compile {}", relative.display()))?;
+            compiled += 1;
+        }
+        objects.push(object);
+    }
+
+    // Written only once everything compiled, so a failed build is retried
+    // with the new flags
+    fs::write(&flags_path, flags)
+        .with_context(|| format!("Failed to write {}", flags_path.display()))?;
+
+    let output = base_dir.join(&config.output);
+    let output_time = modified_time(&output);
+    let needs_link = compiled > 0
+        || output_time.is_none()
+        || objects
+            .iter()
+            .any(|object| modified_time(object) > output_time);
+    if !needs_link {
+        eprintln!("{} is up to date", config.output.display());
+        return Ok(());
+    }
+
+    eprintln!("Linking {}", config.output.display());
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    let mut link_settings_args = vec![format!("-sMODULE_KIND={}", config.module_kind)];
+    link_settings_args.extend(settings_args.iter().cloned());
+
+    let mut args = objects
+        .iter()
+        .map(|object| object.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    args.extend(config.link_flags.iter().cloned());
+    args.extend(["-o".to_owned(), output.to_string_lossy().into_owned()]);
+    compiler::run(args, build_settings(&link_settings_args)?, run_cxx)
+        .with_context(|| format!("Failed to link {}", config.output.display()))?;
+
+    eprintln!(
+        "Built {} ({compiled} sources compiled)",
+        config.output.display()
+    );
+    Ok(())
+}
+
+// The paths are all given explicitly, so OUT_DIR doesn't apply.
+fn build_settings(settings_args: &[String]) -> Result<UserSettings> {
+    let mut user_settings = gather_user_settings(settings_args)?;
+    user_settings.out_dir = None;
+    Ok(user_settings)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Whether the object is missing or older than its source or any header listed
+// in its depfile.
+fn needs_rebuild(object: &Path, depfile: &Path) -> bool {
+    let Some(object_time) = modified_time(object) else {
+        return true;
+    };
+    let Ok(deps) = fs::read_to_string(depfile) else {
+        return true;
+    };
+    let deps = watch::parse_make_deps(&deps);
+    deps.is_empty()
+        || deps
+            .iter()
+            .any(|dep| modified_time(dep).is_none_or(|time| time > object_time))
+}
+
+// The files under `base_dir` matching any of the patterns, sorted.
+fn find_sources(base_dir: &Path, patterns: &[String]) -> Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+    for pattern in patterns {
+        let segments = pattern.split('/').collect::<Vec<_>>();
+
+        // Only walk the part of the tree the pattern can match
+        let literal = segments
+            .iter()
+            .take_while(|segment| !segment.contains(['*', '?']))
+            .count()
+            .min(segments.len() - 1);
+        let root = base_dir.join(segments[..literal].join("/"));
+
+        let mut files = Vec::new();
+        walk_files(&root, &mut files)
+            .with_context(|| format!("Failed to read {}", root.display()))?;
+        for file in files {
+            let Ok(relative) = file.strip_prefix(&root) else {
+                continue;
+            };
+            let components = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>();
+            let components = components.iter().map(|c| &**c).collect::<Vec<_>>();
+            if glob_match(&segments[literal..], &components) {
+                sources.push(file);
+            }
+        }
+    }
+    sources.sort();
+    sources.dedup();
+    Ok(sources)
+}
+
+fn walk_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if dir.is_file() {
+        files.push(dir.to_owned());
+        return Ok(());
+    }
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        // Skip .git and the like
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            walk_files(&entry.path(), files)?;
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+// Matches path components against pattern segments, where `**` matches any
+// number of directories.
+fn glob_match(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.first(), path.first()) {
+        (None, None) => true,
+        (Some(&"**"), _) => {
+            glob_match(&pattern[1..], path) || (!path.is_empty() && glob_match(pattern, &path[1..]))
+        }
+        (Some(segment), Some(component)) => {
+            wildcard_match(segment.as_bytes(), component.as_bytes())
+                && glob_match(&pattern[1..], &path[1..])
+        }
+        _ => false,
+    }
+}
+
+// Matches a single path component, where `*` matches any number of characters
+// and `?` any single one.
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            wildcard_match(&pattern[1..], name)
+                || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) => p == n && wildcard_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        let matches = |pattern: &str, path: &str| {
+            glob_match(
+                &pattern.split('/').collect::<Vec<_>>(),
+                &path.split('/').collect::<Vec<_>>(),
+            )
+        };
+        assert!(matches("*.c", "main.c"));
+        assert!(!matches("*.c", "src/main.c"));
+        assert!(matches("**/*.c", "main.c"));
+        assert!(matches("**/*.c", "a/b/main.c"));
+        assert!(matches("src/**/util?.c", "src/lib/util1.c"));
+        assert!(!matches("src/**/util?.c", "src/lib/util10.c"));
+        assert!(!matches("src/*.c", "src/main.cpp"));
+    }
+
+    #[test]
+    fn test_find_sources() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let dir = tmp.path();
+        fs::create_dir_all(dir.join("src/lib")).unwrap();
+        fs::create_dir_all(dir.join("src/.hidden")).unwrap();
+        for file in [
+            "src/main.c",
+            "src/lib/a.c",
+            "src/lib/a.h",
+            "src/.hidden/b.c",
+        ] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+
+        let sources =
+            find_sources(dir, &["src/**/*.c".to_owned(), "src/main.c".to_owned()]).unwrap();
+        assert_eq!(sources, [dir.join("src/lib/a.c"), dir.join("src/main.c")]);
+    }
+}
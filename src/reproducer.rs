@@ -0,0 +1,225 @@
+//! Reproducer bundles for failed builds, configured through the REPRODUCER
+//! setting.
+//!
+//! When a stage fails, the command lines run so far, the failing command's
+//! input files (and preprocessed source, for compile failures), the
+//! effective settings and tool versions are packed into a tarball in the
+//! configured directory, ready to attach to a bug report.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Mutex, OnceLock},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{objcache, Stage};
+
+static REPRODUCER: OnceLock<Reproducer> = OnceLock::new();
+
+struct Reproducer {
+    dir: PathBuf,
+    settings: String,
+    commands: Mutex<Vec<String>>,
+}
+
+/// Enough of a command to run it again, since `Command` can't be cloned.
+#[derive(Debug)]
+pub(crate) struct CommandLine {
+    program: OsString,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, Option<OsString>)>,
+    current_dir: Option<PathBuf>,
+}
+
+impl CommandLine {
+    pub fn of(command: &Command) -> Self {
+        Self {
+            program: command.get_program().to_owned(),
+            args: command.get_args().map(ToOwned::to_owned).collect(),
+            envs: command
+                .get_envs()
+                .map(|(name, value)| (name.to_owned(), value.map(ToOwned::to_owned)))
+                .collect(),
+            current_dir: command.get_current_dir().map(ToOwned::to_owned),
+        }
+    }
+
+    fn command(&self) -> Command {
+        self.command_with_args(&self.args)
+    }
+
+    // The same command, but with different arguments.
+    fn command_with_args(&self, args: impl IntoIterator<Item = impl AsRef<OsStr>>) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(args);
+        for (name, value) in &self.envs {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+}
+
+/// Starts recording commands, to write a reproducer to `dir` if a stage
+/// fails. `settings` describes the effective settings.
+pub(crate) fn enable(dir: PathBuf, settings: String) {
+    let _ = REPRODUCER.set(Reproducer {
+        dir,
+        settings,
+        commands: Mutex::new(Vec::new()),
+    });
+}
+
+pub(crate) fn is_enabled() -> bool {
+    REPRODUCER.get().is_some()
+}
+
+pub(crate) fn record_command(command: &Command) {
+    if let Some(reproducer) = REPRODUCER.get() {
+        reproducer
+            .commands
+            .lock()
+            .unwrap()
+            .push(format!("{command:?}"));
+    }
+}
+
+/// Writes a reproducer for the failure of `stage` running `command`.
+pub(crate) fn write(stage: Stage, command: &CommandLine) {
+    let Some(reproducer) = REPRODUCER.get() else {
+        return;
+    };
+
+    // Failing to write the reproducer shouldn't hide the actual error.
+    match reproducer.write(stage, command) {
+        Ok(path) => eprintln!(
+            "Wrote a reproducer of the failure to {}; please attach it when reporting \
+            the problem",
+            path.display()
+        ),
+        Err(e) => tracing::warn!("Failed to write reproducer: {e:?}"),
+    }
+}
+
+impl Reproducer {
+    fn write(&self, stage: Stage, command: &CommandLine) -> Result<PathBuf> {
+        let staging_dir =
+            tempfile::TempDir::new().context("Failed to create temporary directory")?;
+        let staging = staging_dir.path();
+
+        let write_file = |name: &str, contents: &[u8]| {
+            fs::write(staging.join(name), contents)
+                .with_context(|| format!("Failed to write {name}"))
+        };
+
+        write_file(
+            "failed-command.txt",
+            format!("{stage:?}: {command:#?}\n").as_bytes(),
+        )?;
+        write_file(
+            "commands.txt",
+            (self.commands.lock().unwrap().join("\n") + "\n").as_bytes(),
+        )?;
+        write_file("settings.txt", self.settings.as_bytes())?;
+
+        let mut versions = format!("wasixcc {}\n", env!("CARGO_PKG_VERSION"));
+        match Command::new(&command.program).arg("--version").output() {
+            Ok(output) => versions.push_str(&String::from_utf8_lossy(&output.stdout)),
+            Err(e) => versions.push_str(&format!("Failed to get tool version: {e}\n")),
+        }
+        write_file("versions.txt", versions.as_bytes())?;
+
+        let inputs_dir = staging.join("inputs");
+        fs::create_dir_all(&inputs_dir).context("Failed to create inputs directory")?;
+        for (index, input) in input_files(&command.args).into_iter().enumerate() {
+            // Inputs from different directories can share a name
+            let name = format!("{index}-{}", input.file_name().unwrap().to_string_lossy());
+            fs::copy(&input, inputs_dir.join(name))
+                .with_context(|| format!("Failed to copy {}", input.display()))?;
+        }
+
+        if stage == Stage::Compile {
+            // Without the output and depfile options
+            let original = command.command();
+            let mut preprocess = command.command_with_args(objcache::key_args(&original));
+            preprocess
+                .arg("-E")
+                .arg("-o")
+                .arg(staging.join("preprocessed.i"));
+            if let Err(e) = preprocess.output() {
+                write_file(
+                    "preprocessed.i.txt",
+                    format!("Failed to preprocess the input: {e}\n").as_bytes(),
+                )?;
+            }
+        }
+
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+        let path = self
+            .dir
+            .join(format!("wasixcc-reproducer-{}.tar.gz", std::process::id()));
+        let file = fs::File::create(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        ));
+        builder
+            .append_dir_all("wasixcc-reproducer", staging)
+            .context("Failed to write reproducer archive")?;
+        builder
+            .into_inner()
+            .and_then(|encoder| encoder.finish())
+            .context("Failed to write reproducer archive")?;
+
+        Ok(path)
+    }
+}
+
+// The files a command reads, i.e. the arguments naming existing files other
+// than the output.
+fn input_files(args: &[OsString]) -> Vec<PathBuf> {
+    let mut inputs = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == OsStr::new("-o") {
+            args.next();
+        } else if Path::new(arg).is_file() {
+            inputs.push(PathBuf::from(arg));
+        }
+    }
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_files() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let source = tmp.path().join("a.c");
+        let output = tmp.path().join("a.o");
+        fs::write(&source, "").unwrap();
+        fs::write(&output, "").unwrap();
+
+        let args = [
+            OsString::from("-c"),
+            source.clone().into(),
+            "-o".into(),
+            output.into(),
+            "-O2".into(),
+        ];
+        assert_eq!(input_files(&args), [source]);
+    }
+}
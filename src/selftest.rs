@@ -0,0 +1,399 @@
+//! `wasixccenv selftest`: builds a set of small programs exercising WASIX
+//! features in each build configuration, runs them under wasmer (or the
+//! configured RUNNER) and reports which ones work.
+//!
+//! Builds run in-process with the user's configuration, overriding only the
+//! settings that define the build configuration.
+
+use super::*;
+
+const RUNNER: &str = "wasmer";
+
+// Where the scenario's directory is mounted in the guest.
+const GUEST_DIR: &str = "/selftest";
+
+struct Configuration {
+    name: &'static str,
+    settings: &'static [(&'static str, &'static str)],
+}
+
+const CONFIGURATIONS: &[Configuration] = &[
+    Configuration {
+        name: "static",
+        settings: &[
+            ("WASM_EXCEPTIONS", "no"),
+            ("PIC", "no"),
+            ("MODULE_KIND", "static-main"),
+        ],
+    },
+    Configuration {
+        name: "static-eh",
+        settings: &[
+            ("WASM_EXCEPTIONS", "yes"),
+            ("PIC", "no"),
+            ("MODULE_KIND", "static-main"),
+        ],
+    },
+    Configuration {
+        name: "dynamic-eh",
+        settings: &[
+            ("WASM_EXCEPTIONS", "yes"),
+            ("PIC", "yes"),
+            ("MODULE_KIND", "dynamic-main"),
+        ],
+    },
+];
+
+struct Build {
+    sources: &'static [(&'static str, &'static str)],
+    output: &'static str,
+    cxx: bool,
+    /// Settings overriding the configuration's, e.g. to build a library.
+    settings: &'static [(&'static str, &'static str)],
+}
+
+struct Scenario {
+    name: &'static str,
+    builds: &'static [Build],
+    /// Arguments passed to the first build's output when running it.
+    args: &'static [&'static str],
+    /// Text the program must print on success.
+    expected_output: &'static str,
+    /// Names of the configurations the scenario applies to.
+    configurations: &'static [&'static str],
+    /// Whether the scenario only runs with `--full`.
+    full_only: bool,
+}
+
+const ALL_CONFIGURATIONS: &[&str] = &["static", "static-eh", "dynamic-eh"];
+
+const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        name: "hello",
+        builds: &[Build {
+            sources: &[("hello.c", include_str!("selftest/hello.c"))],
+            output: "hello.wasm",
+            cxx: false,
+            settings: &[],
+        }],
+        args: &[],
+        expected_output: "hello from wasix",
+        configurations: ALL_CONFIGURATIONS,
+        full_only: false,
+    },
+    Scenario {
+        name: "pthreads",
+        builds: &[Build {
+            sources: &[("pthreads.c", include_str!("selftest/pthreads.c"))],
+            output: "pthreads.wasm",
+            cxx: false,
+            settings: &[],
+        }],
+        args: &[],
+        expected_output: "pthreads ok",
+        configurations: ALL_CONFIGURATIONS,
+        full_only: false,
+    },
+    Scenario {
+        name: "setjmp",
+        builds: &[Build {
+            sources: &[("setjmp.c", include_str!("selftest/setjmp.c"))],
+            output: "setjmp.wasm",
+            cxx: false,
+            settings: &[],
+        }],
+        args: &[],
+        expected_output: "setjmp ok",
+        configurations: ALL_CONFIGURATIONS,
+        full_only: false,
+    },
+    Scenario {
+        name: "exceptions",
+        builds: &[Build {
+            sources: &[("exceptions.cpp", include_str!("selftest/exceptions.cpp"))],
+            output: "exceptions.wasm",
+            cxx: true,
+            settings: &[],
+        }],
+        args: &[],
+        expected_output: "exceptions ok",
+        configurations: &["static-eh", "dynamic-eh"],
+        full_only: false,
+    },
+    Scenario {
+        name: "dlopen",
+        builds: &[
+            Build {
+                sources: &[("dlopen_main.c", include_str!("selftest/dlopen_main.c"))],
+                output: "dlopen.wasm",
+                cxx: false,
+                settings: &[],
+            },
+            Build {
+                sources: &[("dlopen_side.c", include_str!("selftest/dlopen_side.c"))],
+                output: "libside.so",
+                cxx: false,
+                settings: &[("MODULE_KIND", "shared-library")],
+            },
+        ],
+        args: &["/selftest/libside.so"],
+        expected_output: "dlopen ok",
+        configurations: &["dynamic-eh"],
+        full_only: true,
+    },
+    Scenario {
+        name: "sockets",
+        builds: &[Build {
+            sources: &[("sockets.c", include_str!("selftest/sockets.c"))],
+            output: "sockets.wasm",
+            cxx: false,
+            settings: &[],
+        }],
+        args: &[],
+        expected_output: "sockets ok",
+        configurations: ALL_CONFIGURATIONS,
+        full_only: true,
+    },
+];
+
+enum Outcome {
+    Passed,
+    BuildFailed,
+    RunFailed,
+    NotApplicable,
+}
+
+impl Outcome {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Passed => "pass",
+            Self::BuildFailed => "FAIL (build)",
+            Self::RunFailed => "FAIL (run)",
+            Self::NotApplicable => "-",
+        }
+    }
+}
+
+/// Runs the self-test. With `full`, missing toolchain components are
+/// downloaded first, and the slower scenarios are included.
+pub(crate) fn run(full: bool, user_settings: &UserSettings) -> Result<()> {
+    if full {
+        download_missing_components(user_settings)?;
+    }
+
+    let runner = match &user_settings.runner {
+        Some(_) => compiler::runner_command(user_settings)
+            .get_program()
+            .to_owned(),
+        None => RUNNER.into(),
+    };
+    Command::new(&runner)
+        .arg("--version")
+        .output()
+        .with_context(|| format!("Failed to run {runner:?}, which is needed for the self-test"))?;
+
+    // Settings from the command line still apply to the builds
+    let (base_settings_args, _) = separate_user_settings_args(std::env::args().skip(1).collect());
+
+    let work_dir = user_settings.create_temp_dir()?;
+    let scenarios = SCENARIOS
+        .iter()
+        .filter(|scenario| full || !scenario.full_only)
+        .collect::<Vec<_>>();
+
+    let mut outcomes = Vec::new();
+    for scenario in &scenarios {
+        let mut row = Vec::new();
+        for configuration in CONFIGURATIONS {
+            if !scenario.configurations.contains(&configuration.name) {
+                row.push(Outcome::NotApplicable);
+                continue;
+            }
+
+            eprintln!("Running {} ({})...", scenario.name, configuration.name);
+            let dir = work_dir
+                .path()
+                .join(format!("{}-{}", scenario.name, configuration.name));
+            row.push(run_scenario(
+                scenario,
+                configuration,
+                &dir,
+                &base_settings_args,
+                user_settings,
+            ));
+        }
+        outcomes.push(row);
+    }
+
+    print_report(&scenarios, &outcomes);
+
+    let total = outcomes
+        .iter()
+        .flatten()
+        .filter(|o| !matches!(o, Outcome::NotApplicable))
+        .count();
+    let failed = outcomes
+        .iter()
+        .flatten()
+        .filter(|o| matches!(o, Outcome::BuildFailed | Outcome::RunFailed))
+        .count();
+    if failed > 0 {
+        let kept_dir = work_dir.keep();
+        bail!(
+            "{failed} of {total} self-test checks failed; build outputs are kept in {}",
+            kept_dir.display()
+        );
+    }
+
+    eprintln!("All {total} self-test checks passed");
+    Ok(())
+}
+
+fn run_scenario(
+    scenario: &Scenario,
+    configuration: &Configuration,
+    dir: &Path,
+    base_settings_args: &[String],
+    user_settings: &UserSettings,
+) -> Outcome {
+    for build in scenario.builds {
+        if let Err(e) = run_build(build, configuration, dir, base_settings_args) {
+            eprintln!("Building {} failed: {e:?}", build.output);
+            return Outcome::BuildFailed;
+        }
+    }
+
+    let mut command = match &user_settings.runner {
+        // Other runners can't be told where to mount the directory, so it's
+        // the working directory instead, which RUNNER_ARGS must make
+        // available to the guest.
+        Some(_) => {
+            let mut command = compiler::runner_command(user_settings);
+            command
+                .current_dir(dir)
+                .arg(dir.join(scenario.builds[0].output))
+                .args(
+                    scenario
+                        .args
+                        .iter()
+                        .map(|arg| arg.replacen(GUEST_DIR, ".", 1)),
+                );
+            command
+        }
+        None => {
+            let mut command = Command::new(RUNNER);
+            let mut volume = dir.as_os_str().to_owned();
+            volume.push(format!(":{GUEST_DIR}"));
+            command
+                .arg("run")
+                .arg("--net")
+                .arg("--volume")
+                .arg(volume)
+                .arg(dir.join(scenario.builds[0].output))
+                .arg("--")
+                .args(scenario.args);
+            command
+        }
+    };
+    tracing::debug!("Executing command: {command:?}");
+
+    match command.output() {
+        Ok(output) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            if output.status.success() && stdout.contains(scenario.expected_output) {
+                Outcome::Passed
+            } else {
+                eprintln!(
+                    "Running {} failed with {}:\n{stdout}{}",
+                    scenario.name,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr)
+                );
+                Outcome::RunFailed
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to run {command:?}: {e}");
+            Outcome::RunFailed
+        }
+    }
+}
+
+fn run_build(
+    build: &Build,
+    configuration: &Configuration,
+    dir: &Path,
+    base_settings_args: &[String],
+) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+
+    let mut args = vec!["-O2".to_owned()];
+    for (name, contents) in build.sources {
+        let path = dir.join(name);
+        std::fs::write(&path, contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        args.push(path.display().to_string());
+    }
+    args.push("-o".to_owned());
+    args.push(dir.join(build.output).display().to_string());
+
+    // The first value of a setting wins, so the build's settings go first.
+    let mut settings_args = build
+        .settings
+        .iter()
+        .chain(configuration.settings)
+        .map(|(name, value)| format!("-s{name}={value}"))
+        .collect::<Vec<_>>();
+    settings_args.push(format!("-sOUT_DIR={}", dir.display()));
+    settings_args.extend_from_slice(base_settings_args);
+
+    let user_settings = gather_user_settings(&settings_args)?;
+    compiler::run(args, user_settings, build.cxx)
+}
+
+fn download_missing_components(user_settings: &UserSettings) -> Result<()> {
+    let llvm_path = user_settings.llvm_location.install_path();
+    if llvm_path.is_some_and(|path| !path.join("bin").is_dir()) {
+        download::download_llvm(TagSpec::Latest, user_settings)?;
+    }
+
+    if user_settings.sysroot_location.is_none() && !user_settings.sysroot_location()?.is_dir() {
+        download::download_sysroot(TagSpec::Latest, user_settings)?;
+    }
+
+    let (BinaryenLocation::UserProvided(binaryen_path)
+    | BinaryenLocation::DefaultPath(binaryen_path)) = &user_settings.binaryen_location;
+    if !binaryen_path.join("bin").is_dir() {
+        download::download_binaryen(TagSpec::Latest, user_settings)?;
+    }
+
+    Ok(())
+}
+
+fn print_report(scenarios: &[&Scenario], outcomes: &[Vec<Outcome>]) {
+    let name_width = scenarios
+        .iter()
+        .map(|scenario| scenario.name.len())
+        .chain(["scenario".len()])
+        .max()
+        .unwrap_or_default();
+    let column_widths = CONFIGURATIONS
+        .iter()
+        .map(|configuration| configuration.name.len().max("FAIL (build)".len()))
+        .collect::<Vec<_>>();
+
+    let mut header = format!("{:name_width$}", "scenario");
+    for (configuration, width) in CONFIGURATIONS.iter().zip(&column_widths) {
+        header.push_str(&format!("  {:width$}", configuration.name));
+    }
+    println!("{}", header.trim_end());
+
+    for (scenario, row) in scenarios.iter().zip(outcomes) {
+        let mut line = format!("{:name_width$}", scenario.name);
+        for (outcome, width) in row.iter().zip(&column_widths) {
+            line.push_str(&format!("  {:width$}", outcome.label()));
+        }
+        println!("{}", line.trim_end());
+    }
+}
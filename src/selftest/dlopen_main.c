@@ -0,0 +1,30 @@
+#include <dlfcn.h>
+#include <stdio.h>
+
+int main(int argc, char **argv) {
+  if (argc < 2) {
+    printf("usage: %s <library>\n", argv[0]);
+    return 1;
+  }
+
+  void *library = dlopen(argv[1], RTLD_NOW);
+  if (!library) {
+    printf("dlopen failed: %s\n", dlerror());
+    return 1;
+  }
+
+  int (*add)(int, int) = (int (*)(int, int))dlsym(library, "side_add");
+  if (!add) {
+    printf("dlsym failed: %s\n", dlerror());
+    return 1;
+  }
+
+  if (add(2, 3) != 5) {
+    printf("side_add returned %d\n", add(2, 3));
+    return 1;
+  }
+
+  dlclose(library);
+  printf("dlopen ok\n");
+  return 0;
+}
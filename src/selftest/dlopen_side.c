@@ -0,0 +1,6 @@
+static int calls;
+
+int side_add(int a, int b) {
+  calls++;
+  return a + b;
+}
@@ -0,0 +1,30 @@
+#include <cstdio>
+#include <stdexcept>
+#include <string>
+
+struct Guard {
+  int &destroyed;
+  ~Guard() { destroyed++; }
+};
+
+static void thrower(int &destroyed) {
+  Guard guard{destroyed};
+  throw std::runtime_error("expected");
+}
+
+int main() {
+  int destroyed = 0;
+  try {
+    thrower(destroyed);
+  } catch (const std::exception &e) {
+    if (std::string(e.what()) != "expected" || destroyed != 1) {
+      std::printf("caught '%s' with %d destructors run\n", e.what(), destroyed);
+      return 1;
+    }
+    std::printf("exceptions ok\n");
+    return 0;
+  }
+
+  std::printf("exception was not caught\n");
+  return 1;
+}
@@ -0,0 +1,47 @@
+#include <pthread.h>
+#include <stdio.h>
+
+#define THREADS 4
+#define ITERATIONS 1000
+
+static pthread_mutex_t mutex = PTHREAD_MUTEX_INITIALIZER;
+static _Thread_local int local_counter;
+static int counter;
+
+static void *work(void *arg) {
+  (void)arg;
+  for (int i = 0; i < ITERATIONS; i++) {
+    local_counter++;
+    pthread_mutex_lock(&mutex);
+    counter++;
+    pthread_mutex_unlock(&mutex);
+  }
+  return (void *)(long)local_counter;
+}
+
+int main(void) {
+  pthread_t threads[THREADS];
+  for (int i = 0; i < THREADS; i++) {
+    if (pthread_create(&threads[i], NULL, work, NULL) != 0) {
+      printf("pthread_create failed\n");
+      return 1;
+    }
+  }
+
+  for (int i = 0; i < THREADS; i++) {
+    void *result;
+    pthread_join(threads[i], &result);
+    if ((long)result != ITERATIONS) {
+      printf("thread-local counter is %ld\n", (long)result);
+      return 1;
+    }
+  }
+
+  if (counter != THREADS * ITERATIONS) {
+    printf("counter is %d\n", counter);
+    return 1;
+  }
+
+  printf("pthreads ok\n");
+  return 0;
+}
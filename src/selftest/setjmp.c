@@ -0,0 +1,30 @@
+#include <setjmp.h>
+#include <stdio.h>
+
+static jmp_buf env;
+
+static void jump(int depth) {
+  if (depth == 0) {
+    longjmp(env, 42);
+  }
+  jump(depth - 1);
+}
+
+int main(void) {
+  volatile int jumps = 0;
+  int value = setjmp(env);
+  if (value == 0) {
+    jump(10);
+    printf("longjmp returned\n");
+    return 1;
+  }
+
+  jumps++;
+  if (value != 42 || jumps != 1) {
+    printf("setjmp returned %d after %d jumps\n", value, jumps);
+    return 1;
+  }
+
+  printf("setjmp ok\n");
+  return 0;
+}
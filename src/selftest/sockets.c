@@ -0,0 +1,59 @@
+#include <arpa/inet.h>
+#include <netinet/in.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+static struct sockaddr_in address;
+
+static void *client(void *arg) {
+  (void)arg;
+  int fd = socket(AF_INET, SOCK_STREAM, 0);
+  if (fd < 0 || connect(fd, (struct sockaddr *)&address, sizeof(address)) != 0) {
+    return (void *)1;
+  }
+  const char message[] = "ping";
+  write(fd, message, sizeof(message));
+  close(fd);
+  return NULL;
+}
+
+int main(void) {
+  int listener = socket(AF_INET, SOCK_STREAM, 0);
+  if (listener < 0) {
+    printf("socket failed\n");
+    return 1;
+  }
+
+  address.sin_family = AF_INET;
+  address.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+  address.sin_port = 0;
+  socklen_t length = sizeof(address);
+  if (bind(listener, (struct sockaddr *)&address, sizeof(address)) != 0 ||
+      listen(listener, 1) != 0 ||
+      getsockname(listener, (struct sockaddr *)&address, &length) != 0) {
+    printf("failed to listen on loopback\n");
+    return 1;
+  }
+
+  pthread_t thread;
+  pthread_create(&thread, NULL, client, NULL);
+
+  int connection = accept(listener, NULL, NULL);
+  char buffer[16] = {0};
+  ssize_t received = connection < 0 ? -1 : read(connection, buffer, sizeof(buffer));
+
+  void *client_result;
+  pthread_join(thread, &client_result);
+  if (client_result != NULL || received <= 0 || strcmp(buffer, "ping") != 0) {
+    printf("loopback connection failed\n");
+    return 1;
+  }
+
+  close(connection);
+  close(listener);
+  printf("sockets ok\n");
+  return 0;
+}
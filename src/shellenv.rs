@@ -0,0 +1,288 @@
+//! `wasixccenv env`: prints the environment needed to build with wasixcc
+//! from other build systems, e.g. `eval "$(wasixccenv env)"`.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use super::*;
+
+// Variables pointing build systems at the wasixcc tools, along with the
+// CMake variables they correspond to.
+const TOOL_VARIABLES: [(&str, &str, &str); 6] = [
+    ("CC", "CMAKE_C_COMPILER", "wasixcc"),
+    ("CXX", "CMAKE_CXX_COMPILER", "wasix++"),
+    ("AR", "CMAKE_AR", "wasixar"),
+    ("RANLIB", "CMAKE_RANLIB", "wasixranlib"),
+    ("NM", "CMAKE_NM", "wasixnm"),
+    ("LD", "CMAKE_LINKER", "wasixld"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Shell {
+    /// Also used for zsh, which understands the same syntax.
+    Bash,
+    Fish,
+    /// `set()` commands for a CMake toolchain file.
+    Cmake,
+}
+
+impl FromStr for Shell {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" | "zsh" | "sh" => Ok(Self::Bash),
+            "fish" => Ok(Self::Fish),
+            "cmake" => Ok(Self::Cmake),
+            _ => bail!("Unknown shell: {s}; expected bash, zsh, fish or cmake"),
+        }
+    }
+}
+
+/// Prints the tool variables and the explicitly configured settings, from
+/// both `-s` arguments and the environment, in the given shell's syntax.
+pub(crate) fn print_env(shell: Shell) -> Result<()> {
+    for line in env_lines(shell, &current_settings()) {
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+/// The settings explicitly configured for this invocation, by name.
+pub(crate) fn current_settings() -> BTreeMap<String, String> {
+    let (settings_args, _) = separate_user_settings_args(std::env::args().skip(1).collect());
+    configured_settings(&settings_args, std::env::vars())
+}
+
+// Settings given as arguments take precedence over the environment, the same
+// as when they're read by gather_user_settings.
+fn configured_settings(
+    settings_args: &[String],
+    env_vars: impl Iterator<Item = (String, String)>,
+) -> BTreeMap<String, String> {
+    let mut settings = env_vars
+        .filter_map(|(name, value)| Some((name.strip_prefix("WASIXCC_")?.to_owned(), value)))
+        .collect::<BTreeMap<_, _>>();
+
+    for arg in settings_args.iter().rev() {
+        if let Some((name, value)) = arg.strip_prefix("-s").and_then(|arg| arg.split_once('=')) {
+            settings.insert(name.to_owned(), value.to_owned());
+        }
+    }
+
+    settings
+}
+
+fn env_lines(shell: Shell, settings: &BTreeMap<String, String>) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for (name, cmake_name, tool) in TOOL_VARIABLES {
+        lines.push(match shell {
+            Shell::Bash => format!("export {name}={tool}"),
+            Shell::Fish => format!("set -gx {name} {tool}"),
+            Shell::Cmake => format!("set({cmake_name} {tool})"),
+        });
+    }
+
+    for (name, value) in settings {
+        lines.push(match shell {
+            Shell::Bash => format!("export WASIXCC_{name}={}", quote_posix(value)),
+            Shell::Fish => format!("set -gx WASIXCC_{name} {}", quote_fish(value)),
+            Shell::Cmake => format!("set(ENV{{WASIXCC_{name}}} {})", quote_cmake(value)),
+        });
+    }
+
+    lines
+}
+
+/// Rust's target for WASIX, as spelled in the `cc` crate's per-target
+/// variables.
+const CARGO_TARGET: &str = "wasm32_wasmer_wasi";
+
+pub(crate) const CARGO_CONFIG_PATH: &str = ".cargo/config.toml";
+
+// Delimit the section cargo-env manages in .cargo/config.toml, so it can be
+// updated in place.
+const CARGO_CONFIG_BEGIN: &str = "# BEGIN wasixccenv cargo-env";
+const CARGO_CONFIG_END: &str = "# END wasixccenv cargo-env";
+
+/// Variables making the `cc` crate build C and C++ code for WASIX with
+/// wasixcc. Settings are passed in the flags variables as `-s` arguments,
+/// except those with spaces in their values, since the `cc` crate splits
+/// flags on whitespace; those are exported as `WASIXCC_*` variables instead.
+pub(crate) fn cargo_env_vars(settings: &BTreeMap<String, String>) -> Vec<(String, String)> {
+    let mut vars = vec![
+        (format!("CC_{CARGO_TARGET}"), "wasixcc".to_owned()),
+        (format!("CXX_{CARGO_TARGET}"), "wasix++".to_owned()),
+        (format!("AR_{CARGO_TARGET}"), "wasixar".to_owned()),
+    ];
+
+    let (flag_settings, env_settings): (Vec<_>, Vec<_>) = settings
+        .iter()
+        .partition(|(_, value)| !value.contains(char::is_whitespace));
+
+    let flags = flag_settings
+        .iter()
+        .map(|(name, value)| format!("-s{name}={value}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !flags.is_empty() {
+        vars.push((format!("CFLAGS_{CARGO_TARGET}"), flags.clone()));
+        vars.push((format!("CXXFLAGS_{CARGO_TARGET}"), flags));
+    }
+
+    for (name, value) in env_settings {
+        vars.push((format!("WASIXCC_{name}"), value.clone()));
+    }
+
+    vars
+}
+
+pub(crate) fn cargo_env_exports(vars: &[(String, String)]) -> String {
+    vars.iter()
+        .map(|(name, value)| format!("export {name}={}\n", quote_posix(value)))
+        .collect()
+}
+
+/// Adds the variables to the `[env]` table of a cargo configuration file,
+/// replacing the ones added by a previous run.
+pub(crate) fn update_cargo_config(existing: &str, vars: &[(String, String)]) -> Result<String> {
+    let mut section = format!("{CARGO_CONFIG_BEGIN}\n[env]\n");
+    for (name, value) in vars {
+        section.push_str(&format!("{name} = {}\n", quote_toml(value)));
+    }
+    section.push_str(CARGO_CONFIG_END);
+    section.push('\n');
+
+    if let Some(begin) = existing.find(CARGO_CONFIG_BEGIN) {
+        let end = existing[begin..]
+            .find(CARGO_CONFIG_END)
+            .map(|end| begin + end + CARGO_CONFIG_END.len())
+            .with_context(|| {
+                format!("Found {CARGO_CONFIG_BEGIN:?} without {CARGO_CONFIG_END:?}")
+            })?;
+        let rest = existing[end..]
+            .strip_prefix('\n')
+            .unwrap_or(&existing[end..]);
+        return Ok(format!("{}{section}{rest}", &existing[..begin]));
+    }
+
+    if existing.lines().any(|line| line.trim() == "[env]") {
+        bail!(
+            "{CARGO_CONFIG_PATH} already has an [env] table; \
+            add the output of `wasixccenv cargo-env` to it instead"
+        );
+    }
+
+    let separator = match existing {
+        "" => "",
+        s if s.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+    Ok(format!("{existing}{separator}{section}"))
+}
+
+fn quote_toml(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+fn quote_posix(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn quote_fish(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+fn quote_cmake(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', r"\\")
+            .replace('"', "\\\"")
+            .replace('$', r"\$")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cargo_env() {
+        let settings = BTreeMap::from([
+            ("COMPILER_FLAGS".to_owned(), "-O2 -g".to_owned()),
+            ("PIC".to_owned(), "yes".to_owned()),
+            ("WASM_EXCEPTIONS".to_owned(), "yes".to_owned()),
+        ]);
+        let vars = cargo_env_vars(&settings);
+        let var = |name: &str| {
+            vars.iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value.as_str())
+        };
+
+        assert_eq!(var("CC_wasm32_wasmer_wasi"), Some("wasixcc"));
+        assert_eq!(
+            var("CFLAGS_wasm32_wasmer_wasi"),
+            Some("-sPIC=yes -sWASM_EXCEPTIONS=yes")
+        );
+        assert_eq!(var("WASIXCC_COMPILER_FLAGS"), Some("-O2 -g"));
+
+        let vars = vec![("CC_wasm32_wasmer_wasi".to_owned(), "wasixcc".to_owned())];
+        let config =
+            update_cargo_config("[build]\ntarget = \"wasm32-wasmer-wasi\"\n", &vars).unwrap();
+        assert_eq!(
+            config,
+            "[build]\ntarget = \"wasm32-wasmer-wasi\"\n\n\
+            # BEGIN wasixccenv cargo-env\n[env]\nCC_wasm32_wasmer_wasi = \"wasixcc\"\n\
+            # END wasixccenv cargo-env\n"
+        );
+
+        // Running again replaces the section rather than adding another.
+        let vars = vec![(
+            "CC_wasm32_wasmer_wasi".to_owned(),
+            "/bin/wasixcc".to_owned(),
+        )];
+        let updated = update_cargo_config(&config, &vars).unwrap();
+        assert_eq!(updated, config.replace("\"wasixcc\"", "\"/bin/wasixcc\""));
+
+        assert!(update_cargo_config("[env]\nFOO = \"1\"\n", &vars).is_err());
+    }
+
+    #[test]
+    fn test_env_lines() {
+        let settings = configured_settings(
+            &["-sPIC=yes".to_owned(), "-sPIC=no".to_owned()],
+            [
+                ("WASIXCC_PIC".to_owned(), "no".to_owned()),
+                (
+                    "WASIXCC_COMPILER_FLAGS".to_owned(),
+                    "-O2 -DNAME='x'".to_owned(),
+                ),
+                ("PATH".to_owned(), "/bin".to_owned()),
+            ]
+            .into_iter(),
+        );
+
+        let lines = env_lines(Shell::Bash, &settings);
+        assert_eq!(lines[0], "export CC=wasixcc");
+        assert_eq!(
+            &lines[TOOL_VARIABLES.len()..],
+            [
+                r"export WASIXCC_COMPILER_FLAGS='-O2 -DNAME='\''x'\'''",
+                "export WASIXCC_PIC='yes'",
+            ]
+        );
+
+        let lines = env_lines(Shell::Fish, &settings);
+        assert_eq!(
+            lines[TOOL_VARIABLES.len()],
+            r"set -gx WASIXCC_COMPILER_FLAGS '-O2 -DNAME=\'x\''"
+        );
+
+        let lines = env_lines(Shell::Cmake, &settings);
+        assert_eq!(lines[0], "set(CMAKE_C_COMPILER wasixcc)");
+        assert_eq!(lines[lines.len() - 1], r#"set(ENV{WASIXCC_PIC} "yes")"#);
+    }
+}
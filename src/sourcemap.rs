@@ -0,0 +1,305 @@
+//! Source map generation for `-gsource-map`.
+//!
+//! The initial map is built from the DWARF line tables of the linked module,
+//! as printed by `llvm-dwarfdump --debug-line`. wasm-opt then rewrites it
+//! along with the module, so the final map matches the optimized binary.
+
+use std::collections::HashMap;
+
+use super::*;
+
+pub(crate) const SOURCE_MAPPING_URL_SECTION: &str = "sourceMappingURL";
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(serde::Serialize)]
+struct SourceMap {
+    version: u32,
+    sources: Vec<String>,
+    names: Vec<String>,
+    mappings: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct LineEntry {
+    /// Offset relative to the start of the code section's contents.
+    address: u64,
+    source: usize,
+    /// 1-based line number.
+    line: u32,
+    /// 1-based column number, or 0 if unknown.
+    column: u32,
+}
+
+/// Generates a source map for the module at `wasm_path` and writes it to
+/// `map_path`.
+pub(crate) fn generate(
+    wasm_path: &Path,
+    map_path: &Path,
+    user_settings: &UserSettings,
+) -> Result<()> {
+    let wasm = std::fs::read(wasm_path)
+        .with_context(|| format!("Failed to read {}", wasm_path.display()))?;
+    let code_offset = wasm::read_sections(&wasm)?
+        .iter()
+        .find(|section| section.id == wasm::SECTION_CODE)
+        .map(|section| section.offset as u64)
+        .context("Module has no code section")?;
+
+    let mut command = Command::new(user_settings.llvm_location.get_tool_path("llvm-dwarfdump"));
+    command.arg("--debug-line").arg(wasm_path);
+    tracing::info!("Executing command: {command:?}");
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run command: {command:?}"))?;
+    if !output.status.success() {
+        bail!(
+            "llvm-dwarfdump failed with status {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let mut sources = Vec::new();
+    let entries = parse_debug_line(&String::from_utf8_lossy(&output.stdout), &mut sources);
+    if entries.is_empty() {
+        eprintln!(
+            "Warning: no line information found in {}; was it compiled with -g?",
+            wasm_path.display()
+        );
+    }
+
+    let source_map = SourceMap {
+        version: 3,
+        sources,
+        names: Vec::new(),
+        mappings: encode_mappings(entries, code_offset),
+    };
+    let json = serde_json::to_string(&source_map).context("Failed to serialize source map")?;
+    std::fs::write(map_path, json)
+        .with_context(|| format!("Failed to write source map to {}", map_path.display()))
+}
+
+/// Parses the output of `llvm-dwarfdump --debug-line`. Source file names are
+/// collected into `sources`, and entries refer to them by index.
+fn parse_debug_line(output: &str, sources: &mut Vec<String>) -> Vec<LineEntry> {
+    let mut entries = Vec::new();
+    let mut source_indices = HashMap::new();
+
+    // Per line table state
+    let mut directories = HashMap::new();
+    let mut files = HashMap::new();
+    let mut current_file = None;
+
+    for line in output.lines() {
+        let line = line.trim();
+
+        if line.starts_with("debug_line[") {
+            finish_file(&mut current_file, &mut files);
+            directories.clear();
+            files.clear();
+        } else if let Some(rest) = line.strip_prefix("include_directories[") {
+            if let Some((index, value)) = rest.split_once("] = ") {
+                if let Ok(index) = index.trim().parse::<u64>() {
+                    directories.insert(index, unquote(value).to_owned());
+                }
+            }
+        } else if let Some(rest) = line.strip_prefix("file_names[") {
+            finish_file(&mut current_file, &mut files);
+            if let Some(index) = rest.strip_suffix("]:") {
+                if let Ok(index) = index.trim().parse::<u64>() {
+                    current_file = Some((index, (String::new(), None)));
+                }
+            }
+        } else if let Some(name) = line.strip_prefix("name: ") {
+            if let Some((_, (file_name, _))) = &mut current_file {
+                *file_name = unquote(name).to_owned();
+            }
+        } else if let Some(dir_index) = line.strip_prefix("dir_index: ") {
+            if let Some((_, (_, file_dir))) = &mut current_file {
+                *file_dir = dir_index.trim().parse().ok();
+            }
+        } else if line.starts_with("0x") {
+            finish_file(&mut current_file, &mut files);
+
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 4 || fields.contains(&"end_sequence") {
+                continue;
+            }
+            let (Ok(address), Ok(line_number), Ok(column), Ok(file)) = (
+                u64::from_str_radix(&fields[0][2..], 16),
+                fields[1].parse::<u32>(),
+                fields[2].parse::<u32>(),
+                fields[3].parse::<u64>(),
+            ) else {
+                continue;
+            };
+            // Line 0 means the code has no corresponding source line
+            if line_number == 0 {
+                continue;
+            }
+            let Some((name, dir_index)) = files.get(&file) else {
+                continue;
+            };
+
+            let path = match dir_index.and_then(|i| directories.get(&i)) {
+                Some(dir) if !Path::new(name).is_absolute() => {
+                    Path::new(dir).join(name).display().to_string()
+                }
+                _ => name.clone(),
+            };
+            let source = *source_indices.entry(path).or_insert_with_key(|path| {
+                sources.push(path.clone());
+                sources.len() - 1
+            });
+
+            entries.push(LineEntry {
+                address,
+                source,
+                line: line_number,
+                column,
+            });
+        }
+    }
+
+    entries
+}
+
+type FileEntry = (String, Option<u64>);
+
+fn finish_file(current: &mut Option<(u64, FileEntry)>, files: &mut HashMap<u64, FileEntry>) {
+    if let Some((index, file)) = current.take() {
+        files.insert(index, file);
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Encodes the entries as source map mappings. A wasm module is a single
+/// "line" in source map terms, with the file offset as the column.
+fn encode_mappings(mut entries: Vec<LineEntry>, code_offset: u64) -> String {
+    entries.sort_by_key(|entry| entry.address);
+    entries.dedup_by_key(|entry| entry.address);
+
+    let mut mappings = String::new();
+    let (mut last_offset, mut last_source, mut last_line, mut last_column) =
+        (0i64, 0i64, 0i64, 0i64);
+
+    for entry in entries {
+        if !mappings.is_empty() {
+            mappings.push(',');
+        }
+
+        let offset = (code_offset + entry.address) as i64;
+        let source = entry.source as i64;
+        let line = i64::from(entry.line) - 1;
+        let column = i64::from(entry.column.saturating_sub(1));
+
+        encode_vlq(&mut mappings, offset - last_offset);
+        encode_vlq(&mut mappings, source - last_source);
+        encode_vlq(&mut mappings, line - last_line);
+        encode_vlq(&mut mappings, column - last_column);
+
+        (last_offset, last_source, last_line, last_column) = (offset, source, line, column);
+    }
+
+    mappings
+}
+
+fn encode_vlq(out: &mut String, value: i64) {
+    let mut vlq = if value < 0 {
+        (value.unsigned_abs() << 1) | 1
+    } else {
+        (value as u64) << 1
+    };
+    loop {
+        let mut digit = (vlq & 0x1f) as usize;
+        vlq >>= 5;
+        if vlq != 0 {
+            digit |= 0x20;
+        }
+        out.push(BASE64_CHARS[digit] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_vlq() {
+        let encode = |value| {
+            let mut out = String::new();
+            encode_vlq(&mut out, value);
+            out
+        };
+        assert_eq!(encode(0), "A");
+        assert_eq!(encode(1), "C");
+        assert_eq!(encode(-1), "D");
+        assert_eq!(encode(16), "gB");
+        assert_eq!(encode(123), "2H");
+    }
+
+    #[test]
+    fn test_parse_debug_line() {
+        let output = r#"
+debug_line[0x00000000]
+Line table prologue:
+    total_length: 0x00000060
+         version: 5
+include_directories[  0] = "/src/project"
+include_directories[  1] = "include"
+file_names[  0]:
+           name: "main.c"
+      dir_index: 0
+file_names[  1]:
+           name: "util.h"
+      dir_index: 1
+
+Address            Line   Column File   ISA Discriminator OpIndex Flags
+------------------ ------ ------ ------ --- ------------- ------- -------------
+0x0000000000000005      3      0      0   0             0       0  is_stmt
+0x000000000000000a      4      5      1   0             0       0  is_stmt prologue_end
+0x000000000000000c      0      5      1   0             0       0
+0x0000000000000010      4      5      0   0             0       0  is_stmt end_sequence
+"#;
+
+        let mut sources = Vec::new();
+        let entries = parse_debug_line(output, &mut sources);
+        assert_eq!(
+            sources,
+            vec![
+                "/src/project/main.c".to_owned(),
+                "include/util.h".to_owned()
+            ]
+        );
+        assert_eq!(
+            entries,
+            vec![
+                LineEntry {
+                    address: 5,
+                    source: 0,
+                    line: 3,
+                    column: 0
+                },
+                LineEntry {
+                    address: 10,
+                    source: 1,
+                    line: 4,
+                    column: 5
+                },
+            ]
+        );
+
+        assert_eq!(encode_mappings(entries, 100), "yGAEA,KCCI");
+    }
+}
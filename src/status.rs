@@ -0,0 +1,215 @@
+//! `wasixccenv status`: what's installed and where the configuration comes
+//! from.
+
+use std::{collections::BTreeMap, fs};
+
+use super::*;
+
+pub(crate) fn print_status(user_settings: &UserSettings) -> Result<()> {
+    let (settings_args, _) = separate_user_settings_args(std::env::args().skip(1).collect());
+    let sources = setting_sources(&settings_args, std::env::vars());
+    let source = |name: &str| match sources.get(name) {
+        Some((_, source)) => format!("set by {name} in the {source}"),
+        None => "default".to_owned(),
+    };
+
+    println!(
+        "Toolchain: {} ({})",
+        user_settings.toolchain.as_deref().unwrap_or("default"),
+        if sources.contains_key("TOOLCHAIN") {
+            source("TOOLCHAIN")
+        } else if user_settings.toolchain.is_some() {
+            "selected with `wasixccenv use`".to_owned()
+        } else {
+            "default".to_owned()
+        }
+    );
+
+    println!("\nLLVM:");
+    match user_settings.llvm_location.install_path() {
+        Some(path) => {
+            println!(
+                "  location: {} ({})",
+                path.display(),
+                source("LLVM_LOCATION")
+            );
+            if let Some(tag) = compat::read_release_tag(path) {
+                println!("  release:  {tag}");
+            }
+        }
+        None => println!(
+            "  location: versioned tools from PATH ({})",
+            source("LLVM_SYSTEM_VERSION")
+        ),
+    }
+    println!(
+        "  clang:    {}",
+        tool_version(&user_settings.tool_path("clang"))
+    );
+
+    println!("\nSysroot:");
+    match &user_settings.sysroot_location {
+        Some(path) => {
+            println!("  location: {} ({})", path.display(), source("SYSROOT"));
+        }
+        None => {
+            let prefix_source = if sources.contains_key("SYSROOT_PREFIX") {
+                source("SYSROOT_PREFIX")
+            } else if let Some(lockfile) = store::find_lockfile() {
+                format!("pinned by {}", lockfile.display())
+            } else {
+                "default".to_owned()
+            };
+            println!(
+                "  prefix:   {} ({prefix_source})",
+                user_settings.sysroot_prefix.display()
+            );
+            for variant in store::SYSROOT_VARIANTS {
+                let dir = user_settings.sysroot_prefix.join(variant);
+                if !dir.is_dir() {
+                    println!("  {variant:14}  not installed");
+                    continue;
+                }
+                if let Some(source) = store::local_source(&dir, &user_settings.sysroot_store) {
+                    println!("  {variant:14}  local build from {}", source.display());
+                    continue;
+                }
+                println!(
+                    "  {variant:14}  {:20}  {:>9.1} MiB",
+                    compat::read_release_tag(&dir).unwrap_or_else(|| "unknown release".to_owned()),
+                    dir_size(&dir) as f64 / (1024.0 * 1024.0)
+                );
+            }
+        }
+    }
+
+    println!("\nBinaryen:");
+    let (BinaryenLocation::UserProvided(path) | BinaryenLocation::DefaultPath(path)) =
+        &user_settings.binaryen_location;
+    println!(
+        "  location: {} ({})",
+        path.display(),
+        source("BINARYEN_LOCATION")
+    );
+    println!(
+        "  wasm-opt: {}",
+        tool_version(&user_settings.tool_path("wasm-opt"))
+    );
+
+    println!("\nExecutables in PATH:");
+    let executables = installed_executables();
+    if executables.is_empty() {
+        println!("  none; install them with `wasixcc --install-executables <PATH>`");
+    }
+    for (path, target) in executables {
+        match target {
+            Some(target) => println!("  {} -> {}", path.display(), target.display()),
+            None => println!("  {}", path.display()),
+        }
+    }
+
+    println!("\nConfigured settings:");
+    if sources.is_empty() {
+        println!("  none; all settings have their default values");
+    }
+    for (name, (value, source)) in &sources {
+        println!("  {name}={value} ({source})");
+    }
+
+    Ok(())
+}
+
+// The explicitly configured settings with their values and where they're
+// set, with the same precedence as gather_user_settings.
+fn setting_sources(
+    settings_args: &[String],
+    env_vars: impl Iterator<Item = (String, String)>,
+) -> BTreeMap<String, (String, &'static str)> {
+    let mut sources = env_vars
+        .filter_map(|(name, value)| {
+            Some((
+                name.strip_prefix("WASIXCC_")?.to_owned(),
+                (value, "environment"),
+            ))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    for arg in settings_args.iter().rev() {
+        if let Some((name, value)) = arg.strip_prefix("-s").and_then(|arg| arg.split_once('=')) {
+            sources.insert(name.to_owned(), (value.to_owned(), "command line"));
+        }
+    }
+
+    sources
+}
+
+// The first line of the tool's `--version` output.
+pub(crate) fn tool_version(tool: &Path) -> String {
+    match Command::new(tool).arg("--version").output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_owned(),
+        _ => format!("not found ({})", tool.display()),
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+        })
+        .sum()
+}
+
+// The wasix* executables in PATH, with the targets of symlinks such as the
+// ones created by --install-executables.
+fn installed_executables() -> Vec<(PathBuf, Option<PathBuf>)> {
+    let Some(path) = std::env::var_os("PATH") else {
+        return Vec::new();
+    };
+
+    let mut executables = Vec::new();
+    for dir in std::env::split_paths(&path) {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut found = entries
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("wasix"))
+            .map(|entry| (entry.path(), fs::read_link(entry.path()).ok()))
+            .collect::<Vec<_>>();
+        found.sort();
+        executables.extend(found);
+    }
+    executables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setting_sources() {
+        let args = ["-sPIC=yes".to_owned(), "-sPIC=no".to_owned()];
+        let env = [
+            ("WASIXCC_PIC".to_owned(), "maybe".to_owned()),
+            ("WASIXCC_OUT_DIR".to_owned(), "out".to_owned()),
+            ("PATH".to_owned(), "/bin".to_owned()),
+        ];
+        let sources = setting_sources(&args, env.into_iter());
+        assert_eq!(
+            sources.into_iter().collect::<Vec<_>>(),
+            [
+                ("OUT_DIR".to_owned(), ("out".to_owned(), "environment")),
+                ("PIC".to_owned(), ("yes".to_owned(), "command line")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! Content-addressed store of downloaded sysroots, and per-project views of it.
+//!
+//! Sysroot variants are stored in `SYSROOT_STORE/sysroot/<digest>`, where the
+//! digest is the SHA-256 of the variant's release asset. A project pins its
+//! sysroot release in `wasixcc.lock`, and gets a view of the pinned variants
+//! in `.wasixcc/sysroot` next to the lockfile, which is then used as the
+//! sysroot prefix. Views only link into the store, so projects pinning the
+//! same release share it on disk, and switching versions is instant.
+
+use std::collections::BTreeMap;
+
+use super::*;
+
+pub(crate) const LOCK_FILE_NAME: &str = "wasixcc.lock";
+
+const VIEW_DIR: &str = ".wasixcc/sysroot";
+
+/// The sysroot variants a sysroot prefix can hold.
+pub(crate) const SYSROOT_VARIANTS: [&str; 3] = ["sysroot", "sysroot-eh", "sysroot-ehpic"];
+
+// Records the store entry a tree of hardlinks was made from, where symlinks
+// aren't available.
+#[cfg(not(unix))]
+const STORE_ENTRY_FILE_NAME: &str = ".wasixcc-store-entry";
+
+// Records the build tree a sysroot variant was copied from by
+// `wasixccenv link-sysroot --copy`.
+const LOCAL_SOURCE_FILE_NAME: &str = ".wasixcc-local";
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct Lockfile {
+    sysroot: SysrootLock,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SysrootLock {
+    /// The release the sysroots were downloaded from.
+    tag: String,
+    /// Digest of each sysroot variant, keyed by the variant's name.
+    variants: BTreeMap<String, String>,
+}
+
+/// Looks for `wasixcc.lock` in the current directory and its ancestors.
+pub(crate) fn find_lockfile() -> Option<PathBuf> {
+    let current_dir = std::env::current_dir().ok()?;
+    let fs = vfs::get();
+    current_dir
+        .ancestors()
+        .map(|dir| dir.join(LOCK_FILE_NAME))
+        .find(|path| fs.is_file(path))
+}
+
+/// The directory holding the view of the sysroots pinned by `lockfile`.
+pub(crate) fn view_dir(lockfile: &Path) -> PathBuf {
+    lockfile.parent().unwrap_or(Path::new(".")).join(VIEW_DIR)
+}
+
+fn sysroots_dir(store: &Path) -> PathBuf {
+    store.join("sysroot")
+}
+
+fn load_lockfile(path: &Path) -> Result<Lockfile> {
+    let contents = vfs::get()
+        .read_to_string(path)
+        .with_context(|| format!("Failed to read lockfile {}", path.display()))?;
+    toml::from_str(&contents)
+        .with_context(|| format!("Failed to parse lockfile {}", path.display()))
+}
+
+fn write_lockfile(path: &Path, lockfile: &Lockfile) -> Result<()> {
+    let contents = toml::to_string(lockfile).context("Failed to serialize lockfile")?;
+    std::fs::write(
+        path,
+        format!("# Generated by `wasixccenv lock-sysroot`, do not edit.\n{contents}"),
+    )
+    .with_context(|| format!("Failed to write lockfile {}", path.display()))
+}
+
+/// Checks that the project's view is up to date if `sysroot_prefix` is the
+/// view of a lockfile. Called before the sysroot is used, so it only reads;
+/// `wasixccenv sync-sysroot` updates the view.
+pub(crate) fn check_project_view(sysroot_prefix: &Path, store: &Path) -> Result<()> {
+    if !sysroot_prefix.ends_with(VIEW_DIR) {
+        return Ok(());
+    }
+    let Some(project_dir) = sysroot_prefix.parent().and_then(Path::parent) else {
+        return Ok(());
+    };
+    let lockfile_path = project_dir.join(LOCK_FILE_NAME);
+    if !vfs::get().is_file(&lockfile_path) {
+        return Ok(());
+    }
+
+    let lockfile = load_lockfile(&lockfile_path)?;
+    check_view(&lockfile, sysroot_prefix, store)
+}
+
+fn check_view(lockfile: &Lockfile, view_dir: &Path, store: &Path) -> Result<()> {
+    for (name, digest) in &lockfile.sysroot.variants {
+        if !is_linked(&sysroots_dir(store).join(digest), &view_dir.join(name)) {
+            bail!(
+                "Sysroot {name} in {} is not linked to release {}; \
+                run `wasixccenv sync-sysroot` to update it",
+                view_dir.display(),
+                lockfile.sysroot.tag
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Downloads a sysroot release into the store and pins it in the project's
+/// lockfile. The project is the one containing the closest lockfile or
+/// `wasixcc.toml`, or the current directory if there is neither.
+pub(crate) fn lock_sysroot(tag_spec: TagSpec, user_settings: &UserSettings) -> Result<()> {
+    let lockfile_path = match find_lockfile() {
+        Some(path) => path,
+        None => match config::find_config_file() {
+            Some(config) => config.with_file_name(LOCK_FILE_NAME),
+            None => std::env::current_dir()
+                .context("Failed to get current directory")?
+                .join(LOCK_FILE_NAME),
+        },
+    };
+
+    let (tag, stored) = download::download_sysroot_to_store(
+        &tag_spec,
+        &sysroots_dir(&user_settings.sysroot_store),
+        user_settings,
+    )?;
+    let lockfile = Lockfile {
+        sysroot: SysrootLock {
+            tag,
+            variants: stored
+                .into_iter()
+                .map(|sysroot| (sysroot.name, sysroot.digest))
+                .collect(),
+        },
+    };
+
+    write_lockfile(&lockfile_path, &lockfile)?;
+    eprintln!(
+        "Pinned sysroot {} in {}",
+        lockfile.sysroot.tag,
+        lockfile_path.display()
+    );
+
+    materialize_view(
+        &lockfile,
+        &view_dir(&lockfile_path),
+        &user_settings.sysroot_store,
+    )
+}
+
+/// Downloads the sysroots pinned by the project's lockfile into the store if
+/// they're missing, and updates the project's view.
+pub(crate) fn sync_sysroot(user_settings: &UserSettings) -> Result<()> {
+    let lockfile_path =
+        find_lockfile().with_context(|| format!("Could not find {LOCK_FILE_NAME}"))?;
+    let lockfile = load_lockfile(&lockfile_path)?;
+    let sysroots_dir = sysroots_dir(&user_settings.sysroot_store);
+
+    let missing = lockfile
+        .sysroot
+        .variants
+        .values()
+        .any(|digest| !vfs::get().is_dir(&sysroots_dir.join(digest)));
+    if missing {
+        let (_, stored) = download::download_sysroot_to_store(
+            &TagSpec::Tag(lockfile.sysroot.tag.clone()),
+            &sysroots_dir,
+            user_settings,
+        )?;
+        for sysroot in stored {
+            match lockfile.sysroot.variants.get(&sysroot.name) {
+                Some(digest) if *digest != sysroot.digest => bail!(
+                    "Digest of {} from release {} doesn't match {}: expected {digest}, got {}",
+                    sysroot.name,
+                    lockfile.sysroot.tag,
+                    lockfile_path.display(),
+                    sysroot.digest
+                ),
+                _ => (),
+            }
+        }
+    }
+
+    materialize_view(
+        &lockfile,
+        &view_dir(&lockfile_path),
+        &user_settings.sysroot_store,
+    )
+}
+
+fn materialize_view(lockfile: &Lockfile, view_dir: &Path, store: &Path) -> Result<()> {
+    std::fs::create_dir_all(view_dir)
+        .with_context(|| format!("Failed to create sysroot view at {}", view_dir.display()))?;
+
+    for (name, digest) in &lockfile.sysroot.variants {
+        let target = sysroots_dir(store).join(digest);
+        if !vfs::get().is_dir(&target) {
+            bail!(
+                "Sysroot {name} from release {} is not in the store at {}; \
+                run `wasixccenv sync-sysroot` to download it",
+                lockfile.sysroot.tag,
+                store.display()
+            );
+        }
+
+        link_variant(&target, &view_dir.join(name)).with_context(|| {
+            format!("Failed to link sysroot {name} into {}", view_dir.display())
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_linked(target: &Path, link: &Path) -> bool {
+    vfs::get()
+        .read_link(link)
+        .is_ok_and(|current| current == target)
+}
+
+#[cfg(unix)]
+fn link_variant(target: &Path, link: &Path) -> Result<()> {
+    if is_linked(target, link) {
+        return Ok(());
+    }
+
+    // Create the new link next to the old one and rename it into place, so
+    // concurrent builds never see a missing sysroot.
+    let temp_link = link.with_extension(format!("tmp-{}", std::process::id()));
+    let _ = std::fs::remove_file(&temp_link);
+    std::os::unix::fs::symlink(target, &temp_link)?;
+    let fs = vfs::get();
+    if fs.is_dir(link) && fs.read_link(link).is_err() {
+        std::fs::remove_dir_all(link)?;
+    }
+    std::fs::rename(&temp_link, link)?;
+
+    tracing::info!("Linked {} to {}", link.display(), target.display());
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn is_linked(target: &Path, link: &Path) -> bool {
+    vfs::get()
+        .read_to_string(&link.join(STORE_ENTRY_FILE_NAME))
+        .is_ok_and(|current| current == target.display().to_string())
+}
+
+// Creating symlinks may require extra privileges, so use a tree of hardlinks
+// instead, with a marker file recording which store entry it came from.
+#[cfg(not(unix))]
+fn link_variant(target: &Path, link: &Path) -> Result<()> {
+    if is_linked(target, link) {
+        return Ok(());
+    }
+
+    if vfs::get().exists(link) {
+        std::fs::remove_dir_all(link)?;
+    }
+    hardlink_tree(target, link)?;
+    std::fs::write(
+        link.join(STORE_ENTRY_FILE_NAME),
+        target.display().to_string(),
+    )?;
+
+    tracing::info!("Linked {} to {}", link.display(), target.display());
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn hardlink_tree(src: &Path, dst: &Path) -> Result<()> {
+    std::fs::create_dir_all(dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let dst = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            hardlink_tree(&entry.path(), &dst)?;
+        } else {
+            std::fs::hard_link(entry.path(), dst)?;
+        }
+    }
+    Ok(())
+}
+
+/// Installs the sysroots built in a local wasix-libc tree into the sysroot
+/// prefix, as links to the build tree so that rebuilding them takes effect
+/// immediately, or as copies with `copy`.
+pub(crate) fn link_local_sysroot(
+    build_tree: &Path,
+    copy: bool,
+    user_settings: &UserSettings,
+) -> Result<()> {
+    let build_tree = std::path::absolute(build_tree)
+        .with_context(|| format!("Failed to resolve {}", build_tree.display()))?;
+    let variants = SYSROOT_VARIANTS
+        .into_iter()
+        .filter(|variant| vfs::get().is_dir(&build_tree.join(variant)))
+        .collect::<Vec<_>>();
+    if variants.is_empty() {
+        bail!(
+            "No sysroot, sysroot-eh or sysroot-ehpic directory in {}; build wasix-libc first",
+            build_tree.display()
+        );
+    }
+
+    let prefix = &user_settings.sysroot_prefix;
+    std::fs::create_dir_all(prefix)
+        .with_context(|| format!("Failed to create {}", prefix.display()))?;
+
+    for variant in variants {
+        let source = build_tree.join(variant);
+        let target = prefix.join(variant);
+        if copy {
+            if std::fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.is_dir()) {
+                std::fs::remove_dir_all(&target)
+            } else {
+                std::fs::remove_file(&target).or_else(|e| match e.kind() {
+                    std::io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(e),
+                })
+            }
+            .with_context(|| format!("Failed to remove {}", target.display()))?;
+            copy_tree(&source, &target)
+                .and_then(|()| {
+                    std::fs::write(
+                        target.join(LOCAL_SOURCE_FILE_NAME),
+                        source.display().to_string(),
+                    )
+                })
+                .with_context(|| format!("Failed to copy {variant} to {}", target.display()))?;
+        } else {
+            link_variant(&source, &target)
+                .with_context(|| format!("Failed to link {variant} into {}", prefix.display()))?;
+        }
+        eprintln!(
+            "Installed {variant} from {} in {}",
+            source.display(),
+            prefix.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// The local build tree a sysroot variant was installed from with
+/// `wasixccenv link-sysroot`, if it was.
+pub(crate) fn local_source(variant_dir: &Path, store: &Path) -> Option<PathBuf> {
+    #[cfg(unix)]
+    let linked = std::fs::read_link(variant_dir).ok();
+    #[cfg(not(unix))]
+    let linked = std::fs::read_to_string(variant_dir.join(STORE_ENTRY_FILE_NAME))
+        .ok()
+        .map(PathBuf::from);
+
+    match linked {
+        Some(target) if target.starts_with(sysroots_dir(store)) => None,
+        Some(target) => Some(target),
+        None => std::fs::read_to_string(variant_dir.join(LOCAL_SOURCE_FILE_NAME))
+            .ok()
+            .map(PathBuf::from),
+    }
+}
+
+fn copy_tree(src: &Path, dst: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let dst = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_tree(&entry.path(), &dst)?;
+        } else {
+            std::fs::copy(entry.path(), dst)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_dir() {
+        assert_eq!(
+            view_dir(Path::new("/project/wasixcc.lock")),
+            PathBuf::from("/project/.wasixcc/sysroot")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_materialize_view() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let store = tmp.path().join("store");
+        let view = tmp.path().join("project").join(VIEW_DIR);
+        for digest in ["aaaa", "bbbb"] {
+            std::fs::create_dir_all(sysroots_dir(&store).join(digest).join("lib")).unwrap();
+        }
+
+        let mut lockfile = Lockfile {
+            sysroot: SysrootLock {
+                tag: "v2025-01-01.1".to_owned(),
+                variants: BTreeMap::from([("sysroot-eh".to_owned(), "aaaa".to_owned())]),
+            },
+        };
+        assert!(check_view(&lockfile, &view, &store).is_err());
+        materialize_view(&lockfile, &view, &store).unwrap();
+        assert!(view.join("sysroot-eh/lib").is_dir());
+        check_view(&lockfile, &view, &store).unwrap();
+
+        // Switching versions only replaces the link
+        lockfile
+            .sysroot
+            .variants
+            .insert("sysroot-eh".to_owned(), "bbbb".to_owned());
+        assert!(check_view(&lockfile, &view, &store).is_err());
+        materialize_view(&lockfile, &view, &store).unwrap();
+        assert_eq!(
+            std::fs::read_link(view.join("sysroot-eh")).unwrap(),
+            sysroots_dir(&store).join("bbbb")
+        );
+
+        lockfile
+            .sysroot
+            .variants
+            .insert("sysroot".to_owned(), "cccc".to_owned());
+        assert!(materialize_view(&lockfile, &view, &store).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_local_sysroot() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let build_tree = tmp.path().join("wasix-libc");
+        let store = tmp.path().join("store");
+        std::fs::create_dir_all(build_tree.join("sysroot-eh/lib")).unwrap();
+        std::fs::write(build_tree.join("sysroot-eh/lib/libc.a"), "").unwrap();
+
+        for copy in [false, true] {
+            let prefix = tmp.path().join(format!("prefix-{copy}"));
+            let user_settings = UserSettings {
+                sysroot_prefix: prefix.clone(),
+                ..Default::default()
+            };
+            link_local_sysroot(&build_tree, copy, &user_settings).unwrap();
+            assert!(prefix.join("sysroot-eh/lib/libc.a").is_file());
+            assert!(!prefix.join("sysroot").exists());
+            assert_eq!(
+                local_source(&prefix.join("sysroot-eh"), &store),
+                Some(build_tree.join("sysroot-eh"))
+            );
+        }
+
+        assert!(link_local_sysroot(&store, false, &UserSettings::default()).is_err());
+    }
+}
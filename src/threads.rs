@@ -0,0 +1,205 @@
+//! Pruning of thread support from modules that don't use it.
+//!
+//! Modules are always linked with shared memory and TLS exports, since libc
+//! needs them once threads are involved. Engines without threads support can't
+//! instantiate such modules though, so with AUTO_PRUNE_THREADS, modules that
+//! turn out to contain no atomic instructions and no TLS data have these
+//! requirements removed after linking.
+
+use super::*;
+use crate::wasm::{
+    Export, Import, Reader, EXTERNAL_GLOBAL, EXTERNAL_MEMORY, LIMITS_SHARED, SECTION_CODE,
+    SECTION_EXPORT, SECTION_GLOBAL, SECTION_IMPORT,
+};
+
+const TLS_EXPORTS: &[&str] = &["__wasm_init_tls", "__tls_size", "__tls_align", "__tls_base"];
+
+/// Prunes thread support from the module at `path` if it doesn't need it.
+/// Returns whether the module was pruned.
+pub(crate) fn prune_threads(path: &Path) -> Result<bool> {
+    let module =
+        std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let Some(pruned) = prune_module(&module)? else {
+        return Ok(false);
+    };
+
+    std::fs::write(path, pruned).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(true)
+}
+
+fn prune_module(module: &[u8]) -> Result<Option<Vec<u8>>> {
+    let sections = wasm::read_sections(module)?;
+    let contents = |id| {
+        sections
+            .iter()
+            .find(|section| section.id == id)
+            .map(|section| section.contents)
+    };
+
+    if let Some(code) = contents(SECTION_CODE) {
+        match wasm::code_uses_atomics(code) {
+            Ok(false) => (),
+            Ok(true) => {
+                tracing::debug!("Not pruning thread support: module uses atomics");
+                return Ok(None);
+            }
+            // Most likely an instruction newer than the decoder, which may
+            // well be atomic
+            Err(e) => {
+                tracing::debug!("Not pruning thread support: failed to scan code: {e:#}");
+                return Ok(None);
+            }
+        }
+    }
+
+    let imports = match contents(SECTION_IMPORT) {
+        Some(contents) => wasm::read_imports(contents)?,
+        None => vec![],
+    };
+    let exports = match contents(SECTION_EXPORT) {
+        Some(contents) => wasm::read_exports(contents)?,
+        None => vec![],
+    };
+
+    // The linker always exports __tls_size, so this only fails for modules
+    // that weren't linked by us; leave those alone.
+    let Some(tls_size) = exports
+        .iter()
+        .find(|export| export.name == "__tls_size" && export.kind == EXTERNAL_GLOBAL)
+    else {
+        tracing::debug!("Not pruning thread support: __tls_size is not exported");
+        return Ok(None);
+    };
+    let imported_globals = imports
+        .iter()
+        .filter(|import| import.kind == EXTERNAL_GLOBAL)
+        .count() as u32;
+    let tls_size = match tls_size.index.checked_sub(imported_globals) {
+        Some(index) => match contents(SECTION_GLOBAL) {
+            Some(globals) => global_init_i32(globals, index)?,
+            None => None,
+        },
+        None => None,
+    };
+    if tls_size != Some(0) {
+        tracing::debug!("Not pruning thread support: module uses TLS");
+        return Ok(None);
+    }
+
+    let pruned = wasm::rebuild_module(module, |section| match section.id {
+        SECTION_IMPORT => Ok(Some(unshare_memory(section.contents, &imports))),
+        SECTION_EXPORT => Ok(Some(without_tls_exports(section.contents, &exports))),
+        _ => Ok(None),
+    })?;
+    Ok(Some(pruned))
+}
+
+// The value of a global initialized with an `i32.const`, if it is one.
+fn global_init_i32(contents: &[u8], index: u32) -> Result<Option<i32>> {
+    let mut reader = Reader::new(contents);
+    let count = reader.read_leb128_u32()?;
+    if index >= count {
+        return Ok(None);
+    }
+
+    for current in 0..=index {
+        reader.read_value_type()?;
+        // Mutability
+        reader.read_u8()?;
+
+        if current == index {
+            return Ok(match reader.read_u8()? {
+                0x41 => Some(reader.read_leb128_i64()? as i32),
+                _ => None,
+            });
+        }
+
+        while reader.skip_instruction()? != 0x0B {}
+    }
+
+    Ok(None)
+}
+
+fn unshare_memory(contents: &[u8], imports: &[Import]) -> Vec<u8> {
+    let mut contents = contents.to_vec();
+    for import in imports.iter().filter(|i| i.kind == EXTERNAL_MEMORY) {
+        contents[import.descriptor_offset] &= !LIMITS_SHARED;
+    }
+    contents
+}
+
+fn without_tls_exports(contents: &[u8], exports: &[Export]) -> Vec<u8> {
+    let kept = exports
+        .iter()
+        .filter(|export| !TLS_EXPORTS.contains(&export.name))
+        .collect::<Vec<_>>();
+
+    let mut result = Vec::new();
+    wasm::write_leb128_u64(&mut result, kept.len() as u64);
+    for export in kept {
+        result.extend_from_slice(&contents[export.range.clone()]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wasm::{write_name, EXTERNAL_FUNC};
+
+    fn test_module(body: &[u8], tls_size: u8) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut imports = vec![0x01];
+        write_name(&mut imports, "env");
+        write_name(&mut imports, "memory");
+        imports.extend([EXTERNAL_MEMORY, 0x03, 0x01, 0x10]);
+        module.extend(wasm::encode_section(SECTION_IMPORT, &imports));
+
+        // (global i32 (i32.const tls_size))
+        let globals = [0x01, 0x7f, 0x00, 0x41, tls_size, 0x0b];
+        module.extend(wasm::encode_section(SECTION_GLOBAL, &globals));
+
+        let mut exports = vec![0x02];
+        write_name(&mut exports, "__tls_size");
+        exports.extend([EXTERNAL_GLOBAL, 0x00]);
+        write_name(&mut exports, "_start");
+        exports.extend([EXTERNAL_FUNC, 0x00]);
+        module.extend(wasm::encode_section(SECTION_EXPORT, &exports));
+
+        let mut code = vec![0x01, body.len() as u8];
+        code.extend_from_slice(body);
+        module.extend(wasm::encode_section(SECTION_CODE, &code));
+
+        module
+    }
+
+    #[test]
+    fn test_prune_module() {
+        let module = test_module(&[0x00, 0x0b], 0);
+        let pruned = prune_module(&module).unwrap().unwrap();
+
+        let sections = wasm::read_sections(&pruned).unwrap();
+        let imports = wasm::read_imports(sections[0].contents).unwrap();
+        assert_eq!(sections[0].contents[imports[0].descriptor_offset], 0x01);
+        let exports = wasm::read_exports(sections[2].contents).unwrap();
+        assert_eq!(
+            exports.iter().map(|e| e.name).collect::<Vec<_>>(),
+            vec!["_start"]
+        );
+
+        // Modules using TLS or atomics are left alone
+        assert!(prune_module(&test_module(&[0x00, 0x0b], 16))
+            .unwrap()
+            .is_none());
+        let atomic_body = [0x00, 0xfe, 0x03, 0x00, 0x0b];
+        assert!(prune_module(&test_module(&atomic_body, 0))
+            .unwrap()
+            .is_none());
+
+        // As are modules with instructions that can't be decoded
+        let gc_body = [0x00, 0xfb, 0x00, 0x0b];
+        assert!(prune_module(&test_module(&gc_body, 0)).unwrap().is_none());
+    }
+}
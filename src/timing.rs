@@ -0,0 +1,118 @@
+//! Wall time of each build stage, printed at the end of the build when
+//! TIME_REPORT is enabled.
+
+use std::{
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use crate::Stage;
+
+static REPORT: OnceLock<Report> = OnceLock::new();
+
+struct Report {
+    start: Instant,
+    entries: Mutex<Vec<Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    stage: Stage,
+    detail: String,
+    duration: Duration,
+}
+
+fn stage_name(stage: Stage) -> &'static str {
+    match stage {
+        Stage::Compile => "compile",
+        Stage::Link => "link",
+        Stage::Wizer => "wizer",
+        Stage::WasmOpt => "wasm-opt",
+    }
+}
+
+/// Starts timing the build. Stages finished before this is called, or when
+/// it's never called, aren't recorded.
+pub(crate) fn enable() {
+    let _ = REPORT.set(Report {
+        start: Instant::now(),
+        entries: Mutex::new(Vec::new()),
+    });
+}
+
+pub(crate) fn record(stage: Stage, detail: &str, duration: Duration) {
+    if let Some(report) = REPORT.get() {
+        report.entries.lock().unwrap().push(Entry {
+            stage,
+            detail: detail.to_owned(),
+            duration,
+        });
+    }
+}
+
+/// Prints the recorded stages and the total build time to stderr.
+pub(crate) fn print_report() {
+    if let Some(report) = REPORT.get() {
+        let entries = report.entries.lock().unwrap();
+        eprint!("{}", format_report(&entries, report.start.elapsed()));
+    }
+}
+
+fn format_report(entries: &[Entry], total: Duration) -> String {
+    let mut out = String::from("Time report:\n");
+    for entry in entries {
+        out.push_str(&format!(
+            "  {:8}  {:>8.3}s  {}\n",
+            stage_name(entry.stage),
+            entry.duration.as_secs_f64(),
+            entry.detail
+        ));
+    }
+
+    // Totals per stage make it easy to see what dominates the build
+    for stage in [Stage::Compile, Stage::Link, Stage::Wizer, Stage::WasmOpt] {
+        let stage_entries = entries.iter().filter(|entry| entry.stage == stage);
+        let count = stage_entries.clone().count();
+        if count > 1 {
+            let duration = stage_entries.map(|entry| entry.duration).sum::<Duration>();
+            out.push_str(&format!(
+                "  {:8}  {:>8.3}s  ({count} invocations)\n",
+                stage_name(stage),
+                duration.as_secs_f64()
+            ));
+        }
+    }
+    out.push_str(&format!("  {:8}  {:>8.3}s\n", "total", total.as_secs_f64()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_report() {
+        let entry = |stage, detail: &str, millis| Entry {
+            stage,
+            detail: detail.to_owned(),
+            duration: Duration::from_millis(millis),
+        };
+        let entries = [
+            entry(Stage::Compile, "a.c", 250),
+            entry(Stage::Compile, "b.c", 500),
+            entry(Stage::Link, "app.wasm", 100),
+            entry(Stage::WasmOpt, "app.wasm", 1500),
+        ];
+
+        assert_eq!(
+            format_report(&entries, Duration::from_millis(2400)),
+            "Time report:\n\
+             \x20 compile      0.250s  a.c\n\
+             \x20 compile      0.500s  b.c\n\
+             \x20 link         0.100s  app.wasm\n\
+             \x20 wasm-opt     1.500s  app.wasm\n\
+             \x20 compile      0.750s  (2 invocations)\n\
+             \x20 total        2.400s\n"
+        );
+    }
+}
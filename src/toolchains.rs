@@ -0,0 +1,149 @@
+//! Named toolchains installed side by side under `~/.wasixcc/toolchains`,
+//! selected with the TOOLCHAIN setting or `wasixccenv use`.
+//!
+//! A toolchain is just a directory with the usual `llvm`, `sysroot` and
+//! `binaryen` subdirectories, which become the default locations of those
+//! components while it's selected. Downloads then install into it too.
+
+use std::fs;
+
+use super::*;
+
+// Holds the name of the toolchain selected with `wasixccenv use`.
+const CURRENT_FILE_NAME: &str = "current";
+
+// Selecting this name goes back to the components in `~/.wasixcc`.
+const DEFAULT_TOOLCHAIN: &str = "default";
+
+/// The directory the default component locations are relative to, which is
+/// `~/.wasixcc` unless a toolchain is selected.
+pub(crate) fn base_dir(toolchain: Option<&str>) -> PathBuf {
+    let wasixcc_dir = std::env::home_dir()
+        .map(|home| home.join(".wasixcc"))
+        .unwrap_or_else(|| PathBuf::from("/lib/wasixcc"));
+    match toolchain {
+        Some(name) => wasixcc_dir.join("toolchains").join(name),
+        None => wasixcc_dir,
+    }
+}
+
+fn toolchains_dir() -> PathBuf {
+    base_dir(None).join("toolchains")
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("Invalid toolchain name: {name:?}");
+    }
+    Ok(())
+}
+
+/// The selected toolchain: the TOOLCHAIN setting if given, otherwise the
+/// one selected with `wasixccenv use`.
+pub(crate) fn selected(setting: Option<String>) -> Result<Option<String>> {
+    let name = match setting {
+        Some(name) => name,
+        None => match vfs::get().read_to_string(&toolchains_dir().join(CURRENT_FILE_NAME)) {
+            Ok(contents) => contents.trim().to_owned(),
+            Err(_) => return Ok(None),
+        },
+    };
+
+    if name.is_empty() || name == DEFAULT_TOOLCHAIN {
+        return Ok(None);
+    }
+    validate_name(&name)?;
+    Ok(Some(name))
+}
+
+/// Selects the toolchain used when TOOLCHAIN isn't set, or prints the
+/// installed toolchains when no name is given.
+pub(crate) fn use_toolchain(name: Option<&str>, user_settings: &UserSettings) -> Result<()> {
+    let dir = toolchains_dir();
+    let current_file = dir.join(CURRENT_FILE_NAME);
+
+    let Some(name) = name else {
+        let selected = user_settings.toolchain.as_deref();
+        println!(
+            "{} {DEFAULT_TOOLCHAIN} ({})",
+            if selected.is_none() { "*" } else { " " },
+            base_dir(None).display()
+        );
+        for installed in list_installed(&dir)? {
+            let marker = if selected == Some(installed.as_str()) {
+                "*"
+            } else {
+                " "
+            };
+            println!("{marker} {installed}");
+        }
+        return Ok(());
+    };
+
+    if name == DEFAULT_TOOLCHAIN {
+        if current_file.exists() {
+            fs::remove_file(&current_file)
+                .with_context(|| format!("Failed to remove {}", current_file.display()))?;
+        }
+        eprintln!(
+            "Using the default toolchain in {}",
+            base_dir(None).display()
+        );
+        return Ok(());
+    }
+
+    validate_name(name)?;
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    fs::write(&current_file, format!("{name}\n"))
+        .with_context(|| format!("Failed to write {}", current_file.display()))?;
+
+    let toolchain_dir = base_dir(Some(name));
+    if toolchain_dir.is_dir() {
+        eprintln!("Using toolchain {name} in {}", toolchain_dir.display());
+    } else {
+        eprintln!(
+            "Using toolchain {name}, which isn't installed yet; run `wasixccenv \
+            download-all` to install it in {}",
+            toolchain_dir.display()
+        );
+    }
+    Ok(())
+}
+
+fn list_installed(dir: &Path) -> Result<Vec<String>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selected() {
+        vfs::with_file_system(vfs::MemoryFileSystem::default(), || {
+            assert_eq!(
+                selected(Some("nightly".to_owned())).unwrap().as_deref(),
+                Some("nightly")
+            );
+            assert_eq!(selected(Some("default".to_owned())).unwrap(), None);
+            assert!(selected(Some("../llvm".to_owned())).is_err());
+        });
+
+        let current = toolchains_dir().join(CURRENT_FILE_NAME);
+        let fs = vfs::MemoryFileSystem::default().with_file(&current, "stable\n");
+        vfs::with_file_system(fs, || {
+            assert_eq!(selected(None).unwrap().as_deref(), Some("stable"));
+        });
+    }
+}
@@ -0,0 +1,99 @@
+//! Chrome trace-event output of the subprocesses run during a build,
+//! configured through the TRACE_FILE setting.
+//!
+//! Events are appended to the file in the JSON array format, which allows
+//! leaving out the closing bracket. That way every wasixcc invocation of a
+//! multi-file build can add its events to the same file, and the result can
+//! be loaded in Perfetto or `chrome://tracing` as is. Timestamps are relative
+//! to the unix epoch so events from different processes line up.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+
+static TRACE_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// A complete event, i.e. one with both a start time and a duration.
+#[derive(Debug, serde::Serialize)]
+struct Event {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    /// Start time in microseconds.
+    ts: u128,
+    /// Duration in microseconds.
+    dur: u128,
+    pid: u32,
+    tid: u32,
+    args: EventArgs,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct EventArgs {
+    command: String,
+    success: bool,
+}
+
+/// Starts recording subprocesses to `path`. Subprocesses run before this is
+/// called, or when it's never called, aren't recorded.
+pub(crate) fn enable(path: PathBuf) {
+    let _ = TRACE_FILE.set(path);
+}
+
+/// Records a subprocess that was started at `start` and ran for `duration`.
+pub(crate) fn record(command: &Command, start: SystemTime, duration: Duration, success: bool) {
+    let Some(path) = TRACE_FILE.get() else {
+        return;
+    };
+
+    let name = Path::new(command.get_program())
+        .file_name()
+        .unwrap_or(command.get_program())
+        .to_string_lossy()
+        .into_owned();
+    let event = Event {
+        name,
+        cat: "subprocess",
+        ph: "X",
+        ts: start
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros(),
+        dur: duration.as_micros(),
+        pid: std::process::id(),
+        tid: 0,
+        args: EventArgs {
+            command: format!("{command:?}"),
+            success,
+        },
+    };
+
+    // A trace that can't be written shouldn't break the build.
+    if let Err(e) = append(path, &event) {
+        tracing::warn!("Failed to write trace event to {}: {e:?}", path.display());
+    }
+}
+
+fn append(path: &Path, event: &Event) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let is_new = file.metadata()?.len() == 0;
+
+    // Written with a single call, so events from concurrent builds don't
+    // get interleaved.
+    let mut line = if is_new { "[\n" } else { "" }.to_owned();
+    line.push_str(&serde_json::to_string(event).context("Failed to serialize trace event")?);
+    line.push_str(",\n");
+    file.write_all(line.as_bytes())
+        .context("Failed to write trace event")
+}
@@ -0,0 +1,169 @@
+//! File system access used when probing the environment: sysroot and
+//! toolchain checks, config file discovery and per-directory flags.
+//!
+//! Probing goes through the [`FileSystem`] trait so that the host file system
+//! can be replaced, e.g. by an in-memory file system in tests or a virtual
+//! sysroot served from elsewhere. Compiling and linking still happen on the
+//! host file system, since that's what the underlying tools see, so files the
+//! build itself produces (objects, wasm-opt's output in the temporary
+//! directory) are checked there directly.
+
+#[cfg(test)]
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+use super::*;
+
+pub trait FileSystem: Send + Sync {
+    fn is_file(&self, path: &Path) -> bool;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    fn exists(&self, path: &Path) -> bool {
+        self.is_file(path) || self.is_dir(path)
+    }
+
+    fn read_to_string(&self, path: &Path) -> std::io::Result<String>;
+
+    fn read_link(&self, path: &Path) -> std::io::Result<PathBuf>;
+}
+
+/// The real file system of the host.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostFileSystem;
+
+impl FileSystem for HostFileSystem {
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+
+    fn read_link(&self, path: &Path) -> std::io::Result<PathBuf> {
+        std::fs::read_link(path)
+    }
+}
+
+static FILE_SYSTEM: OnceLock<Arc<dyn FileSystem>> = OnceLock::new();
+
+/// Replaces the file system used for probing. Must be called before any
+/// other wasixcc function; fails if the file system was already set or used.
+pub fn set_file_system(fs: Arc<dyn FileSystem>) -> Result<()> {
+    FILE_SYSTEM
+        .set(fs)
+        .map_err(|_| anyhow::anyhow!("The file system was already initialized"))
+}
+
+#[cfg(test)]
+thread_local! {
+    static TEST_FILE_SYSTEM: std::cell::RefCell<Option<Arc<dyn FileSystem>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+pub(crate) fn get() -> Arc<dyn FileSystem> {
+    #[cfg(test)]
+    if let Some(fs) = TEST_FILE_SYSTEM.with(|fs| fs.borrow().clone()) {
+        return fs;
+    }
+
+    FILE_SYSTEM.get_or_init(|| Arc::new(HostFileSystem)).clone()
+}
+
+/// Runs `f` with `fs` as the file system of the current thread.
+#[cfg(test)]
+pub(crate) fn with_file_system<R>(fs: impl FileSystem + 'static, f: impl FnOnce() -> R) -> R {
+    struct Reset(Option<Arc<dyn FileSystem>>);
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            TEST_FILE_SYSTEM.with(|fs| *fs.borrow_mut() = self.0.take());
+        }
+    }
+
+    let previous = TEST_FILE_SYSTEM.with(|current| current.borrow_mut().replace(Arc::new(fs)));
+    let _reset = Reset(previous);
+    f()
+}
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct MemoryFileSystem {
+    files: HashMap<PathBuf, String>,
+    dirs: HashSet<PathBuf>,
+}
+
+#[cfg(test)]
+impl MemoryFileSystem {
+    pub fn with_dir(mut self, path: impl AsRef<Path>) -> Self {
+        self.dirs
+            .extend(path.as_ref().ancestors().map(Path::to_path_buf));
+        self
+    }
+
+    pub fn with_file(mut self, path: impl AsRef<Path>, contents: &str) -> Self {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            self = self.with_dir(parent);
+        }
+        self.files.insert(path.to_path_buf(), contents.to_owned());
+        self
+    }
+}
+
+#[cfg(test)]
+impl FileSystem for MemoryFileSystem {
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.dirs.contains(path)
+    }
+
+    fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        self.files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))
+    }
+
+    fn read_link(&self, _path: &Path) -> std::io::Result<PathBuf> {
+        Err(std::io::Error::from(std::io::ErrorKind::InvalidInput))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_file_system() {
+        let fs = MemoryFileSystem::default()
+            .with_dir("/opt/sysroot/lib")
+            .with_file("/etc/wasixcc.toml", "[matrix]");
+
+        with_file_system(fs, || {
+            let fs = get();
+            assert!(fs.is_dir(Path::new("/opt/sysroot")));
+            assert!(fs.exists(Path::new("/opt/sysroot/lib")));
+            assert!(!fs.is_file(Path::new("/opt/sysroot/lib")));
+            assert!(fs.is_file(Path::new("/etc/wasixcc.toml")));
+            assert_eq!(
+                fs.read_to_string(Path::new("/etc/wasixcc.toml")).unwrap(),
+                "[matrix]"
+            );
+            assert!(fs.read_to_string(Path::new("/etc/missing")).is_err());
+        });
+
+        assert!(!get().is_dir(Path::new("/opt/sysroot/lib/does-not-exist")));
+    }
+}
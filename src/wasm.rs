@@ -0,0 +1,624 @@
+//! Minimal reader and writer for the WebAssembly binary format; just enough
+//! to inspect and patch modules after linking.
+
+use std::ops::Range;
+
+use super::*;
+
+pub(crate) const SECTION_CUSTOM: u8 = 0;
+pub(crate) const SECTION_IMPORT: u8 = 2;
+pub(crate) const SECTION_TABLE: u8 = 4;
+pub(crate) const SECTION_MEMORY: u8 = 5;
+pub(crate) const SECTION_GLOBAL: u8 = 6;
+pub(crate) const SECTION_EXPORT: u8 = 7;
+pub(crate) const SECTION_CODE: u8 = 10;
+pub(crate) const SECTION_DATA: u8 = 11;
+
+pub(crate) const EXTERNAL_FUNC: u8 = 0;
+pub(crate) const EXTERNAL_TABLE: u8 = 1;
+pub(crate) const EXTERNAL_MEMORY: u8 = 2;
+pub(crate) const EXTERNAL_GLOBAL: u8 = 3;
+pub(crate) const EXTERNAL_TAG: u8 = 4;
+
+pub(crate) const LIMITS_HAS_MAX: u8 = 0x01;
+pub(crate) const LIMITS_SHARED: u8 = 0x02;
+
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+#[derive(Debug)]
+pub(crate) struct Section<'a> {
+    pub id: u8,
+    /// Name of custom sections, `None` for all other sections.
+    pub name: Option<&'a str>,
+    /// Offset of the section's contents (after the id and size) in the file.
+    pub offset: usize,
+    /// Range of the whole section, including the id and size, in the file.
+    pub range: Range<usize>,
+    /// The section's contents, including the name for custom sections.
+    pub contents: &'a [u8],
+}
+
+#[derive(Debug)]
+pub(crate) struct Import<'a> {
+    pub module: &'a str,
+    pub field: &'a str,
+    pub kind: u8,
+    /// Offset of the import's descriptor (after the kind) in the section.
+    pub descriptor_offset: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct Export<'a> {
+    pub name: &'a str,
+    pub kind: u8,
+    pub index: u32,
+    /// Range of the whole export entry in the section.
+    pub range: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Limits {
+    pub flags: u8,
+    pub min: u64,
+    pub max: Option<u64>,
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .context("Unexpected end of wasm data")?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .context("Unexpected end of wasm data")?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn read_name(&mut self) -> Result<&'a str> {
+        let len = self.read_leb128_u32()? as usize;
+        std::str::from_utf8(self.read_bytes(len)?).context("Invalid UTF-8 name in wasm data")
+    }
+
+    pub fn read_leb128_u64(&mut self) -> Result<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            if shift >= 64 {
+                bail!("Invalid LEB128 value in wasm data");
+            }
+            result |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    pub fn read_leb128_i64(&mut self) -> Result<i64> {
+        let mut result = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            if shift >= 64 {
+                bail!("Invalid LEB128 value in wasm data");
+            }
+            result |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    result |= -1 << shift;
+                }
+                return Ok(result);
+            }
+        }
+    }
+
+    /// Reads a value type, including the heap type of reference types.
+    pub fn read_value_type(&mut self) -> Result<u8> {
+        let value_type = self.read_u8()?;
+        // (ref ht) and (ref null ht)
+        if value_type == 0x63 || value_type == 0x64 {
+            self.read_leb128_i64()?;
+        }
+        Ok(value_type)
+    }
+
+    /// Reads table or memory limits.
+    pub fn read_limits(&mut self) -> Result<Limits> {
+        let flags = self.read_u8()?;
+        let min = self.read_leb128_u64()?;
+        let max = if flags & LIMITS_HAS_MAX != 0 {
+            Some(self.read_leb128_u64()?)
+        } else {
+            None
+        };
+        // Custom page size
+        if flags & 0x08 != 0 {
+            self.read_leb128_u32()?;
+        }
+        Ok(Limits { flags, min, max })
+    }
+
+    fn read_memarg(&mut self) -> Result<()> {
+        let align = self.read_leb128_u32()?;
+        // Bit 6 of the alignment indicates an explicit memory index
+        if align & 0x40 != 0 {
+            self.read_leb128_u32()?;
+        }
+        self.read_leb128_u64()?;
+        Ok(())
+    }
+
+    /// Skips over a single instruction, returning its opcode. For prefixed
+    /// instructions, only the prefix is returned.
+    pub fn skip_instruction(&mut self) -> Result<u8> {
+        let opcode = self.read_u8()?;
+        match opcode {
+            // Instructions without immediates
+            0x00
+            | 0x01
+            | 0x05
+            | 0x0A
+            | 0x0B
+            | 0x0F
+            | 0x19
+            | 0x1A
+            | 0x1B
+            | 0x45..=0xC4
+            | 0xD1
+            | 0xD3
+            | 0xD4 => (),
+
+            // Block types
+            0x02 | 0x03 | 0x04 | 0x06 => {
+                self.read_leb128_i64()?;
+            }
+
+            // A single index or label
+            0x07..=0x09
+            | 0x0C
+            | 0x0D
+            | 0x10
+            | 0x12
+            | 0x14
+            | 0x15
+            | 0x18
+            | 0x20..=0x26
+            | 0x3F
+            | 0x40
+            | 0xD2
+            | 0xD5
+            | 0xD6 => {
+                self.read_leb128_u32()?;
+            }
+
+            // br_table
+            0x0E => {
+                let count = self.read_leb128_u32()?;
+                for _ in 0..=count {
+                    self.read_leb128_u32()?;
+                }
+            }
+
+            // call_indirect and return_call_indirect
+            0x11 | 0x13 => {
+                self.read_leb128_u32()?;
+                self.read_leb128_u32()?;
+            }
+
+            // select with types
+            0x1C => {
+                let count = self.read_leb128_u32()?;
+                for _ in 0..count {
+                    self.read_value_type()?;
+                }
+            }
+
+            // try_table
+            0x1F => {
+                self.read_leb128_i64()?;
+                let count = self.read_leb128_u32()?;
+                for _ in 0..count {
+                    match self.read_u8()? {
+                        0x00 | 0x01 => {
+                            self.read_leb128_u32()?;
+                            self.read_leb128_u32()?;
+                        }
+                        0x02 | 0x03 => {
+                            self.read_leb128_u32()?;
+                        }
+                        kind => bail!("Unknown catch kind {kind:#x}"),
+                    }
+                }
+            }
+
+            // Loads and stores
+            0x28..=0x3E => self.read_memarg()?,
+
+            0x41 | 0x42 | 0xD0 => {
+                self.read_leb128_i64()?;
+            }
+            0x43 => {
+                self.read_bytes(4)?;
+            }
+            0x44 => {
+                self.read_bytes(8)?;
+            }
+
+            // Bulk memory, table and saturating truncation instructions
+            0xFC => match self.read_leb128_u32()? {
+                0..=7 => (),
+                9 | 11 | 13 | 15..=17 => {
+                    self.read_leb128_u32()?;
+                }
+                8 | 10 | 12 | 14 => {
+                    self.read_leb128_u32()?;
+                    self.read_leb128_u32()?;
+                }
+                op => bail!("Unknown instruction 0xfc {op}"),
+            },
+
+            // SIMD instructions
+            0xFD => match self.read_leb128_u32()? {
+                0..=11 | 92 | 93 => self.read_memarg()?,
+                12 | 13 => {
+                    self.read_bytes(16)?;
+                }
+                21..=34 => {
+                    self.read_u8()?;
+                }
+                84..=91 => {
+                    self.read_memarg()?;
+                    self.read_u8()?;
+                }
+                _ => (),
+            },
+
+            // Atomic instructions
+            0xFE => match self.read_leb128_u32()? {
+                // atomic.fence
+                3 => {
+                    self.read_u8()?;
+                }
+                _ => self.read_memarg()?,
+            },
+
+            _ => bail!("Unknown instruction {opcode:#x}"),
+        }
+        Ok(opcode)
+    }
+
+    pub fn read_leb128_u32(&mut self) -> Result<u32> {
+        u32::try_from(self.read_leb128_u64()?).context("LEB128 value out of range")
+    }
+}
+
+pub(crate) fn write_leb128_u64(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_name(out: &mut Vec<u8>, name: &str) {
+    write_leb128_u64(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+pub(crate) fn read_sections(bytes: &[u8]) -> Result<Vec<Section<'_>>> {
+    if bytes.len() < 8 || &bytes[..4] != WASM_MAGIC {
+        bail!("Not a WebAssembly binary");
+    }
+
+    let mut reader = Reader::new(&bytes[8..]);
+    let mut sections = Vec::new();
+
+    while !reader.is_empty() {
+        let start = 8 + reader.pos();
+        let id = reader.read_u8()?;
+        let size = reader.read_leb128_u32()? as usize;
+        let offset = 8 + reader.pos();
+        let contents = reader.read_bytes(size)?;
+
+        let name = if id == SECTION_CUSTOM {
+            Some(Reader::new(contents).read_name()?)
+        } else {
+            None
+        };
+
+        sections.push(Section {
+            id,
+            name,
+            offset,
+            range: start..offset + size,
+            contents,
+        });
+    }
+
+    Ok(sections)
+}
+
+pub(crate) fn read_imports(contents: &[u8]) -> Result<Vec<Import<'_>>> {
+    let mut reader = Reader::new(contents);
+    let count = reader.read_leb128_u32()?;
+    let mut imports = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        let module = reader.read_name()?;
+        let field = reader.read_name()?;
+        let kind = reader.read_u8()?;
+        let descriptor_offset = reader.pos();
+        match kind {
+            EXTERNAL_FUNC => {
+                reader.read_leb128_u32()?;
+            }
+            EXTERNAL_TABLE => {
+                reader.read_value_type()?;
+                reader.read_limits()?;
+            }
+            EXTERNAL_MEMORY => {
+                reader.read_limits()?;
+            }
+            EXTERNAL_GLOBAL => {
+                reader.read_value_type()?;
+                reader.read_u8()?;
+            }
+            EXTERNAL_TAG => {
+                reader.read_u8()?;
+                reader.read_leb128_u32()?;
+            }
+            _ => bail!("Unknown import kind {kind:#x}"),
+        }
+        imports.push(Import {
+            module,
+            field,
+            kind,
+            descriptor_offset,
+        });
+    }
+
+    Ok(imports)
+}
+
+pub(crate) fn read_exports(contents: &[u8]) -> Result<Vec<Export<'_>>> {
+    let mut reader = Reader::new(contents);
+    let count = reader.read_leb128_u32()?;
+    let mut exports = Vec::with_capacity(count as usize);
+
+    for _ in 0..count {
+        let start = reader.pos();
+        let name = reader.read_name()?;
+        let kind = reader.read_u8()?;
+        let index = reader.read_leb128_u32()?;
+        exports.push(Export {
+            name,
+            kind,
+            index,
+            range: start..reader.pos(),
+        });
+    }
+
+    Ok(exports)
+}
+
+/// Returns whether any function body in the code section uses atomic
+/// instructions.
+pub(crate) fn code_uses_atomics(contents: &[u8]) -> Result<bool> {
+    let mut reader = Reader::new(contents);
+    let count = reader.read_leb128_u32()?;
+
+    for _ in 0..count {
+        let size = reader.read_leb128_u32()? as usize;
+        let mut body = Reader::new(reader.read_bytes(size)?);
+
+        let local_groups = body.read_leb128_u32()?;
+        for _ in 0..local_groups {
+            body.read_leb128_u32()?;
+            body.read_value_type()?;
+        }
+
+        while !body.is_empty() {
+            if body.skip_instruction()? == 0xFE {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+pub(crate) fn encode_section(id: u8, contents: &[u8]) -> Vec<u8> {
+    let mut section = vec![id];
+    write_leb128_u64(&mut section, contents.len() as u64);
+    section.extend_from_slice(contents);
+    section
+}
+
+/// Rebuilds the module, replacing the contents of sections for which
+/// `replace` returns new contents.
+pub(crate) fn rebuild_module(
+    bytes: &[u8],
+    mut replace: impl FnMut(&Section) -> Result<Option<Vec<u8>>>,
+) -> Result<Vec<u8>> {
+    let mut module = bytes[..8].to_vec();
+    for section in read_sections(bytes)? {
+        match replace(&section)? {
+            Some(contents) => module.extend(encode_section(section.id, &contents)),
+            None => module.extend_from_slice(&bytes[section.range]),
+        }
+    }
+    Ok(module)
+}
+
+pub(crate) fn encode_custom_section(name: &str, payload: &[u8]) -> Vec<u8> {
+    let mut contents = Vec::new();
+    write_name(&mut contents, name);
+    contents.extend_from_slice(payload);
+
+    encode_section(SECTION_CUSTOM, &contents)
+}
+
+/// Returns a copy of the module without the custom sections for which
+/// `predicate` returns true.
+pub(crate) fn remove_custom_sections(
+    bytes: &[u8],
+    predicate: impl Fn(&str) -> bool,
+) -> Result<Vec<u8>> {
+    let mut module = bytes[..8].to_vec();
+    for section in read_sections(bytes)? {
+        if !section.name.is_some_and(&predicate) {
+            module.extend_from_slice(&bytes[section.range]);
+        }
+    }
+    Ok(module)
+}
+
+/// Appends a custom section to the end of the module at `path`.
+pub(crate) fn append_custom_section(path: &Path, name: &str, payload: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    file.write_all(&encode_custom_section(name, payload))
+        .with_context(|| format!("Failed to write custom section to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_module(sections: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        for (id, contents) in sections {
+            module.push(*id);
+            write_leb128_u64(&mut module, contents.len() as u64);
+            module.extend_from_slice(contents);
+        }
+        module
+    }
+
+    #[test]
+    fn test_leb128_roundtrip() {
+        for value in [0u64, 1, 127, 128, 624485, u32::MAX as u64] {
+            let mut out = Vec::new();
+            write_leb128_u64(&mut out, value);
+            assert_eq!(Reader::new(&out).read_leb128_u64().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_read_sections() {
+        let mut module = test_module(&[(1, &[0x00]), (SECTION_CODE, &[0x00, 0x01])]);
+        module.extend(encode_custom_section("foo", b"bar"));
+
+        let sections = read_sections(&module).unwrap();
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[1].id, SECTION_CODE);
+        assert_eq!(sections[1].offset, 13);
+        assert_eq!(sections[1].range, 11..15);
+        assert_eq!(sections[2].name, Some("foo"));
+
+        assert!(read_sections(b"not wasm").is_err());
+    }
+
+    #[test]
+    fn test_read_leb128_i64() {
+        assert_eq!(Reader::new(&[0x7f]).read_leb128_i64().unwrap(), -1);
+        assert_eq!(Reader::new(&[0x40]).read_leb128_i64().unwrap(), -64);
+        assert_eq!(
+            Reader::new(&[0xc0, 0xbb, 0x78]).read_leb128_i64().unwrap(),
+            -123456
+        );
+        assert_eq!(Reader::new(&[0x3f]).read_leb128_i64().unwrap(), 63);
+    }
+
+    #[test]
+    fn test_code_uses_atomics() {
+        // One function: (block (i32.const 0) (i32.load offset=4) (drop)) with
+        // an i64 local
+        let body = [
+            0x01, 0x01, 0x7e, 0x02, 0x40, 0x41, 0x00, 0x28, 0x02, 0x04, 0x1a, 0x0b, 0x0b,
+        ];
+        let mut code = vec![0x01, body.len() as u8];
+        code.extend_from_slice(&body);
+        assert!(!code_uses_atomics(&code).unwrap());
+
+        // Same with i32.atomic.load (0xfe 0x10)
+        let body = [0x00, 0x41, 0x00, 0xfe, 0x10, 0x02, 0x00, 0x1a, 0x0b];
+        let mut code = vec![0x01, body.len() as u8];
+        code.extend_from_slice(&body);
+        assert!(code_uses_atomics(&code).unwrap());
+    }
+
+    #[test]
+    fn test_read_imports_and_exports() {
+        let mut imports = vec![0x02];
+        write_name(&mut imports, "env");
+        write_name(&mut imports, "memory");
+        imports.extend([EXTERNAL_MEMORY, 0x03, 0x01, 0x80, 0x80, 0x04]);
+        write_name(&mut imports, "env");
+        write_name(&mut imports, "f");
+        imports.extend([EXTERNAL_FUNC, 0x00]);
+
+        let parsed = read_imports(&imports).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].kind, EXTERNAL_MEMORY);
+        assert_eq!((parsed[0].module, parsed[0].field), ("env", "memory"));
+        assert_eq!(imports[parsed[0].descriptor_offset], 0x03);
+        assert_eq!(parsed[1].kind, EXTERNAL_FUNC);
+
+        let mut exports = vec![0x01];
+        write_name(&mut exports, "_start");
+        exports.extend([EXTERNAL_FUNC, 0x05]);
+        let parsed = read_exports(&exports).unwrap();
+        assert_eq!(parsed[0].name, "_start");
+        assert_eq!(parsed[0].index, 5);
+        assert_eq!(parsed[0].range, 1..exports.len());
+    }
+
+    #[test]
+    fn test_remove_custom_sections() {
+        let mut module = test_module(&[(SECTION_CODE, &[0x00])]);
+        let expected = module.clone();
+        module.extend(encode_custom_section(".debug_info", b"x"));
+        module.extend(encode_custom_section(".debug_line", b"y"));
+
+        let stripped = remove_custom_sections(&module, |name| name.starts_with(".debug_")).unwrap();
+        assert_eq!(stripped, expected);
+    }
+}
@@ -0,0 +1,152 @@
+//! `wasixcc --watch`: rebuilds whenever one of the build's inputs, or a
+//! header they include, changes.
+
+use std::{
+    collections::BTreeSet,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use notify::Watcher;
+
+use super::*;
+
+// Editors often save in several steps, so wait for changes to settle.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+pub(crate) fn run(args: Vec<String>, settings_args: &[String], run_cxx: bool) -> Result<()> {
+    loop {
+        let started = Instant::now();
+        match compiler::run(args.clone(), gather_user_settings(settings_args)?, run_cxx) {
+            Ok(()) => eprintln!(
+                "[watch] Build succeeded in {:.2}s",
+                started.elapsed().as_secs_f64()
+            ),
+            Err(e) => {
+                print_error(&e);
+                eprintln!("[watch] Build failed");
+            }
+        }
+
+        // Headers may have been added or removed, so find them again
+        let files = watched_files(&args, settings_args, run_cxx)?;
+        eprintln!("[watch] Watching {} files for changes...", files.len());
+        let changed = wait_for_change(&files)?;
+        eprintln!("[watch] {} changed, rebuilding", changed.display());
+    }
+}
+
+// The build's inputs, with the headers included by its sources.
+fn watched_files(
+    args: &[String],
+    settings_args: &[String],
+    run_cxx: bool,
+) -> Result<BTreeSet<PathBuf>> {
+    let (compiler_inputs, linker_inputs) =
+        compiler::build_inputs(args.to_vec(), gather_user_settings(settings_args)?, run_cxx)?;
+
+    let mut files = BTreeSet::new();
+    for input in &compiler_inputs {
+        files.insert(input.clone());
+
+        let compile_command = compiler::query_compile_command(
+            args.to_vec(),
+            gather_user_settings(settings_args)?,
+            run_cxx,
+            input,
+        )?;
+        let mut command = Command::new(&compile_command.arguments[0]);
+        command.args(&compile_command.arguments[1..]).arg("-M");
+        match command.output() {
+            Ok(output) if output.status.success() => {
+                files.extend(parse_make_deps(&String::from_utf8_lossy(&output.stdout)));
+            }
+            // The next build reports the problem
+            _ => tracing::debug!("Failed to find the dependencies of {}", input.display()),
+        }
+    }
+    files.extend(linker_inputs);
+
+    Ok(files
+        .into_iter()
+        .filter_map(|file| std::path::absolute(file).ok())
+        .collect())
+}
+
+// The prerequisites listed by a Makefile rule, as printed by `clang -M`.
+pub(crate) fn parse_make_deps(rule: &str) -> Vec<PathBuf> {
+    let rule = rule.replace("\\\n", " ");
+    let Some((_, prerequisites)) = rule.split_once(": ") else {
+        return Vec::new();
+    };
+
+    let mut deps = Vec::new();
+    let mut current = String::new();
+    let mut chars = prerequisites.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&' ') => current.push(chars.next().unwrap()),
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    deps.push(PathBuf::from(std::mem::take(&mut current)));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        deps.push(PathBuf::from(current));
+    }
+    deps
+}
+
+// Blocks until one of `files` is modified, created or removed, and returns
+// it. Directories are watched rather than the files themselves, since
+// editors often save by replacing the file.
+fn wait_for_change(files: &BTreeSet<PathBuf>) -> Result<PathBuf> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).context("Failed to watch files")?;
+    let dirs = files
+        .iter()
+        .filter_map(|file| file.parent())
+        .collect::<BTreeSet<_>>();
+    for dir in dirs {
+        watcher
+            .watch(dir, notify::RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+    }
+
+    loop {
+        let event = receiver
+            .recv()
+            .context("File watcher stopped")?
+            .context("Failed to watch files")?;
+        if event.kind.is_access() {
+            continue;
+        }
+        if let Some(changed) = event.paths.into_iter().find(|path| files.contains(path)) {
+            std::thread::sleep(DEBOUNCE);
+            while receiver.try_recv().is_ok() {}
+            return Ok(changed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_make_deps() {
+        let rule = "main.o: src/main.c include/a.h \\\n  include/my\\ header.h\n";
+        assert_eq!(
+            parse_make_deps(rule),
+            [
+                PathBuf::from("src/main.c"),
+                PathBuf::from("include/a.h"),
+                PathBuf::from("include/my header.h"),
+            ]
+        );
+        assert!(parse_make_deps("").is_empty());
+    }
+}